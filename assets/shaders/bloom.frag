@@ -7,6 +7,9 @@
 SAMPLER_2D(0, image);
 SAMPLER_2D(1, mainImage);
 SAMPLER_2D(2, lensDirt);
+SAMPLER_2D(3, lensFlare);
+SAMPLER_2D(4, starburst);
+SAMPLER_2D(5, streaks);
 
 UNIFORM_BLOCK_BEGIN(7, BloomParams)
     float spread;
@@ -15,6 +18,17 @@ UNIFORM_BLOCK_BEGIN(7, BloomParams)
     int useLensDirt;
     float lensDirtIntensity;
     vec3 tint;
+    int useLensFlare;
+    float lensFlareIntensity;
+    int ghostCount;
+    float ghostDispersal;
+    float haloWidth;
+    float chromaticDistortion;
+    float starburstIntensity;
+    int useStreaks;
+    float streakIntensity;
+    float streakStretch;
+    vec3 streakTint;
 UNIFORM_BLOCK_END
 
 INPUT_BLOCK_BEGIN(0, VsOut)
@@ -33,6 +47,59 @@ vec3 Prefilter (vec3 c) {
     return c * contribution;
 }
 
+#if defined(BLOOM_PASS_LENS_FLARE)
+    // Pseudo lens flare of John Chapman, the bright parts of the image mirrored through the
+    // center as ghosts and a halo, with the color channels split along the flare.
+    vec3 SampleChromatic(vec2 texcoord, vec2 direction, vec3 distortion)
+    {
+        return vec3(
+            texture(image, texcoord + direction * distortion.r).r,
+            texture(image, texcoord + direction * distortion.g).g,
+            texture(image, texcoord + direction * distortion.b).b
+        );
+    }
+
+    vec3 LensFlare(vec2 texcoord)
+    {
+        vec2 texelSize = 1.0 / textureSize(image, 0);
+        vec2 flipped = 1.0 - texcoord;
+        vec2 ghostVector = (0.5 - flipped) * ghostDispersal;
+        vec2 direction = length(ghostVector) > EPSILON ? normalize(ghostVector) : vec2(0.0);
+        vec3 distortion = vec3(-texelSize.x, 0.0, texelSize.x) * chromaticDistortion;
+        float maxDistance = length(vec2(0.5));
+
+        vec3 result = vec3(0.0);
+
+        // Ghosts, fading out towards the edges.
+        for (int i = 0; i < ghostCount; ++i) {
+            vec2 offset = fract(flipped + ghostVector * float(i));
+            float weight = pow(1.0 - length(0.5 - offset) / maxDistance, 10.0);
+            result += SampleChromatic(offset, direction, distortion) * weight;
+        }
+
+        // Halo, a ring of haloWidth around the center.
+        vec2 haloTexcoord = flipped + direction * haloWidth;
+        float haloWeight = pow(1.0 - length(0.5 - fract(haloTexcoord)) / maxDistance, 5.0);
+        result += SampleChromatic(haloTexcoord, direction, distortion) * haloWeight;
+
+        return result;
+    }
+#elif defined(BLOOM_PASS_UPSAMPLE_APPLY)
+    // The starburst turns with the camera, so it does not look painted on the lens.
+    float Starburst(vec2 texcoord)
+    {
+        float cameraRotation = dot(LIB_VIEW_MATRIX[0].xyz, vec3(0.0, 0.0, 1.0))
+            + dot(LIB_VIEW_MATRIX[2].xyz, vec3(0.0, 1.0, 0.0));
+        float c = cos(cameraRotation);
+        float s = sin(cameraRotation);
+
+        vec2 size = vec2(textureSize(mainImage, 0));
+        vec2 position = (texcoord - 0.5) * vec2(size.x / size.y, 1.0);
+
+        return texture(starburst, mat2(c, s, -s, c) * position + 0.5).r;
+    }
+#endif
+
 void main()
 {
     vec2 halfpixel = (1.0 / textureSize(image, 0)) * 0.5;
@@ -45,6 +112,24 @@ void main()
     outColor = Downsample(image, fsIn.texcoord, halfpixel);
 #elif defined(BLOOM_PASS_UPSAMPLE)
     outColor = Upsample(image, fsIn.texcoord, halfpixel);
+#elif defined(BLOOM_PASS_LENS_FLARE)
+    outColor = vec4(LensFlare(fsIn.texcoord), 1.0);
+#elif defined(BLOOM_PASS_STREAK_DOWNSAMPLE)
+    // Horizontal only, the streaks keep the height of the image.
+    float dx = 1.0 / textureSize(image, 0).x;
+    vec3 c0 = texture(image, fsIn.texcoord - vec2(dx * 1.5, 0.0)).rgb;
+    vec3 c1 = texture(image, fsIn.texcoord - vec2(dx * 0.5, 0.0)).rgb;
+    vec3 c2 = texture(image, fsIn.texcoord + vec2(dx * 0.5, 0.0)).rgb;
+    vec3 c3 = texture(image, fsIn.texcoord + vec2(dx * 1.5, 0.0)).rgb;
+    outColor = vec4((c0 + c1 * 3.0 + c2 * 3.0 + c3) / 8.0, 1.0);
+#elif defined(BLOOM_PASS_STREAK_UPSAMPLE)
+    // The lower level, in image, is stretched over the higher one, in mainImage.
+    float dx = 1.5 / textureSize(image, 0).x;
+    vec3 c0 = texture(image, fsIn.texcoord - vec2(dx, 0.0)).rgb;
+    vec3 c1 = texture(image, fsIn.texcoord).rgb;
+    vec3 c2 = texture(image, fsIn.texcoord + vec2(dx, 0.0)).rgb;
+    vec3 high = texture(mainImage, fsIn.texcoord).rgb;
+    outColor = vec4(mix(high, c0 * 0.25 + c1 * 0.5 + c2 * 0.25, streakStretch), 1.0);
 #elif defined(BLOOM_PASS_UPSAMPLE_APPLY)
     vec3 bloom = intensity * Upsample(image, fsIn.texcoord, halfpixel).rgb;
 
@@ -55,6 +140,18 @@ void main()
         bloom += lensDirt;
     }
 
+    if (useLensFlare == TRUE)
+    {
+        vec2 flareHalfpixel = (1.0 / textureSize(lensFlare, 0)) * 0.5;
+        vec3 flare = Upsample(lensFlare, fsIn.texcoord, flareHalfpixel).rgb * lensFlareIntensity;
+        bloom += flare * mix(1.0, Starburst(fsIn.texcoord), starburstIntensity);
+    }
+
+    if (useStreaks == TRUE)
+    {
+        bloom += texture(streaks, fsIn.texcoord).rgb * streakTint * streakIntensity;
+    }
+
     bloom *= tint;
 
     vec4 mainImageColor = texture(mainImage, fsIn.texcoord);
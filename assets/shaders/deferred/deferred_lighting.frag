@@ -0,0 +1,115 @@
+#version 450 core
+#extension GL_ARB_separate_shader_objects : enable
+
+#define DEFERRED_LIGHTING_PASS
+
+#include "assets/shaders/library/engine.glsl"
+#include "assets/shaders/library/gbuffer.glsl"
+
+#define GBUFFER_VIEW_LIT                    0
+#define GBUFFER_VIEW_ALBEDO                 1
+#define GBUFFER_VIEW_NORMALS                2
+#define GBUFFER_VIEW_METALLIC               3
+#define GBUFFER_VIEW_ROUGHNESS              4
+#define GBUFFER_VIEW_AO                     5
+#define GBUFFER_VIEW_EMISSION               6
+#define GBUFFER_VIEW_DEPTH                  7
+
+INPUT_BLOCK_BEGIN(0, VsOut)
+    vec2 texcoord;
+INPUT_BLOCK_END_NAMED(fsIn)
+
+OUTPUT(0, vec4, outColor);
+
+// Same layout as DeferredLightingBlock in deferred.rs.
+UNIFORM_BLOCK_BEGIN(12, DeferredLightingBlock)
+    mat4 inverseViewProjection;
+    int gbufferView;
+UNIFORM_BLOCK_END
+
+// The material units are free during the lighting pass.
+SAMPLER_2D(0, gbufferAlbedo);
+SAMPLER_2D(1, gbufferNormal);
+SAMPLER_2D(2, gbufferMetallicRoughnessAo);
+SAMPLER_2D(6, gbufferEmission);
+SAMPLER_2D(11, gbufferDepth);
+
+#include "assets/shaders/library/pbs_lighting.glsl"
+
+vec3 ReconstructWorldPosition(in vec2 texcoord, in float depth)
+{
+    vec4 position = inverseViewProjection * vec4(vec3(texcoord, depth) * 2.0 - 1.0, 1.0);
+    return position.xyz / position.w;
+}
+
+void PopulateShadingProperties(out ShadingProperties props, in float depth)
+{
+    vec4 albedo = texture(gbufferAlbedo, fsIn.texcoord);
+    vec3 m_r_ao = texture(gbufferMetallicRoughnessAo, fsIn.texcoord).rgb;
+
+    props.worldPosition = ReconstructWorldPosition(fsIn.texcoord, depth);
+    props.n = OctahedronDecode(texture(gbufferNormal, fsIn.texcoord).rg);
+    props.worldNormal = props.n;
+    props.v = normalize(LIB_CAMERA_POSITION.xyz - props.worldPosition);
+    props.r = reflect(-props.v, props.n);
+    props.NoV = clamp(abs(dot(props.n, props.v)), 0.0, 1.0);
+
+    props.albedo = vec4(albedo.rgb, 1.0);
+    props.metallic = m_r_ao.r;
+    props.perceptualRoughness = m_r_ao.g;
+    props.roughness = PerceptualRoughnessToRoughness(props.perceptualRoughness);
+    props.ao = m_r_ao.b;
+
+    ApplyScreenSpaceAO(props);
+    PopulateSpecularAO(props);
+    PopulateIBLProperties(props);
+    ApplyScreenSpaceReflections(props);
+    CalculateF0(props, albedo.a);
+}
+
+vec3 GBufferView(in float depth)
+{
+    switch (gbufferView) {
+        case GBUFFER_VIEW_ALBEDO:
+            return texture(gbufferAlbedo, fsIn.texcoord).rgb;
+        case GBUFFER_VIEW_NORMALS:
+            return OctahedronDecode(texture(gbufferNormal, fsIn.texcoord).rg) * 0.5 + 0.5;
+        case GBUFFER_VIEW_METALLIC:
+            return texture(gbufferMetallicRoughnessAo, fsIn.texcoord).rrr;
+        case GBUFFER_VIEW_ROUGHNESS:
+            return texture(gbufferMetallicRoughnessAo, fsIn.texcoord).ggg;
+        case GBUFFER_VIEW_AO:
+            return texture(gbufferMetallicRoughnessAo, fsIn.texcoord).bbb;
+        case GBUFFER_VIEW_EMISSION:
+            return texture(gbufferEmission, fsIn.texcoord).rgb;
+        case GBUFFER_VIEW_DEPTH: {
+            vec3 position = ReconstructWorldPosition(fsIn.texcoord, depth);
+            return vec3(-(LIB_VIEW_MATRIX * vec4(position, 1.0)).z / LIB_CAMERA_FAR_PLANE);
+        }
+        default:
+            return vec3(0.0);
+    }
+}
+
+// Fullscreen lighting of the G-buffer written by gbuffer.frag. Background pixels are discarded so
+// that the skybox can be drawn afterwards.
+void main()
+{
+    float depth = texture(gbufferDepth, fsIn.texcoord).r;
+
+    if (depth == 1.0) {
+        discard;
+    }
+
+    if (gbufferView != GBUFFER_VIEW_LIT) {
+        outColor = vec4(GBufferView(depth), 1.0);
+        return;
+    }
+
+    ShadingProperties props;
+    PopulateShadingProperties(props, depth);
+
+    vec3 emission = texture(gbufferEmission, fsIn.texcoord).rgb;
+
+    outColor = vec4(ApplyFog(props, EvaluateAnalyticalLights(props) + IBL(props) + emission), 1.0);
+}
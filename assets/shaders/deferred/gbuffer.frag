@@ -0,0 +1,38 @@
+#version 450 core
+#extension GL_ARB_separate_shader_objects : enable
+
+#include "assets/shaders/library/engine.glsl"
+#include "assets/shaders/library/gbuffer.glsl"
+
+INPUT_BLOCK_BEGIN(0, VsOut)
+    vec3 wViewDirection;
+    vec3 wNormal;
+    vec4 wTangent;
+    vec2 texcoord;
+INPUT_BLOCK_END_NAMED(fsIn)
+
+INPUT(4, vec4, clipPosition);
+INPUT(5, vec4, previousClipPosition);
+
+OUTPUT(0, vec4, outAlbedo);
+OUTPUT(1, vec2, outNormal);
+OUTPUT(2, vec4, outMetallicRoughnessAo);
+OUTPUT(3, vec4, outEmission);
+OUTPUT(4, vec2, outVelocity);
+
+#include "assets/shaders/library/pbs_material.glsl"
+
+// Writes the surface of the PBS material to the G-buffer, lighting happens in
+// deferred_lighting.frag.
+void main()
+{
+    ShadingProperties props;
+    PopulateVectorProducts(props);
+    PopulateMaterialProperties(props);
+
+    outAlbedo = vec4(props.albedo.rgb, reflectance);
+    outNormal = OctahedronEncode(props.n);
+    outMetallicRoughnessAo = vec4(props.metallic, props.perceptualRoughness, props.ao, 0.0);
+    outEmission = vec4(MaterialEmission(), 0.0);
+    outVelocity = ScreenSpaceVelocity(clipPosition, previousClipPosition);
+}
@@ -7,12 +7,25 @@ SAMPLER_2D(0, mainImage);
 SAMPLER_2D(1, depthTex);
 SAMPLER_2D(2, cocTex);
 SAMPLER_2D(3, dofTex);
+SAMPLER_2D(4, focusTex);
+SAMPLER_2D(5, nearTex);
+
+// Same layout as DepthOfFieldUniforms in dof.rs. Distances are in meters.
+UNIFORM_BLOCK_BEGIN(21, DepthOfFieldBlock)
+    float focusDistance;
+    float apertureDiameter;
+    float focalLength;
+    float pixelsPerMeter; // Image height over sensor height.
+    vec2 autofocusPoint;
+    float autofocusBlend;
+    float maxCocRadius; // In pixels of the full resolution image.
+UNIFORM_BLOCK_END
 
 INPUT_BLOCK_BEGIN(0, VsOut)
     vec2 texcoord;
 INPUT_BLOCK_END_NAMED(fsIn)
 
-#ifdef DOF_PASS_COC
+#if defined(DOF_PASS_COC) || defined(DOF_PASS_AUTOFOCUS)
     OUTPUT(0, float, outColor);
 #else
     OUTPUT(0, vec4, outColor);
@@ -26,105 +39,161 @@ float linearize_depth(float depth)
     return (2.0 * zNear * zFar / (zFar + zNear - depthNdc * (zFar - zNear)));
 }
 
-#if defined(DOF_PASS_COC)
+#if defined(DOF_PASS_AUTOFOCUS)
+    // Average distance of the 5x5 pixels around the autofocus point, eased towards from the
+    // previous focus distance.
+    void AutofocusPass()
+    {
+        ivec2 size = textureSize(depthTex, 0);
+        ivec2 center = ivec2(autofocusPoint * vec2(size));
+
+        float sum = 0.0;
+        for (int y = -2; y <= 2; ++y) {
+            for (int x = -2; x <= 2; ++x) {
+                ivec2 pixel = clamp(center + ivec2(x, y), ivec2(0), size - 1);
+                sum += linearize_depth(texelFetch(depthTex, pixel, 0).r);
+            }
+        }
+
+        float previous = texelFetch(focusTex, ivec2(0), 0).r;
+        outColor = mix(previous, sum / 25.0, autofocusBlend);
+    }
+#elif defined(DOF_PASS_COC)
+    // Signed radius in pixels of the circle of confusion of the thin lens, negative in the near
+    // field. Same as ThinLens::circle_of_confusion in camera.rs.
     void CoCPass()
     {
-        float depth = 0.0;
-        depth = linearize_depth(texture(depthTex, fsIn.texcoord).r);// LIB_LINEARIZE_DEPTH(texture(depthTex, fsIn.texcoord).r);
+        float depth = linearize_depth(texture(depthTex, fsIn.texcoord).r);
+
+    #if defined(DOF_AUTOFOCUS)
+        float focus = texelFetch(focusTex, ivec2(0), 0).r;
+    #else
+        float focus = focusDistance;
+    #endif
+        // The lens cannot focus closer than its focal length.
+        focus = max(focus, focalLength * 1.001);
 
-        float coc = clamp((depth - LIB_CAMERA_FOCUS_DISTANCE) / LIB_CAMERA_FOCUS_RANGE, -1.0, 1.0) * LIB_CAMERA_BOKEH_RADIUS;
+        float coc = apertureDiameter * focalLength * (depth - focus) / (depth * (focus - focalLength));
 
-        outColor = coc;
+        outColor = clamp(0.5 * coc * pixelsPerMeter, -maxCocRadius, maxCocRadius);
+    }
+#elif defined(DOF_PASS_BOKEH_FAR) || defined(DOF_PASS_BOKEH_NEAR)
+    // 0: small kernel (16 samples), 1: medium kernel (22 samples)
+    #ifndef BOKEH_KERNEL_SIZE
+        SPECIALIZATION_CONSTANT(0, int, BOKEH_KERNEL_SIZE, 1);
+    #endif
+
+    // From https://github.com/Unity-Technologies/PostProcessing/
+    // blob/v2/PostProcessing/Shaders/Builtins/DiskKernels.hlsl
+    const vec2 kernelSmall[16] = {
+        vec2(0, 0),
+        vec2(0.54545456, 0),
+        vec2(0.16855472, 0.5187581),
+        vec2(-0.44128203, 0.3206101),
+        vec2(-0.44128197, -0.3206102),
+        vec2(0.1685548, -0.5187581),
+        vec2(1, 0),
+        vec2(0.809017, 0.58778524),
+        vec2(0.30901697, 0.95105654),
+        vec2(-0.30901703, 0.9510565),
+        vec2(-0.80901706, 0.5877852),
+        vec2(-1, 0),
+        vec2(-0.80901694, -0.58778536),
+        vec2(-0.30901664, -0.9510566),
+        vec2(0.30901712, -0.9510565),
+        vec2(0.80901694, -0.5877853),
+    };
+
+    const vec2 kernelMedium[22] = {
+        vec2(0, 0),
+        vec2(0.53333336, 0),
+        vec2(0.3325279, 0.4169768),
+        vec2(-0.11867785, 0.5199616),
+        vec2(-0.48051673, 0.2314047),
+        vec2(-0.48051673, -0.23140468),
+        vec2(-0.11867763, -0.51996166),
+        vec2(0.33252785, -0.4169769),
+        vec2(1, 0),
+        vec2(0.90096885, 0.43388376),
+        vec2(0.6234898, 0.7818315),
+        vec2(0.22252098, 0.9749279),
+        vec2(-0.22252095, 0.9749279),
+        vec2(-0.62349, 0.7818314),
+        vec2(-0.90096885, 0.43388382),
+        vec2(-1, 0),
+        vec2(-0.90096885, -0.43388376),
+        vec2(-0.6234896, -0.7818316),
+        vec2(-0.22252055, -0.974928),
+        vec2(0.2225215, -0.9749278),
+        vec2(0.6234897, -0.7818316),
+        vec2(0.90096885, -0.43388376),
+    };
+
+    const int KERNEL_SAMPLE_COUNT = BOKEH_KERNEL_SIZE == 0 ? 16 : 22;
+
+    vec2 KernelSample(int k)
+    {
+        return BOKEH_KERNEL_SIZE == 0 ? kernelSmall[k] : kernelMedium[k];
     }
-#elif defined(DOF_PASS_BOKEH)
-    #define BOKEH_KERNEL_MEDIUM
-    #if defined(BOKEH_KERNEL_SMALL)
-        // From https://github.com/Unity-Technologies/PostProcessing/
-        // blob/v2/PostProcessing/Shaders/Builtins/DiskKernels.hlsl
-        #define KERNEL_SAMPLE_COUNT 16
-        const vec2 kernel[KERNEL_SAMPLE_COUNT] = {
-            vec2(0, 0),
-            vec2(0.54545456, 0),
-            vec2(0.16855472, 0.5187581),
-            vec2(-0.44128203, 0.3206101),
-            vec2(-0.44128197, -0.3206102),
-            vec2(0.1685548, -0.5187581),
-            vec2(1, 0),
-            vec2(0.809017, 0.58778524),
-            vec2(0.30901697, 0.95105654),
-            vec2(-0.30901703, 0.9510565),
-            vec2(-0.80901706, 0.5877852),
-            vec2(-1, 0),
-            vec2(-0.80901694, -0.58778536),
-            vec2(-0.30901664, -0.9510566),
-            vec2(0.30901712, -0.9510565),
-            vec2(0.80901694, -0.5877853),
-        };
-    #elif defined(BOKEH_KERNEL_MEDIUM)
-        #define KERNEL_SAMPLE_COUNT 22
-        const vec2 kernel[KERNEL_SAMPLE_COUNT] = {
-            vec2(0, 0),
-            vec2(0.53333336, 0),
-            vec2(0.3325279, 0.4169768),
-            vec2(-0.11867785, 0.5199616),
-            vec2(-0.48051673, 0.2314047),
-            vec2(-0.48051673, -0.23140468),
-            vec2(-0.11867763, -0.51996166),
-            vec2(0.33252785, -0.4169769),
-            vec2(1, 0),
-            vec2(0.90096885, 0.43388376),
-            vec2(0.6234898, 0.7818315),
-            vec2(0.22252098, 0.9749279),
-            vec2(-0.22252095, 0.9749279),
-            vec2(-0.62349, 0.7818314),
-            vec2(-0.90096885, 0.43388382),
-            vec2(-1, 0),
-            vec2(-0.90096885, -0.43388376),
-            vec2(-0.6234896, -0.7818316),
-            vec2(-0.22252055, -0.974928),
-            vec2(0.2225215, -0.9749278),
-            vec2(0.6234897, -0.7818316),
-            vec2(0.90096885, -0.43388376),
-        };
-    #endif //BOKEH_KERNEL_SMALL
 
     float Weigh(float coc, float radius)
     {
         return clamp((coc - radius + 2.0) / 2.0, 0.0, 1.0);
     }
 
-    void BokehPass()
-    {
-        vec3 bgColor = vec3(0.0);
-        vec3 fgColor = vec3(0.0);
-        float bgWeight = 0.0;
-        float fgWeight = 0.0;
-
-        vec2 texelSize =  1.0 / textureSize(mainImage, 0);
-
-        for (int k = 0; k < KERNEL_SAMPLE_COUNT; k++) {
-            vec2 offset = kernel[k] * LIB_CAMERA_BOKEH_RADIUS;
-            float radius = length(offset);
-            offset *= texelSize;
-            vec4 s = texture(mainImage, fsIn.texcoord + offset);
-
-            float bgw = Weigh(max(0.0, s.a), radius);
-            bgColor += s.rgb * bgw;
-            bgWeight += bgw;
-
-            float fgw = Weigh(-s.a, radius);
-            fgColor += s.rgb * fgw;
-            fgWeight += fgw;
+    // The CoC of the half resolution image is in its own pixels, as are the kernel offsets.
+    #if defined(DOF_PASS_BOKEH_FAR)
+        // Background gather. A sample only spreads as far as the smaller of its CoC and the one
+        // of the pixel, so sharp or near pixels are not blurred over by the background and the
+        // background does not pick up the foreground.
+        void BokehFarPass()
+        {
+            vec3 color = vec3(0.0);
+            float weight = 0.0;
+
+            vec2 texelSize = 1.0 / textureSize(mainImage, 0);
+            float kernelRadius = maxCocRadius * 0.5;
+            float centerCoc = texture(mainImage, fsIn.texcoord).a;
+
+            for (int k = 0; k < KERNEL_SAMPLE_COUNT; k++) {
+                vec2 offset = KernelSample(k) * kernelRadius;
+                float radius = length(offset);
+                vec4 s = texture(mainImage, fsIn.texcoord + offset * texelSize);
+
+                float w = Weigh(max(0.0, min(centerCoc, s.a)), radius);
+                color += s.rgb * w;
+                weight += w;
+            }
+
+            outColor = vec4(color / (weight + float(weight == 0.0)), 1.0);
         }
+    #else
+        // Foreground gather. Near samples spread over whatever is behind them, in focus pixels
+        // included, and the alpha is the coverage of the near field.
+        void BokehNearPass()
+        {
+            vec3 color = vec3(0.0);
+            float weight = 0.0;
 
-        bgColor *= 1.0 / (bgWeight + float((bgWeight == 0)));
-        fgColor *= 1.0 / (fgWeight + float((fgWeight == 0)));
+            vec2 texelSize = 1.0 / textureSize(mainImage, 0);
+            float kernelRadius = maxCocRadius * 0.5;
 
-        float bgfg = min(1.0, fgWeight * PI / KERNEL_SAMPLE_COUNT);
+            for (int k = 0; k < KERNEL_SAMPLE_COUNT; k++) {
+                vec2 offset = KernelSample(k) * kernelRadius;
+                float radius = length(offset);
+                vec4 s = texture(mainImage, fsIn.texcoord + offset * texelSize);
 
-        vec3 color = mix(bgColor, fgColor, bgfg);
-        outColor = vec4(color, bgfg);
-    }
+                float w = Weigh(-s.a, radius);
+                color += s.rgb * w;
+                weight += w;
+            }
+
+            color *= 1.0 / (weight + float(weight == 0.0));
+
+            float coverage = min(1.0, weight * PI / KERNEL_SAMPLE_COUNT);
+            outColor = vec4(color, coverage);
+        }
+    #endif
 #elif defined(DOF_PASS_DOWNSAMPLE)
     float Weigh(vec3 c)
     {
@@ -172,9 +241,10 @@ float linearize_depth(float depth)
         // Premultiply CoC again
         color *= smoothstep(0, texelSize.y * 2.0, abs(coc));
 
-        outColor = vec4(color, coc);
+        // In half resolution pixels.
+        outColor = vec4(color, coc * 0.5);
 #else
-        outColor = vec4(texture(mainImage, fsIn.texcoord).rgb, coc);
+        outColor = vec4(texture(mainImage, fsIn.texcoord).rgb, coc * 0.5);
 #endif
     }
 #else
@@ -191,26 +261,36 @@ float linearize_depth(float depth)
         outColor = color * 0.25;
     }
 
+    // The far field replaces the pixels behind the focus, the near field is composited over
+    // everything.
     void DofCombinePass()
     {
         vec4 source = texture(mainImage, fsIn.texcoord);
         float coc = texture(cocTex, fsIn.texcoord).r;
-        vec4 dof = texture(dofTex, fsIn.texcoord);
+        vec3 far = texture(dofTex, fsIn.texcoord).rgb;
+        vec4 near = texture(nearTex, fsIn.texcoord);
+
+        vec3 color = mix(source.rgb, far, smoothstep(0.1, 1.0, coc));
+
+        float nearAlpha = max(near.a, smoothstep(0.1, 1.0, -coc));
+        color = mix(color, near.rgb, nearAlpha);
 
-        float dofStrength = smoothstep(0.1, 1.0, abs(coc));
-        vec3 color = mix(source.rgb, dof.rgb, dofStrength + dof.a - dofStrength * dof.a);
         outColor = vec4(color, source.a);
     }
-#endif // DOF_PASS_BOKEH
+#endif
 
 void main()
 {
-#if defined(DOF_PASS_COC)
+#if defined(DOF_PASS_AUTOFOCUS)
+    AutofocusPass();
+#elif defined(DOF_PASS_COC)
     CoCPass();
 #elif defined(DOF_PASS_DOWNSAMPLE)
     DownsamplePass();
-#elif defined(DOF_PASS_BOKEH)
-    BokehPass();
+#elif defined(DOF_PASS_BOKEH_FAR)
+    BokehFarPass();
+#elif defined(DOF_PASS_BOKEH_NEAR)
+    BokehNearPass();
 #elif defined(DOF_PASS_BOKEH_BLUR)
     BokehBlurPass();
 #elif defined(DOF_PASS_COMBINE)
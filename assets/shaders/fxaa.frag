@@ -0,0 +1,152 @@
+#version 450 core
+#extension GL_ARB_separate_shader_objects : enable
+
+// FXAA 3.11 (Timothy Lottes), quality variant with the PC preset 12 search steps.
+
+layout(binding = 0) uniform sampler2D image;
+
+layout(std140, binding = 16) uniform FxaaBlock {
+    float subpixelQuality;
+    float edgeThreshold;
+    float edgeThresholdMin;
+    float _padding;
+};
+
+layout(location = 0) in VsOut {
+    vec2 texcoord;
+} fsIn;
+
+layout(location = 0) out vec4 outColor;
+
+#define FXAA_SEARCH_STEPS 5
+const float kSearchSteps[FXAA_SEARCH_STEPS] = float[](1.5, 2.0, 2.0, 4.0, 12.0);
+
+// The input is linear after the tone mapper, the edge search works on perceptual luma.
+float Luma(vec3 color)
+{
+    return sqrt(dot(color, vec3(0.299, 0.587, 0.114)));
+}
+
+float LumaAt(vec2 uv)
+{
+    return Luma(textureLod(image, uv, 0.0).rgb);
+}
+
+float LumaAt(vec2 uv, ivec2 offset)
+{
+    return Luma(textureLodOffset(image, uv, 0.0, offset).rgb);
+}
+
+void main()
+{
+    vec2 uv = fsIn.texcoord;
+    vec2 texel = 1.0 / vec2(textureSize(image, 0));
+
+    vec3 center = textureLod(image, uv, 0.0).rgb;
+    float lumaM = Luma(center);
+    float lumaS = LumaAt(uv, ivec2( 0, -1));
+    float lumaE = LumaAt(uv, ivec2( 1,  0));
+    float lumaN = LumaAt(uv, ivec2( 0,  1));
+    float lumaW = LumaAt(uv, ivec2(-1,  0));
+
+    float rangeMax = max(max(max(lumaS, lumaE), max(lumaN, lumaW)), lumaM);
+    float rangeMin = min(min(min(lumaS, lumaE), min(lumaN, lumaW)), lumaM);
+    float range = rangeMax - rangeMin;
+
+    if (range < max(edgeThresholdMin, rangeMax * edgeThreshold)) {
+        outColor = vec4(center, 1.0);
+        return;
+    }
+
+    float lumaNW = LumaAt(uv, ivec2(-1,  1));
+    float lumaSE = LumaAt(uv, ivec2( 1, -1));
+    float lumaNE = LumaAt(uv, ivec2( 1,  1));
+    float lumaSW = LumaAt(uv, ivec2(-1, -1));
+
+    float lumaNS = lumaN + lumaS;
+    float lumaWE = lumaW + lumaE;
+    float lumaNWSW = lumaNW + lumaSW;
+    float lumaNESE = lumaNE + lumaSE;
+    float lumaNWNE = lumaNW + lumaNE;
+    float lumaSWSE = lumaSW + lumaSE;
+
+    // Sub-pixel aliasing amount, from the low-pass of the 3x3 neighbourhood.
+    float subpixA = (2.0 * lumaNS + 2.0 * lumaWE + lumaNWSW + lumaNESE) / 12.0;
+    float subpixB = clamp(abs(subpixA - lumaM) / range, 0.0, 1.0);
+    float subpixC = smoothstep(0.0, 1.0, subpixB);
+    float subpixBlend = subpixC * subpixC * subpixelQuality;
+
+    float edgeHorizontal = abs(lumaNWSW - 2.0 * lumaW) + 2.0 * abs(lumaNS - 2.0 * lumaM) + abs(lumaNESE - 2.0 * lumaE);
+    float edgeVertical = abs(lumaSWSE - 2.0 * lumaS) + 2.0 * abs(lumaWE - 2.0 * lumaM) + abs(lumaNWNE - 2.0 * lumaN);
+    bool horizontal = edgeHorizontal >= edgeVertical;
+
+    float lumaNegative = horizontal ? lumaS : lumaW;
+    float lumaPositive = horizontal ? lumaN : lumaE;
+    float stepLength = horizontal ? texel.y : texel.x;
+
+    float gradientNegative = abs(lumaNegative - lumaM);
+    float gradientPositive = abs(lumaPositive - lumaM);
+    bool pairNegative = gradientNegative >= gradientPositive;
+    float gradient = max(gradientNegative, gradientPositive) * 0.25;
+
+    float lumaLocalAverage;
+    if (pairNegative) {
+        stepLength = -stepLength;
+        lumaLocalAverage = 0.5 * (lumaNegative + lumaM);
+    } else {
+        lumaLocalAverage = 0.5 * (lumaPositive + lumaM);
+    }
+
+    // Walk along the edge, half a pixel towards the pair.
+    vec2 edgeUV = uv;
+    vec2 edgeStep;
+    if (horizontal) {
+        edgeUV.y += 0.5 * stepLength;
+        edgeStep = vec2(texel.x, 0.0);
+    } else {
+        edgeUV.x += 0.5 * stepLength;
+        edgeStep = vec2(0.0, texel.y);
+    }
+
+    vec2 uvNegative = edgeUV - edgeStep;
+    vec2 uvPositive = edgeUV + edgeStep;
+    float lumaEndNegative = LumaAt(uvNegative) - lumaLocalAverage;
+    float lumaEndPositive = LumaAt(uvPositive) - lumaLocalAverage;
+    bool doneNegative = abs(lumaEndNegative) >= gradient;
+    bool donePositive = abs(lumaEndPositive) >= gradient;
+
+    for (int i = 0; i < FXAA_SEARCH_STEPS && !(doneNegative && donePositive); ++i) {
+        if (!doneNegative) {
+            uvNegative -= edgeStep * kSearchSteps[i];
+            lumaEndNegative = LumaAt(uvNegative) - lumaLocalAverage;
+            doneNegative = abs(lumaEndNegative) >= gradient;
+        }
+        if (!donePositive) {
+            uvPositive += edgeStep * kSearchSteps[i];
+            lumaEndPositive = LumaAt(uvPositive) - lumaLocalAverage;
+            donePositive = abs(lumaEndPositive) >= gradient;
+        }
+    }
+
+    float distanceNegative = horizontal ? uv.x - uvNegative.x : uv.y - uvNegative.y;
+    float distancePositive = horizontal ? uvPositive.x - uv.x : uvPositive.y - uv.y;
+    bool closerNegative = distanceNegative < distancePositive;
+    float distanceClosest = min(distanceNegative, distancePositive);
+    float spanLength = distanceNegative + distancePositive;
+
+    // Only blend when the luma at the closest end varies in the opposite direction of the center.
+    bool centerSmaller = lumaM - lumaLocalAverage < 0.0;
+    bool correctVariation = ((closerNegative ? lumaEndNegative : lumaEndPositive) < 0.0) != centerSmaller;
+    float edgeBlend = correctVariation ? 0.5 - distanceClosest / spanLength : 0.0;
+
+    float offset = max(edgeBlend, subpixBlend);
+
+    vec2 finalUV = uv;
+    if (horizontal) {
+        finalUV.y += offset * stepLength;
+    } else {
+        finalUV.x += offset * stepLength;
+    }
+
+    outColor = vec4(textureLod(image, finalUV, 0.0).rgb, 1.0);
+}
@@ -0,0 +1,162 @@
+#version 450 core
+#extension GL_ARB_separate_shader_objects : enable
+
+#include "assets/shaders/library/engine.glsl"
+
+SAMPLER_2D(0, mainImage);
+SAMPLER_2D(1, spectralLut);
+
+// Same layout as LensUniforms in lens.rs.
+UNIFORM_BLOCK_BEGIN(22, LensBlock)
+    float chromaticAberrationIntensity;
+    int chromaticAberrationSamples;
+    float vignetteIntensity;
+    float tanHalfFov;
+    float grainIntensity;
+    float grainSize;
+    float grainResponse;
+    float grainSeed;
+    float distortionK1;
+    float distortionK2;
+    float distortionScale;
+    float aspectRatio;
+UNIFORM_BLOCK_END
+
+INPUT_BLOCK_BEGIN(0, VsOut)
+    vec2 texcoord;
+INPUT_BLOCK_END_NAMED(fsIn)
+
+OUTPUT(0, vec4, outColor);
+
+// Position relative to the center of the image, 1 in the corners. Keeps the lens radially
+// symmetric whatever the aspect ratio.
+vec2 LensPosition(in vec2 texcoord)
+{
+    return (texcoord * 2.0 - 1.0) * vec2(aspectRatio, 1.0) / sqrt(aspectRatio * aspectRatio + 1.0);
+}
+
+vec2 LensTexcoord(in vec2 position)
+{
+    return position * sqrt(aspectRatio * aspectRatio + 1.0) / vec2(aspectRatio, 1.0) * 0.5 + 0.5;
+}
+
+#if defined(LENS_DISTORTION)
+    // Brown-Conrady radial distortion, barrel for positive coefficients and pincushion for
+    // negative ones. Same as Lens::screen_to_scene in lens.rs.
+    vec2 Distort(in vec2 texcoord)
+    {
+        vec2 position = LensPosition(texcoord);
+        float r2 = dot(position, position);
+        return LensTexcoord(position * (1.0 + distortionK1 * r2 + distortionK2 * r2 * r2) * distortionScale);
+    }
+#else
+    vec2 Distort(in vec2 texcoord)
+    {
+        return texcoord;
+    }
+#endif
+
+#if defined(LENS_CHROMATIC_ABERRATION)
+    // Lateral chromatic aberration, each sample along the radial offset is tinted with the
+    // spectral LUT at the matching wavelength. The LUT sums to white, so the weights are
+    // normalized per channel.
+    vec3 ChromaticAberration(in vec2 texcoord)
+    {
+        vec2 position = texcoord * 2.0 - 1.0;
+        vec2 end = texcoord - position * dot(position, position) * chromaticAberrationIntensity;
+        vec2 delta = (end - texcoord) / float(chromaticAberrationSamples);
+
+        vec3 sum = vec3(0.0);
+        vec3 weight = vec3(0.0);
+
+        for (int i = 0; i < chromaticAberrationSamples; ++i) {
+            float t = (float(i) + 0.5) / float(chromaticAberrationSamples);
+            vec3 tint = textureLod(spectralLut, vec2(t, 0.5), 0.0).rgb;
+
+            sum += texture(mainImage, texcoord + delta * float(i)).rgb * tint;
+            weight += tint;
+        }
+
+        return sum / max(weight, vec3(EPSILON));
+    }
+#endif
+
+#if defined(LENS_VIGNETTE)
+    // Natural vignetting, the irradiance on the sensor falls off with the fourth power of the
+    // cosine of the angle to the optical axis.
+    float Vignette(in vec2 texcoord)
+    {
+        vec2 position = (texcoord * 2.0 - 1.0) * vec2(aspectRatio, 1.0) * tanHalfFov;
+        float cosTheta2 = 1.0 / (1.0 + dot(position, position));
+
+        return mix(1.0, cosTheta2 * cosTheta2, vignetteIntensity);
+    }
+#endif
+
+#if defined(LENS_FILM_GRAIN)
+    float Hash(in uvec3 v)
+    {
+        v = v * 1664525u + 1013904223u;
+        v.x += v.y * v.z;
+        v.y += v.z * v.x;
+        v.z += v.x * v.y;
+        v ^= v >> 16u;
+        v.x += v.y * v.z;
+        return float(v.x) * (1.0 / 4294967296.0);
+    }
+
+    // Value noise with grains of grainSize pixels, a new pattern every frame.
+    float Grain(in vec2 pixel)
+    {
+        vec2 position = pixel / grainSize;
+        uvec2 cell = uvec2(ivec2(floor(position)) + 65536);
+        vec2 f = fract(position);
+        f = f * f * (3.0 - 2.0 * f);
+
+        uint seed = uint(grainSeed * 65536.0);
+        float a = Hash(uvec3(cell, seed));
+        float b = Hash(uvec3(cell + uvec2(1, 0), seed));
+        float c = Hash(uvec3(cell + uvec2(0, 1), seed));
+        float d = Hash(uvec3(cell + uvec2(1, 1), seed));
+
+        return mix(mix(a, b, f.x), mix(c, d, f.x), f.y) * 2.0 - 1.0;
+    }
+
+    // Grain is most visible in the shadows and midtones, less so in the highlights as the
+    // response goes to 1.
+    vec3 FilmGrain(in vec3 color, in vec2 pixel)
+    {
+        float luminance = 1.0 - sqrt(dot(clamp(color, 0.0, 1.0), vec3(0.2126, 0.7152, 0.0722)));
+        luminance = mix(1.0, luminance, grainResponse);
+
+        return max(color + color * Grain(pixel) * grainIntensity * luminance, 0.0);
+    }
+#endif
+
+void main()
+{
+    vec2 texcoord = Distort(fsIn.texcoord);
+
+#if defined(LENS_CHROMATIC_ABERRATION)
+    vec3 color = ChromaticAberration(texcoord);
+#else
+    vec3 color = texture(mainImage, texcoord).rgb;
+#endif
+
+#if defined(LENS_DISTORTION)
+    // Coefficients of opposite signs can still push the corners past the edges of the image.
+    if (any(lessThan(texcoord, vec2(0.0))) || any(greaterThan(texcoord, vec2(1.0)))) {
+        color = vec3(0.0);
+    }
+#endif
+
+#if defined(LENS_VIGNETTE)
+    color *= Vignette(fsIn.texcoord);
+#endif
+
+#if defined(LENS_FILM_GRAIN)
+    color = FilmGrain(color, gl_FragCoord.xy);
+#endif
+
+    outColor = vec4(color, 1.0);
+}
@@ -0,0 +1,38 @@
+#ifndef AMBIENT_OCCLUSION_GLSL_
+#define AMBIENT_OCCLUSION_GLSL_
+
+#include "assets/shaders/library/core_defines.glsl"
+
+#define MAX_SSAO_SAMPLES 64
+
+// Same layout as AmbientOcclusionBlock in ssao.rs.
+UNIFORM_BLOCK_BEGIN(13, AmbientOcclusionBlock)
+    mat4 ssaoInverseProjection;
+    vec4 ssaoKernel[MAX_SSAO_SAMPLES];
+    vec4 ssaoScreenSize; // xy: size of the G-buffer, zw: its inverse.
+    vec4 ssaoMapSize;    // xy: size of the occlusion map, zw: its inverse.
+    float ssaoRadius;
+    float ssaoIntensity;
+    float ssaoBias;
+    int ssaoSampleCount;
+    float ssaoBlurSharpness;
+UNIFORM_BLOCK_END
+
+#ifndef SSAO_PASS
+SAMPLER_2D(12, ambientOcclusionMap);
+
+// Blurred occlusion of the pixel at fragCoord, 1 meaning unoccluded.
+float ScreenSpaceAO(in vec2 fragCoord)
+{
+    return texture(ambientOcclusionMap, fragCoord * ssaoScreenSize.zw).r;
+}
+#endif
+
+// View space position of a pixel from its depth buffer value.
+vec3 SSAOViewPosition(in vec2 texcoord, in float depth)
+{
+    vec4 position = ssaoInverseProjection * vec4(vec3(texcoord, depth) * 2.0 - 1.0, 1.0);
+    return position.xyz / position.w;
+}
+
+#endif // AMBIENT_OCCLUSION_GLSL_
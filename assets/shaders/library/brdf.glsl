@@ -95,7 +95,7 @@ float V_SmithGGXCorrelatedFast(float NoV, float NoL, float roughness) {
     return 0.5 / (GGXV + GGXL);
 }
 
-vec3 FillamentBRDF(in ShadingProperties props)
+vec3 FillamentBRDF(in ShadingProperties props, in vec3 lightColor)
 {
     vec3 F = FresnelSchlick(props.HoV, props.F0);
     float D = D_GGX(props.NoH, props.roughness);
@@ -105,7 +105,7 @@ vec3 FillamentBRDF(in ShadingProperties props)
 
 
     //TODO: This is wrong.
-    if (mulriScattering == 1) {
+    if (multiScattering == 1) {
         //TODO: This is wrong.
         vec3 energyCompensation = 1.0 + props.F0 * (1.0 / props.brdfLUT.x - 1.0);
         // Scale the specular lobe to account for multiscattering
@@ -116,10 +116,10 @@ vec3 FillamentBRDF(in ShadingProperties props)
     vec3 kS = F;
     vec3 kD = (vec3(1.0) - kS) * (1.0 - props.metallic);
 
-    return (kD * props.albedo.rgb * ONE_OVER_PI + specular) * lightColor.rgb * props.NoL;
+    return (kD * props.albedo.rgb * ONE_OVER_PI + specular) * lightColor * props.NoL;
 }
 
-vec3 UE4BRDF(in ShadingProperties props)
+vec3 UE4BRDF(in ShadingProperties props, in vec3 lightColor)
 {
     vec3 F = FresnelSchlick(props.HoV, props.F0);
     float D = DistributionGGX(props.NoH, props.roughness);
@@ -135,15 +135,15 @@ vec3 UE4BRDF(in ShadingProperties props)
     vec3 kS = F;
     vec3 kD = (vec3(1.0) - kS) * (1.0 - props.metallic);
 
-    return (kD * props.albedo.rgb * ONE_OVER_PI + specular) * lightColor.rgb * props.NoL;
+    return (kD * props.albedo.rgb * ONE_OVER_PI + specular) * lightColor * props.NoL;
 }
 
-vec3 BRDF(in ShadingProperties props)
+vec3 BRDF(in ShadingProperties props, in vec3 lightColor)
 {
     #if defined(FEATURE_BRDF_FILLAMENT)
-        return FillamentBRDF(props);
+        return FillamentBRDF(props, lightColor);
     #elif defined(FEATURE_BRDF_UE4)
-        return UE4BRDF(props);
+        return UE4BRDF(props, lightColor);
     #else
         return vec3(1.0, 0.0, 1.0);
     #endif
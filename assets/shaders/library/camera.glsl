@@ -9,14 +9,18 @@ UNIFORM_BLOCK_BEGIN(0, CameraData)
     mat4 view_projection;
     vec4 cameraPosition;
     vec4 proj_params; // x: near, y:far, z: linearize depth denom, w: linearize depth num.
-    vec3 dof_params;
+    mat4 inverse_view_projection;
+    mat4 previous_view_projection; // Without jitter.
+    vec4 jitter; // xy: current projection jitter, zw: previous one, in NDC.
 UNIFORM_BLOCK_END
 
-#define LIB_CAMERA_FOCUS_DISTANCE dof_params.x
-#define LIB_CAMERA_FOCUS_RANGE dof_params.y
-#define LIB_CAMERA_BOKEH_RADIUS dof_params.z
-
+#define LIB_VIEW_MATRIX view
+#define LIB_PROJECTION_MATRIX projection
 #define LIB_VIEW_PROJECTION_MATRIX view_projection
+#define LIB_INVERSE_VIEW_PROJECTION_MATRIX inverse_view_projection
+#define LIB_PREVIOUS_VIEW_PROJECTION_MATRIX previous_view_projection
+#define LIB_CAMERA_JITTER jitter.xy
+#define LIB_CAMERA_PREVIOUS_JITTER jitter.zw
 #define LIB_CAMERA_POSITION cameraPosition
 #define LIB_CAMERA_NEAR_PLANE proj_params.x
 #define LIB_CAMERA_FAR_PLANE proj_params.y
@@ -0,0 +1,91 @@
+#ifndef COLOR_GRADING_GLSL_
+#define COLOR_GRADING_GLSL_
+
+#include "assets/shaders/library/core_defines.glsl"
+
+// Same layout as ColorGradingUniforms in color_grading.rs.
+UNIFORM_BLOCK_BEGIN(19, ColorGradingBlock)
+    vec4 gradingWhiteBalance;   // xyz: LMS scale.
+    vec4 gradingLift;
+    vec4 gradingGamma;
+    vec4 gradingGain;
+    vec4 gradingMixerRed;
+    vec4 gradingMixerGreen;
+    vec4 gradingMixerBlue;
+    vec4 gradingParams;         // x: contrast, y: saturation, z: LUT size, w: LUT contribution.
+UNIFORM_BLOCK_END
+
+SAMPLER_3D(1, gradingLut);
+
+#define GRADING_MIDDLE_GREY 0.18
+
+// Linear sRGB to CIECAT02 LMS and back.
+const mat3 LinearToLmsMat =
+{
+    {3.90405e-1, 5.49941e-1, 8.92632e-3},
+    {7.08416e-2, 9.63172e-1, 1.35775e-3},
+    {2.31082e-2, 1.28021e-1, 9.36245e-1}
+};
+
+const mat3 LmsToLinearMat =
+{
+    { 2.85847e+0, -1.62879e+0, -2.48910e-2},
+    {-2.10182e-1,  1.15820e+0,  3.24281e-4},
+    {-4.18120e-2, -1.18169e-1,  1.06867e+0}
+};
+
+float GradingLuminance(vec3 color)
+{
+    return dot(color, vec3(0.2126, 0.7152, 0.0722));
+}
+
+vec3 LinearToSrgb(vec3 color)
+{
+    return mix(12.92 * color, 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055, step(0.0031308, color));
+}
+
+vec3 SrgbToLinear(vec3 color)
+{
+    return mix(color / 12.92, pow((color + 0.055) / 1.055, vec3(2.4)), step(0.04045, color));
+}
+
+// Scene referred adjustments, before the tone mapping.
+vec3 GradeHdr(vec3 color)
+{
+    vec3 lms = color * LinearToLmsMat;
+    color = (lms * gradingWhiteBalance.xyz) * LmsToLinearMat;
+
+    color = vec3(
+        dot(color, gradingMixerRed.xyz),
+        dot(color, gradingMixerGreen.xyz),
+        dot(color, gradingMixerBlue.xyz));
+    color = max(color, 0.0);
+
+    // Contrast in log space, pivoting around middle grey.
+    vec3 logColor = log2(color + EPSILON) - log2(GRADING_MIDDLE_GREY);
+    color = exp2(logColor * gradingParams.x + log2(GRADING_MIDDLE_GREY)) - EPSILON;
+    color = max(color, 0.0);
+
+    float luminance = GradingLuminance(color);
+    return max(mix(vec3(luminance), color, gradingParams.y), 0.0);
+}
+
+// Display referred adjustments, after the tone mapping.
+vec3 GradeLdr(vec3 color)
+{
+    color = gradingGain.xyz * (color + gradingLift.xyz * (1.0 - color));
+    return pow(clamp(color, 0.0, 1.0), 1.0 / max(gradingGamma.xyz, EPSILON));
+}
+
+// The LUT maps sRGB encoded colors, like the screenshots graded in external tools.
+vec3 ApplyGradingLut(vec3 color)
+{
+    float size = gradingParams.z;
+    vec3 encoded = LinearToSrgb(clamp(color, 0.0, 1.0));
+    vec3 uvw = encoded * ((size - 1.0) / size) + 0.5 / size;
+    vec3 graded = SrgbToLinear(textureLod(gradingLut, uvw, 0.0).rgb);
+
+    return mix(color, graded, gradingParams.w);
+}
+
+#endif // COLOR_GRADING_GLSL_
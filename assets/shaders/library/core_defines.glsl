@@ -10,11 +10,24 @@
 
 #define SAMPLER_2D(bind_slot, name) layout(binding = bind_slot) uniform sampler2D name
 #define SAMPLER_CUBE(bind_slot, name) layout(binding = bind_slot) uniform samplerCube name
+#define SAMPLER_3D(bind_slot, name) layout(binding = bind_slot) uniform sampler3D name
+#define SAMPLER_2D_SHADOW(bind_slot, name) layout(binding = bind_slot) uniform sampler2DShadow name
+#define SAMPLER_2D_ARRAY_SHADOW(bind_slot, name) layout(binding = bind_slot) uniform sampler2DArrayShadow name
+
+#define IMAGE_2D(bind_slot, format, name) layout(binding = bind_slot, format) uniform image2D name
+#define IMAGE_CUBE(bind_slot, format, name) layout(binding = bind_slot, format) uniform imageCube name
+#define IMAGE_3D(bind_slot, format, name) layout(binding = bind_slot, format) uniform image3D name
 
 #define UNIFORM_BLOCK_BEGIN(bind_slot, name) layout(std140, binding = bind_slot) uniform name {
 #define UNIFORM_BLOCK_END };
 #define UNIFORM_BLOCK_END_NAMED(name) } name;
 
+#define BUFFER_BLOCK_BEGIN(bind_slot, name) layout(std430, binding = bind_slot) buffer name {
+#define BUFFER_BLOCK_END };
+#define BUFFER_BLOCK_END_NAMED(name) } name;
+
+#define LOCAL_SIZE(x, y, z) layout(local_size_x = x, local_size_y = y, local_size_z = z) in
+
 #define INPUT(loc, type, name) layout(location = loc) in type name
 
 #define INPUT_BLOCK_BEGIN(loc, name) layout(location = loc) in name {
@@ -27,6 +40,14 @@
 #define OUTPUT_BLOCK_END };
 #define OUTPUT_BLOCK_END_NAMED(name) } name;
 
+// Specialization constants only exist for SPIR-V modules. For GLSL sources the engine injects the
+// value as a define with the same name, so declarations should be wrapped in #ifndef <name>.
+#ifdef SPECIALIZATION_CONSTANTS_SUPPORTED
+    #define SPECIALIZATION_CONSTANT(id, type, name, default_value) layout(constant_id = id) const type name = default_value
+#else
+    #define SPECIALIZATION_CONSTANT(id, type, name, default_value) const type name = default_value
+#endif
+
 #define ERROR_COLOR vec4(1.0, 0.0, 1.0, 1.0)
 
 #endif //CORE_DEFINES_GLSL_
@@ -0,0 +1,20 @@
+#ifndef ENVIRONMENT_GLSL_
+#define ENVIRONMENT_GLSL_
+
+#include "assets/shaders/library/core_defines.glsl"
+
+// Written by EnvironmentRenderer (environment.rs). The target environment is the one being faded
+// in and only contributes while environmentBlend > 0.
+UNIFORM_BLOCK_BEGIN(6, EnvironmentBlock)
+    mat4 environmentRotation;           // World space to environment space.
+    vec2 environmentIntensity;          // x: current, y: target.
+    vec2 environmentMaxReflectionLod;   // x: current, y: target.
+    float environmentBlend;
+UNIFORM_BLOCK_END
+
+vec3 WorldToEnvironment(in vec3 direction)
+{
+    return mat3(environmentRotation) * direction;
+}
+
+#endif // ENVIRONMENT_GLSL_
@@ -0,0 +1,75 @@
+#ifndef EXPOSURE_GLSL_
+#define EXPOSURE_GLSL_
+
+#include "assets/shaders/library/core_defines.glsl"
+
+// Must match HISTOGRAM_BIN_COUNT in auto_exposure.rs.
+#define HISTOGRAM_BIN_COUNT 256
+
+#define METERING_MODE_AVERAGE 0
+#define METERING_MODE_CENTER_WEIGHTED 1
+#define METERING_MODE_SPOT 2
+
+// Radius of the spot metering area, relative to half the screen diagonal.
+#define METERING_SPOT_RADIUS 0.15
+
+// The metering weights are accumulated as integers with this precision.
+#define METERING_WEIGHT_SCALE 16.0
+
+// Same layout as ExposureUniforms in auto_exposure.rs.
+UNIFORM_BLOCK_BEGIN(18, ExposureBlock)
+    float exposureMinEv;
+    float exposureMaxEv;
+    float exposureLowPercent;
+    float exposureHighPercent;
+    float exposureSpeedUp;
+    float exposureSpeedDown;
+    float exposureCompensation;
+    float exposureDeltaTime;
+    int exposureMeteringMode;
+    int exposureReset;
+UNIFORM_BLOCK_END
+
+BUFFER_BLOCK_BEGIN(5, ExposureBuffer)
+    float adaptedLogLuminance;
+    float autoExposure;
+    float _exposurePadding[2];
+    uint luminanceHistogram[HISTOGRAM_BIN_COUNT];
+BUFFER_BLOCK_END
+
+// The histogram covers the luminances of the EV100 range, EV100 = log2(L * 100 / 12.5).
+float MinLogLuminance()
+{
+    return exposureMinEv - 3.0;
+}
+
+float LogLuminanceRange()
+{
+    return max(exposureMaxEv - exposureMinEv, EPSILON);
+}
+
+uint LuminanceToBin(float luminance)
+{
+    float t = (log2(max(luminance, EPSILON)) - MinLogLuminance()) / LogLuminanceRange();
+    return min(uint(clamp(t, 0.0, 1.0) * HISTOGRAM_BIN_COUNT), HISTOGRAM_BIN_COUNT - 1u);
+}
+
+float BinToLogLuminance(uint bin)
+{
+    return MinLogLuminance() + (float(bin) + 0.5) / HISTOGRAM_BIN_COUNT * LogLuminanceRange();
+}
+
+float MeteringWeight(vec2 uv)
+{
+    float distanceToCenter = length(uv - 0.5) * 2.0 / sqrt(2.0);
+
+    if (exposureMeteringMode == METERING_MODE_CENTER_WEIGHTED) {
+        return 1.0 - smoothstep(0.0, 1.0, distanceToCenter);
+    } else if (exposureMeteringMode == METERING_MODE_SPOT) {
+        return distanceToCenter < METERING_SPOT_RADIUS ? 1.0 : 0.0;
+    }
+
+    return 1.0;
+}
+
+#endif // EXPOSURE_GLSL_
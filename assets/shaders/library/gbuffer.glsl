@@ -0,0 +1,45 @@
+#ifndef GBUFFER_GLSL_
+#define GBUFFER_GLSL_
+
+#include "assets/shaders/library/camera.glsl"
+
+// G-buffer layout, must match GBuffer in deferred.rs:
+//   0: albedo (rgb), reflectance (a)     RGBA8
+//   1: octahedron encoded normal (rg)    RG16F
+//   2: metallic, roughness, ao (rgb)     RGBA8
+//   3: emission (rgb)                    RGBA16F
+//   4: velocity (rg)                     RG16F
+//   depth                                DEPTH24_STENCIL8
+
+// Reference: A Survey of Efficient Representations for Independent Unit Vectors, Cigolle et al.
+vec2 OctahedronWrap(in vec2 v)
+{
+    return (1.0 - abs(v.yx)) * vec2(v.x >= 0.0 ? 1.0 : -1.0, v.y >= 0.0 ? 1.0 : -1.0);
+}
+
+// Maps a unit vector to [-1, 1]^2.
+vec2 OctahedronEncode(in vec3 n)
+{
+    n /= abs(n.x) + abs(n.y) + abs(n.z);
+    return n.z >= 0.0 ? n.xy : OctahedronWrap(n.xy);
+}
+
+vec3 OctahedronDecode(in vec2 encoded)
+{
+    vec3 n = vec3(encoded, 1.0 - abs(encoded.x) - abs(encoded.y));
+    float t = clamp(-n.z, 0.0, 1.0);
+    n.xy += vec2(n.x >= 0.0 ? -t : t, n.y >= 0.0 ? -t : t);
+    return normalize(n);
+}
+
+// Motion between the previous and the current frame in texture coordinates, without the
+// projection jitter so that a still scene has no velocity.
+vec2 ScreenSpaceVelocity(in vec4 clipPosition, in vec4 previousClipPosition)
+{
+    vec2 currentNdc = clipPosition.xy / clipPosition.w - LIB_CAMERA_JITTER;
+    vec2 previousNdc = previousClipPosition.xy / previousClipPosition.w;
+
+    return (currentNdc - previousNdc) * 0.5;
+}
+
+#endif // GBUFFER_GLSL_
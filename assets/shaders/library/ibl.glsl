@@ -1,6 +1,8 @@
 #ifndef IBL_GLSL_
 #define IBL_GLSL_
 
+#include "assets/shaders/library/spherical_harmonics.glsl"
+
 vec3 EnvironmentBRDFApprox( vec3 F0, float roughness, float NoV )
 {
     const vec4 c0 = vec4( -1, -0.0275, -0.572, 0.022 );
@@ -37,7 +39,7 @@ vec3 GetSpecularDominantDirection(const vec3 n, const vec3 r, in float roughness
 }
 
 // reference: https://github.com/google/filament/blob/main/shaders/src/light_indirect.fs
-float PerceptualRoughnessToLod(in float perceptualRoughness)
+float PerceptualRoughnessToLod(in float perceptualRoughness, in float maxReflectionLod)
 {
     return maxReflectionLod * perceptualRoughness * (2.0 - perceptualRoughness);
 }
@@ -0,0 +1,85 @@
+#ifndef IBL_BAKING_GLSL_
+#define IBL_BAKING_GLSL_
+
+UNIFORM_BLOCK_BEGIN(8, IblBakeBlock)
+    float perceptualRoughness;
+    float sourceResolution;
+    float sourceLod;
+    uint sampleCount;
+UNIFORM_BLOCK_END
+
+// Largest finite half float. Bright texels (e.g. the sun) are clamped to it to avoid infinities.
+#define HALF_FLOAT_MAX 65504.0
+
+// Maps a texel of a cubemap face to the direction going through its center, following the
+// face orientation table of the OpenGL specification.
+vec3 CubemapTexelDirection(in uvec3 texel, in uint faceSize)
+{
+    vec2 uv = (vec2(texel.xy) + 0.5) / float(faceSize) * 2.0 - 1.0;
+
+    vec3 direction;
+    switch (int(texel.z)) {
+        case 0: direction = vec3(1.0, -uv.y, -uv.x); break;
+        case 1: direction = vec3(-1.0, -uv.y, uv.x); break;
+        case 2: direction = vec3(uv.x, 1.0, uv.y); break;
+        case 3: direction = vec3(uv.x, -1.0, -uv.y); break;
+        case 4: direction = vec3(uv.x, -uv.y, 1.0); break;
+        default: direction = vec3(-uv.x, -uv.y, -1.0); break;
+    }
+
+    return normalize(direction);
+}
+
+vec2 EquirectangularUV(in vec3 direction)
+{
+    return vec2(atan(direction.z, direction.x) * 0.5 * ONE_OVER_PI + 0.5, acos(clamp(direction.y, -1.0, 1.0)) * ONE_OVER_PI);
+}
+
+// Reference: http://holger.dammertz.org/stuff/notes_HammersleyOnHemisphere.html
+vec2 Hammersley(in uint i, in uint sampleCount)
+{
+    return vec2(float(i) / float(sampleCount), float(bitfieldReverse(i)) * 2.3283064365386963e-10);
+}
+
+mat3 TangentFrame(in vec3 n)
+{
+    vec3 up = abs(n.y) < 0.999 ? vec3(0.0, 1.0, 0.0) : vec3(0.0, 0.0, 1.0);
+    vec3 t = normalize(cross(up, n));
+    vec3 b = cross(n, t);
+    return mat3(t, b, n);
+}
+
+// Reference: https://cdn2.unrealengine.com/Resources/files/2013SiggraphPresentationsNotes-26915738.pdf
+vec3 ImportanceSampleGGX(in vec2 xi, in float a)
+{
+    float phi = 2.0 * PI * xi.x;
+    float cosTheta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    float sinTheta = sqrt(1.0 - cosTheta * cosTheta);
+    return vec3(sinTheta * cos(phi), sinTheta * sin(phi), cosTheta);
+}
+
+vec3 ImportanceSampleCosine(in vec2 xi)
+{
+    float phi = 2.0 * PI * xi.x;
+    float cosTheta = sqrt(1.0 - xi.y);
+    float sinTheta = sqrt(xi.y);
+    return vec3(sinTheta * cos(phi), sinTheta * sin(phi), cosTheta);
+}
+
+float D_GGX(in float NoH, in float a)
+{
+    float a2 = a * a;
+    float f = (NoH * a2 - NoH) * NoH + 1.0;
+    return a2 / (PI * f * f);
+}
+
+// Picks the source mip whose texel solid angle matches the solid angle covered by a sample.
+// Reference: https://developer.nvidia.com/gpugems/gpugems3/part-iii-rendering/chapter-20-gpu-based-importance-sampling
+float FilteredSampleLod(in float pdf, in uint sampleCount, in float resolution)
+{
+    float saSample = 1.0 / (float(sampleCount) * pdf + EPSILON);
+    float saTexel = 4.0 * PI / (6.0 * resolution * resolution);
+    return max(0.5 * log2(saSample / saTexel) + 1.0, 0.0);
+}
+
+#endif // IBL_BAKING_GLSL_
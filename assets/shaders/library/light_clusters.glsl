@@ -0,0 +1,85 @@
+#ifndef LIGHT_CLUSTERS_GLSL_
+#define LIGHT_CLUSTERS_GLSL_
+
+#include "assets/shaders/library/core_defines.glsl"
+#include "assets/shaders/library/camera.glsl"
+
+// Must match MAX_LIGHTS_PER_CLUSTER in light_clusters.rs.
+#define MAX_LIGHTS_PER_CLUSTER 128
+
+// Same layout as ClusterBlock in light_clusters.rs.
+UNIFORM_BLOCK_BEGIN(11, ClusterBlock)
+    uvec4 clusterGridSize;      // xyz: clusters per axis, w: total cluster count.
+    vec4 clusterTileSize;       // xy: tile size in pixels, zw: 1 / viewport size.
+    vec4 clusterDepthParams;    // x: slice scale, y: slice bias, z: near, w: far.
+    uint clusterMaxLightIndices;
+    float clusterHeatmapScale;
+UNIFORM_BLOCK_END
+
+// View space bounding box of a cluster.
+struct ClusterBounds {
+    vec4 minPoint;
+    vec4 maxPoint;
+};
+
+BUFFER_BLOCK_BEGIN(2, ClusterBoundsBuffer)
+    ClusterBounds clusterBounds[];
+BUFFER_BLOCK_END
+
+// Offset (x) and count (y) of the cluster lights in clusterLightIndices.
+BUFFER_BLOCK_BEGIN(3, ClusterLightGridBuffer)
+    uvec2 clusterLightGrid[];
+BUFFER_BLOCK_END
+
+BUFFER_BLOCK_BEGIN(4, ClusterLightIndicesBuffer)
+    uint clusterLightIndexCount;
+    uint clusterLightIndices[];
+BUFFER_BLOCK_END
+
+// View depth at which the slice starts, slices are distributed exponentially between the near
+// and far planes.
+float ClusterSliceDepth(in uint slice)
+{
+    float near = clusterDepthParams.z;
+    float far = clusterDepthParams.w;
+
+    return near * pow(far / near, float(slice) / float(clusterGridSize.z));
+}
+
+uvec3 ClusterCoordinates(in uint clusterIndex)
+{
+    return uvec3(
+        clusterIndex % clusterGridSize.x,
+        (clusterIndex / clusterGridSize.x) % clusterGridSize.y,
+        clusterIndex / (clusterGridSize.x * clusterGridSize.y));
+}
+
+uint ClusterIndex(in vec2 fragCoord, in float viewDepth)
+{
+    uint slice = uint(max(log(viewDepth) * clusterDepthParams.x + clusterDepthParams.y, 0.0));
+    uvec3 cluster = min(uvec3(uvec2(fragCoord / clusterTileSize.xy), slice), clusterGridSize.xyz - 1u);
+
+    return cluster.x + clusterGridSize.x * (cluster.y + clusterGridSize.y * cluster.z);
+}
+
+// Offset (x) and count (y) of the lights affecting the fragment, in clusterLightIndices.
+uvec2 ClusterLightRange(in vec2 fragCoord, in vec3 worldPosition)
+{
+    float viewDepth = -(LIB_VIEW_MATRIX * vec4(worldPosition, 1.0)).z;
+
+    return clusterLightGrid[ClusterIndex(fragCoord, viewDepth)];
+}
+
+// Black for empty clusters, then blue to red as the light count reaches clusterHeatmapScale.
+vec3 LightClusterHeatmap(in uint lightCount)
+{
+    if (lightCount == 0u) {
+        return vec3(0.0);
+    }
+
+    float t = clamp(float(lightCount) / clusterHeatmapScale, 0.0, 1.0);
+
+    return clamp(1.5 - abs(4.0 * t - vec3(3.0, 2.0, 1.0)), 0.0, 1.0);
+}
+
+#endif // LIGHT_CLUSTERS_GLSL_
@@ -0,0 +1,30 @@
+#ifndef LIGHTS_GLSL_
+#define LIGHTS_GLSL_
+
+#include "assets/shaders/library/core_defines.glsl"
+
+// Must match the LIGHT_TYPE_* constants in light.rs.
+#define LIGHT_TYPE_DIRECTIONAL  0
+#define LIGHT_TYPE_POINT        1
+#define LIGHT_TYPE_SPOT         2
+
+// Same layout as GpuLight in light.rs. Directional lights store their illuminance (lux) in
+// intensity, point and spot lights their luminous intensity (candela).
+struct Light {
+    vec3 position;
+    float inverseRangeSquared;
+    vec3 color;
+    float intensity;
+    vec3 direction;     // Direction the light travels in.
+    int type;
+    vec2 spotScaleOffset;
+    int shadowIndex;    // -1 when unshadowed. Index in PointShadowBuffer for point lights.
+    float padding;
+};
+
+BUFFER_BLOCK_BEGIN(0, LightBuffer)
+    uint lightCount;
+    Light lights[];
+BUFFER_BLOCK_END
+
+#endif // LIGHTS_GLSL_
@@ -2,6 +2,19 @@
 #define PBS_COMMON_GLSL_
 
 #include "assets/shaders/library/sampling_utils.glsl"
+#include "assets/shaders/library/lights.glsl"
+
+UNIFORM_BLOCK_BEGIN(2, PerFrameBlock)
+    vec2 ssVarianceAndThreshold;
+    int specularAA;
+    int specularAO;
+    int renderMode;
+    int multiScattering;
+    int clusteredLighting;
+    int screenSpaceAO;
+    int screenSpaceReflections;
+    int volumetricFog;
+UNIFORM_BLOCK_END
 
 struct ShadingProperties {
     vec4 albedo;
@@ -20,6 +33,8 @@ struct ShadingProperties {
     float NoH;
     float HoV;
     vec3 worldNormal;
+    vec3 worldPosition;
+    vec3 v;
     vec3 n;
     vec3 r;
     vec3 t;
@@ -49,6 +64,8 @@ float RoughnessToPerceptualRoughness(float roughness)
     return sqrt(roughness);
 }
 
+// The deferred lighting pass reads the surface from the G-buffer instead of the vertex outputs.
+#ifndef DEFERRED_LIGHTING_PASS
 void CalculateTextureCoordinates(inout ShadingProperties props)
 {
     props.texcoord = fsIn.texcoord;
@@ -88,6 +105,8 @@ void PopulateVectorProducts(inout ShadingProperties props)
     mat3 tangentToWorldMat = CreateTangentToWorldMatrix(props.worldNormal, props.t, fsIn.wTangent.w);
 
     vec3 v = normalize(fsIn.wViewDirection);
+    props.v = v;
+    props.worldPosition = LIB_CAMERA_POSITION.xyz - fsIn.wViewDirection;
 
 #ifdef FEATURE_PARALLAX_MAPPING
     mat3 worldToTangentMat = transpose(tangentToWorldMat);
@@ -100,16 +119,57 @@ void PopulateVectorProducts(inout ShadingProperties props)
     // TODO: SampleNormalMap is not defined in this file. Fix this
     props.n = normalize(tangentToWorldMat * SampleNormalMap(normalMap, props.texcoord, 1.0));
 
-    vec3 l = normalize(wLightDirection).xyz;
-    vec3 h = normalize(l + v);
     props.r = reflect(-v, props.n);
 
-    props.NoH = clamp(dot(props.n, h), 0.0, 1.0);
-
     props.NoV = clamp(abs(dot(props.n, v)), 0.0, 1.0);
+}
+#endif // DEFERRED_LIGHTING_PASS
+
+// Reference: Moving Frostbite to Physically Based Rendering 3.0, section 4.7.2.
+// Inverse square falloff, windowed so that it reaches zero at the light range.
+float DistanceAttenuation(in vec3 unnormalizedLightVector, in float inverseRangeSquared)
+{
+    float distanceSquared = dot(unnormalizedLightVector, unnormalizedLightVector);
+    float factor = distanceSquared * inverseRangeSquared;
+    float smoothFactor = clamp(1.0 - factor * factor, 0.0, 1.0);
+
+    return smoothFactor * smoothFactor / max(distanceSquared, 1e-4);
+}
+
+float SpotAngleAttenuation(in vec3 l, in vec3 spotDirection, in vec2 spotScaleOffset)
+{
+    float cd = dot(-l, spotDirection);
+    float attenuation = clamp(cd * spotScaleOffset.x + spotScaleOffset.y, 0.0, 1.0);
 
+    return attenuation * attenuation;
+}
+
+// Fills the light dependent dot products and returns the light reaching the surface, before the
+// NoL term.
+vec3 PopulateLightProducts(inout ShadingProperties props, in Light light)
+{
+    vec3 l;
+    float attenuation = 1.0;
+
+    if (light.type == LIGHT_TYPE_DIRECTIONAL) {
+        l = -light.direction;
+    } else {
+        vec3 unnormalizedLightVector = light.position - props.worldPosition;
+        l = normalize(unnormalizedLightVector);
+        attenuation = DistanceAttenuation(unnormalizedLightVector, light.inverseRangeSquared);
+
+        if (light.type == LIGHT_TYPE_SPOT) {
+            attenuation *= SpotAngleAttenuation(l, light.direction, light.spotScaleOffset);
+        }
+    }
+
+    vec3 h = normalize(l + props.v);
+
+    props.NoH = clamp(dot(props.n, h), 0.0, 1.0);
     props.NoL = clamp(dot(props.n, l), 0.0, 1.0);
-    props.HoV = clamp(dot(h, v), 0.0, 1.0);
+    props.HoV = clamp(dot(h, props.v), 0.0, 1.0);
+
+    return light.color * (light.intensity * attenuation);
 }
 
 #endif //PBS_COMMON_GLSL_
\ No newline at end of file
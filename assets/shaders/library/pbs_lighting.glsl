@@ -0,0 +1,143 @@
+#ifndef PBS_LIGHTING_GLSL_
+#define PBS_LIGHTING_GLSL_
+
+#include "assets/shaders/library/core_defines.glsl"
+#include "assets/shaders/library/environment.glsl"
+
+// Lighting of a populated ShadingProperties, shared by the forward and deferred lighting passes.
+
+#if defined(FEATURE_SHADOWS) || defined(FEATURE_SHADOWS_PCF)
+    #define SHADOWS_ENABLED
+    #include "assets/shaders/library/shadows.glsl"
+    #include "assets/shaders/library/point_shadows.glsl"
+#endif
+
+// The libraries below are toggled at runtime by the flags of the PerFrameBlock, a keyword for
+// each of them would double the number of variants of the lit shaders.
+#include "assets/shaders/library/light_clusters.glsl"
+
+#include "assets/shaders/library/ambient_occlusion.glsl"
+
+#include "assets/shaders/library/screen_space_reflections.glsl"
+
+#include "assets/shaders/library/volumetric_fog.glsl"
+
+SAMPLER_2D(3, brdfLUT);
+
+SAMPLER_CUBE(4, irradianceMap);
+SAMPLER_CUBE(5, radianceMap);
+SAMPLER_CUBE(7, targetIrradianceMap);
+SAMPLER_CUBE(8, targetRadianceMap);
+
+#include "assets/shaders/library/pbs_common.glsl"
+#include "assets/shaders/library/brdf.glsl"
+#include "assets/shaders/library/ibl.glsl"
+
+void CalculateF0(inout ShadingProperties props, in float reflectance)
+{
+    props.F0 = 0.16 * reflectance * reflectance * (1.0 - props.metallic) + props.albedo.rgb * props.metallic;
+}
+
+// Multiplies the screen space occlusion into the material one, before the specular occlusion is
+// derived from it. Only the indirect lighting is occluded.
+void ApplyScreenSpaceAO(inout ShadingProperties props)
+{
+    if (screenSpaceAO == TRUE) {
+        props.ao *= ScreenSpaceAO(gl_FragCoord.xy);
+    }
+}
+
+void PopulateSpecularAO(inout ShadingProperties props)
+{
+    if (specularAO == 1) {
+        props.so = ComputeSpecularAO(props.NoV, props.ao, props.roughness);
+        props.horizonSo = ComputeHorizonSpecularAO(props.r, props.n);
+    }
+}
+
+void PopulateIBLProperties(inout ShadingProperties props)
+{
+    vec3 n = WorldToEnvironment(props.n);
+    vec3 specular_direction = WorldToEnvironment(GetSpecularDominantDirection(props.n, props.r, props.roughness));
+
+#ifdef FEATURE_SH_IRRADIANCE
+    // The coefficients are already blended and scaled by the environment intensities
+    props.irradiance = EvaluateSHIrradiance(n);
+#else
+    props.irradiance = texture(irradianceMap, n).rgb * environmentIntensity.x;
+#endif
+
+    float lod = PerceptualRoughnessToLod(props.perceptualRoughness, environmentMaxReflectionLod.x);
+    props.radiance = textureLod(radianceMap, specular_direction, lod).rgb * environmentIntensity.x;
+
+    if (environmentBlend > 0.0) {
+#ifndef FEATURE_SH_IRRADIANCE
+        vec3 targetIrradiance = texture(targetIrradianceMap, n).rgb * environmentIntensity.y;
+        props.irradiance = mix(props.irradiance, targetIrradiance, environmentBlend);
+#endif
+
+        float targetLod = PerceptualRoughnessToLod(props.perceptualRoughness, environmentMaxReflectionLod.y);
+        vec3 targetRadiance = textureLod(targetRadianceMap, specular_direction, targetLod).rgb * environmentIntensity.y;
+        props.radiance = mix(props.radiance, targetRadiance, environmentBlend);
+    }
+
+    props.brdfLUT = texture(brdfLUT, vec2(props.NoV, props.perceptualRoughness)).rg;
+}
+
+// Replaces the radiance cubemap by the screen space reflections where they are confident.
+void ApplyScreenSpaceReflections(inout ShadingProperties props)
+{
+    if (screenSpaceReflections == TRUE) {
+        vec4 reflection = ScreenSpaceReflection(gl_FragCoord.xy);
+        props.radiance = mix(props.radiance, reflection.rgb, reflection.a);
+    }
+}
+
+// Fogs the fully lit color of the surface. Debug render modes are left untouched.
+vec3 ApplyFog(in ShadingProperties props, in vec3 color)
+{
+    return volumetricFog == TRUE ? ApplyVolumetricFog(color, props.worldPosition) : color;
+}
+
+vec3 EvaluateLight(in ShadingProperties props, in Light light)
+{
+    vec3 lightColor = PopulateLightProducts(props, light);
+
+    if (props.NoL <= 0.0) {
+        return vec3(0.0);
+    }
+
+#ifdef SHADOWS_ENABLED
+    if (light.shadowIndex >= 0) {
+        if (light.type == LIGHT_TYPE_DIRECTIONAL) {
+            lightColor *= DirectionalShadow(props.worldPosition, props.worldNormal, light.direction);
+        } else if (light.type == LIGHT_TYPE_POINT) {
+            lightColor *= PointLightShadow(light.shadowIndex, light.position, props.worldPosition, props.worldNormal);
+        }
+    }
+#endif
+
+    return BRDF(props, lightColor);
+}
+
+vec3 EvaluateAnalyticalLights(in ShadingProperties props)
+{
+    vec3 color = vec3(0.0);
+
+    if (clusteredLighting == TRUE) {
+        // Only the lights assigned to the cluster of the fragment.
+        uvec2 lightRange = ClusterLightRange(gl_FragCoord.xy, props.worldPosition);
+
+        for (uint i = 0u; i < lightRange.y; ++i) {
+            color += EvaluateLight(props, lights[clusterLightIndices[lightRange.x + i]]);
+        }
+    } else {
+        for (uint i = 0u; i < lightCount; ++i) {
+            color += EvaluateLight(props, lights[i]);
+        }
+    }
+
+    return color;
+}
+
+#endif // PBS_LIGHTING_GLSL_
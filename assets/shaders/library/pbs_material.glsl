@@ -0,0 +1,65 @@
+#ifndef PBS_MATERIAL_GLSL_
+#define PBS_MATERIAL_GLSL_
+
+#include "assets/shaders/library/core_defines.glsl"
+
+// Metallic/roughness material inputs shared by the forward and G-buffer passes. The including
+// shader declares the VsOut input block (fsIn) before including this file.
+
+#define MIN_ROUGHNESS                       0.045
+
+UNIFORM_BLOCK_BEGIN(4, MaterialBlock)
+    vec4 baseColor;
+    vec4 emissive;      // rgb: color, a: intensity.
+    float metallicScale;
+    float metallicBias;
+    float roughnessScale;
+    float roughnessBias;
+    float aoScale;
+    float aoBias;
+    float reflectance;
+#ifdef FEATURE_PARALLAX_MAPPING
+    float pomMinLayers;
+    float pomMaxLayers;
+    float pomDisplacementScale;
+    int parallaxMappingMethod;
+#endif // FEATURE_PARALLAX_MAPPING
+UNIFORM_BLOCK_END
+
+SAMPLER_2D(0, albedoMap);
+SAMPLER_2D(1, normalMap);
+SAMPLER_2D(2, m_r_aoMap);
+
+#ifdef FEATURE_PARALLAX_MAPPING
+    SAMPLER_2D(6, displacementMap);
+#endif
+
+#ifdef FEATURE_PARALLAX_MAPPING
+    #include "assets/shaders/library/parallax_mapping.glsl"
+#endif // FEATURE_PARALLAX_MAPPING
+
+#include "assets/shaders/library/pbs_common.glsl"
+#include "assets/shaders/library/brdf.glsl"
+
+vec3 MaterialEmission()
+{
+    return emissive.rgb * emissive.a;
+}
+
+void PopulateMaterialProperties(inout ShadingProperties props)
+{
+    props.albedo = texture(albedoMap, props.texcoord) * vec4(baseColor.rgb, 1.0);
+
+    vec3 m_r_ao = texture(m_r_aoMap, props.texcoord).rgb;
+    props.metallic = clamp((m_r_ao.r + metallicBias) * metallicScale, 0.0, 1.0);
+    props.perceptualRoughness = clamp((m_r_ao.g + roughnessBias) * roughnessScale, MIN_ROUGHNESS, 1.0) ;
+
+    if (specularAA == 1) {
+        props.perceptualRoughness = BiasedAxisAlignedGeometricSpecularAA(props.worldNormal, props.perceptualRoughness);
+    }
+
+    props.roughness = PerceptualRoughnessToRoughness(props.perceptualRoughness);
+    props.ao = clamp((m_r_ao.b + aoBias) * aoScale, 0.0, 1.0);
+}
+
+#endif // PBS_MATERIAL_GLSL_
@@ -0,0 +1,107 @@
+#ifndef POINT_SHADOWS_GLSL_
+#define POINT_SHADOWS_GLSL_
+
+#include "assets/shaders/library/core_defines.glsl"
+
+// Same layout as GpuPointShadow in point_shadows.rs.
+struct PointShadow {
+    vec4 faceRects[6];  // Offset (xy) and size (zw) of each cube face in the atlas.
+    float near;
+    float far;
+    float resolution;
+    float normalOffset; // In texels.
+};
+
+BUFFER_BLOCK_BEGIN(1, PointShadowBuffer)
+    PointShadow pointShadows[];
+BUFFER_BLOCK_END
+
+SAMPLER_2D_SHADOW(10, pointShadowAtlas);
+
+// Must match cube_face_basis in point_shadows.rs.
+const vec3 CUBE_FACE_FORWARD[6] = vec3[](
+    vec3(1.0, 0.0, 0.0),
+    vec3(-1.0, 0.0, 0.0),
+    vec3(0.0, 1.0, 0.0),
+    vec3(0.0, -1.0, 0.0),
+    vec3(0.0, 0.0, 1.0),
+    vec3(0.0, 0.0, -1.0)
+);
+
+const vec3 CUBE_FACE_UP[6] = vec3[](
+    vec3(0.0, -1.0, 0.0),
+    vec3(0.0, -1.0, 0.0),
+    vec3(0.0, 0.0, 1.0),
+    vec3(0.0, 0.0, -1.0),
+    vec3(0.0, -1.0, 0.0),
+    vec3(0.0, -1.0, 0.0)
+);
+
+int CubeFace(in vec3 direction)
+{
+    vec3 absDirection = abs(direction);
+
+    if (absDirection.x >= absDirection.y && absDirection.x >= absDirection.z) {
+        return direction.x > 0.0 ? 0 : 1;
+    } else if (absDirection.y >= absDirection.z) {
+        return direction.y > 0.0 ? 2 : 3;
+    }
+
+    return direction.z > 0.0 ? 4 : 5;
+}
+
+// Compares against a texel of the face tile, clamped so that filtering never reads a
+// neighbouring tile.
+float SamplePointShadowTile(in vec2 uv, in float depth, in vec4 rect, in vec2 atlasTexelSize)
+{
+    vec2 minUv = rect.xy + atlasTexelSize * 0.5;
+    vec2 maxUv = rect.xy + rect.zw - atlasTexelSize * 0.5;
+
+    return texture(pointShadowAtlas, vec3(clamp(uv, minUv, maxUv), depth));
+}
+
+// Visibility of the point light at lightPosition, using the cube face the fragment projects
+// onto. Same projection as the cube face matrices used to render the atlas.
+float PointLightShadow(in int shadowIndex, in vec3 lightPosition, in vec3 worldPosition, in vec3 worldNormal)
+{
+    PointShadow shadow = pointShadows[shadowIndex];
+
+    vec3 lightToFragment = worldPosition - lightPosition;
+    float distance = length(lightToFragment);
+
+    // A texel covers 2 * distance / resolution world units on a 90 degrees face.
+    float NoL = clamp(dot(worldNormal, -lightToFragment / distance), 0.0, 1.0);
+    float texelWorldSize = 2.0 * distance / shadow.resolution;
+    lightToFragment += worldNormal * (shadow.normalOffset * texelWorldSize * (1.0 - NoL));
+
+    int face = CubeFace(lightToFragment);
+    vec3 forward = CUBE_FACE_FORWARD[face];
+    vec3 right = normalize(cross(forward, CUBE_FACE_UP[face]));
+    vec3 up = cross(right, forward);
+
+    float z = dot(lightToFragment, forward);
+    vec2 ndc = vec2(dot(lightToFragment, right), dot(lightToFragment, up)) / z;
+
+    float n = shadow.near;
+    float f = shadow.far;
+    float depth = ((f + n) / (f - n) - 2.0 * f * n / ((f - n) * z)) * 0.5 + 0.5;
+
+    vec4 rect = shadow.faceRects[face];
+    vec2 uv = rect.xy + (ndc * 0.5 + 0.5) * rect.zw;
+    vec2 atlasTexelSize = 1.0 / vec2(textureSize(pointShadowAtlas, 0));
+
+#ifdef FEATURE_SHADOWS_PCF
+    float visibility = 0.0;
+    for (int y = -1; y <= 1; ++y) {
+        for (int x = -1; x <= 1; ++x) {
+            visibility += SamplePointShadowTile(uv + vec2(x, y) * atlasTexelSize, depth, rect, atlasTexelSize);
+        }
+    }
+
+    return visibility / 9.0;
+#else
+    return SamplePointShadowTile(uv, depth, rect, atlasTexelSize);
+#endif
+}
+
+#endif // POINT_SHADOWS_GLSL_
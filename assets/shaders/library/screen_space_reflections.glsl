@@ -0,0 +1,31 @@
+#ifndef SCREEN_SPACE_REFLECTIONS_GLSL_
+#define SCREEN_SPACE_REFLECTIONS_GLSL_
+
+#include "assets/shaders/library/core_defines.glsl"
+
+// Same layout as ReflectionBlock in ssr.rs.
+UNIFORM_BLOCK_BEGIN(14, ReflectionBlock)
+    mat4 ssrInverseProjection;
+    mat4 ssrInverseView;
+    mat4 ssrPreviousViewProjection;
+    vec4 ssrScreenSize; // xy: size of the G-buffer, zw: its inverse.
+    int ssrHiZMaxLevel;
+    int ssrMaxIterations;
+    float ssrThickness;
+    float ssrMaxRoughness;
+    float ssrScreenEdgeFade;
+    float ssrHistoryMaxLod;
+    int ssrHistoryValid;
+UNIFORM_BLOCK_END
+
+#ifndef SSR_PASS
+SAMPLER_2D(14, reflectionMap);
+
+// Reflected radiance (rgb) and confidence (a) of the pixel at fragCoord.
+vec4 ScreenSpaceReflection(in vec2 fragCoord)
+{
+    return texture(reflectionMap, fragCoord * ssrScreenSize.zw);
+}
+#endif
+
+#endif // SCREEN_SPACE_REFLECTIONS_GLSL_
@@ -0,0 +1,94 @@
+#ifndef SHADOWS_GLSL_
+#define SHADOWS_GLSL_
+
+#include "assets/shaders/library/core_defines.glsl"
+#include "assets/shaders/library/camera.glsl"
+
+// Must match MAX_CASCADES in shadows.rs.
+#define MAX_SHADOW_CASCADES 4
+
+// Same layout as ShadowBlock in shadows.rs.
+UNIFORM_BLOCK_BEGIN(9, ShadowBlock)
+    mat4 shadowCascadeMatrices[MAX_SHADOW_CASCADES];
+    vec4 shadowCascadeSplits;       // Far view distance of each cascade.
+    vec4 shadowCascadeTexelSizes;   // World space size of a shadow map texel of each cascade.
+    float shadowNormalOffset;       // In texels.
+    int shadowPcfRadius;
+    int shadowCascadeCount;
+    int shadowDebugCascades;
+UNIFORM_BLOCK_END
+
+#ifndef SHADOW_DEPTH_PASS
+
+SAMPLER_2D_ARRAY_SHADOW(9, shadowCascades);
+
+// Index of the cascade covering the given world position, shadowCascadeCount when it is beyond
+// the last cascade.
+int SelectShadowCascade(in vec3 worldPosition)
+{
+    float viewDepth = -(LIB_VIEW_MATRIX * vec4(worldPosition, 1.0)).z;
+
+    for (int i = 0; i < shadowCascadeCount; ++i) {
+        if (viewDepth < shadowCascadeSplits[i]) {
+            return i;
+        }
+    }
+
+    return shadowCascadeCount;
+}
+
+float SampleShadowCascade(in int cascade, in vec3 worldPosition)
+{
+    vec4 shadowPosition = shadowCascadeMatrices[cascade] * vec4(worldPosition, 1.0);
+    vec3 uvDepth = shadowPosition.xyz / shadowPosition.w * 0.5 + 0.5;
+
+#ifdef FEATURE_SHADOWS_PCF
+    vec2 texelSize = 1.0 / vec2(textureSize(shadowCascades, 0).xy);
+
+    float visibility = 0.0;
+    for (int y = -shadowPcfRadius; y <= shadowPcfRadius; ++y) {
+        for (int x = -shadowPcfRadius; x <= shadowPcfRadius; ++x) {
+            vec2 uv = uvDepth.xy + vec2(x, y) * texelSize;
+            visibility += texture(shadowCascades, vec4(uv, float(cascade), uvDepth.z));
+        }
+    }
+
+    float kernelSize = float(2 * shadowPcfRadius + 1);
+    return visibility / (kernelSize * kernelSize);
+#else
+    return texture(shadowCascades, vec4(uvDepth.xy, float(cascade), uvDepth.z));
+#endif
+}
+
+// Visibility of a directional light travelling in lightDirection. The lookup position is pushed
+// along the geometric normal, more so at grazing angles where self shadowing is the most likely.
+float DirectionalShadow(in vec3 worldPosition, in vec3 worldNormal, in vec3 lightDirection)
+{
+    int cascade = SelectShadowCascade(worldPosition);
+
+    if (cascade >= shadowCascadeCount) {
+        return 1.0;
+    }
+
+    float NoL = clamp(dot(worldNormal, -lightDirection), 0.0, 1.0);
+    float offset = shadowNormalOffset * shadowCascadeTexelSizes[cascade] * (1.0 - NoL);
+
+    return SampleShadowCascade(cascade, worldPosition + worldNormal * offset);
+}
+
+vec3 ShadowCascadeDebugColor(in vec3 worldPosition)
+{
+    const vec3 colors[MAX_SHADOW_CASCADES + 1] = vec3[](
+        vec3(1.0, 0.25, 0.25),
+        vec3(0.25, 1.0, 0.25),
+        vec3(0.25, 0.25, 1.0),
+        vec3(1.0, 1.0, 0.25),
+        vec3(1.0)
+    );
+
+    return colors[SelectShadowCascade(worldPosition)];
+}
+
+#endif // SHADOW_DEPTH_PASS
+
+#endif // SHADOWS_GLSL_
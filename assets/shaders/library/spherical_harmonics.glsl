@@ -0,0 +1,38 @@
+#ifndef SPHERICAL_HARMONICS_GLSL_
+#define SPHERICAL_HARMONICS_GLSL_
+
+// Real L2 basis, same ordering as sh9_basis(...) in spherical_harmonics.rs.
+void SHBasis(in vec3 d, out float basis[9])
+{
+    basis[0] = 0.282095;
+    basis[1] = 0.488603 * d.y;
+    basis[2] = 0.488603 * d.z;
+    basis[3] = 0.488603 * d.x;
+    basis[4] = 1.092548 * d.x * d.y;
+    basis[5] = 1.092548 * d.y * d.z;
+    basis[6] = 0.315392 * (3.0 * d.z * d.z - 1.0);
+    basis[7] = 1.092548 * d.x * d.z;
+    basis[8] = 0.546274 * (d.x * d.x - d.y * d.y);
+}
+
+#ifdef FEATURE_SH_IRRADIANCE
+    // Irradiance coefficients, already convolved with the cosine lobe and divided by PI.
+    UNIFORM_BLOCK_BEGIN(5, SphericalHarmonicsBlock)
+        vec4 shCoefficients[9];
+    UNIFORM_BLOCK_END
+
+    vec3 EvaluateSHIrradiance(in vec3 n)
+    {
+        float basis[9];
+        SHBasis(n, basis);
+
+        vec3 irradiance = vec3(0.0);
+        for (int i = 0; i < 9; ++i) {
+            irradiance += shCoefficients[i].rgb * basis[i];
+        }
+
+        return max(irradiance, vec3(0.0));
+    }
+#endif
+
+#endif // SPHERICAL_HARMONICS_GLSL_
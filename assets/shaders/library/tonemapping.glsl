@@ -3,10 +3,20 @@
 
 #include "assets/shaders/library/core_utils.glsl"
 
+// Same layout as ToneMappingPerFrameUniforms in tone_mapper.rs.
 layout(std140, binding = 3) uniform ToneMappingBlock
 {
     float whiteThreshold;
     float exposure;
+    int agxLook;
+    int comparisonOperator;
+    float comparisonSplit;
+    float neutralStartCompression;
+    float neutralDesaturation;
+    float paperWhiteNits;
+    vec4 gtParams;      // x: max brightness, y: contrast, z: linear section start, w: linear section length.
+    vec4 gtToeParams;   // x: black tightness, y: pedestal.
+    float peakNits;
 };
 
 // TONEMAPPING FUNCTIONS ------------------------------------------------
@@ -115,6 +125,162 @@ vec3 RomBinDaHouse(vec3 color)
     return exp(-1.0 / (2.72 * color + 0.15));
 }
 
+// Minimal AgX by Benjamin Wrensch, with a polynomial fit of the default contrast curve.
+// Reference: https://iolite-engine.com/blog_posts/minimal_agx_implementation
+// The matrices are given by columns, unlike the ACES ones above.
+const mat3 AgXInsetMat =
+{
+    {0.842479062253094, 0.0423282422610123, 0.0423756549057051},
+    {0.0784335999999992, 0.878468636469772, 0.0784336},
+    {0.0792237451477643, 0.0791661274605434, 0.879142973793104}
+};
+
+const mat3 AgXOutsetMat =
+{
+    { 1.19687900512017, -0.0528968517574562, -0.0529716355144438},
+    {-0.0980208811401368, 1.15190312990417, -0.0980434501171241},
+    {-0.0990297440797205, -0.0989611768448433, 1.15107367264116}
+};
+
+#define AGX_LOOK_BASE 0
+#define AGX_LOOK_GOLDEN 1
+#define AGX_LOOK_PUNCHY 2
+
+vec3 AgXDefaultContrastApprox(vec3 x)
+{
+    vec3 x2 = x * x;
+    vec3 x4 = x2 * x2;
+
+    return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+}
+
+vec3 AgXLook(vec3 color)
+{
+    vec3 slope = vec3(1.0);
+    vec3 power = vec3(1.0);
+    float saturation = 1.0;
+
+    if (agxLook == AGX_LOOK_GOLDEN) {
+        slope = vec3(1.0, 0.9, 0.5);
+        power = vec3(0.8);
+        saturation = 0.8;
+    } else if (agxLook == AGX_LOOK_PUNCHY) {
+        power = vec3(1.35);
+        saturation = 1.4;
+    }
+
+    float luma = dot(color, vec3(0.2126, 0.7152, 0.0722));
+    color = pow(color * slope, power);
+
+    return luma + saturation * (color - luma);
+}
+
+vec3 AgX(vec3 color)
+{
+    const float minEv = -12.47393;
+    const float maxEv = 4.026069;
+
+    color = AgXInsetMat * color;
+    color = clamp(log2(max(color, 1e-10)), minEv, maxEv);
+    color = (color - minEv) / (maxEv - minEv);
+    color = AgXDefaultContrastApprox(color);
+
+    color = AgXLook(max(color, 0.0));
+
+    // Back to linear with the 2.2 power of the reference display.
+    color = AgXOutsetMat * color;
+    return pow(max(color, 0.0), vec3(2.2));
+}
+
+// Reference: https://github.com/KhronosGroup/ToneMapping/tree/main/PBR_Neutral
+vec3 PbrNeutral(vec3 color)
+{
+    float x = min(color.r, min(color.g, color.b));
+    float offset = x < 0.08 ? x - 6.25 * x * x : 0.04;
+    color -= offset;
+
+    float peak = max(color.r, max(color.g, color.b));
+    if (peak < neutralStartCompression) {
+        return color;
+    }
+
+    float d = 1.0 - neutralStartCompression;
+    float newPeak = 1.0 - d * d / (peak + d - neutralStartCompression);
+    color *= newPeak / peak;
+
+    float g = 1.0 - 1.0 / (neutralDesaturation * (peak - newPeak) + 1.0);
+    return mix(color, vec3(newPeak), g);
+}
+
+// Gran Turismo tone mapping by Hajime Uchimura: a toe, a linear section and an exponential shoulder.
+// Reference: https://www.desmos.com/calculator/gslcdxvipg
+vec3 GranTurismo(vec3 x)
+{
+    float P = gtParams.x;
+    float a = gtParams.y;
+    float m = gtParams.z;
+    float l = gtParams.w;
+    float c = gtToeParams.x;
+    float b = gtToeParams.y;
+
+    float l0 = ((P - m) * l) / a;
+    float S0 = m + l0;
+    float S1 = m + a * l0;
+    float C2 = (a * P) / (P - S1);
+    float CP = -C2 / P;
+
+    vec3 w0 = 1.0 - smoothstep(0.0, m, x);
+    vec3 w2 = step(m + l0, x);
+    vec3 w1 = 1.0 - w0 - w2;
+
+    vec3 T = m * pow(x / m, vec3(c)) + b;
+    vec3 S = P - (P - S1) * exp(CP * (x - S0));
+    vec3 L = m + a * (x - m);
+
+    return T * w0 + L * w1 + S * w2;
+}
+
+// HDR OUTPUT ENCODING ------------------------------------------------
+const mat3 Rec709ToRec2020Mat =
+{
+    {0.627404, 0.329283, 0.043313},
+    {0.069097, 0.919540, 0.011362},
+    {0.016391, 0.088013, 0.895595}
+};
+
+// SMPTE ST 2084 inverse EOTF, from absolute luminance in nits.
+vec3 NitsToPQ(vec3 nits)
+{
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+
+    vec3 y = pow(clamp(nits / 10000.0, 0.0, 1.0), vec3(m1));
+    return pow((c1 + c2 * y) / (1.0 + c3 * y), vec3(m2));
+}
+
+// Operators by index, in the order of TONEMAPPER_SHADER_KEYWORDS in tone_mapper.rs. Only used to
+// compare an operator with the one selected by the keywords.
+vec3 ToneMapOperator(int index, vec3 color)
+{
+    switch (index) {
+        case 0: return ACESFitted(color);
+        case 1: return ACESFilm(color);
+        case 2: return Reinhard(color);
+        case 3: return LumaBasedReinhard(color);
+        case 4: return WhitePreservingLumaBasedReinhard(color);
+        case 5: return Uncharted2(color);
+        case 6: return RomBinDaHouse(color);
+        case 7: return AgX(color);
+        case 8: return PbrNeutral(color);
+        case 9: return GranTurismo(color);
+    }
+
+    return vec3(1.0, 0.0, 0.0);
+}
+
 #if defined(TONE_MAP_FUNC_ACES_FITTED)
 
     #define TONE_MAP(x) ACESFitted(x)
@@ -143,6 +309,18 @@ vec3 RomBinDaHouse(vec3 color)
 
     #define TONE_MAP(x) RomBinDaHouse(x)
 
+#elif defined(TONE_MAP_FUNC_AGX)
+
+    #define TONE_MAP(x) AgX(x)
+
+#elif defined(TONE_MAP_FUNC_PBR_NEUTRAL)
+
+    #define TONE_MAP(x) PbrNeutral(x)
+
+#elif defined(TONE_MAP_FUNC_GRAN_TURISMO)
+
+    #define TONE_MAP(x) GranTurismo(x)
+
 #else
 
     #define TONE_MAP(x) vec3(1.0, 0.0, 0.0)
@@ -0,0 +1,59 @@
+#ifndef VOLUMETRIC_FOG_GLSL_
+#define VOLUMETRIC_FOG_GLSL_
+
+#include "assets/shaders/library/core_defines.glsl"
+
+// Same layout as FogBlock in volumetric_fog.rs.
+UNIFORM_BLOCK_BEGIN(23, FogBlock)
+    mat4 fogViewProjection;             // Without jitter, the froxels do not move with TAA.
+    mat4 fogInverseViewProjection;
+    mat4 fogPreviousViewProjection;
+    vec4 fogCameraPosition;
+    vec4 fogCameraForward;
+    vec4 fogLightDirection;             // xyz: direction the light travels in, w: anisotropy.
+    vec4 fogLightColor;                 // rgb: color times illuminance.
+    vec4 fogAlbedo;                     // rgb: scattering albedo, w: ambient intensity.
+    vec4 fogNoiseOffset;                // xyz: wind offset, w: noise frequency.
+    uvec4 fogGridSize;                  // xyz: froxels per axis, w: frame index.
+    vec4 fogDepthParams;                // x: near, y: far, z: slice jitter.
+    float fogDensity;
+    float fogHeightFalloff;
+    float fogBaseHeight;
+    float fogNoiseIntensity;
+    float fogTemporalBlend;
+    int fogHistoryValid;
+UNIFORM_BLOCK_END
+
+// The slices are distributed quadratically between the near and far distances, which keeps
+// more of them close to the camera without starving the distance as much as exponential slices.
+float FogSliceDepth(in float w)
+{
+    return mix(fogDepthParams.x, fogDepthParams.y, w * w);
+}
+
+float FogDepthToSlice(in float viewDepth)
+{
+    return sqrt(clamp((viewDepth - fogDepthParams.x) / (fogDepthParams.y - fogDepthParams.x), 0.0, 1.0));
+}
+
+// Froxel volume coordinates of a world position. The view depth is the w of its clip position.
+vec3 FogVolumeCoordinates(in vec3 worldPosition, in mat4 viewProjection)
+{
+    vec4 clip = viewProjection * vec4(worldPosition, 1.0);
+    return vec3(clip.xy / clip.w * 0.5 + 0.5, FogDepthToSlice(clip.w));
+}
+
+#ifndef VOLUMETRIC_FOG_PASS
+SAMPLER_3D(13, fogVolume);
+
+// Attenuates the lit color of a surface by the fog in front of it and adds the light scattered
+// towards the camera. The integrated volume holds the in-scattering (rgb) and transmittance (a)
+// from the camera to the end of each slice.
+vec3 ApplyVolumetricFog(in vec3 color, in vec3 worldPosition)
+{
+    vec4 fog = texture(fogVolume, FogVolumeCoordinates(worldPosition, fogViewProjection));
+    return color * fog.a + fog.rgb;
+}
+#endif
+
+#endif // VOLUMETRIC_FOG_GLSL_
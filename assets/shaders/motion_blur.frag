@@ -0,0 +1,175 @@
+#version 450 core
+#extension GL_ARB_separate_shader_objects : enable
+
+#include "assets/shaders/library/engine.glsl"
+
+SAMPLER_2D(0, mainImage);
+SAMPLER_2D(1, depthTex);
+SAMPLER_2D(2, velocityTex);
+SAMPLER_2D(3, tileTex);
+
+// Same layout as MotionBlurUniforms in motion_blur.rs.
+UNIFORM_BLOCK_BEGIN(20, MotionBlurBlock)
+    float shutterFraction;
+    float maxBlurRadius;
+    int sampleCount;
+    float softDepthExtent;
+UNIFORM_BLOCK_END
+
+INPUT_BLOCK_BEGIN(0, VsOut)
+    vec2 texcoord;
+INPUT_BLOCK_END_NAMED(fsIn)
+
+#if defined(MOTION_BLUR_PASS_RECONSTRUCT)
+    OUTPUT(0, vec4, outColor);
+#else
+    OUTPUT(0, vec2, outColor);
+#endif
+
+// Half of the distance in pixels the surface seen through the pixel moves while the shutter is
+// open, clamped to the blur radius. The G-buffer has no velocity where there is no geometry,
+// there it is the camera motion of the far plane.
+vec2 BlurVector(in vec2 texcoord)
+{
+    vec2 velocity = texture(velocityTex, texcoord).rg;
+
+    float depth = texture(depthTex, texcoord).r;
+    if (depth >= 1.0) {
+        vec4 position = LIB_INVERSE_VIEW_PROJECTION_MATRIX * vec4(vec3(texcoord, depth) * 2.0 - 1.0, 1.0);
+        vec4 previous = LIB_PREVIOUS_VIEW_PROJECTION_MATRIX * (position / position.w);
+
+        vec2 currentNdc = texcoord * 2.0 - 1.0 - LIB_CAMERA_JITTER;
+        velocity = (currentNdc - previous.xy / previous.w) * 0.5;
+    }
+
+    vec2 blur = velocity * vec2(textureSize(velocityTex, 0)) * shutterFraction * 0.5;
+    float blurLength = length(blur);
+
+    return blurLength > maxBlurRadius ? blur * (maxBlurRadius / blurLength) : blur;
+}
+
+#if defined(MOTION_BLUR_PASS_TILE_MAX)
+    // Longest blur vector of the tile of maxBlurRadius x maxBlurRadius pixels of the fragment.
+    void TileMaxPass()
+    {
+        int tileSize = int(maxBlurRadius);
+        ivec2 size = textureSize(velocityTex, 0);
+        ivec2 origin = ivec2(gl_FragCoord.xy) * tileSize;
+
+        vec2 tileMax = vec2(0.0);
+
+        for (int y = 0; y < tileSize; ++y) {
+            for (int x = 0; x < tileSize; ++x) {
+                ivec2 pixel = min(origin + ivec2(x, y), size - 1);
+                vec2 blur = BlurVector((vec2(pixel) + 0.5) / vec2(size));
+
+                if (dot(blur, blur) > dot(tileMax, tileMax)) {
+                    tileMax = blur;
+                }
+            }
+        }
+
+        outColor = tileMax;
+    }
+#elif defined(MOTION_BLUR_PASS_NEIGHBOUR_MAX)
+    // Longest blur vector of the 3x3 tiles around the fragment, the furthest a pixel can be
+    // blurred into the tile from.
+    void NeighbourMaxPass()
+    {
+        ivec2 size = textureSize(tileTex, 0);
+        ivec2 tile = ivec2(gl_FragCoord.xy);
+
+        vec2 neighbourMax = vec2(0.0);
+
+        for (int y = -1; y <= 1; ++y) {
+            for (int x = -1; x <= 1; ++x) {
+                vec2 blur = texelFetch(tileTex, clamp(tile + ivec2(x, y), ivec2(0), size - 1), 0).rg;
+
+                if (dot(blur, blur) > dot(neighbourMax, neighbourMax)) {
+                    neighbourMax = blur;
+                }
+            }
+        }
+
+        outColor = neighbourMax;
+    }
+#elif defined(MOTION_BLUR_PASS_RECONSTRUCT)
+    // McGuire et al., A Reconstruction Filter for Plausible Motion Blur.
+    float InterleavedGradientNoise(in vec2 position)
+    {
+        return fract(52.9829189 * fract(dot(position, vec2(0.06711056, 0.00583715))));
+    }
+
+    // 1 when a is in front of b, fading to 0 over the soft depth extent.
+    float SoftDepthCompare(in float a, in float b)
+    {
+        return clamp(1.0 - (a - b) / softDepthExtent, 0.0, 1.0);
+    }
+
+    float Cone(in float offset, in float blurLength)
+    {
+        return clamp(1.0 - offset / blurLength, 0.0, 1.0);
+    }
+
+    float Cylinder(in float offset, in float blurLength)
+    {
+        return 1.0 - smoothstep(0.95 * blurLength, 1.05 * blurLength, offset);
+    }
+
+    void ReconstructPass()
+    {
+        vec2 texelSize = 1.0 / vec2(textureSize(mainImage, 0));
+        vec3 color = texture(mainImage, fsIn.texcoord).rgb;
+
+        vec2 neighbourMax = texture(tileTex, fsIn.texcoord).rg;
+        float neighbourMaxLength = length(neighbourMax);
+
+        // Less than half a pixel of blur in the neighbourhood.
+        if (neighbourMaxLength <= 0.5) {
+            outColor = vec4(color, 1.0);
+            return;
+        }
+
+        float depth = LIB_LINEARIZE_DEPTH(texture(depthTex, fsIn.texcoord).r);
+        float blurLength = max(length(BlurVector(fsIn.texcoord)), 0.5);
+
+        float totalWeight = 1.0 / blurLength;
+        vec3 sum = color * totalWeight;
+
+        float jitter = InterleavedGradientNoise(gl_FragCoord.xy) - 0.5;
+
+        for (int i = 0; i < sampleCount; ++i) {
+            float t = mix(-1.0, 1.0, (float(i) + jitter + 1.0) / float(sampleCount + 1));
+            vec2 sampleTexcoord = fsIn.texcoord + neighbourMax * t * texelSize;
+
+            float sampleDepth = LIB_LINEARIZE_DEPTH(texture(depthTex, sampleTexcoord).r);
+            float sampleBlurLength = max(length(BlurVector(sampleTexcoord)), 0.5);
+            float offset = abs(t) * neighbourMaxLength;
+
+            // The sample blurs over the pixel when it is in front, the pixel is blurred over the
+            // sample when it is behind, and both when they move together.
+            float foreground = SoftDepthCompare(sampleDepth, depth);
+            float background = SoftDepthCompare(depth, sampleDepth);
+
+            float weight = foreground * Cone(offset, sampleBlurLength)
+                + background * Cone(offset, blurLength)
+                + 2.0 * Cylinder(offset, sampleBlurLength) * Cylinder(offset, blurLength);
+
+            totalWeight += weight;
+            sum += texture(mainImage, sampleTexcoord).rgb * weight;
+        }
+
+        outColor = vec4(sum / totalWeight, 1.0);
+    }
+#endif
+
+void main()
+{
+#if defined(MOTION_BLUR_PASS_TILE_MAX)
+    TileMaxPass();
+#elif defined(MOTION_BLUR_PASS_NEIGHBOUR_MAX)
+    NeighbourMaxPass();
+#elif defined(MOTION_BLUR_PASS_RECONSTRUCT)
+    ReconstructPass();
+#endif
+}
@@ -13,6 +13,7 @@ INPUT(4, vec3, inColor);
 UNIFORM_BLOCK_BEGIN(1, PerDrawBlock)
     mat4 model;
     mat4 normalMatrix;
+    mat4 previousModel;
 UNIFORM_BLOCK_END
 
 out gl_PerVertex {
@@ -31,6 +32,10 @@ OUTPUT_BLOCK_BEGIN(0, VsOut)
     vec2 texcoord;
 OUTPUT_BLOCK_END_NAMED(vsOut)
 
+// Outside of the block so that only the G-buffer shader has to declare them.
+OUTPUT(4, vec4, clipPosition);
+OUTPUT(5, vec4, previousClipPosition);
+
 void main()
 {
     //Transform vertex to clipspace.
@@ -38,6 +43,9 @@ void main()
     vec4 wVertexPosition = model * lVertexPosition;
     gl_Position = LIB_VIEW_PROJECTION_MATRIX * wVertexPosition;
 
+    clipPosition = gl_Position;
+    previousClipPosition = LIB_PREVIOUS_VIEW_PROJECTION_MATRIX * previousModel * lVertexPosition;
+
     mat3 normalMat = mat3(normalMatrix);
     //Calculate the normal. Bring it to world space
     vsOut.wNormal = normalMat * inNormal;
@@ -0,0 +1,21 @@
+#version 450 core
+#extension GL_ARB_separate_shader_objects : enable
+
+#include "assets/shaders/library/core_defines.glsl"
+
+INPUT(0, vec3, inPosition);
+
+UNIFORM_BLOCK_BEGIN(1, PerDrawBlock)
+    mat4 model;
+    mat4 normalMatrix;
+UNIFORM_BLOCK_END
+
+out gl_PerVertex {
+    vec4 gl_Position;
+};
+
+void main()
+{
+    // World space, projected to each cascade in the geometry shader.
+    gl_Position = model * vec4(inPosition, 1.0);
+}
@@ -1,15 +1,26 @@
 #version 450 core
 #extension GL_ARB_separate_shader_objects : enable
 
-layout(location = 0) in VsOut {
+#include "assets/shaders/library/core_defines.glsl"
+#include "assets/shaders/library/environment.glsl"
+
+INPUT_BLOCK_BEGIN(0, VsOut)
     vec3 texcoord;
-} fsIn;
+INPUT_BLOCK_END_NAMED(fsIn)
 
-layout(binding = 0) uniform samplerCube skybox;
+SAMPLER_CUBE(0, skybox);
+SAMPLER_CUBE(1, targetSkybox);
 
-layout(location = 0) out vec4 outColor;
+OUTPUT(0, vec4, outColor);
 
 void main()
 {
-    outColor = texture(skybox, fsIn.texcoord);
+    vec3 color = texture(skybox, fsIn.texcoord).rgb * environmentIntensity.x;
+
+    if (environmentBlend > 0.0) {
+        vec3 targetColor = texture(targetSkybox, fsIn.texcoord).rgb * environmentIntensity.y;
+        color = mix(color, targetColor, environmentBlend);
+    }
+
+    outColor = vec4(color, 1.0);
 }
@@ -1,21 +1,18 @@
 #version 450 core
 #extension GL_ARB_separate_shader_objects : enable
 
-layout(location = 0) in vec3 inPosition;
+#include "assets/shaders/library/engine.glsl"
+#include "assets/shaders/library/environment.glsl"
 
-layout(std140, binding = 0) uniform CameraMatrices
-{
-    mat4 view;
-    mat4 projection;
-};
+INPUT(0, vec3, inPosition);
 
 out gl_PerVertex {
     vec4 gl_Position;
 };
 
-layout(location = 0) out VsOut {
+OUTPUT_BLOCK_BEGIN(0, VsOut)
     vec3 texcoord;
-} vsOut;
+OUTPUT_BLOCK_END_NAMED(vsOut)
 
 void main()
 {
@@ -26,5 +23,5 @@ void main()
 
     // The cube drawn is centered at the origin so
     // each position is a direction from the origin
-    vsOut.texcoord = inPosition;
+    vsOut.texcoord = WorldToEnvironment(inPosition);
 }
@@ -0,0 +1,240 @@
+#version 450 core
+#extension GL_ARB_separate_shader_objects : enable
+
+// SMAA 1x (Jimenez et al.), luma edge detection and orthogonal patterns.
+// Edges texture: r is the edge with the left neighbour, g the edge with the neighbour at y - 1.
+// Blend weights: r/g blend along y between the pixel and its y - 1 neighbour, b/a along x
+// between the pixel and its x - 1 neighbour (how much the pixel takes / gives).
+
+layout(binding = 0) uniform sampler2D image;
+layout(binding = 1) uniform sampler2D edgesTexture;
+layout(binding = 2) uniform sampler2D blendTexture;
+layout(binding = 3) uniform sampler2D areaTexture;
+layout(binding = 4) uniform sampler2D searchTexture;
+
+layout(std140, binding = 17) uniform SmaaBlock {
+    float threshold;
+    float localContrastAdaptation;
+    int maxSearchSteps;
+    float _padding;
+};
+
+layout(location = 0) in VsOut {
+    vec2 texcoord;
+} fsIn;
+
+#define AREA_MAX_DISTANCE 16.0
+#define AREA_SIZE 80.0
+#define SEARCH_HALF_WIDTH 33
+
+#if defined(SMAA_PASS_EDGE_DETECTION)
+layout(location = 0) out vec2 outEdges;
+
+float Luma(vec2 uv, ivec2 offset)
+{
+    // Tone mapped linear color, edges are detected on gamma luma.
+    vec3 color = textureLodOffset(image, uv, 0.0, offset).rgb;
+    return dot(sqrt(color), vec3(0.2126, 0.7152, 0.0722));
+}
+
+void main()
+{
+    vec2 uv = fsIn.texcoord;
+
+    float L = Luma(uv, ivec2(0, 0));
+    float Lleft = Luma(uv, ivec2(-1, 0));
+    float Ldown = Luma(uv, ivec2(0, -1));
+
+    vec4 delta;
+    delta.xy = abs(L - vec2(Lleft, Ldown));
+    vec2 edges = step(vec2(threshold), delta.xy);
+
+    if (dot(edges, vec2(1.0)) == 0.0) {
+        discard;
+    }
+
+    // Local contrast adaptation, drops the edges dominated by a stronger neighbouring one.
+    float Lright = Luma(uv, ivec2(1, 0));
+    float Lup = Luma(uv, ivec2(0, 1));
+    delta.zw = abs(L - vec2(Lright, Lup));
+
+    vec2 maxDelta = max(delta.xy, delta.zw);
+
+    float Lleftleft = Luma(uv, ivec2(-2, 0));
+    float Ldowndown = Luma(uv, ivec2(0, -2));
+    delta.zw = abs(vec2(Lleft, Ldown) - vec2(Lleftleft, Ldowndown));
+
+    maxDelta = max(maxDelta.xy, delta.zw);
+    float finalDelta = max(maxDelta.x, maxDelta.y);
+
+    edges *= step(finalDelta, localContrastAdaptation * delta.xy);
+
+    outEdges = edges;
+}
+#endif // SMAA_PASS_EDGE_DETECTION
+
+#if defined(SMAA_PASS_BLENDING_WEIGHTS)
+layout(location = 0) out vec4 outWeights;
+
+// Length adjustment of a search from the last bilinear fetch of two edges, see smaa_search_lut.
+// e.x holds the crossing edges and e.y the edges followed by the search.
+float SearchLength(vec2 e, int side)
+{
+    ivec2 index = ivec2(round(e * 32.0));
+    return texelFetch(searchTexture, ivec2(index.x + side * SEARCH_HALF_WIDTH, index.y), 0).r * (255.0 / 127.0);
+}
+
+// Each fetch reads two pixels at once. The loops stop at a crossing edge or at the end of the line.
+float SearchXLeft(vec2 uv, float end, vec2 texel)
+{
+    vec2 e = vec2(0.0, 1.0);
+    while (uv.x > end && e.g > 0.8281 && e.r == 0.0) {
+        e = textureLod(edgesTexture, uv, 0.0).rg;
+        uv.x -= 2.0 * texel.x;
+    }
+
+    float offset = 3.25 - SearchLength(e, 0);
+    return uv.x + offset * texel.x;
+}
+
+float SearchXRight(vec2 uv, float end, vec2 texel)
+{
+    vec2 e = vec2(0.0, 1.0);
+    while (uv.x < end && e.g > 0.8281 && e.r == 0.0) {
+        e = textureLod(edgesTexture, uv, 0.0).rg;
+        uv.x += 2.0 * texel.x;
+    }
+
+    float offset = 3.25 - SearchLength(e, 1);
+    return uv.x - offset * texel.x;
+}
+
+float SearchYDown(vec2 uv, float end, vec2 texel)
+{
+    vec2 e = vec2(1.0, 0.0);
+    while (uv.y > end && e.r > 0.8281 && e.g == 0.0) {
+        e = textureLod(edgesTexture, uv, 0.0).rg;
+        uv.y -= 2.0 * texel.y;
+    }
+
+    float offset = 3.25 - SearchLength(e.gr, 0);
+    return uv.y + offset * texel.y;
+}
+
+float SearchYUp(vec2 uv, float end, vec2 texel)
+{
+    vec2 e = vec2(1.0, 0.0);
+    while (uv.y < end && e.r > 0.8281 && e.g == 0.0) {
+        e = textureLod(edgesTexture, uv, 0.0).rg;
+        uv.y += 2.0 * texel.y;
+    }
+
+    float offset = 3.25 - SearchLength(e.gr, 1);
+    return uv.y - offset * texel.y;
+}
+
+// Coverage of the pixel for a line of the given end distances and crossing edges, see smaa_area_lut.
+vec2 Area(vec2 dist, float e1, float e2)
+{
+    vec2 uv = AREA_MAX_DISTANCE * round(4.0 * vec2(e1, e2)) + sqrt(dist);
+    uv = (uv + 0.5) / AREA_SIZE;
+    return textureLod(areaTexture, uv, 0.0).rg;
+}
+
+void main()
+{
+    vec2 uv = fsIn.texcoord;
+    vec2 size = vec2(textureSize(edgesTexture, 0));
+    vec2 texel = 1.0 / size;
+    vec2 pixel = uv * size;
+
+    // Fetch positions chosen to read two edges and their crossing edges with one bilinear fetch.
+    vec4 offset0 = uv.xyxy + vec4(-0.25, -0.125, 1.25, -0.125) * texel.xyxy;
+    vec4 offset1 = uv.xyxy + vec4(-0.125, -0.25, -0.125, 1.25) * texel.xyxy;
+    vec4 searchEnd = vec4(offset0.xz, offset1.yw) + vec4(-2.0, 2.0, -2.0, 2.0) * texel.xxyy * float(maxSearchSteps);
+
+    vec4 weights = vec4(0.0);
+    vec2 e = textureLod(edgesTexture, uv, 0.0).rg;
+
+    // Edge with the y - 1 neighbour, follows the line along x.
+    if (e.g > 0.0) {
+        vec3 coords;
+        vec2 d;
+
+        coords.x = SearchXLeft(offset0.xy, searchEnd.x, texel);
+        coords.y = offset1.y;
+        d.x = coords.x;
+
+        // Crossing edges at the left end, 0.75 weight on this row and 0.25 on the row below.
+        float e1 = textureLod(edgesTexture, coords.xy, 0.0).r;
+
+        coords.z = SearchXRight(offset0.zw, searchEnd.y, texel);
+        d.y = coords.z;
+
+        d = abs(round(d * size.x - pixel.x));
+
+        float e2 = textureLodOffset(edgesTexture, coords.zy, 0.0, ivec2(1, 0)).r;
+
+        weights.rg = Area(d, e1, e2);
+    }
+
+    // Edge with the x - 1 neighbour, follows the line along y.
+    if (e.r > 0.0) {
+        vec3 coords;
+        vec2 d;
+
+        coords.y = SearchYDown(offset1.xy, searchEnd.z, texel);
+        coords.x = offset0.x;
+        d.x = coords.y;
+
+        float e1 = textureLod(edgesTexture, coords.xy, 0.0).g;
+
+        coords.z = SearchYUp(offset1.zw, searchEnd.w, texel);
+        d.y = coords.z;
+
+        d = abs(round(d * size.y - pixel.y));
+
+        float e2 = textureLodOffset(edgesTexture, coords.xz, 0.0, ivec2(0, 1)).g;
+
+        weights.ba = Area(d, e1, e2);
+    }
+
+    outWeights = weights;
+}
+#endif // SMAA_PASS_BLENDING_WEIGHTS
+
+#if defined(SMAA_PASS_NEIGHBORHOOD_BLENDING)
+layout(location = 0) out vec4 outColor;
+
+void main()
+{
+    vec2 uv = fsIn.texcoord;
+    vec2 texel = 1.0 / vec2(textureSize(image, 0));
+
+    // Weights towards x + 1, y + 1, x - 1 and y - 1. The ones towards the positive neighbours are
+    // stored by them.
+    vec4 a;
+    a.x = textureLodOffset(blendTexture, uv, 0.0, ivec2(1, 0)).a;
+    a.y = textureLodOffset(blendTexture, uv, 0.0, ivec2(0, 1)).g;
+    a.zw = textureLod(blendTexture, uv, 0.0).br;
+
+    if (dot(a, vec4(1.0)) < 1e-5) {
+        outColor = vec4(textureLod(image, uv, 0.0).rgb, 1.0);
+        return;
+    }
+
+    bool horizontal = max(a.x, a.z) > max(a.y, a.w);
+
+    vec4 blendingOffset = horizontal ? vec4(a.x, 0.0, -a.z, 0.0) : vec4(0.0, a.y, 0.0, -a.w);
+    vec2 blendingWeight = horizontal ? a.xz : a.yw;
+    blendingWeight /= dot(blendingWeight, vec2(1.0));
+
+    // The bilinear filter does the blend with the neighbour.
+    vec4 blendingUV = uv.xyxy + blendingOffset * texel.xyxy;
+
+    vec3 color = blendingWeight.x * textureLod(image, blendingUV.xy, 0.0).rgb;
+    color += blendingWeight.y * textureLod(image, blendingUV.zw, 0.0).rgb;
+
+    outColor = vec4(color, 1.0);
+}
+#endif // SMAA_PASS_NEIGHBORHOOD_BLENDING
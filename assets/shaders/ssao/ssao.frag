@@ -0,0 +1,63 @@
+#version 450 core
+#extension GL_ARB_separate_shader_objects : enable
+
+#define SSAO_PASS
+
+#include "assets/shaders/library/engine.glsl"
+#include "assets/shaders/library/gbuffer.glsl"
+#include "assets/shaders/library/ambient_occlusion.glsl"
+
+INPUT_BLOCK_BEGIN(0, VsOut)
+    vec2 texcoord;
+INPUT_BLOCK_END_NAMED(fsIn)
+
+OUTPUT(0, float, outOcclusion);
+
+SAMPLER_2D(1, gbufferNormal);
+SAMPLER_2D(2, gbufferDepth);
+
+// Reference: Next Generation Post Processing in Call of Duty: Advanced Warfare, Jimenez.
+float InterleavedGradientNoise(in vec2 position)
+{
+    return fract(52.9829189 * fract(dot(position, vec2(0.06711056, 0.00583715))));
+}
+
+// Normal oriented hemisphere SSAO. The kernel of ssao.rs is rotated per pixel around the normal,
+// and a sample occludes when the depth buffer in front of it is closer to the camera than the
+// sample itself, within the radius.
+void main()
+{
+    float depth = texture(gbufferDepth, fsIn.texcoord).r;
+
+    if (depth == 1.0) {
+        outOcclusion = 1.0;
+        return;
+    }
+
+    vec3 position = SSAOViewPosition(fsIn.texcoord, depth);
+    vec3 normal = normalize(mat3(LIB_VIEW_MATRIX) * OctahedronDecode(texture(gbufferNormal, fsIn.texcoord).rg));
+
+    float angle = 2.0 * PI * InterleavedGradientNoise(gl_FragCoord.xy);
+    vec3 random = vec3(cos(angle), sin(angle), 0.0);
+
+    vec3 tangent = normalize(random - normal * dot(random, normal));
+    vec3 bitangent = cross(normal, tangent);
+    mat3 tbn = mat3(tangent, bitangent, normal);
+
+    float occlusion = 0.0;
+
+    for (int i = 0; i < ssaoSampleCount; ++i) {
+        vec3 samplePosition = position + tbn * ssaoKernel[i].xyz * ssaoRadius;
+
+        vec4 offset = LIB_PROJECTION_MATRIX * vec4(samplePosition, 1.0);
+        vec2 sampleTexcoord = offset.xy / offset.w * 0.5 + 0.5;
+
+        float sampleDepth = texture(gbufferDepth, sampleTexcoord).r;
+        float sceneZ = SSAOViewPosition(sampleTexcoord, sampleDepth).z;
+
+        float rangeCheck = smoothstep(0.0, 1.0, ssaoRadius / abs(position.z - sceneZ));
+        occlusion += (sceneZ >= samplePosition.z + ssaoBias ? 1.0 : 0.0) * rangeCheck;
+    }
+
+    outOcclusion = pow(1.0 - occlusion / float(ssaoSampleCount), ssaoIntensity);
+}
@@ -0,0 +1,52 @@
+#version 450 core
+#extension GL_ARB_separate_shader_objects : enable
+
+#define SSAO_PASS
+
+#include "assets/shaders/library/engine.glsl"
+#include "assets/shaders/library/ambient_occlusion.glsl"
+
+#define BLUR_RADIUS 4
+
+INPUT_BLOCK_BEGIN(0, VsOut)
+    vec2 texcoord;
+INPUT_BLOCK_END_NAMED(fsIn)
+
+OUTPUT(0, float, outOcclusion);
+
+SAMPLER_2D(0, occlusionMap);
+SAMPLER_2D(2, gbufferDepth);
+
+#if defined(SSAO_BLUR_HORIZONTAL)
+    #define BLUR_DIRECTION vec2(ssaoMapSize.z, 0.0)
+#else
+    #define BLUR_DIRECTION vec2(0.0, ssaoMapSize.w)
+#endif
+
+float ViewDepth(in vec2 texcoord)
+{
+    return -SSAOViewPosition(texcoord, texture(gbufferDepth, texcoord).r).z;
+}
+
+// Separable bilateral blur: a gaussian whose taps are rejected when their depth differs from the
+// depth of the center pixel, so that the occlusion does not leak across silhouettes.
+void main()
+{
+    float centerDepth = ViewDepth(fsIn.texcoord);
+
+    float sum = 0.0;
+    float weightSum = 0.0;
+
+    for (int i = -BLUR_RADIUS; i <= BLUR_RADIUS; ++i) {
+        vec2 texcoord = fsIn.texcoord + BLUR_DIRECTION * float(i);
+
+        float depthDelta = (ViewDepth(texcoord) - centerDepth) / max(centerDepth, 1e-4);
+        float spatialWeight = exp(-float(i * i) / (2.0 * BLUR_RADIUS));
+        float weight = spatialWeight * exp(-depthDelta * depthDelta * ssaoBlurSharpness * ssaoBlurSharpness * 100.0);
+
+        sum += texture(occlusionMap, texcoord).r * weight;
+        weightSum += weight;
+    }
+
+    outOcclusion = sum / max(weightSum, 1e-4);
+}
@@ -0,0 +1,171 @@
+#version 450 core
+#extension GL_ARB_separate_shader_objects : enable
+
+#define SSR_PASS
+
+#include "assets/shaders/library/engine.glsl"
+#include "assets/shaders/library/gbuffer.glsl"
+#include "assets/shaders/library/screen_space_reflections.glsl"
+
+// Fraction of a full resolution texel by which rays are pushed across cell boundaries.
+#define HIZ_CROSS_EPSILON 0.01
+
+INPUT_BLOCK_BEGIN(0, VsOut)
+    vec2 texcoord;
+INPUT_BLOCK_END_NAMED(fsIn)
+
+OUTPUT(0, vec4, outReflection);
+
+SAMPLER_2D(0, historyColor);
+SAMPLER_2D(1, gbufferNormal);
+SAMPLER_2D(2, gbufferDepth);
+SAMPLER_2D(3, gbufferMetallicRoughnessAo);
+SAMPLER_2D(4, hizDepth);
+
+vec3 ViewPosition(in vec2 texcoord, in float depth)
+{
+    vec4 position = ssrInverseProjection * vec4(vec3(texcoord, depth) * 2.0 - 1.0, 1.0);
+    return position.xyz / position.w;
+}
+
+// Texture coordinates (xy) and depth buffer value (z) of a view space position.
+vec3 ProjectToScreen(in vec3 position)
+{
+    vec4 clip = LIB_PROJECTION_MATRIX * vec4(position, 1.0);
+    return clip.xyz / clip.w * 0.5 + 0.5;
+}
+
+vec2 CellCount(in int level)
+{
+    return vec2(textureSize(hizDepth, level));
+}
+
+vec2 Cell(in vec2 position, in vec2 cellCount)
+{
+    return floor(position * cellCount);
+}
+
+// Point where the ray o + d * z leaves the cell, nudged into the next one.
+vec3 IntersectCellBoundary(in vec3 o, in vec3 d, in vec2 cell, in vec2 cellCount, in vec2 crossStep, in vec2 crossOffset)
+{
+    vec2 planes = (cell + crossStep) / cellCount;
+    vec2 solutions = (planes - o.xy) / d.xy;
+
+    vec3 intersection = o + d * min(solutions.x, solutions.y);
+    intersection.xy += solutions.x < solutions.y ? vec2(crossOffset.x, 0.0) : vec2(0.0, crossOffset.y);
+
+    return intersection;
+}
+
+// Hierarchical-Z traversal of the closest depth pyramid. The ray walks in screen space, going up a
+// level every time it crosses a cell in front of the geometry and down a level every time it
+// reaches the closest depth of its cell. A hit is found when it goes below the first level.
+// Reference: Hi-Z Screen-Space Cone-Traced Reflections, Uludag, GPU Pro 5.
+bool HiZTrace(in vec3 start, in vec3 direction, out vec3 hit)
+{
+    // Parameterized by the depth buffer value so that stepping to a depth plane is a single madd.
+    vec3 d = direction / direction.z;
+    d.xy = mix(d.xy, vec2(1e-7), lessThan(abs(d.xy), vec2(1e-7)));
+    vec3 o = start - d * start.z;
+
+    vec2 crossStep = vec2(d.x >= 0.0 ? 1.0 : 0.0, d.y >= 0.0 ? 1.0 : 0.0);
+    vec2 crossOffset = (crossStep * 2.0 - 1.0) * ssrScreenSize.zw * HIZ_CROSS_EPSILON;
+
+    // Leaves the starting cell first so that the surface does not hit itself.
+    vec2 cellCount = CellCount(0);
+    vec3 ray = IntersectCellBoundary(o, d, Cell(start.xy, cellCount), cellCount, crossStep, crossOffset);
+
+    int level = 0;
+    int iterations = 0;
+
+    while (level >= 0 && iterations < ssrMaxIterations) {
+        if (any(lessThan(ray.xy, vec2(0.0))) || any(greaterThan(ray.xy, vec2(1.0))) || ray.z >= 1.0) {
+            return false;
+        }
+
+        cellCount = CellCount(level);
+        vec2 oldCell = Cell(ray.xy, cellCount);
+
+        float closestDepth = texelFetch(hizDepth, ivec2(oldCell), level).r;
+        vec3 next = ray.z < closestDepth ? o + d * closestDepth : ray;
+
+        if (any(notEqual(oldCell, Cell(next.xy, cellCount)))) {
+            next = IntersectCellBoundary(o, d, oldCell, cellCount, crossStep, crossOffset);
+            level = min(ssrHiZMaxLevel, level + 2);
+        }
+
+        ray = next;
+        --level;
+        ++iterations;
+    }
+
+    hit = ray;
+    return level < 0;
+}
+
+// Tangent of the half angle of the cone containing most of the specular lobe, from the Phong
+// exponent matching the GGX roughness.
+float SpecularConeTangent(in float roughness)
+{
+    float specularPower = 2.0 / max(roughness * roughness, 1e-4) - 2.0;
+    float cosine = pow(0.244, 1.0 / (specularPower + 1.0));
+    return sqrt(1.0 - cosine * cosine) / cosine;
+}
+
+// Traces the reflection of every G-buffer pixel and reads the reflected radiance from the lit
+// color of the previous frame. Outputs the radiance and a confidence that fades out near the
+// screen borders, for rays going towards the camera and for rough surfaces.
+void main()
+{
+    outReflection = vec4(0.0);
+
+    float depth = texture(gbufferDepth, fsIn.texcoord).r;
+    float perceptualRoughness = texture(gbufferMetallicRoughnessAo, fsIn.texcoord).g;
+
+    if (depth == 1.0 || perceptualRoughness > ssrMaxRoughness || ssrHistoryValid == 0) {
+        return;
+    }
+
+    vec3 position = ViewPosition(fsIn.texcoord, depth);
+    vec3 normal = normalize(mat3(LIB_VIEW_MATRIX) * OctahedronDecode(texture(gbufferNormal, fsIn.texcoord).rg));
+    vec3 reflected = reflect(normalize(position), normal);
+
+    // The closest depth pyramid can only be traversed away from the camera.
+    float facingFade = 1.0 - smoothstep(-0.1, 0.0, reflected.z);
+
+    if (facingFade <= 0.0) {
+        return;
+    }
+
+    // Ends the ray on the far plane.
+    float rayLength = (-LIB_CAMERA_FAR_PLANE - position.z) / reflected.z;
+    vec3 start = vec3(fsIn.texcoord, depth);
+    vec3 end = ProjectToScreen(position + reflected * rayLength);
+
+    vec3 hit;
+    if (!HiZTrace(start, end - start, hit)) {
+        return;
+    }
+
+    float sceneDepth = texelFetch(hizDepth, ivec2(hit.xy * ssrScreenSize.xy), 0).r;
+    vec3 hitPosition = ViewPosition(hit.xy, sceneDepth);
+
+    if (hitPosition.z - ViewPosition(hit.xy, hit.z).z > ssrThickness) {
+        return;
+    }
+
+    vec4 previous = ssrPreviousViewProjection * ssrInverseView * vec4(hitPosition, 1.0);
+    vec2 historyTexcoord = previous.xy / previous.w * 0.5 + 0.5;
+
+    vec2 edges = smoothstep(vec2(0.0), vec2(ssrScreenEdgeFade), historyTexcoord)
+        * (1.0 - smoothstep(vec2(1.0 - ssrScreenEdgeFade), vec2(1.0), historyTexcoord));
+    float roughnessFade = 1.0 - smoothstep(ssrMaxRoughness * 0.75, ssrMaxRoughness, perceptualRoughness);
+
+    float roughness = perceptualRoughness * perceptualRoughness;
+    float coneRadius = length((hit.xy - fsIn.texcoord) * ssrScreenSize.xy) * SpecularConeTangent(roughness);
+    float lod = clamp(log2(max(coneRadius, 1.0)), 0.0, ssrHistoryMaxLod);
+
+    vec3 radiance = textureLod(historyColor, historyTexcoord, lod).rgb;
+
+    outReflection = vec4(radiance, edges.x * edges.y * facingFade * roughnessFade);
+}
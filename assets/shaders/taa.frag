@@ -0,0 +1,151 @@
+#version 450 core
+#extension GL_ARB_separate_shader_objects : enable
+
+#include "assets/shaders/library/engine.glsl"
+
+SAMPLER_2D(0, mainImage);
+SAMPLER_2D(1, depthTex);
+SAMPLER_2D(2, historyTex);
+SAMPLER_2D(3, velocityTex);
+
+// Same layout as TaaUniforms in taa.rs.
+UNIFORM_BLOCK_BEGIN(15, TaaBlock)
+    float feedback;
+    float sharpness;
+    int historyValid;
+UNIFORM_BLOCK_END
+
+INPUT_BLOCK_BEGIN(0, VsOut)
+    vec2 texcoord;
+INPUT_BLOCK_END_NAMED(fsIn)
+
+#if defined(TAA_PASS_VELOCITY)
+    OUTPUT(0, vec2, outColor);
+#else
+    OUTPUT(0, vec4, outColor);
+#endif
+
+vec3 RGBToYCoCg(in vec3 rgb)
+{
+    return vec3(
+         0.25 * rgb.r + 0.5 * rgb.g + 0.25 * rgb.b,
+         0.5  * rgb.r               - 0.5  * rgb.b,
+        -0.25 * rgb.r + 0.5 * rgb.g - 0.25 * rgb.b);
+}
+
+vec3 YCoCgToRGB(in vec3 ycocg)
+{
+    return vec3(
+        ycocg.x + ycocg.y - ycocg.z,
+        ycocg.x           + ycocg.z,
+        ycocg.x - ycocg.y - ycocg.z);
+}
+
+#if defined(TAA_PASS_VELOCITY)
+    // Camera motion of the surface seen through each pixel, from its depth and the camera
+    // matrices of both frames, in texture coordinates. The jitter is removed so that a still
+    // camera has no velocity.
+    void VelocityPass()
+    {
+        float depth = texture(depthTex, fsIn.texcoord).r;
+
+        vec4 position = LIB_INVERSE_VIEW_PROJECTION_MATRIX * vec4(vec3(fsIn.texcoord, depth) * 2.0 - 1.0, 1.0);
+        position /= position.w;
+
+        vec4 previous = LIB_PREVIOUS_VIEW_PROJECTION_MATRIX * position;
+        vec2 previousNdc = previous.xy / previous.w;
+        vec2 currentNdc = fsIn.texcoord * 2.0 - 1.0 - LIB_CAMERA_JITTER;
+
+        outColor = (currentNdc - previousNdc) * 0.5;
+    }
+#elif defined(TAA_PASS_RESOLVE)
+    // Karis, High Quality Temporal Supersampling. Weighting by the inverse luma keeps the
+    // fireflies of the HDR input from dominating the accumulation.
+    float LumaWeight(in vec3 ycocg)
+    {
+        return 1.0 / (1.0 + ycocg.x);
+    }
+
+    void ResolvePass()
+    {
+        vec2 texelSize = 1.0 / vec2(textureSize(mainImage, 0));
+
+        vec3 current = RGBToYCoCg(texture(mainImage, fsIn.texcoord).rgb);
+
+        if (historyValid == 0) {
+            outColor = vec4(YCoCgToRGB(current), 1.0);
+            return;
+        }
+
+        // Bounding box of the 3x3 neighbourhood, and velocity of its closest pixel so that the
+        // edges of foreground objects are reprojected with them.
+        vec3 neighbourhoodMin = current;
+        vec3 neighbourhoodMax = current;
+        vec2 closestOffset = vec2(0.0);
+        float closestDepth = 1.0;
+
+        for (int y = -1; y <= 1; ++y) {
+            for (int x = -1; x <= 1; ++x) {
+                vec2 offset = vec2(x, y) * texelSize;
+
+                vec3 neighbour = RGBToYCoCg(texture(mainImage, fsIn.texcoord + offset).rgb);
+                neighbourhoodMin = min(neighbourhoodMin, neighbour);
+                neighbourhoodMax = max(neighbourhoodMax, neighbour);
+
+                float depth = texture(depthTex, fsIn.texcoord + offset).r;
+                if (depth < closestDepth) {
+                    closestDepth = depth;
+                    closestOffset = offset;
+                }
+            }
+        }
+
+        vec2 velocity = texture(velocityTex, fsIn.texcoord + closestOffset).rg;
+        vec2 historyTexcoord = fsIn.texcoord - velocity;
+
+        if (any(lessThan(historyTexcoord, vec2(0.0))) || any(greaterThan(historyTexcoord, vec2(1.0)))) {
+            outColor = vec4(YCoCgToRGB(current), 1.0);
+            return;
+        }
+
+        vec3 history = RGBToYCoCg(texture(historyTex, historyTexcoord).rgb);
+        history = clamp(history, neighbourhoodMin, neighbourhoodMax);
+
+        float currentWeight = (1.0 - feedback) * LumaWeight(current);
+        float historyWeight = feedback * LumaWeight(history);
+
+        vec3 resolved = (current * currentWeight + history * historyWeight) / (currentWeight + historyWeight);
+
+        outColor = vec4(YCoCgToRGB(resolved), 1.0);
+    }
+#elif defined(TAA_PASS_OUTPUT)
+    void OutputPass()
+    {
+        vec3 color = texture(historyTex, fsIn.texcoord).rgb;
+
+    #if defined(TAA_SHARPEN)
+        // Unsharp mask with the 4 direct neighbours.
+        vec2 texelSize = 1.0 / vec2(textureSize(historyTex, 0));
+
+        vec3 neighbours = texture(historyTex, fsIn.texcoord + vec2(texelSize.x, 0.0)).rgb
+            + texture(historyTex, fsIn.texcoord - vec2(texelSize.x, 0.0)).rgb
+            + texture(historyTex, fsIn.texcoord + vec2(0.0, texelSize.y)).rgb
+            + texture(historyTex, fsIn.texcoord - vec2(0.0, texelSize.y)).rgb;
+
+        color = max(color + (color * 4.0 - neighbours) * sharpness, vec3(0.0));
+    #endif
+
+        outColor = vec4(color, 1.0);
+    }
+#endif
+
+void main()
+{
+#if defined(TAA_PASS_VELOCITY)
+    VelocityPass();
+#elif defined(TAA_PASS_RESOLVE)
+    ResolvePass();
+#elif defined(TAA_PASS_OUTPUT)
+    OutputPass();
+#endif
+}
@@ -3,6 +3,14 @@
 
 #include "assets/shaders/library/tonemapping.glsl"
 
+#if defined(AUTO_EXPOSURE)
+#include "assets/shaders/library/exposure.glsl"
+#endif
+
+#if defined(COLOR_GRADING) || defined(COLOR_GRADING_LUT)
+#include "assets/shaders/library/color_grading.glsl"
+#endif
+
 layout(binding = 0) uniform sampler2D image;
 
 layout(location = 0) in VsOut {
@@ -13,7 +21,46 @@ layout(location = 0) out vec4 outColor;
 
 void main()
 {
+#if defined(AUTO_EXPOSURE)
+    vec3 color = texture(image, fsIn.texcoord).rgb * autoExposure;
+#else
     vec3 color = texture(image, fsIn.texcoord).rgb * exposure;
+#endif
+
+#if defined(COLOR_GRADING)
+    color = GradeHdr(color);
+#endif
+
+#if defined(OUTPUT_HDR10) || defined(OUTPUT_SCRGB)
+    // The SDR curves map to [0, 1], stretch them so that their white is the display peak.
+    color *= paperWhiteNits / peakNits;
+#endif
+
+#if defined(TONE_MAP_COMPARISON)
+    // Split screen, the comparison operator on the right.
+    float split = fsIn.texcoord.x - comparisonSplit;
+    if (abs(split) * textureSize(image, 0).x < 1.0) {
+        outColor = vec4(1.0);
+        return;
+    }
+    color = split > 0.0 ? ToneMapOperator(comparisonOperator, color) : TONE_MAP(color);
+#else
+    color = TONE_MAP(color);
+#endif
+
+#if defined(COLOR_GRADING)
+    color = GradeLdr(color);
+#endif
+
+#if defined(COLOR_GRADING_LUT)
+    color = ApplyGradingLut(color);
+#endif
+
+#if defined(OUTPUT_HDR10)
+    color = NitsToPQ((color * Rec709ToRec2020Mat) * peakNits);
+#elif defined(OUTPUT_SCRGB)
+    color *= peakNits / 80.0;
+#endif
 
-    outColor = vec4(TONE_MAP(color), 1.0);
+    outColor = vec4(color, 1.0);
 }
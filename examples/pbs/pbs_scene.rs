@@ -1,4 +1,6 @@
+use std::fs;
 use std::mem;
+use std::path::PathBuf;
 use std::rc::Rc;
 
 use crevice::std140::AsStd140;
@@ -7,14 +9,25 @@ use glutin::event::{
 };
 
 use engine::rendering::buffer::BufferStorageFlags;
-use engine::rendering::shader::{Shader, ShaderCreateInfo};
+use engine::rendering::deferred::{DeferredRenderer, GBufferAttachment};
+use engine::rendering::environment::{Environment, EnvironmentRenderer, SkyboxSource};
+use engine::rendering::light::{DirectionalLight, Light, LightBuffer, PointLight, SpotLight};
+use engine::rendering::ibl::{IblBakeSettings, IblBaker};
+use engine::rendering::light_clusters::{LightClusterSettings, LightClusters};
+use engine::rendering::point_shadows::{PointShadowAtlas, PointShadowSettings};
+use engine::rendering::postprocess::ssao::{AmbientOcclusionSettings, ScreenSpaceAmbientOcclusion};
+use engine::rendering::postprocess::ssr::{ReflectionSettings, ScreenSpaceReflections};
+use engine::rendering::query::GpuTimer;
+use engine::rendering::render_graph::{RenderGraph, RenderGraphSummary, WriteAccess};
+use engine::rendering::shadows::{CascadedShadowMap, CascadedShadowSettings, ShadowFilter};
+use engine::rendering::volumetric_fog::{VolumetricFog, VolumetricFogSettings};
 use engine::{
     camera::Camera,
     color::srgb_to_linear3f,
     core::camera::CameraBuilder,
     imgui::*,
     math::{
-        inverse,
+        inverse, lerp_scalar,
         matrix::Mat4,
         transpose,
         vector::{UVec2, Vec2, Vec3, Vec4},
@@ -25,60 +38,74 @@ use engine::{
             AttachmentType, Framebuffer, FramebufferAttachmentCreateInfo, TextureFilter,
         },
         material::{Material, PbsMetallicRoughnessMaterial},
-        mesh::utilities::generate_cube,
         mesh::Mesh,
         postprocess::{
-            bloom::Bloom, tone_mapper::ToneMapper, PostprocessingStack,
-            PostprocessingStackBuilder,
+            bloom::Bloom, fxaa::Fxaa, lens::Lens, motion_blur::MotionBlur, smaa::Smaa,
+            taa::TemporalAntiAliasing, tone_mapper::ToneMapper, PostprocessingEffect, PostprocessingInput, PostprocessingStack,
+            PostprocessingStackBuilder, PostprocessingTarget,
         },
-        sampler::{Anisotropy, MagnificationFilter, MinificationFilter, Sampler, WrappingMode},
-        shader::ShaderStage,
-        state::{DepthFunction, FaceCulling, StateManager},
-        texture::{SizedTextureFormat, TextureCube},
+        shader::Shader,
+        state::StateManager,
+        texture::SizedTextureFormat,
         Draw,
     },
     scene::Scene,
     scene::Transition,
     Context, Msaa,
 };
-use engine::postprocess::dof::DepthOfField;
+use engine::postprocess::dof::{BokehKernelSize, DepthOfField};
 
-struct EnvironmentMaps {
-    skybox: TextureCube,
-    irradiance: TextureCube,
-    radiance: TextureCube,
-}
+/// Baked environments and LUTs, generated by the example and not tracked.
+const BAKED_ASSETS_PATH: &str = "textures/pbs/baked";
 
-#[repr(usize)]
-enum SkyboxType {
-    Original,
-    Radiance,
-    Irradiance,
-}
+const BRDF_LUT_SIZE: u32 = 512;
+const BRDF_LUT_SAMPLE_COUNT: u32 = 1024;
 
-struct Environment {
-    maps: [EnvironmentMaps; 2],
-    skybox_program_pipeline: Rc<Shader>,
-    skybox_mesh: Mesh,
+struct Environments {
+    environments: [Rc<Environment>; 3],
+    renderer: EnvironmentRenderer,
     active_environment: usize,
-    skybox_type: SkyboxType,
+    transition_duration: f32,
+    baker: IblBaker,
+    /// Where "Regenerate BRDF LUT" writes the LUT. The materials keep using the shipped one.
+    brdf_lut_path: PathBuf,
+    /// Outcome of the last LUT regeneration, shown under the button.
+    brdf_lut_status: Option<String>,
+}
+
+struct PointLightRing {
+    count: i32,
+    radius: f32,
+    height: f32,
+    range: f32,
+    luminous_power: f32,
+    cast_shadows: bool,
 }
 
 struct Lighting {
     light_direction: [f32; 3],
     light_color: [f32; 3],
     light_intensity: f32,
+    shadow_filter: ShadowFilter,
+    point_lights: PointLightRing,
+    spot_light_enabled: bool,
+    spot_light: SpotLight,
+    spot_light_temperature: f32,
+    light_buffer: LightBuffer,
+    clustered_lighting: bool,
     geometric_specular_aa: bool,
     specular_ao: bool,
+    sh_irradiance: bool,
     brdf_type: usize,
     multi_scattering: bool,
     ss_variance_and_threshold: Vec2,
-    max_reflection_lod: i32,
 }
 
 struct Model {
     pub mesh: Rc<Mesh>,
     pub transform: Mat4,
+    /// Transform of the previous frame, for the velocity of the G-buffer.
+    pub previous_transform: Mat4,
 }
 
 #[derive(Default)]
@@ -98,19 +125,21 @@ struct Controls {
 struct VertexPerDrawUniforms {
     model_matrix: mint::ColumnMatrix4<f32>,
     normal_matrix: mint::ColumnMatrix4<f32>,
+    previous_model_matrix: mint::ColumnMatrix4<f32>,
 }
 
 #[repr(C)]
 #[derive(Debug, AsStd140)]
 struct FragmentPerFrameUniforms {
-    light_direction: mint::Vector4<f32>,
-    light_color: mint::Vector4<f32>,
     ss_variance_and_threshold: mint::Vector2<f32>,
     geometric_specular_aa: i32,
     specular_ao: i32,
     render_mode: i32,
     multi_scattering: i32,
-    max_reflection_lod: f32,
+    clustered_lighting: i32,
+    screen_space_ao: i32,
+    screen_space_reflections: i32,
+    volumetric_fog: i32,
 }
 
 // TODO: Use this to group framebuffers
@@ -123,13 +152,22 @@ pub struct PbsScene {
     camera: Camera,
     model: Model,
     material: PbsMetallicRoughnessMaterial,
-    environment: Environment,
+    environment: Environments,
     msaa_framebuffers: [Framebuffer; 4],
     resolve_framebuffer: Framebuffer,
-    sampler_linear: Sampler,
     post_stack: PostprocessingStack,
     controls: Controls,
     lighting: Lighting,
+    shadows: CascadedShadowMap,
+    point_shadows: PointShadowAtlas,
+    clusters: LightClusters,
+    fog: VolumetricFog,
+    deferred: DeferredRenderer,
+    deferred_shading: bool,
+    ssao: ScreenSpaceAmbientOcclusion,
+    ssr: ScreenSpaceReflections,
+    gpu_timer: GpuTimer,
+    render_graph: RenderGraphSummary,
     render_mode: usize,
     vertex_per_draw_ubo: Buffer,
     fragment_per_frame_ubo: Buffer,
@@ -154,6 +192,10 @@ impl PbsScene {
             .fov(60)
             .near_plane(0.5)
             .far_plane(500.0)
+            // A 1/60 s shutter gives the motion blur a 360 degree shutter angle at 60 fps, the
+            // sensitivity keeps the exposure of the default 0.55 s at ISO 500.
+            .shutter_speed(1.0 / 60.0)
+            .sensitivity(16500.0)
             .orbit_speed(10.0)
             .orbit_dampening(3.0)
             .zoom_speed(30.0)
@@ -162,23 +204,10 @@ impl PbsScene {
             .max_distance(200.0)
             .build();
 
-        let shader_manager = device.shader_manager();
-        let skybox_prog = shader_manager.create_shader(
-            &ShaderCreateInfo::builder("SkyboxShader")
-                .stage(ShaderStage::Vertex, asset_path.join("shaders/skybox.vert"))
-                .stage(
-                    ShaderStage::Fragment,
-                    asset_path.join("shaders/skybox.frag"),
-                )
-                .build(),
-        );
-
         let mesh = asset_manager
             .load_mesh(asset_path.join("models/cerberus/cerberus.glb"))
             .expect("Failed to load mesh");
 
-        let skybox_mesh = generate_cube(1.0);
-
         let albedo = asset_manager
             .load_texture_2d(
                 asset_path.join("textures/cerberus/Cerberus_A.png"),
@@ -203,45 +232,159 @@ impl PbsScene {
             )
             .expect("Failed to load normals texture");
 
-        let skybox_exterior =
-            TextureCube::new_from_file(asset_path.join("textures/pbs/ktx/skybox/skybox2.ktx"))
-                .expect("Failed to load Skybox");
-
-        let irradiance_exterior = TextureCube::new_from_file(
-            asset_path.join("textures/pbs/ktx/irradiance/irradiance2.ktx"),
-        )
-        .expect("Failed to load Irradiance map");
-
-        let radiance_exterior =
-            TextureCube::new_from_file(asset_path.join("textures/pbs/ktx/radiance/radiance2.ktx"))
-                .expect("Failed to load Radiance map");
-
-        let skybox_interior =
-            TextureCube::new_from_file(asset_path.join("textures/pbs/ktx/skybox/ibl_skybox.ktx"))
-                .expect("Failed to load Skybox");
-
-        let irradiance_interior = TextureCube::new_from_file(
-            asset_path.join("textures/pbs/ktx/irradiance/ibl_irradiance.ktx"),
-        )
-        .expect("Failed to load Irradiance map");
-
-        let radiance_interior = TextureCube::new_from_file(
-            asset_path.join("textures/pbs/ktx/radiance/ibl_radiance.ktx"),
-        )
-        .expect("Failed to load Radiance map");
-
-        let environments = [
-            EnvironmentMaps {
-                skybox: skybox_exterior,
-                irradiance: irradiance_exterior,
-                radiance: radiance_exterior,
-            },
-            EnvironmentMaps {
-                skybox: skybox_interior,
-                irradiance: irradiance_interior,
-                radiance: radiance_interior,
-            },
-        ];
+        let exterior = asset_manager
+            .load_environment(
+                "Exterior",
+                asset_path.join("textures/pbs/ktx/skybox/skybox2.ktx"),
+                asset_path.join("textures/pbs/ktx/irradiance/irradiance2.ktx"),
+                asset_path.join("textures/pbs/ktx/radiance/radiance2.ktx"),
+            )
+            .expect("Failed to load Exterior environment");
+
+        let interior = asset_manager
+            .load_environment(
+                "Interior",
+                asset_path.join("textures/pbs/ktx/skybox/ibl_skybox.ktx"),
+                asset_path.join("textures/pbs/ktx/irradiance/ibl_irradiance.ktx"),
+                asset_path.join("textures/pbs/ktx/radiance/ibl_radiance.ktx"),
+            )
+            .expect("Failed to load Interior environment");
+
+        let ibl_baker = IblBaker::new(Context::new(
+            window,
+            device,
+            asset_manager,
+            timer,
+            framebuffer_cache,
+            settings,
+        ));
+
+        // Baking the HDR image takes a while, it is only done when the cached KTX files are
+        // missing. Delete them to rebake.
+        let pillars_cache = asset_path.join(BAKED_ASSETS_PATH);
+        let pillars = asset_manager
+            .load_environment(
+                "Pillars (Baked)",
+                pillars_cache.join("pillars_skybox.ktx"),
+                pillars_cache.join("pillars_irradiance.ktx"),
+                pillars_cache.join("pillars_radiance.ktx"),
+            )
+            .unwrap_or_else(|_| {
+                let pillars_hdr = asset_manager
+                    .load_texture_2d(asset_path.join("textures/pillars_4k.hdr"), false, false)
+                    .expect("Failed to load HDR environment");
+
+                let baked = ibl_baker.bake(&pillars_hdr, &IblBakeSettings::default());
+
+                fs::create_dir_all(&pillars_cache)
+                    .map_err(|e| e.to_string())
+                    .and_then(|_| baked.save_ktx(&pillars_cache, "pillars"))
+                    .expect("Failed to cache the Pillars environment");
+
+                Rc::new(Environment::from_baked("Pillars (Baked)", baked))
+            });
+
+        let mut environment_renderer = EnvironmentRenderer::new(
+            Context::new(
+                window,
+                device,
+                asset_manager,
+                timer,
+                framebuffer_cache,
+                settings,
+            ),
+            Rc::clone(&interior),
+        );
+        environment_renderer.set_skybox_source(SkyboxSource::Radiance);
+
+        let shadows = CascadedShadowMap::new(
+            Context::new(
+                window,
+                device,
+                asset_manager,
+                timer,
+                framebuffer_cache,
+                settings,
+            ),
+            CascadedShadowSettings::default(),
+        );
+
+        let point_shadows = PointShadowAtlas::new(
+            Context::new(
+                window,
+                device,
+                asset_manager,
+                timer,
+                framebuffer_cache,
+                settings,
+            ),
+            PointShadowSettings::default(),
+        );
+
+        let clusters = LightClusters::new(
+            Context::new(
+                window,
+                device,
+                asset_manager,
+                timer,
+                framebuffer_cache,
+                settings,
+            ),
+            LightClusterSettings::default(),
+        );
+
+        let fog = VolumetricFog::new(
+            Context::new(
+                window,
+                device,
+                asset_manager,
+                timer,
+                framebuffer_cache,
+                settings,
+            ),
+            VolumetricFogSettings::default(),
+        );
+
+        let deferred = DeferredRenderer::new(
+            Context::new(
+                window,
+                device,
+                asset_manager,
+                timer,
+                framebuffer_cache,
+                settings,
+            ),
+            UVec2::new(window.inner_size().width, window.inner_size().height),
+        );
+
+        let mut ssao = ScreenSpaceAmbientOcclusion::new(
+            Context::new(
+                window,
+                device,
+                asset_manager,
+                timer,
+                framebuffer_cache,
+                settings,
+            ),
+            UVec2::new(window.inner_size().width, window.inner_size().height),
+            AmbientOcclusionSettings::default(),
+        );
+        let mut ssr = ScreenSpaceReflections::new(
+            Context::new(
+                window,
+                device,
+                asset_manager,
+                timer,
+                framebuffer_cache,
+                settings,
+            ),
+            UVec2::new(window.inner_size().width, window.inner_size().height),
+            ReflectionSettings::default(),
+        );
+
+        // Matches the default variant of the lit shaders.
+        ssao.disable();
+        ssr.disable();
 
         let msaa_framebuffers = [
             Framebuffer::new(
@@ -345,9 +488,47 @@ impl PbsScene {
             settings,
         ));
 
+        // Post-process anti-aliasing alternatives to TAA, cheaper than MSAA.
+        let mut fxaa = Fxaa::new(Context::new(
+            window,
+            device,
+            asset_manager,
+            timer,
+            framebuffer_cache,
+            settings,
+        ));
+        fxaa.disable();
+
+        let mut smaa = Smaa::new(Context::new(
+            window,
+            device,
+            asset_manager,
+            timer,
+            framebuffer_cache,
+            settings,
+        ));
+        smaa.disable();
+
+        let mut lens = Lens::new(Context::new(
+            window,
+            device,
+            asset_manager,
+            timer,
+            framebuffer_cache,
+            settings,
+        ));
+        lens.disable();
+
         let post_stack = PostprocessingStackBuilder::new()
-            .with_effect(bloom)
-            .with_effect(DepthOfField::new(Context::new(
+            .with_effect(TemporalAntiAliasing::new(Context::new(
+                window,
+                device,
+                asset_manager,
+                timer,
+                framebuffer_cache,
+                settings,
+            )))
+            .with_effect(MotionBlur::new(Context::new(
                 window,
                 device,
                 asset_manager,
@@ -355,6 +536,18 @@ impl PbsScene {
                 framebuffer_cache,
                 settings,
             )))
+            .with_effect(bloom)
+            .with_effect(DepthOfField::new(
+                Context::new(
+                    window,
+                    device,
+                    asset_manager,
+                    timer,
+                    framebuffer_cache,
+                    settings,
+                ),
+                BokehKernelSize::Medium,
+            ))
             .with_effect(ToneMapper::new(Context::new(
                 window,
                 device,
@@ -363,18 +556,11 @@ impl PbsScene {
                 framebuffer_cache,
                 settings,
             )))
+            .with_effect(fxaa)
+            .with_effect(smaa)
+            .with_effect(lens)
             .build();
 
-        let sampler_mipmap_linear = Sampler::new(
-            MinificationFilter::LinearMipmapLinear,
-            MagnificationFilter::Linear,
-            WrappingMode::ClampToEdge,
-            WrappingMode::ClampToEdge,
-            WrappingMode::ClampToEdge,
-            Vec4::new(0.0, 0.0, 0.0, 0.0),
-            Anisotropy::X16,
-        );
-
         let material = PbsMetallicRoughnessMaterial::new(
             Context::new(
                 window,
@@ -414,18 +600,20 @@ impl PbsScene {
             model: Model {
                 mesh,
                 transform: Mat4::identity(),
+                previous_transform: Mat4::identity(),
             },
             material,
-            environment: Environment {
-                maps: environments,
-                skybox_program_pipeline: skybox_prog,
-                skybox_mesh,
+            environment: Environments {
+                environments: [exterior, interior, pillars],
+                renderer: environment_renderer,
                 active_environment: 1,
-                skybox_type: SkyboxType::Radiance,
+                transition_duration: 1.0,
+                baker: ibl_baker,
+                brdf_lut_path: asset_path.join(BAKED_ASSETS_PATH).join("ibl_brdf_lut.png"),
+                brdf_lut_status: None,
             },
             msaa_framebuffers,
             resolve_framebuffer,
-            sampler_linear: sampler_mipmap_linear,
             post_stack,
             controls: Controls {
                 mouse_sensitivity: 2.0,
@@ -435,13 +623,45 @@ impl PbsScene {
                 light_direction: [0.4, 0.0, -1.0],
                 light_color: [1.0, 1.0, 1.0],
                 light_intensity: 5.0,
+                shadow_filter: ShadowFilter::Off,
+                point_lights: PointLightRing {
+                    count: 8,
+                    radius: 30.0,
+                    height: 10.0,
+                    range: 40.0,
+                    luminous_power: 5000.0,
+                    cast_shadows: true,
+                },
+                spot_light_enabled: false,
+                spot_light: SpotLight {
+                    position: Vec3::new(0.0, 40.0, -20.0),
+                    direction: Vec3::new(0.0, -1.0, 0.5),
+                    range: 100.0,
+                    luminous_power: 20000.0,
+                    inner_angle: 15.0,
+                    outer_angle: 25.0,
+                    ..Default::default()
+                },
+                spot_light_temperature: 3200.0,
+                light_buffer: LightBuffer::default(),
+                clustered_lighting: false,
                 geometric_specular_aa: true,
                 specular_ao: true,
+                sh_irradiance: false,
                 brdf_type: 0,
                 multi_scattering: true,
                 ss_variance_and_threshold: Vec2::new(0.25, 0.18),
-                max_reflection_lod: 5,
             },
+            shadows,
+            point_shadows,
+            clusters,
+            fog,
+            deferred,
+            deferred_shading: false,
+            ssao,
+            ssr,
+            gpu_timer: GpuTimer::new(),
+            render_graph: RenderGraphSummary::default(),
             render_mode: 0,
             vertex_per_draw_ubo,
             fragment_per_frame_ubo,
@@ -449,6 +669,15 @@ impl PbsScene {
         }
     }
 
+    fn shadow_pass(&self) {
+        if self.lighting.shadow_filter == ShadowFilter::Off {
+            return;
+        }
+
+        self.shadows.render(|| self.model.mesh.draw());
+        self.point_shadows.render(|| self.model.mesh.draw());
+    }
+
     fn geometry_pass(&self) {
         let framebuffer = &self.msaa_framebuffers[self.msaa_framebuffer_index];
         framebuffer.bind();
@@ -457,20 +686,12 @@ impl PbsScene {
         self.material.bind();
 
         let shader = self.material.shader();
-
-        const IRRADIANCE_MAP_BINDING_INDEX: u32 = 4;
-        const RADIANCE_MAP_BINDING_INDEX: u32 = 5;
-        shader
-            .bind_texture_cube(
-                IRRADIANCE_MAP_BINDING_INDEX,
-                &self.environment.maps[self.environment.active_environment].irradiance,
-                &self.sampler_linear,
-            )
-            .bind_texture_cube(
-                RADIANCE_MAP_BINDING_INDEX,
-                &self.environment.maps[self.environment.active_environment].radiance,
-                &self.sampler_linear,
-            );
+        self.environment.renderer.bind_ibl_maps(&shader);
+        self.ssao.bind_ao_map(&shader);
+        self.ssr.bind_reflection_map(&shader);
+        self.shadows.bind_shadow_map(&shader);
+        self.point_shadows.bind_shadow_atlas(&shader);
+        self.fog.bind_fog_volume(&shader);
 
         self.model.mesh.draw();
 
@@ -479,40 +700,45 @@ impl PbsScene {
         self.material.unbind()
     }
 
-    fn skybox_pass(&self) {
-        StateManager::depth_function(DepthFunction::LessOrEqual);
-        StateManager::face_culling(FaceCulling::Front);
-
-        let framebuffer = &self.msaa_framebuffers[self.msaa_framebuffer_index];
-
-        framebuffer.bind();
-
-        self.environment.skybox_program_pipeline.bind();
+    fn deferred_geometry_pass(&self) {
+        self.deferred.geometry_pass(|| {
+            self.material.bind_gbuffer();
+            self.model.mesh.draw();
+            self.material.unbind_gbuffer();
+        });
+    }
 
-        let environment_map = match self.environment.skybox_type {
-            SkyboxType::Original => {
-                &self.environment.maps[self.environment.active_environment].skybox
-            }
-            SkyboxType::Radiance => {
-                &self.environment.maps[self.environment.active_environment].radiance
-            }
-            SkyboxType::Irradiance => {
-                &self.environment.maps[self.environment.active_environment].irradiance
-            }
-        };
+    fn deferred_lighting_pass(&self) {
+        self.resolve_framebuffer
+            .clear(&Vec4::new(0.0, 0.0, 0.0, 1.0));
+
+        let shader = self.deferred.lighting_shader();
+        self.environment.renderer.bind_ibl_maps(&shader);
+        self.material.bind_brdf_lut(&shader);
+        self.ssao.bind_ao_map(&shader);
+        self.ssr.bind_reflection_map(&shader);
+        self.shadows.bind_shadow_map(&shader);
+        self.point_shadows.bind_shadow_atlas(&shader);
+        self.fog.bind_fog_volume(&shader);
+
+        self.deferred
+            .lighting_pass(&self.camera, &self.resolve_framebuffer);
+
+        self.resolve_framebuffer.bind();
+        self.environment.renderer.draw_skybox();
+    }
 
-        self.environment.skybox_program_pipeline.bind_texture_cube(
-            0,
-            &environment_map,
-            &self.sampler_linear,
-        );
+    // Shaders of the forward and deferred lighting paths, which share their lighting keywords.
+    fn lit_shaders(&self) -> [Rc<Shader>; 2] {
+        [self.material.shader(), self.deferred.lighting_shader()]
+    }
 
-        self.environment.skybox_mesh.draw();
+    fn skybox_pass(&self) {
+        let framebuffer = &self.msaa_framebuffers[self.msaa_framebuffer_index];
 
-        self.environment.skybox_program_pipeline.unbind();
+        framebuffer.bind();
 
-        StateManager::depth_function(DepthFunction::Less);
-        StateManager::face_culling(FaceCulling::Back)
+        self.environment.renderer.draw_skybox();
     }
 
     fn msaa_resolve(&self) {
@@ -527,37 +753,97 @@ impl PbsScene {
         framebuffer.unbind(true);
     }
 
-    fn update_uniform_buffers(&self) {
+    fn volumetric_fog_pass(&mut self, time: f32) {
+        let light = self.directional_light();
+        let shadows = if light.cast_shadows {
+            Some(&self.shadows)
+        } else {
+            None
+        };
+
+        self.fog.update(
+            &self.camera,
+            &light,
+            shadows,
+            &self.environment.renderer,
+            time,
+        );
+    }
+
+    fn directional_light(&self) -> DirectionalLight {
+        // The inspector edits the direction towards the light.
+        DirectionalLight {
+            direction: -Vec3::from(self.lighting.light_direction),
+            color: srgb_to_linear3f(&self.lighting.light_color.into()),
+            temperature: None,
+            illuminance: self.lighting.light_intensity,
+            cast_shadows: self.lighting.shadow_filter != ShadowFilter::Off,
+        }
+    }
+
+    fn update_lights(&mut self, viewport_height: u32) {
+        let mut lights: Vec<Light> = vec![self.directional_light().into()];
+
+        let ring = &self.lighting.point_lights;
+        let shadows_enabled = self.lighting.shadow_filter != ShadowFilter::Off;
+        lights.extend((0..ring.count).map(|index| {
+            let t = index as f32 / ring.count as f32;
+            let angle = t * 2.0 * std::f32::consts::PI;
+
+            Light::from(PointLight {
+                position: Vec3::new(angle.cos() * ring.radius, ring.height, angle.sin() * ring.radius),
+                range: ring.range,
+                temperature: Some(lerp_scalar(2000.0, 10000.0, t)),
+                luminous_power: ring.luminous_power,
+                cast_shadows: shadows_enabled && ring.cast_shadows,
+                ..Default::default()
+            })
+        }));
+
+        if self.lighting.spot_light_enabled {
+            lights.push(
+                SpotLight {
+                    temperature: Some(self.lighting.spot_light_temperature),
+                    ..self.lighting.spot_light
+                }
+                .into(),
+            );
+        }
+
+        self.point_shadows
+            .update(&self.camera, viewport_height, &lights);
+        self.lighting
+            .light_buffer
+            .update_with_shadows(&lights, &self.point_shadows);
+    }
+
+    fn update_uniform_buffers(&mut self) {
         let vertex_per_draw_uniforms = VertexPerDrawUniforms {
             model_matrix: self.model.transform.into(),
             normal_matrix: transpose(&inverse(&self.model.transform)).into(),
+            previous_model_matrix: self.model.previous_transform.into(),
         };
 
         self.vertex_per_draw_ubo
             .fill_mapped(0, &vertex_per_draw_uniforms.as_std140());
-
-        let mut light_color: Vec3 = srgb_to_linear3f(&self.lighting.light_color.into());
-        light_color *= self.lighting.light_intensity;
+        self.model.previous_transform = self.model.transform;
 
         let fragment_per_frame_uniforms = FragmentPerFrameUniforms {
-            light_direction: Vec4::new(
-                self.lighting.light_direction[0],
-                self.lighting.light_direction[1],
-                self.lighting.light_direction[2],
-                1.0,
-            )
-            .into(),
-            light_color: Vec4::new(light_color.x, light_color.y, light_color.z, 0.0).into(),
             ss_variance_and_threshold: self.lighting.ss_variance_and_threshold.clone_owned().into(),
             geometric_specular_aa: self.lighting.geometric_specular_aa as i32,
             specular_ao: self.lighting.specular_ao as i32,
             render_mode: self.render_mode as i32,
             multi_scattering: self.lighting.multi_scattering as i32,
-            max_reflection_lod: self.lighting.max_reflection_lod as f32,
+            clustered_lighting: self.lighting.clustered_lighting as i32,
+            screen_space_ao: self.ssao.enabled() as i32,
+            screen_space_reflections: self.ssr.enabled() as i32,
+            volumetric_fog: self.fog.enabled() as i32,
         };
 
         self.fragment_per_frame_ubo
             .fill_mapped(0, &fragment_per_frame_uniforms.as_std140());
+
+        self.environment.renderer.update_uniforms();
     }
 }
 
@@ -662,6 +948,12 @@ impl Scene for PbsScene {
         self.controls.prev_x = self.controls.mouse_x;
         self.controls.prev_y = self.controls.mouse_y;
 
+        let taa_enabled = self
+            .post_stack
+            .get::<TemporalAntiAliasing>()
+            .map_or(false, |taa| taa.enabled());
+        self.camera.set_jitter_enabled(taa_enabled);
+
         self.camera.update(
             window.inner_size(),
             dx,
@@ -672,44 +964,224 @@ impl Scene for PbsScene {
 
         self.controls.scroll = 0.0;
 
+        self.environment.renderer.update(timer.delta_time());
+
         Transition::None
     }
 
-    fn pre_draw(&mut self, _: Context) {
-        self.update_uniform_buffers()
+    fn pre_draw(&mut self, context: Context) {
+        let Context { window, .. } = context;
+
+        self.update_lights(window.inner_size().height);
+        self.update_uniform_buffers();
+
+        if self.lighting.clustered_lighting {
+            let size = window.inner_size();
+            self.clusters
+                .update(&self.camera, UVec2::new(size.width, size.height));
+        }
+
+        if self.lighting.shadow_filter != ShadowFilter::Off {
+            let direction = self.directional_light().direction;
+            self.shadows.update(&self.camera, &direction);
+        }
     }
 
     fn draw(&mut self, context: Context) {
-        let Context {
-            window,
-            device,
-            asset_manager,
-            timer,
-            framebuffer_cache,
-            settings,
-        } = context;
+        // The scene framebuffers are owned by the scene, the graph only orders the passes using
+        // them and culls the ones whose results are not needed.
+        let mut graph = RenderGraph::<PbsScene>::new();
+        let shadow_maps = graph.import("Shadow Maps");
+        let gbuffer = graph.import("G-Buffer");
+        let ao_map = graph.import("AO Map");
+        let reflection_map = graph.import("Reflection Map");
+        let fog_volume = graph.import("Fog Volume");
+        let ssr_history = graph.import("SSR History");
+        let msaa_color = graph.import("MSAA Color");
+        let lit_color = graph.import("Lit Color");
+        let backbuffer = graph.import("Backbuffer");
+
+        let shadow_maps = if self.lighting.shadow_filter != ShadowFilter::Off {
+            graph.add_pass(
+                "Shadows",
+                |pass| {
+                    pass.write(shadow_maps, WriteAccess::RenderTarget);
+                },
+                |scene, _, _| scene.shadow_pass(),
+            )[0]
+        } else {
+            shadow_maps
+        };
+
+        let fog_volume = if self.fog.enabled() {
+            graph.add_pass(
+                "Volumetric Fog",
+                |pass| {
+                    pass.read(shadow_maps).write(fog_volume, WriteAccess::Image);
+                },
+                |scene, _, context| {
+                    let Context { timer, .. } = context;
+                    scene.volumetric_fog_pass(timer.elapsed_time())
+                },
+            )[0]
+        } else {
+            fog_volume
+        };
+
+        // The forward path renders the G-buffer as a prepass for the screen space effects, it is
+        // culled when nothing reads it.
+        let gbuffer = graph.add_pass(
+            "G-Buffer",
+            |pass| {
+                pass.write(gbuffer, WriteAccess::RenderTarget);
+            },
+            |scene, _, _| scene.deferred_geometry_pass(),
+        )[0];
+
+        let ao_map = if self.ssao.enabled() {
+            graph.add_pass(
+                "SSAO",
+                |pass| {
+                    pass.read(gbuffer).write(ao_map, WriteAccess::RenderTarget);
+                },
+                |scene, _, _| scene.ssao.apply(scene.deferred.gbuffer(), &scene.camera),
+            )[0]
+        } else {
+            ao_map
+        };
 
-        self.geometry_pass();
-        self.skybox_pass();
-        self.msaa_resolve();
+        let reflection_map = if self.ssr.enabled() {
+            graph.add_pass(
+                "SSR",
+                |pass| {
+                    pass.read(gbuffer)
+                        .read(ssr_history)
+                        .write(reflection_map, WriteAccess::RenderTarget);
+                },
+                |scene, _, _| scene.ssr.apply(scene.deferred.gbuffer(), &scene.camera),
+            )[0]
+        } else {
+            reflection_map
+        };
+
+        let lit_color = if self.deferred_shading {
+            graph.add_pass(
+                "Deferred Lighting",
+                |pass| {
+                    pass.read(gbuffer)
+                        .read(shadow_maps)
+                        .read(ao_map)
+                        .read(reflection_map)
+                        .read(fog_volume)
+                        .write(lit_color, WriteAccess::RenderTarget);
+                },
+                |scene, _, _| scene.deferred_lighting_pass(),
+            )[0]
+        } else {
+            let msaa_color = graph.add_pass(
+                "Forward",
+                |pass| {
+                    pass.read(shadow_maps)
+                        .read(ao_map)
+                        .read(reflection_map)
+                        .read(fog_volume)
+                        .write(msaa_color, WriteAccess::RenderTarget);
+                },
+                |scene, _, _| scene.geometry_pass(),
+            )[0];
+
+            let msaa_color = graph.add_pass(
+                "Skybox",
+                |pass| {
+                    pass.write(msaa_color, WriteAccess::RenderTarget);
+                },
+                |scene, _, _| scene.skybox_pass(),
+            )[0];
+
+            graph.add_pass(
+                "MSAA Resolve",
+                |pass| {
+                    pass.read(msaa_color)
+                        .write(lit_color, WriteAccess::RenderTarget);
+                },
+                |scene, _, _| scene.msaa_resolve(),
+            )[0]
+        };
 
-        if let Some(tone_mapper) = self.post_stack.get_mut::<ToneMapper>() {
-            tone_mapper.set_exposure(self.camera.exposure())
+        // Kept for the next frame, runs after the SSR pass read the previous history.
+        if self.ssr.enabled() {
+            graph.add_pass(
+                "SSR History",
+                |pass| {
+                    pass.read(lit_color)
+                        .write(ssr_history, WriteAccess::RenderTarget)
+                        .side_effect();
+                },
+                |scene, _, _| {
+                    scene
+                        .ssr
+                        .update_history(&scene.resolve_framebuffer, &scene.camera)
+                },
+            );
         }
 
-        self.post_stack.apply(
-            &self.resolve_framebuffer,
-            Context::new(
-                window,
-                device,
-                asset_manager,
-                timer,
-                framebuffer_cache,
-                settings,
-            ),
-        );
+        let motion_blur_enabled = self
+            .post_stack
+            .get::<MotionBlur>()
+            .map_or(false, |motion_blur| motion_blur.enabled());
+        let gbuffer_pass = self.deferred_shading
+            || self.ssao.enabled()
+            || self.ssr.enabled()
+            || motion_blur_enabled;
+        let backbuffer = graph.add_pass(
+            "Postprocessing",
+            |pass| {
+                pass.read(lit_color);
+                if gbuffer_pass {
+                    pass.read(gbuffer);
+                }
+                pass.write(backbuffer, WriteAccess::RenderTarget);
+            },
+            move |scene, _, context| {
+                scene.gpu_timer.end();
+
+                if let Some(tone_mapper) = scene.post_stack.get_mut::<ToneMapper>() {
+                    tone_mapper.set_exposure(scene.camera.exposure())
+                }
+
+                if let Some(dof) = scene.post_stack.get_mut::<DepthOfField>() {
+                    dof.set_lens(scene.camera.lens())
+                }
+
+                if let Some(lens) = scene.post_stack.get_mut::<Lens>() {
+                    lens.set_field_of_view(scene.camera.fov_deg() as f32)
+                }
+
+                let mut post_input =
+                    PostprocessingInput::new(&scene.resolve_framebuffer).with_camera(&scene.camera);
+                if gbuffer_pass {
+                    let gbuffer = scene.deferred.gbuffer();
+                    post_input = post_input
+                        .with_normals(gbuffer.texture_id(GBufferAttachment::Normal))
+                        .with_velocity(gbuffer.texture_id(GBufferAttachment::Velocity));
+                }
+
+                scene.post_stack.apply(
+                    &post_input,
+                    PostprocessingTarget::DefaultFramebuffer,
+                    context,
+                );
+
+                scene.resolve_framebuffer.unbind(true);
+            },
+        )[0];
+        graph.mark_output(backbuffer);
+
+        let mut graph = graph.compile();
+        self.render_graph = graph.summary();
 
-        self.resolve_framebuffer.unbind(true);
+        self.gpu_timer.begin();
+        graph.execute(self, context);
     }
 
     fn gui(&mut self, context: Context, ui: &Ui) {
@@ -749,9 +1221,23 @@ impl Scene for PbsScene {
                         "Fresnel * Radiance",
                         "Analytical Lights Only",
                         "IBL only",
+                        "Light Clusters Heatmap",
                     ],
                 );
 
+                ui.checkbox("Deferred Shading", &mut self.deferred_shading);
+                ui.text(format!(
+                    "Scene GPU time: {:.2} ms ({})",
+                    self.gpu_timer.elapsed_ms(),
+                    if self.deferred_shading { "deferred" } else { "forward" }
+                ));
+
+                if self.deferred_shading {
+                    self.deferred.gui(ui);
+                }
+
+                self.render_graph.gui(ui);
+
                 ui.spacing();
 
                 // Material
@@ -773,6 +1259,15 @@ impl Scene for PbsScene {
                         .open_on_double_click(true)
                         .framed(false)
                         .build(ui, || {
+                            ui.checkbox(
+                                "Clustered Lighting",
+                                &mut self.lighting.clustered_lighting,
+                            );
+
+                            if self.lighting.clustered_lighting {
+                                self.clusters.gui(ui);
+                            }
+
                             imgui::TreeNode::new("Directional Light")
                                 .default_open(true)
                                 .open_on_arrow(true)
@@ -795,9 +1290,104 @@ impl Scene for PbsScene {
                                     .alpha(false)
                                     .build(ui);
 
-                                    imgui::Slider::new("Light Intensity", 0.01, 300.0)
+                                    imgui::Slider::new("Illuminance (lux)", 0.01, 300.0)
                                         .display_format("%.1f")
                                         .build(ui, &mut self.lighting.light_intensity);
+
+                                    let mut shadow_filter = self.lighting.shadow_filter as usize;
+                                    if ui.combo_simple_string(
+                                        "Shadows",
+                                        &mut shadow_filter,
+                                        &["Off", "Hard", "PCF"],
+                                    ) {
+                                        self.lighting.shadow_filter = [
+                                            ShadowFilter::Off,
+                                            ShadowFilter::Hard,
+                                            ShadowFilter::Pcf,
+                                        ][shadow_filter];
+                                        for shader in self.lit_shaders().iter() {
+                                            self.lighting.shadow_filter.apply_keywords(shader);
+                                        }
+                                    }
+
+                                    if self.lighting.shadow_filter != ShadowFilter::Off {
+                                        self.shadows.gui(ui);
+                                    }
+                                });
+
+                            imgui::TreeNode::new("Point Lights")
+                                .default_open(false)
+                                .open_on_arrow(true)
+                                .open_on_double_click(true)
+                                .framed(false)
+                                .build(ui, || {
+                                    let ring = &mut self.lighting.point_lights;
+                                    imgui::Slider::new("Count", 0, 512).build(ui, &mut ring.count);
+                                    imgui::Slider::new("Radius", 1.0, 100.0)
+                                        .display_format("%.1f")
+                                        .build(ui, &mut ring.radius);
+                                    imgui::Slider::new("Height", -50.0, 50.0)
+                                        .display_format("%.1f")
+                                        .build(ui, &mut ring.height);
+                                    imgui::Slider::new("Range", 1.0, 200.0)
+                                        .display_format("%.1f")
+                                        .build(ui, &mut ring.range);
+                                    imgui::Slider::new("Luminous Power (lm)", 0.0, 50000.0)
+                                        .display_format("%.0f")
+                                        .build(ui, &mut ring.luminous_power);
+                                    ui.checkbox("Cast Shadows", &mut ring.cast_shadows);
+
+                                    if ring.cast_shadows
+                                        && self.lighting.shadow_filter != ShadowFilter::Off
+                                    {
+                                        self.point_shadows.gui(ui);
+                                    }
+                                });
+
+                            ui.checkbox("##spotlight", &mut self.lighting.spot_light_enabled);
+                            ui.same_line_with_pos(72.0);
+                            imgui::TreeNode::new("Spot Light")
+                                .default_open(false)
+                                .open_on_arrow(true)
+                                .open_on_double_click(true)
+                                .framed(false)
+                                .build(ui, || {
+                                    let spot_light = &mut self.lighting.spot_light;
+
+                                    let mut position: [f32; 3] = spot_light.position.into();
+                                    if imgui::Drag::new("Position")
+                                        .display_format("%.1f")
+                                        .speed(0.1)
+                                        .build_array(ui, &mut position)
+                                    {
+                                        spot_light.position = position.into();
+                                    }
+
+                                    let mut direction: [f32; 3] = spot_light.direction.into();
+                                    if imgui::Drag::new("Direction")
+                                        .range(-1.0, 1.0)
+                                        .display_format("%.2f")
+                                        .speed(0.01)
+                                        .build_array(ui, &mut direction)
+                                    {
+                                        spot_light.direction = direction.into();
+                                    }
+
+                                    imgui::Slider::new("Inner Angle", 0.0, 90.0)
+                                        .display_format("%.1f")
+                                        .build(ui, &mut spot_light.inner_angle);
+                                    imgui::Slider::new("Outer Angle", 0.0, 90.0)
+                                        .display_format("%.1f")
+                                        .build(ui, &mut spot_light.outer_angle);
+                                    imgui::Slider::new("Range", 1.0, 200.0)
+                                        .display_format("%.1f")
+                                        .build(ui, &mut spot_light.range);
+                                    imgui::Slider::new("Luminous Power (lm)", 0.0, 100000.0)
+                                        .display_format("%.0f")
+                                        .build(ui, &mut spot_light.luminous_power);
+                                    imgui::Slider::new("Temperature (K)", 1000.0, 15000.0)
+                                        .display_format("%.0f")
+                                        .build(ui, &mut self.lighting.spot_light_temperature);
                                 });
 
                             imgui::TreeNode::new("BRDF")
@@ -811,13 +1401,14 @@ impl Scene for PbsScene {
                                         &mut self.lighting.brdf_type,
                                         &["Fillament", "Unreal Engine 4"],
                                     ) {
-                                        let shader = self.material.shader();
-                                        if self.lighting.brdf_type == 0 {
-                                            shader.disable_keyword("FEATURE_BRDF_UE4");
-                                            shader.enable_keyword("FEATURE_BRDF_FILLAMENT");
-                                        } else {
-                                            shader.disable_keyword("FEATURE_BRDF_FILLAMENT");
-                                            shader.enable_keyword("FEATURE_BRDF_UE4");
+                                        for shader in self.lit_shaders().iter() {
+                                            if self.lighting.brdf_type == 0 {
+                                                shader.disable_keyword("FEATURE_BRDF_UE4");
+                                                shader.enable_keyword("FEATURE_BRDF_FILLAMENT");
+                                            } else {
+                                                shader.disable_keyword("FEATURE_BRDF_FILLAMENT");
+                                                shader.enable_keyword("FEATURE_BRDF_UE4");
+                                            }
                                         }
                                     }
 
@@ -865,24 +1456,105 @@ impl Scene for PbsScene {
                         .build(ui, || {
                             ui.checkbox("Specular AO", &mut self.lighting.specular_ao);
 
-                            ui.combo_simple_string(
+                            let mut ssao = self.ssao.enabled();
+                            if ui.checkbox("Screen Space AO", &mut ssao) {
+                                if ssao {
+                                    self.ssao.enable();
+                                } else {
+                                    self.ssao.disable();
+                                }
+                            }
+
+                            if ssao {
+                                self.ssao.gui(ui);
+                            }
+
+                            let mut ssr = self.ssr.enabled();
+                            if ui.checkbox("Screen Space Reflections", &mut ssr) {
+                                if ssr {
+                                    self.ssr.enable();
+                                } else {
+                                    self.ssr.disable();
+                                }
+                            }
+
+                            if ssr {
+                                self.ssr.gui(ui);
+                            }
+
+                            if ui.checkbox(
+                                "SH Irradiance",
+                                &mut self.lighting.sh_irradiance,
+                            ) {
+                                for shader in self.lit_shaders().iter() {
+                                    if self.lighting.sh_irradiance {
+                                        shader.enable_keyword("FEATURE_SH_IRRADIANCE");
+                                    } else {
+                                        shader.disable_keyword("FEATURE_SH_IRRADIANCE");
+                                    }
+                                }
+                            }
+
+                            if ui.combo_simple_string(
                                 "Environment",
                                 &mut self.environment.active_environment,
-                                &["Exterior", "Interior"],
-                            );
+                                &["Exterior", "Interior", "Pillars (Baked)"],
+                            ) {
+                                self.environment.renderer.transition_to(
+                                    Rc::clone(
+                                        &self.environment.environments
+                                            [self.environment.active_environment],
+                                    ),
+                                    self.environment.transition_duration,
+                                );
+                            }
+
+                            imgui::Slider::new("Transition Duration", 0.0, 5.0)
+                                .display_format("%.1f s")
+                                .build(ui, &mut self.environment.transition_duration);
+
+                            self.environment.renderer.gui(ui);
+
+                            if ui.small_button("Regenerate BRDF LUT") {
+                                let lut = self
+                                    .environment
+                                    .baker
+                                    .bake_brdf_lut(BRDF_LUT_SIZE, BRDF_LUT_SAMPLE_COUNT);
+
+                                let path = &self.environment.brdf_lut_path;
+                                let saved = fs::create_dir_all(path.parent().unwrap())
+                                    .map_err(|e| e.to_string())
+                                    .and_then(|_| IblBaker::save_brdf_lut(&lut, path));
+
+                                self.environment.brdf_lut_status = Some(match saved {
+                                    Ok(_) => format!("Saved to {}", path.display()),
+                                    Err(error) => format!("Failed to save: {}", error),
+                                });
+                            }
 
-                            let skybox_type_ref = unsafe {
-                                &mut *(&mut self.environment.skybox_type as *mut SkyboxType
-                                    as *mut usize)
-                            };
-                            ui.combo_simple_string(
-                                "Skybox",
-                                skybox_type_ref,
-                                &["Original", "Radiance", "Irradiance"],
-                            );
+                            if let Some(status) = &self.environment.brdf_lut_status {
+                                ui.text_disabled(status);
+                            }
+                        });
 
-                            imgui::Slider::new("Max reflection LOD", 1, 9)
-                                .build(ui, &mut self.lighting.max_reflection_lod);
+                    imgui::TreeNode::new("Participating Media")
+                        .default_open(false)
+                        .open_on_arrow(true)
+                        .open_on_double_click(true)
+                        .framed(false)
+                        .build(ui, || {
+                            let mut fog = self.fog.enabled();
+                            if ui.checkbox("Volumetric Fog", &mut fog) {
+                                if fog {
+                                    self.fog.enable();
+                                } else {
+                                    self.fog.disable();
+                                }
+                            }
+
+                            if fog {
+                                self.fog.gui(ui);
+                            }
                         });
                 }
 
@@ -5,7 +5,8 @@ use glutin::event::{
     ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
 };
 
-use engine::rendering::shader::ShaderCreateInfo;
+use engine::rendering::environment::{Environment, EnvironmentRenderer, SkyboxSource};
+use engine::rendering::light::{DirectionalLight, Light, LightBuffer};
 use engine::{
     camera::Camera,
     color::srgb_to_linear3f,
@@ -26,12 +27,11 @@ use engine::{
         mesh::utilities::generate_cube,
         mesh::Mesh,
         postprocess::{
-            bloom::Bloom, tone_mapper::ToneMapper, PostprocessingStack, PostprocessingStackBuilder,
+            bloom::Bloom, tone_mapper::ToneMapper, PostprocessingInput, PostprocessingStack,
+            PostprocessingStackBuilder, PostprocessingTarget,
         },
-        sampler::{Anisotropy, MagnificationFilter, MinificationFilter, Sampler, WrappingMode},
-        shader::{Shader, ShaderStage},
-        state::{DepthFunction, FaceCulling, StateManager},
-        texture::{SizedTextureFormat, TextureCube},
+        state::StateManager,
+        texture::SizedTextureFormat,
         Draw,
     },
     scene::Scene,
@@ -39,42 +39,30 @@ use engine::{
     Context, Msaa,
 };
 
-struct EnvironmentMaps {
-    skybox: TextureCube,
-    irradiance: TextureCube,
-    radiance: TextureCube,
-}
-
-#[repr(usize)]
-enum SkyboxType {
-    Original,
-    Radiance,
-    Irradiance,
-}
-
-struct Environment {
-    maps: [EnvironmentMaps; 2],
-    skybox_program_pipeline: Rc<Shader>,
-    skybox_mesh: Rc<Mesh>,
+struct Environments {
+    environments: [Rc<Environment>; 2],
+    renderer: EnvironmentRenderer,
     active_environment: usize,
-    skybox_type: SkyboxType,
+    transition_duration: f32,
 }
 
 struct Lighting {
     light_direction: [f32; 3],
     light_color: [f32; 3],
     light_intensity: f32,
+    light_buffer: LightBuffer,
     geometric_specular_aa: bool,
     specular_ao: bool,
     brdf_type: usize,
     multi_scattering: bool,
     ss_variance_and_threshold: Vec2,
-    max_reflection_lod: i32,
 }
 
 struct Model {
     pub mesh: Rc<Mesh>,
     pub transform: Mat4,
+    /// Transform of the previous frame, for the velocity of the G-buffer.
+    pub previous_transform: Mat4,
 }
 
 #[derive(Default)]
@@ -94,20 +82,21 @@ struct Controls {
 struct VertexPerDrawUniforms {
     model_matrix: mint::ColumnMatrix4<f32>,
     normal_matrix: mint::ColumnMatrix4<f32>,
+    previous_model_matrix: mint::ColumnMatrix4<f32>,
 }
 
 #[repr(C)]
 #[derive(Debug, AsStd140)]
 struct FragmentPerFrameUniforms {
-    light_direction: mint::Vector4<f32>,
-    light_color: mint::Vector4<f32>,
     ss_variance_and_threshold: mint::Vector2<f32>,
     geometric_specular_aa: i32,
     specular_ao: i32,
     render_mode: i32,
-    brdf_type: i32,
     multi_scattering: i32,
-    max_reflection_lod: f32,
+    clustered_lighting: i32,
+    screen_space_ao: i32,
+    screen_space_reflections: i32,
+    volumetric_fog: i32,
 }
 
 // TODO: Use this to group framebuffers
@@ -120,10 +109,9 @@ pub struct PomScene {
     camera: Camera,
     model: Model,
     material: PbsMetallicRoughnessMaterial,
-    environment: Environment,
+    environment: Environments,
     msaa_framebuffers: [Framebuffer; 4],
     resolve_framebuffer: Framebuffer,
-    sampler_linear: Sampler,
     post_stack: PostprocessingStack,
     controls: Controls,
     lighting: Lighting,
@@ -159,21 +147,8 @@ impl PomScene {
             .max_distance(4.0)
             .build();
 
-        let shader_manager = device.shader_manager();
-        let skybox_prog = shader_manager.create_shader(
-            &ShaderCreateInfo::builder("SkyboxShader")
-                .stage(ShaderStage::Vertex, asset_path.join("shaders/skybox.vert"))
-                .stage(
-                    ShaderStage::Fragment,
-                    asset_path.join("shaders/skybox.frag"),
-                )
-                .build(),
-        );
-
         let mesh = Rc::new(generate_cube(1.0));
 
-        let skybox_mesh = Rc::clone(&mesh);
-
         let albedo = asset_manager
             .load_texture_2d(
                 asset_path.join("textures/pbs/castle_brick/castle_brick_albedo.png"),
@@ -206,45 +181,23 @@ impl PomScene {
             )
             .expect("Failed to load displacement texture");
 
-        let skybox_exterior =
-            TextureCube::new_from_file(asset_path.join("textures/pbs/ktx/skybox/skybox2.ktx"))
-                .expect("Failed to load Skybox");
-
-        let irradiance_exterior = TextureCube::new_from_file(
-            asset_path.join("textures/pbs/ktx/irradiance/irradiance2.ktx"),
-        )
-        .expect("Failed to load Irradiance map");
-
-        let radiance_exterior =
-            TextureCube::new_from_file(asset_path.join("textures/pbs/ktx/radiance/radiance2.ktx"))
-                .expect("Failed to load Radiance map");
-
-        let skybox_interior =
-            TextureCube::new_from_file(asset_path.join("textures/pbs/ktx/skybox/ibl_skybox.ktx"))
-                .expect("Failed to load Skybox");
-
-        let irradiance_interior = TextureCube::new_from_file(
-            asset_path.join("textures/pbs/ktx/irradiance/ibl_irradiance.ktx"),
-        )
-        .expect("Failed to load Irradiance map");
-
-        let radiance_interior = TextureCube::new_from_file(
-            asset_path.join("textures/pbs/ktx/radiance/ibl_radiance.ktx"),
-        )
-        .expect("Failed to load Radiance map");
-
-        let environments = [
-            EnvironmentMaps {
-                skybox: skybox_exterior,
-                irradiance: irradiance_exterior,
-                radiance: radiance_exterior,
-            },
-            EnvironmentMaps {
-                skybox: skybox_interior,
-                irradiance: irradiance_interior,
-                radiance: radiance_interior,
-            },
-        ];
+        let exterior = asset_manager
+            .load_environment(
+                "Exterior",
+                asset_path.join("textures/pbs/ktx/skybox/skybox2.ktx"),
+                asset_path.join("textures/pbs/ktx/irradiance/irradiance2.ktx"),
+                asset_path.join("textures/pbs/ktx/radiance/radiance2.ktx"),
+            )
+            .expect("Failed to load Exterior environment");
+
+        let interior = asset_manager
+            .load_environment(
+                "Interior",
+                asset_path.join("textures/pbs/ktx/skybox/ibl_skybox.ktx"),
+                asset_path.join("textures/pbs/ktx/irradiance/ibl_irradiance.ktx"),
+                asset_path.join("textures/pbs/ktx/radiance/ibl_radiance.ktx"),
+            )
+            .expect("Failed to load Interior environment");
 
         let msaa_framebuffers = [
             Framebuffer::new(
@@ -361,15 +314,18 @@ impl PomScene {
             )))
             .build();
 
-        let sampler_mipmap_linear = Sampler::new(
-            MinificationFilter::LinearMipmapLinear,
-            MagnificationFilter::Linear,
-            WrappingMode::ClampToEdge,
-            WrappingMode::ClampToEdge,
-            WrappingMode::ClampToEdge,
-            Vec4::new(0.0, 0.0, 0.0, 0.0),
-            Anisotropy::X16,
+        let mut environment_renderer = EnvironmentRenderer::new(
+            Context::new(
+                window,
+                device,
+                asset_manager,
+                timer,
+                framebuffer_cache,
+                settings,
+            ),
+            Rc::clone(&interior),
         );
+        environment_renderer.set_skybox_source(SkyboxSource::Radiance);
 
         let material = PbsMetallicRoughnessMaterial::new(
             Context::new(
@@ -410,18 +366,17 @@ impl PomScene {
             model: Model {
                 mesh,
                 transform: Mat4::identity(),
+                previous_transform: Mat4::identity(),
             },
             material,
-            environment: Environment {
-                maps: environments,
-                skybox_program_pipeline: skybox_prog,
-                skybox_mesh,
+            environment: Environments {
+                environments: [exterior, interior],
+                renderer: environment_renderer,
                 active_environment: 1,
-                skybox_type: SkyboxType::Radiance,
+                transition_duration: 1.0,
             },
             msaa_framebuffers,
             resolve_framebuffer,
-            sampler_linear: sampler_mipmap_linear,
             post_stack,
             controls: Controls {
                 mouse_sensitivity: 2.0,
@@ -431,12 +386,12 @@ impl PomScene {
                 light_direction: [0.4, 0.0, -1.0],
                 light_color: [1.0, 1.0, 1.0],
                 light_intensity: 5.0,
+                light_buffer: LightBuffer::default(),
                 geometric_specular_aa: true,
                 specular_ao: true,
                 brdf_type: 0,
                 multi_scattering: true,
                 ss_variance_and_threshold: Vec2::new(0.25, 0.18),
-                max_reflection_lod: 5,
             },
             render_mode: 0,
             vertex_per_draw_ubo,
@@ -452,21 +407,9 @@ impl PomScene {
 
         self.material.bind();
 
-        let shader = self.material.shader();
-
-        const IRRADIANCE_MAP_BINDING_INDEX: u32 = 4;
-        const RADIANCE_MAP_BINDING_INDEX: u32 = 5;
-        shader
-            .bind_texture_cube(
-                IRRADIANCE_MAP_BINDING_INDEX,
-                &self.environment.maps[self.environment.active_environment].irradiance,
-                &self.sampler_linear,
-            )
-            .bind_texture_cube(
-                RADIANCE_MAP_BINDING_INDEX,
-                &self.environment.maps[self.environment.active_environment].radiance,
-                &self.sampler_linear,
-            );
+        self.environment
+            .renderer
+            .bind_ibl_maps(&self.material.shader());
 
         self.model.mesh.draw();
 
@@ -476,39 +419,11 @@ impl PomScene {
     }
 
     fn skybox_pass(&self) {
-        StateManager::depth_function(DepthFunction::LessOrEqual);
-        StateManager::face_culling(FaceCulling::Front);
-
         let framebuffer = &self.msaa_framebuffers[self.msaa_framebuffer_index];
 
         framebuffer.bind();
 
-        self.environment.skybox_program_pipeline.bind();
-
-        let environment_map = match self.environment.skybox_type {
-            SkyboxType::Original => {
-                &self.environment.maps[self.environment.active_environment].skybox
-            }
-            SkyboxType::Radiance => {
-                &self.environment.maps[self.environment.active_environment].radiance
-            }
-            SkyboxType::Irradiance => {
-                &self.environment.maps[self.environment.active_environment].irradiance
-            }
-        };
-
-        self.environment.skybox_program_pipeline.bind_texture_cube(
-            0,
-            &environment_map,
-            &self.sampler_linear,
-        );
-
-        self.environment.skybox_mesh.draw();
-
-        self.environment.skybox_program_pipeline.unbind();
-
-        StateManager::depth_function(DepthFunction::Less);
-        StateManager::face_culling(FaceCulling::Back)
+        self.environment.renderer.draw_skybox();
     }
 
     fn msaa_resolve(&self) {
@@ -523,38 +438,51 @@ impl PomScene {
         framebuffer.unbind(true);
     }
 
-    fn update_uniform_buffers(&self) {
+    fn directional_light(&self) -> DirectionalLight {
+        // The inspector edits the direction towards the light.
+        DirectionalLight {
+            direction: -Vec3::from(self.lighting.light_direction),
+            color: srgb_to_linear3f(&self.lighting.light_color.into()),
+            temperature: None,
+            illuminance: self.lighting.light_intensity,
+            cast_shadows: false,
+        }
+    }
+
+    fn update_lights(&mut self) {
+        let lights = [Light::Directional(self.directional_light())];
+
+        self.lighting.light_buffer.update(&lights);
+    }
+
+    fn update_uniform_buffers(&mut self) {
         let vertex_per_draw_uniforms = VertexPerDrawUniforms {
             model_matrix: self.model.transform.into(),
             normal_matrix: transpose(&inverse(&self.model.transform)).into(),
+            previous_model_matrix: self.model.previous_transform.into(),
         };
 
         self.vertex_per_draw_ubo
             .fill_mapped(0, &vertex_per_draw_uniforms.as_std140());
-
-        let mut light_color: Vec3 = srgb_to_linear3f(&self.lighting.light_color.into());
-        light_color *= self.lighting.light_intensity;
+        self.model.previous_transform = self.model.transform;
 
         let fragment_per_frame_uniforms = FragmentPerFrameUniforms {
-            light_direction: Vec4::new(
-                self.lighting.light_direction[0],
-                self.lighting.light_direction[1],
-                self.lighting.light_direction[2],
-                1.0,
-            )
-            .into(),
-            light_color: Vec4::new(light_color.x, light_color.y, light_color.z, 0.0).into(),
             ss_variance_and_threshold: self.lighting.ss_variance_and_threshold.clone_owned().into(),
             geometric_specular_aa: self.lighting.geometric_specular_aa as i32,
             specular_ao: self.lighting.specular_ao as i32,
             render_mode: self.render_mode as i32,
-            brdf_type: self.lighting.brdf_type as i32,
             multi_scattering: self.lighting.multi_scattering as i32,
-            max_reflection_lod: self.lighting.max_reflection_lod as f32,
+            // The scene has no light clusters, screen space effects or fog.
+            clustered_lighting: 0,
+            screen_space_ao: 0,
+            screen_space_reflections: 0,
+            volumetric_fog: 0,
         };
 
         self.fragment_per_frame_ubo
             .fill_mapped(0, &fragment_per_frame_uniforms.as_std140());
+
+        self.environment.renderer.update_uniforms();
     }
 }
 
@@ -669,10 +597,13 @@ impl Scene for PomScene {
 
         self.controls.scroll = 0.0;
 
+        self.environment.renderer.update(timer.delta_time());
+
         Transition::None
     }
 
     fn pre_draw(&mut self, _: Context) {
+        self.update_lights();
         self.update_uniform_buffers()
     }
 
@@ -695,7 +626,8 @@ impl Scene for PomScene {
         }
 
         self.post_stack.apply(
-            &self.resolve_framebuffer,
+            &PostprocessingInput::new(&self.resolve_framebuffer),
+            PostprocessingTarget::DefaultFramebuffer,
             Context::new(
                 window,
                 device,
@@ -786,7 +718,7 @@ impl Scene for PomScene {
                                     .alpha(false)
                                     .build(ui);
 
-                                    imgui::Slider::new("Light Intensity", 0.01, 300.0)
+                                    imgui::Slider::new("Illuminance (lux)", 0.01, 300.0)
                                         .display_format("%.1f")
                                         .build(ui, &mut self.lighting.light_intensity);
                                 });
@@ -847,24 +779,25 @@ impl Scene for PomScene {
                         .build(ui, || {
                             ui.checkbox("Specular AO", &mut self.lighting.specular_ao);
 
-                            ui.combo_simple_string(
+                            if ui.combo_simple_string(
                                 "Environment",
                                 &mut self.environment.active_environment,
                                 &["Exterior", "Interior"],
-                            );
-
-                            let skybox_type_ref = unsafe {
-                                &mut *(&mut self.environment.skybox_type as *mut SkyboxType
-                                    as *mut usize)
-                            };
-                            ui.combo_simple_string(
-                                "Skybox",
-                                skybox_type_ref,
-                                &["Original", "Radiance", "Irradiance"],
-                            );
-
-                            imgui::Slider::new("Max reflection LOD", 1, 9)
-                                .build(ui, &mut self.lighting.max_reflection_lod);
+                            ) {
+                                self.environment.renderer.transition_to(
+                                    Rc::clone(
+                                        &self.environment.environments
+                                            [self.environment.active_environment],
+                                    ),
+                                    self.environment.transition_duration,
+                                );
+                            }
+
+                            imgui::Slider::new("Transition Duration", 0.0, 5.0)
+                                .display_format("%.1f s")
+                                .build(ui, &mut self.environment.transition_duration);
+
+                            self.environment.renderer.gui(ui);
                         });
                 }
 
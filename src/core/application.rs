@@ -7,10 +7,10 @@ use glutin::{
     event::{Event, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
     window::{Fullscreen, Window, WindowBuilder},
-    Api, ContextBuilder, ContextWrapper, GlProfile, GlRequest, PossiblyCurrent,
+    Api, ContextBuilder, ContextWrapper, GlProfile, GlRequest, NotCurrent, PossiblyCurrent,
 };
 
-use crate::rendering::device::Device;
+use crate::rendering::device::{Device, DisplayOutput};
 use crate::{
     core::{
         asset::AssetManager,
@@ -220,30 +220,45 @@ impl Application {
             window_builder = window_builder.with_fullscreen(Some(Fullscreen::Exclusive(video_mode)))
         }
 
-        let windowed_context = ContextBuilder::new()
-            .with_double_buffer(Some(true))
-            .with_gl_profile(GlProfile::Core)
-            //.with_gl_robustness(Robustness::RobustNoResetNotification)
-            .with_multisampling(0)
-            .with_vsync(settings.vsync)
-            .with_gl(GlRequest::Specific(
-                Api::OpenGl,
-                (
-                    settings.graphics_api_version.major as u8,
-                    settings.graphics_api_version.minor as u8,
-                ),
-            ))
-            .build_windowed(window_builder, &event_loop)?;
+        let context_builder = Self::context_builder(settings);
+
+        let windowed_context = if settings.hdr_output {
+            // Prefer a half float default framebuffer (scRGB), then a 10 bit one (HDR10).
+            context_builder
+                .clone()
+                .with_float_color_buffer(true)
+                .with_pixel_format(48, 16)
+                .with_srgb(false)
+                .build_windowed(window_builder.clone(), &event_loop)
+                .or_else(|_| {
+                    context_builder
+                        .clone()
+                        .with_pixel_format(30, 2)
+                        .with_srgb(false)
+                        .build_windowed(window_builder.clone(), &event_loop)
+                })
+                .or_else(|e| {
+                    eprintln!("HDR output is not available, falling back to SDR: {}", e);
+                    context_builder.build_windowed(window_builder, &event_loop)
+                })?
+        } else {
+            context_builder.build_windowed(window_builder, &event_loop)?
+        };
 
         let windowed_context = unsafe { windowed_context.make_current().unwrap() };
 
         gl::load_with(|s| windowed_context.get_proc_address(s) as *const _);
 
+        let display_output = DisplayOutput::query();
+
         unsafe {
             gl::Enable(gl::DEPTH_TEST);
             gl::Enable(gl::CULL_FACE);
             gl::Enable(gl::MULTISAMPLE);
-            gl::Enable(gl::FRAMEBUFFER_SRGB);
+            // HDR outputs are encoded by the tone mapper.
+            if display_output == DisplayOutput::Sdr {
+                gl::Enable(gl::FRAMEBUFFER_SRGB);
+            }
             gl::Enable(gl::TEXTURE_CUBE_MAP_SEAMLESS);
 
             gl::Viewport(
@@ -263,6 +278,22 @@ impl Application {
         Ok((event_loop, windowed_context))
     }
 
+    fn context_builder(settings: &Settings) -> ContextBuilder<'static, NotCurrent> {
+        ContextBuilder::new()
+            .with_double_buffer(Some(true))
+            .with_gl_profile(GlProfile::Core)
+            //.with_gl_robustness(Robustness::RobustNoResetNotification)
+            .with_multisampling(0)
+            .with_vsync(settings.vsync)
+            .with_gl(GlRequest::Specific(
+                Api::OpenGl,
+                (
+                    settings.graphics_api_version.major as u8,
+                    settings.graphics_api_version.minor as u8,
+                ),
+            ))
+    }
+
     extern "system" fn debug_callback(
         _source: GLenum,
         message_type: GLenum,
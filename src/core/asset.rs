@@ -1,3 +1,4 @@
+use crate::rendering::environment::Environment;
 use crate::rendering::mesh::Mesh;
 use crate::rendering::texture::{Texture2D, Texture2DLoadConfig, TextureCube};
 use std::collections::HashMap;
@@ -21,6 +22,7 @@ pub struct AssetManager {
     textures: HashMap<String, Rc<Texture2D>>,
     cube_maps: HashMap<String, Rc<TextureCube>>,
     meshes: HashMap<String, Rc<Mesh>>,
+    environments: HashMap<String, Rc<Environment>>,
 }
 
 impl AssetManager {
@@ -83,6 +85,27 @@ impl AssetManager {
         }
     }
 
+    pub fn load_environment<P: AsRef<Path>>(
+        &mut self,
+        name: &str,
+        skybox_path: P,
+        irradiance_path: P,
+        radiance_path: P,
+    ) -> Result<Rc<Environment>, String> {
+        let environment = Rc::new(Environment::new(
+            name,
+            self.load_texture_cube(skybox_path)?,
+            self.load_texture_cube(irradiance_path)?,
+            self.load_texture_cube(radiance_path)?,
+        ));
+
+        self.environments
+            .entry(String::from(name))
+            .or_insert_with(|| Rc::clone(&environment));
+
+        Ok(environment)
+    }
+
     pub fn get_texture_2d(&self, name: &str) -> Option<Rc<Texture2D>> {
         if let Some(rc_tex) = self.textures.get(name) {
             return Some(Rc::clone(rc_tex));
@@ -99,6 +122,14 @@ impl AssetManager {
         None
     }
 
+    pub fn get_environment(&self, name: &str) -> Option<Rc<Environment>> {
+        if let Some(rc_environment) = self.environments.get(name) {
+            return Some(Rc::clone(rc_environment));
+        }
+
+        None
+    }
+
     pub fn get_mesh(&self, name: &str) -> Option<Rc<Mesh>> {
         if let Some(rc_mesh) = self.meshes.get(name) {
             return Some(Rc::clone(&rc_mesh));
@@ -2,7 +2,7 @@ use glutin::dpi::PhysicalSize;
 use nalgebra_glm::{normalize, quat_normalize};
 use crevice::std140::AsStd140;
 
-use crate::core::math::{clamp_scalar, rotate_vec3, Vec4};
+use crate::core::math::{clamp_scalar, radical_inverse, rotate_vec3, Vec2, Vec4};
 use crate::core::{math, math::matrix, math::Axes, math::Mat4, math::Quat, math::Vec3};
 use crate::imgui::{Gui, Ui};
 use crate::math::{perspective, quaternion};
@@ -16,22 +16,80 @@ struct CameraUniformBlock {
     view_projection_matrix: mint::ColumnMatrix4<f32>,
     eye_position: mint::Vector4<f32>,
     projection_params: mint::Vector4<f32>,
-    dof_params: mint::Vector3<f32>,
+    inverse_view_projection: mint::ColumnMatrix4<f32>,
+    previous_view_projection: mint::ColumnMatrix4<f32>,
+    jitter: mint::Vector4<f32>,
+}
+
+/// Number of projection jitter offsets cycled through before the sequence repeats.
+pub const JITTER_SEQUENCE_LENGTH: u32 = 8;
+
+/// Sub-pixel offset in pixels, in [-0.5, 0.5), of the `index`th projection jitter. Follows the
+/// Halton (2, 3) sequence, skipping its first element which is always 0.
+pub fn halton_jitter(index: u32) -> Vec2 {
+    Vec2::new(
+        radical_inverse(index + 1, 2) - 0.5,
+        radical_inverse(index + 1, 3) - 0.5,
+    )
+}
+
+/// Thin lens model of a `Camera`. Distances are in meters, one meter being one world unit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThinLens {
+    pub focal_length: f32,
+    /// Aperture as an f-number.
+    pub aperture: f32,
+    pub focus_distance: f32,
+    pub sensor_height: f32,
+}
+
+impl ThinLens {
+    pub fn aperture_diameter(&self) -> f32 {
+        self.focal_length / self.aperture
+    }
+
+    /// Signed diameter, on the sensor, of the circle of confusion of a point at `distance` along
+    /// the view direction. Negative in front of the focus distance, in the near field, and
+    /// positive behind it, in the far field. The same as the CoC pass of dof.frag.
+    pub fn circle_of_confusion(&self, distance: f32) -> f32 {
+        // The lens cannot focus closer than its focal length.
+        let focus_distance = self.focus_distance.max(self.focal_length * 1.001);
+
+        self.aperture_diameter() * self.focal_length * (distance - focus_distance)
+            / (distance * (focus_distance - self.focal_length))
+    }
+
+    /// Signed radius of the circle of confusion in pixels of an image `image_height` pixels tall.
+    pub fn circle_of_confusion_radius_pixels(&self, distance: f32, image_height: u32) -> f32 {
+        0.5 * self.circle_of_confusion(distance) * image_height as f32 / self.sensor_height
+    }
+
+    /// Closest focus distance for which everything up to infinity has a circle of confusion
+    /// smaller than `max_circle_of_confusion`, in meters on the sensor.
+    pub fn hyperfocal_distance(&self, max_circle_of_confusion: f32) -> f32 {
+        self.focal_length * self.focal_length / (self.aperture * max_circle_of_confusion)
+            + self.focal_length
+    }
 }
 
 pub struct Camera {
     position: Vec3,
     orientation: Quat,
     transform: Mat4,
+    projection: Mat4,
+    unjittered_projection: Mat4,
+    jitter_enabled: bool,
+    jitter_index: u32,
+    jitter: Vec2,
+    aspect_ratio: f32,
     fov_deg: u32,
     near_plane: f32,
     far_plane: f32,
     aperture: f32,
     shutter_speed: f32,
     sensitivity: f32,
+    sensor_height: f32,
     focus_distance: f32,
-    focus_range: f32,
-    bokeh_radius: f32,
     orbit_speed: f32,
     zoom_speed: f32,
     orbit_dampening: f32,
@@ -54,6 +112,86 @@ impl Camera {
         &self.transform
     }
 
+    /// Projection used for rendering, including the sub-pixel jitter when it is enabled.
+    pub fn projection(&self) -> &Mat4 {
+        &self.projection
+    }
+
+    pub fn unjittered_projection(&self) -> &Mat4 {
+        &self.unjittered_projection
+    }
+
+    pub fn jitter_enabled(&self) -> bool {
+        self.jitter_enabled
+    }
+
+    /// Offsets the projection by a different sub-pixel amount every `update`, so that temporal
+    /// anti-aliasing can accumulate several samples per pixel.
+    pub fn set_jitter_enabled(&mut self, enabled: bool) {
+        self.jitter_enabled = enabled
+    }
+
+    /// Current projection jitter in normalized device coordinates.
+    pub fn jitter(&self) -> Vec2 {
+        self.jitter
+    }
+
+    /// Exposure time in seconds.
+    pub fn shutter_speed(&self) -> f32 {
+        self.shutter_speed
+    }
+
+    /// Aperture as an f-number.
+    pub fn aperture(&self) -> f32 {
+        self.aperture
+    }
+
+    /// Height of the sensor in millimeters, 24 for a full frame camera.
+    pub fn sensor_height(&self) -> f32 {
+        self.sensor_height
+    }
+
+    /// Focal length in millimeters giving the vertical field of view on the sensor.
+    pub fn focal_length(&self) -> f32 {
+        0.5 * self.sensor_height / (0.5 * (self.fov_deg as f32).to_radians()).tan()
+    }
+
+    /// Distance in meters to the plane in focus.
+    pub fn focus_distance(&self) -> f32 {
+        self.focus_distance
+    }
+
+    pub fn set_focus_distance(&mut self, focus_distance: f32) {
+        self.focus_distance = focus_distance.max(0.01)
+    }
+
+    pub fn lens(&self) -> ThinLens {
+        ThinLens {
+            focal_length: self.focal_length() * 0.001,
+            aperture: self.aperture,
+            focus_distance: self.focus_distance,
+            sensor_height: self.sensor_height * 0.001,
+        }
+    }
+
+    /// Vertical field of view in degrees.
+    pub fn fov_deg(&self) -> u32 {
+        self.fov_deg
+    }
+
+    pub fn near_plane(&self) -> f32 {
+        self.near_plane
+    }
+
+    pub fn far_plane(&self) -> f32 {
+        self.far_plane
+    }
+
+    /// Width over height of the viewport the camera was last updated with.
+    pub fn aspect_ratio(&self) -> f32 {
+        self.aspect_ratio
+    }
+
     pub fn orbit_speed(&self) -> f32 {
         self.orbit_speed
     }
@@ -107,6 +245,9 @@ impl Camera {
         mouse_scroll: f32,
         dt: f32,
     ) {
+        let previous_view_projection = self.unjittered_projection * self.transform;
+        let previous_jitter = self.jitter;
+
         self.pitch += mouse_dy * self.orbit_speed * dt;
 
         self.yaw += mouse_dx * self.orbit_speed * dt;
@@ -135,7 +276,8 @@ impl Camera {
 
         self.look_at(self.position, Vec3::new(0.0, 0.0, 0.0), Axes::up());
 
-        let projection = perspective(
+        self.aspect_ratio = window_size.width as f32 / window_size.height as f32;
+        self.unjittered_projection = perspective(
             window_size.width,
             window_size.height,
             self.fov_deg,
@@ -143,17 +285,43 @@ impl Camera {
             self.far_plane,
         );
 
+        self.jitter = if self.jitter_enabled {
+            self.jitter_index = (self.jitter_index + 1) % JITTER_SEQUENCE_LENGTH;
+
+            let offset = halton_jitter(self.jitter_index);
+            Vec2::new(
+                2.0 * offset.x / window_size.width.max(1) as f32,
+                2.0 * offset.y / window_size.height.max(1) as f32,
+            )
+        } else {
+            Vec2::new(0.0, 0.0)
+        };
+
+        // Translates the clip space positions, which offsets the NDC by the jitter.
+        self.projection = Mat4::new_translation(&Vec3::new(self.jitter.x, self.jitter.y, 0.0))
+            * self.unjittered_projection;
+
+        let view_projection = self.projection * self.transform;
+
         let block = CameraUniformBlock {
             view: self.transform.into(),
-            projection: projection.into(),
-            view_projection_matrix: (projection * self.transform).into(),
+            projection: self.projection.into(),
+            view_projection_matrix: view_projection.into(),
             eye_position: Vec4::new(self.position.x, self.position.y, self.position.z, 1.0).into(),
             projection_params: [
                 self.near_plane,
                 self.far_plane,
                 self.far_plane / (self.far_plane - self.near_plane),
                 (-self.far_plane * self.near_plane) / (self.far_plane - self.near_plane)].into(),
-            dof_params: [self.focus_distance, self.focus_range, self.bokeh_radius].into(),
+            inverse_view_projection: math::inverse(&view_projection).into(),
+            previous_view_projection: previous_view_projection.into(),
+            jitter: [
+                self.jitter.x,
+                self.jitter.y,
+                previous_jitter.x,
+                previous_jitter.y,
+            ]
+            .into(),
         };
 
         self.uniform_buffer.fill_mapped(0, &block.as_std140())
@@ -177,9 +345,8 @@ pub struct CameraBuilder {
     aperture: f32,
     shutter_speed: f32,
     sensitivity: f32,
+    sensor_height: f32,
     focus_distance: f32,
-    focus_range: f32,
-    bokeh_radius: f32,
     orbit_speed: f32,
     zoom_speed: f32,
     orbit_dampening: f32,
@@ -199,9 +366,8 @@ impl Default for CameraBuilder {
             aperture: 1.4,
             shutter_speed: 0.55,
             sensitivity: 500.0,
+            sensor_height: 24.0,
             focus_distance: 36.68,
-            focus_range: 39.90,
-            bokeh_radius: 3.5,
             orbit_speed: 1.0,
             zoom_speed: 1.0,
             orbit_dampening: 0.0,
@@ -257,6 +423,18 @@ impl CameraBuilder {
         self
     }
 
+    /// Height of the sensor in millimeters.
+    pub fn sensor_height(mut self, sensor_height: f32) -> Self {
+        self.sensor_height = sensor_height;
+        self
+    }
+
+    /// Distance in meters to the plane in focus.
+    pub fn focus_distance(mut self, focus_distance: f32) -> Self {
+        self.focus_distance = focus_distance;
+        self
+    }
+
     pub fn orbit_speed(mut self, orbit_speed: f32) -> Self {
         self.orbit_speed = orbit_speed;
         self
@@ -305,15 +483,20 @@ impl CameraBuilder {
             position: self.position,
             orientation: matrix::to_rotation_quat(&transform),
             transform,
+            projection: Mat4::identity(),
+            unjittered_projection: Mat4::identity(),
+            jitter_enabled: false,
+            jitter_index: 0,
+            jitter: Vec2::new(0.0, 0.0),
+            aspect_ratio: 1.0,
             fov_deg: self.fov_deg,
             near_plane: self.near_plane,
             far_plane: self.far_plane,
             aperture: self.aperture,
             shutter_speed: self.shutter_speed,
             sensitivity: self.sensitivity,
+            sensor_height: self.sensor_height,
             focus_distance: self.focus_distance,
-            focus_range: self.focus_range,
-            bokeh_radius: self.bokeh_radius,
             orbit_speed: self.orbit_speed,
             zoom_speed: self.zoom_speed,
             min_distance: self.min_distance,
@@ -370,28 +553,22 @@ impl Gui for Camera {
                         }
 
                         let mut focus_distance = self.focus_distance;
-                        if imgui::Slider::new("Focus Distance", 0.1, 500.0)
+                        if imgui::Slider::new("Focus Distance (m)", 0.1, 500.0)
                             .display_format("%.2f")
                             .build(ui, &mut focus_distance)
                         {
-                            self.focus_distance = focus_distance;
+                            self.set_focus_distance(focus_distance);
                         }
 
-                        let mut focus_range = self.focus_range;
-                        if imgui::Slider::new("Focus Range", 0.1, 100.0)
-                            .display_format("%.2f")
-                            .build(ui, &mut focus_range)
+                        let mut sensor_height = self.sensor_height;
+                        if imgui::Slider::new("Sensor Height (mm)", 4.0, 56.0)
+                            .display_format("%.1f")
+                            .build(ui, &mut sensor_height)
                         {
-                            self.focus_range = focus_range;
+                            self.sensor_height = sensor_height;
                         }
 
-                        let mut bokeh_radius = self.bokeh_radius;
-                        if imgui::Slider::new("Bokeh Radius", 1.0, 10.0)
-                            .display_format("%.2f")
-                            .build(ui, &mut bokeh_radius)
-                        {
-                            self.bokeh_radius = bokeh_radius;
-                        }
+                        ui.text_disabled(format!("Focal Length: {:.1}mm", self.focal_length()));
                     });
 
                 imgui::TreeNode::new("Projection")
@@ -472,3 +649,79 @@ impl Gui for Camera {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 50mm f/2.8 lens focused at 3 meters on a full frame sensor.
+    fn lens() -> ThinLens {
+        ThinLens {
+            focal_length: 0.05,
+            aperture: 2.8,
+            focus_distance: 3.0,
+            sensor_height: 0.024,
+        }
+    }
+
+    fn assert_relative_eq(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() <= expected.abs() * 1e-3,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn circle_of_confusion_is_zero_at_the_focus_distance() {
+        let lens = lens();
+        assert!(lens.circle_of_confusion(lens.focus_distance).abs() < 1e-9);
+    }
+
+    #[test]
+    fn circle_of_confusion_is_negative_in_front_and_positive_behind() {
+        let lens = lens();
+        assert!(lens.circle_of_confusion(1.0) < 0.0);
+        assert!(lens.circle_of_confusion(10.0) > 0.0);
+    }
+
+    #[test]
+    fn circle_of_confusion_tends_to_its_limit_at_infinity() {
+        let lens = lens();
+        let limit = lens.aperture_diameter() * lens.focal_length
+            / (lens.focus_distance - lens.focal_length);
+
+        assert_relative_eq(lens.circle_of_confusion(1e6), limit);
+    }
+
+    #[test]
+    fn hyperfocal_distance_bounds_the_circle_of_confusion_at_infinity() {
+        let max_circle_of_confusion = 0.00003;
+        let lens = ThinLens {
+            focus_distance: lens().hyperfocal_distance(max_circle_of_confusion),
+            ..lens()
+        };
+
+        assert_relative_eq(lens.circle_of_confusion(1e6).abs(), max_circle_of_confusion);
+    }
+
+    #[test]
+    fn focus_distance_is_clamped_beyond_the_focal_length() {
+        let lens = ThinLens {
+            focus_distance: 0.01,
+            ..lens()
+        };
+        let clamped = ThinLens {
+            focus_distance: lens.focal_length * 1.001,
+            ..lens
+        };
+
+        for &distance in [0.1, 1.0, 100.0].iter() {
+            let circle_of_confusion = lens.circle_of_confusion(distance);
+
+            assert!(circle_of_confusion.is_finite());
+            assert_eq!(circle_of_confusion, clamped.circle_of_confusion(distance));
+        }
+    }
+}
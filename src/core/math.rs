@@ -114,6 +114,10 @@ pub mod matrix {
         )
     }
 
+    pub fn orthographic(left: f32, right: f32, bottom: f32, top: f32, near: f32, far: f32) -> Mat4 {
+        glm::ortho(left, right, bottom, top, near, far)
+    }
+
     pub fn look_at(position: &Vec3, target: &Vec3, up: &Vec3) -> Mat4 {
         glm::look_at(position, target, up)
     }
@@ -145,6 +149,22 @@ pub fn lerp_scalar(a: f32, b: f32, t: f32) -> f32 {
     glm::lerp_scalar(a, b, t)
 }
 
+/// Van der Corput radical inverse of `index` in `base`, the `index`th element of the Halton
+/// sequence of that base.
+pub fn radical_inverse(mut index: u32, base: u32) -> f32 {
+    let inverse_base = 1.0 / base as f32;
+    let mut fraction = inverse_base;
+    let mut result = 0.0;
+
+    while index > 0 {
+        result += (index % base) as f32 * fraction;
+        index /= base;
+        fraction *= inverse_base;
+    }
+
+    result
+}
+
 pub fn spherical_to_cartesian(theta: f32, phi: f32) -> Vec3 {
     let theta = theta.to_radians();
     let phi = phi.to_radians();
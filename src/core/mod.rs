@@ -44,6 +44,10 @@ pub struct Settings {
     pub fullscreen: bool,
     pub msaa: Msaa,
     pub vsync: bool,
+    /// Requests a floating point or 10 bit default framebuffer for HDR displays. Falls back to
+    /// SDR when the platform does not provide one.
+    #[serde(default)]
+    pub hdr_output: bool,
     pub default_clear_color: Vec4,
 }
 
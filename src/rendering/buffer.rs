@@ -242,6 +242,46 @@ impl Buffer {
         }
     }
 
+    pub fn fill_slice<T>(&self, offset: isize, data: &[T]) {
+        assert!(
+            self.storage_flags.intersects(BufferStorageFlags::DYNAMIC),
+            "Cannot fill non-mapped buffer. \n \
+                Reason: Not able to call glBufferSubData(...).\n\
+                Hint: Create the buffer using BufferStorageFlags::DYNAMIC \
+                for non-mapped data updates."
+        );
+
+        let size = (data.len() * mem::size_of::<T>()) as isize;
+        assert!(
+            offset >= 0 && offset + size <= self.size,
+            "Buffer fill out of buffer range. Buffer size: {}, Requested offset: {}, Requested size: {}",
+            self.size,
+            offset,
+            size
+        );
+
+        unsafe {
+            gl::NamedBufferSubData(self.id, offset, size, data.as_ptr() as *const GLvoid)
+        }
+    }
+
+    /// Reads back the buffer content with glGetNamedBufferSubData. Waits for the GPU writes to
+    /// the buffer to finish, so it is best used on data written a few frames earlier.
+    pub fn read_slice<T>(&self, offset: isize, data: &mut [T]) {
+        let size = (data.len() * mem::size_of::<T>()) as isize;
+        assert!(
+            offset >= 0 && offset + size <= self.size,
+            "Buffer read out of buffer range. Buffer size: {}, Requested offset: {}, Requested size: {}",
+            self.size,
+            offset,
+            size
+        );
+
+        unsafe {
+            gl::GetNamedBufferSubData(self.id, offset, size, data.as_mut_ptr() as *mut GLvoid)
+        }
+    }
+
     pub fn fill_mapped<T: Sized>(&self, offset: isize, data: &T) {
         assert_ne!(
             self.mapped_ptr,
@@ -317,12 +357,12 @@ impl Buffer {
 
     pub fn copy(buffer_copy_info: BufferCopyInfo) {
         assert!(
-            buffer_copy_info.source_offset > 0,
-            "Buffer copy source offset must be > 0."
+            buffer_copy_info.source_offset >= 0,
+            "Buffer copy source offset must be >= 0."
         );
         assert!(
-            buffer_copy_info.destination_offset > 0,
-            "Buffer copy destination offset must be > 0"
+            buffer_copy_info.destination_offset >= 0,
+            "Buffer copy destination offset must be >= 0"
         );
         assert!(buffer_copy_info.size > 0, "Buffer copy size must be > 0.");
         assert!(
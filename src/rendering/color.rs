@@ -12,3 +12,63 @@ pub fn srgb_to_linear4f(color: &Vec4) -> Vec4 {
 pub fn srgb_to_linear3f(color: &Vec3) -> Vec3 {
     pow(&color, &Vec3::new(2.2, 2.2, 2.2))
 }
+
+pub const MIN_COLOR_TEMPERATURE: f32 = 1000.0;
+pub const MAX_COLOR_TEMPERATURE: f32 = 15000.0;
+
+/// Linear sRGB color of a black body radiator at the given temperature in Kelvin, normalized so
+/// that the largest component is 1. Uses Krystek's rational approximation of the Planckian locus
+/// in CIE 1960 UCS, valid from 1000K to 15000K.
+pub fn kelvin_to_rgb(temperature: f32) -> Vec3 {
+    let t = temperature
+        .max(MIN_COLOR_TEMPERATURE)
+        .min(MAX_COLOR_TEMPERATURE);
+    let t2 = t * t;
+
+    let u = (0.860_117_757 + 1.541_182_54e-4 * t + 1.286_412_12e-7 * t2)
+        / (1.0 + 8.424_202_35e-4 * t + 7.081_451_63e-7 * t2);
+    let v = (0.317_398_726 + 4.228_062_45e-5 * t + 4.204_816_91e-8 * t2)
+        / (1.0 - 2.897_418_16e-5 * t + 1.614_560_53e-7 * t2);
+
+    // CIE 1960 UCS to CIE 1931 xy, then to XYZ with Y = 1.
+    let denominator = 2.0 * u - 8.0 * v + 4.0;
+    let x = 3.0 * u / denominator;
+    let y = 2.0 * v / denominator;
+
+    let big_x = x / y;
+    let big_z = (1.0 - x - y) / y;
+
+    let rgb = Vec3::new(
+        3.240_454_2 * big_x - 1.537_138_5 - 0.498_531_4 * big_z,
+        -0.969_266_0 * big_x + 1.876_010_8 + 0.041_556_0 * big_z,
+        0.055_643_4 * big_x - 0.204_025_9 + 1.057_225_2 * big_z,
+    )
+    .map(|component| component.max(0.0));
+
+    rgb / rgb.max()
+}
+
+/// Per channel LMS scale that white balances a scene lit by the illuminant of the given
+/// temperature and tint offsets, both from -1 to 1. Positive temperatures warm the image and
+/// positive tints add magenta. The illuminant moves along the CIE standard illuminant D series
+/// around D65, the scale is D65 over the illuminant in the CIECAT02 LMS space.
+pub fn white_balance_lms(temperature: f32, tint: f32) -> Vec3 {
+    let t1 = temperature * 100.0 / 65.0;
+    let t2 = tint * 100.0 / 65.0;
+
+    let x = 0.31271 - t1 * if t1 < 0.0 { 0.1 } else { 0.05 };
+    let standard_illuminant_y = 2.87 * x - 3.0 * x * x - 0.275_095_07;
+    let y = standard_illuminant_y + t2 * 0.05;
+
+    // CIE 1931 xy to XYZ with Y = 1, then to LMS.
+    let big_x = x / y;
+    let big_z = (1.0 - x - y) / y;
+
+    let lms = Vec3::new(
+        0.7328 * big_x + 0.4296 - 0.1624 * big_z,
+        -0.7036 * big_x + 1.6975 + 0.0061 * big_z,
+        0.0030 * big_x + 0.0136 + 0.9834 * big_z,
+    );
+
+    Vec3::new(0.949_237, 1.035_42, 1.087_28).component_div(&lms)
+}
@@ -0,0 +1,341 @@
+use std::rc::Rc;
+
+use crate::{
+    core::camera::Camera,
+    core::math::{inverse, UVec2, Vec4},
+    core::Msaa,
+    imgui::{Gui, Ui},
+    mesh::utilities::draw_full_screen_quad,
+    rendering::{
+        buffer::{Buffer, BufferStorageFlags, BufferTarget, MapModeFlags},
+        framebuffer::{
+            AttachmentType, Framebuffer, FramebufferAttachmentCreateInfo, FramebufferError,
+        },
+        postprocess::FULLSCREEN_VERTEX_SHADER_PATH,
+        sampler::{Anisotropy, MagnificationFilter, MinificationFilter, Sampler, WrappingMode},
+        shader::{Shader, ShaderCreateInfo, ShaderStage},
+        texture::SizedTextureFormat,
+    },
+    Context,
+};
+
+const DEFERRED_LIGHTING_FRAGMENT_SHADER_PATH: &str =
+    "assets/shaders/deferred/deferred_lighting.frag";
+
+const DEFERRED_LIGHTING_UBO_BINDING_INDEX: u32 = 12;
+
+// Must match the G-buffer samplers of deferred_lighting.frag.
+const GBUFFER_ALBEDO_BINDING_INDEX: u32 = 0;
+const GBUFFER_NORMAL_BINDING_INDEX: u32 = 1;
+const GBUFFER_METALLIC_ROUGHNESS_AO_BINDING_INDEX: u32 = 2;
+const GBUFFER_EMISSION_BINDING_INDEX: u32 = 6;
+const GBUFFER_DEPTH_BINDING_INDEX: u32 = 11;
+
+/// Attachments of the `GBuffer`, in attachment order.
+#[repr(usize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GBufferAttachment {
+    /// Albedo (rgb) and reflectance (a).
+    Albedo,
+    /// Octahedron encoded world space normal.
+    Normal,
+    /// Metallic (r), perceptual roughness (g) and ambient occlusion (b).
+    MetallicRoughnessAo,
+    /// Emitted light, in the same units as the lit scene color.
+    Emission,
+    /// Screen space velocity in texture coordinates per frame, without the camera jitter. Zero
+    /// where there is no geometry.
+    Velocity,
+    Depth,
+}
+
+impl GBufferAttachment {
+    pub const ALL: [GBufferAttachment; 6] = [
+        GBufferAttachment::Albedo,
+        GBufferAttachment::Normal,
+        GBufferAttachment::MetallicRoughnessAo,
+        GBufferAttachment::Emission,
+        GBufferAttachment::Velocity,
+        GBufferAttachment::Depth,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            GBufferAttachment::Albedo => "GBuffer-Albedo",
+            GBufferAttachment::Normal => "GBuffer-Normal",
+            GBufferAttachment::MetallicRoughnessAo => "GBuffer-MetallicRoughnessAo",
+            GBufferAttachment::Emission => "GBuffer-Emission",
+            GBufferAttachment::Velocity => "GBuffer-Velocity",
+            GBufferAttachment::Depth => "GBuffer-Depth",
+        }
+    }
+
+    pub fn format(self) -> SizedTextureFormat {
+        match self {
+            GBufferAttachment::Albedo => SizedTextureFormat::Rgba8,
+            GBufferAttachment::Normal => SizedTextureFormat::Rg16f,
+            GBufferAttachment::MetallicRoughnessAo => SizedTextureFormat::Rgba8,
+            GBufferAttachment::Emission => SizedTextureFormat::Rgba16f,
+            GBufferAttachment::Velocity => SizedTextureFormat::Rg16f,
+            // Same format as the scene depth so that it can be blitted for the passes after
+            // lighting.
+            GBufferAttachment::Depth => SizedTextureFormat::Depth24Stencil8,
+        }
+    }
+}
+
+/// What the deferred lighting pass outputs. Everything but `Lit` shows a single G-buffer
+/// channel.
+#[repr(usize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GBufferView {
+    Lit,
+    Albedo,
+    Normals,
+    Metallic,
+    Roughness,
+    AmbientOcclusion,
+    Emission,
+    Depth,
+}
+
+impl GBufferView {
+    pub const ALL: [GBufferView; 8] = [
+        GBufferView::Lit,
+        GBufferView::Albedo,
+        GBufferView::Normals,
+        GBufferView::Metallic,
+        GBufferView::Roughness,
+        GBufferView::AmbientOcclusion,
+        GBufferView::Emission,
+        GBufferView::Depth,
+    ];
+
+    pub const NAMES: [&'static str; 8] = [
+        "Lit",
+        "Albedo",
+        "Normals",
+        "Metallic",
+        "Roughness",
+        "Ambient Occlusion",
+        "Emission",
+        "Linear Depth",
+    ];
+}
+
+/// Non multisampled framebuffer holding the surface attributes of the deferred path. See
+/// `GBufferAttachment` for the layout.
+pub struct GBuffer {
+    framebuffer: Framebuffer,
+}
+
+impl GBuffer {
+    pub fn new(size: UVec2) -> Result<Self, FramebufferError> {
+        let attachments = GBufferAttachment::ALL
+            .iter()
+            .map(|attachment| {
+                FramebufferAttachmentCreateInfo::new(
+                    attachment.name(),
+                    attachment.format(),
+                    AttachmentType::Texture,
+                )
+            })
+            .collect();
+
+        let framebuffer = Framebuffer::new("GBuffer", size, Msaa::None, attachments)?;
+
+        Ok(GBuffer { framebuffer })
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    pub fn size(&self) -> UVec2 {
+        self.framebuffer.size()
+    }
+
+    /// Texture id of the attachment.
+    pub fn texture_id(&self, attachment: GBufferAttachment) -> u32 {
+        self.framebuffer
+            .texture_attachment(attachment as usize)
+            .id()
+    }
+}
+
+#[repr(C)]
+struct DeferredLightingBlock {
+    inverse_view_projection: mint::ColumnMatrix4<f32>,
+    gbuffer_view: i32,
+    _padding: [i32; 3],
+}
+
+/// Deferred shading path. The geometry pass writes the scene to a `GBuffer` and a fullscreen pass
+/// lights every pixel once with the same lighting code as the forward PBS shader.
+pub struct DeferredRenderer {
+    gbuffer: GBuffer,
+    lighting_shader: Rc<Shader>,
+    lighting_ubo: Buffer,
+    sampler_nearest: Sampler,
+    view: GBufferView,
+}
+
+impl DeferredRenderer {
+    pub fn new(context: Context, size: UVec2) -> Self {
+        let Context { device, .. } = context;
+
+        // Same keywords as the forward PBS shader, minus the material ones.
+        let create_info = ShaderCreateInfo::builder("Deferred Lighting Shader")
+            .stage(ShaderStage::Vertex, FULLSCREEN_VERTEX_SHADER_PATH)
+            .stage(
+                ShaderStage::Fragment,
+                DEFERRED_LIGHTING_FRAGMENT_SHADER_PATH,
+            )
+            .keyword_set(&["FEATURE_BRDF_FILLAMENT", "FEATURE_BRDF_UE4"])
+            .keyword_set(&["_", "FEATURE_SH_IRRADIANCE"])
+            .keyword_set(&["_", "FEATURE_SHADOWS", "FEATURE_SHADOWS_PCF"])
+            .build();
+
+        let lighting_shader = device.shader_manager().create_shader(&create_info);
+
+        let mut lighting_ubo = Buffer::new(
+            "Deferred Lighting UBO",
+            std::mem::size_of::<DeferredLightingBlock>() as isize,
+            BufferTarget::Uniform,
+            BufferStorageFlags::MAP_WRITE_PERSISTENT_COHERENT,
+        );
+        lighting_ubo.bind(DEFERRED_LIGHTING_UBO_BINDING_INDEX);
+        lighting_ubo.map(MapModeFlags::MAP_WRITE_PERSISTENT_COHERENT);
+
+        let sampler_nearest = Sampler::new(
+            MinificationFilter::Nearest,
+            MagnificationFilter::Nearest,
+            WrappingMode::ClampToEdge,
+            WrappingMode::ClampToEdge,
+            WrappingMode::ClampToEdge,
+            Vec4::new(0.0, 0.0, 0.0, 0.0),
+            Anisotropy::None,
+        );
+
+        let gbuffer =
+            GBuffer::new(size).unwrap_or_else(|error| panic!("G-buffer creation error: {}", error));
+
+        DeferredRenderer {
+            gbuffer,
+            lighting_shader,
+            lighting_ubo,
+            sampler_nearest,
+            view: GBufferView::Lit,
+        }
+    }
+
+    pub fn gbuffer(&self) -> &GBuffer {
+        &self.gbuffer
+    }
+
+    /// Shader of the lighting pass. Its keywords must follow the ones of the forward shader, and
+    /// the IBL maps, shadow maps and BRDF LUT are bound on it before `lighting_pass`.
+    pub fn lighting_shader(&self) -> Rc<Shader> {
+        Rc::clone(&self.lighting_shader)
+    }
+
+    pub fn view(&self) -> GBufferView {
+        self.view
+    }
+
+    pub fn set_view(&mut self, view: GBufferView) {
+        self.view = view;
+    }
+
+    /// Renders the geometry drawn by `draw` into the G-buffer. `draw` binds a G-buffer writing
+    /// shader such as the one of `PbsMetallicRoughnessMaterial::bind_gbuffer`.
+    pub fn geometry_pass<F: FnMut()>(&self, mut draw: F) {
+        let framebuffer = self.gbuffer.framebuffer();
+
+        framebuffer.bind();
+        framebuffer.clear(&Vec4::new(0.0, 0.0, 0.0, 0.0));
+
+        draw();
+
+        framebuffer.unbind(false);
+    }
+
+    /// Lights the G-buffer into `target`, then copies the G-buffer depth into it so that the
+    /// skybox and transparent passes can be depth tested. Pixels without geometry are left
+    /// untouched.
+    pub fn lighting_pass(&self, camera: &Camera, target: &Framebuffer) {
+        let view_projection = camera.projection() * camera.transform();
+
+        self.lighting_ubo.fill_mapped(
+            0,
+            &DeferredLightingBlock {
+                inverse_view_projection: inverse(&view_projection).into(),
+                gbuffer_view: self.view as i32,
+                _padding: [0; 3],
+            },
+        );
+
+        target.bind();
+
+        self.lighting_shader.bind();
+
+        [
+            (GBufferAttachment::Albedo, GBUFFER_ALBEDO_BINDING_INDEX),
+            (GBufferAttachment::Normal, GBUFFER_NORMAL_BINDING_INDEX),
+            (
+                GBufferAttachment::MetallicRoughnessAo,
+                GBUFFER_METALLIC_ROUGHNESS_AO_BINDING_INDEX,
+            ),
+            (GBufferAttachment::Emission, GBUFFER_EMISSION_BINDING_INDEX),
+            (GBufferAttachment::Depth, GBUFFER_DEPTH_BINDING_INDEX),
+        ]
+        .iter()
+        .for_each(|&(attachment, binding_index)| {
+            self.lighting_shader.bind_texture_2d_with_id(
+                binding_index,
+                self.gbuffer.texture_id(attachment),
+                &self.sampler_nearest,
+            );
+        });
+
+        draw_full_screen_quad();
+
+        self.lighting_shader.unbind();
+
+        Framebuffer::blit_depth(self.gbuffer.framebuffer(), target);
+    }
+}
+
+impl Gui for DeferredRenderer {
+    fn gui(&mut self, ui: &Ui) {
+        imgui::TreeNode::new("G-Buffer")
+            .default_open(false)
+            .open_on_arrow(true)
+            .open_on_double_click(true)
+            .framed(false)
+            .build(ui, || {
+                let mut view = self.view as usize;
+                if ui.combo_simple_string("View", &mut view, &GBufferView::NAMES) {
+                    self.view = GBufferView::ALL[view];
+                }
+
+                ui.spacing();
+
+                let size = self.gbuffer.size();
+                let width = 160.0;
+                let height = width * size.y as f32 / size.x.max(1) as f32;
+
+                for attachment in GBufferAttachment::ALL.iter() {
+                    ui.text(attachment.name());
+                    // Flipped vertically, GL textures start at the bottom.
+                    imgui::Image::new(
+                        (self.gbuffer.texture_id(*attachment) as usize).into(),
+                        [width, height],
+                    )
+                    .uv0([0.0, 1.0])
+                    .uv1([1.0, 0.0])
+                    .build(ui);
+                }
+            });
+    }
+}
@@ -3,6 +3,46 @@ use crate::rendering::shader::shader_manager::ShaderManager;
 use gl_bindings as gl;
 use std::ffi::CStr;
 
+/// Encoding expected by the default framebuffer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DisplayOutput {
+    /// 8 bit sRGB.
+    Sdr,
+    /// 10 bit, PQ encoded Rec. 2020.
+    Hdr10,
+    /// Half float, linear Rec. 709 where 1.0 is 80 nits.
+    ScRgb,
+}
+
+impl DisplayOutput {
+    /// Infers the output from the format of the back buffer of the current context.
+    pub fn query() -> Self {
+        let mut component_type = 0;
+        let mut red_size = 0;
+
+        unsafe {
+            gl::GetNamedFramebufferAttachmentParameteriv(
+                0,
+                gl::BACK_LEFT,
+                gl::FRAMEBUFFER_ATTACHMENT_COMPONENT_TYPE,
+                &mut component_type,
+            );
+            gl::GetNamedFramebufferAttachmentParameteriv(
+                0,
+                gl::BACK_LEFT,
+                gl::FRAMEBUFFER_ATTACHMENT_RED_SIZE,
+                &mut red_size,
+            );
+        }
+
+        match (component_type as u32, red_size) {
+            (gl::FLOAT, _) => Self::ScRgb,
+            (_, 10) => Self::Hdr10,
+            _ => Self::Sdr,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct DeviceInfo {
     version: String,
@@ -14,6 +54,7 @@ pub struct DeviceInfo {
     max_uniform_buffer_bindings: i32,
     max_uniform_block_size: i32,
     max_uniform_locations: i32,
+    display_output: DisplayOutput,
 }
 
 impl Default for DeviceInfo {
@@ -65,6 +106,7 @@ impl Default for DeviceInfo {
             max_uniform_buffer_bindings,
             max_uniform_block_size,
             max_uniform_locations,
+            display_output: DisplayOutput::query(),
         }
     }
 }
@@ -73,6 +115,10 @@ impl DeviceInfo {
     pub fn new() -> Self {
         Default::default()
     }
+
+    pub fn display_output(&self) -> DisplayOutput {
+        self.display_output
+    }
 }
 
 #[derive(Default)]
@@ -113,6 +159,7 @@ impl Gui for Device {
                     "Max framebuffer height: {}",
                     info.max_framebuffer_height
                 ));
+                ui.text(format!("Display output: {:?}", info.display_output));
             });
     }
 }
@@ -0,0 +1,493 @@
+use std::mem;
+use std::rc::Rc;
+
+use crevice::std140::AsStd140;
+
+use crate::{
+    core::math::{rotate, transpose, Axes, Mat4, Vec2, Vec4},
+    imgui::{Gui, Ui},
+    rendering::{
+        buffer::{Buffer, BufferStorageFlags, BufferTarget, MapModeFlags},
+        ibl::BakedEnvironment,
+        mesh::{utilities::generate_cube, Mesh},
+        sampler::{Anisotropy, MagnificationFilter, MinificationFilter, Sampler, WrappingMode},
+        shader::{Shader, ShaderCreateInfo, ShaderStage},
+        spherical_harmonics::{SphericalHarmonics, SphericalHarmonicsBlock},
+        state::{DepthFunction, FaceCulling, StateManager},
+        texture::TextureCube,
+        Draw,
+    },
+    Context,
+};
+
+const ENVIRONMENT_UBO_BINDING_INDEX: u32 = 6;
+const SPHERICAL_HARMONICS_UBO_BINDING_INDEX: u32 = 5;
+
+pub const IRRADIANCE_MAP_BINDING_INDEX: u32 = 4;
+pub const RADIANCE_MAP_BINDING_INDEX: u32 = 5;
+pub const TARGET_IRRADIANCE_MAP_BINDING_INDEX: u32 = 7;
+pub const TARGET_RADIANCE_MAP_BINDING_INDEX: u32 = 8;
+
+const SKYBOX_BINDING_INDEX: u32 = 0;
+const TARGET_SKYBOX_BINDING_INDEX: u32 = 1;
+
+const SKYBOX_VERTEX_SHADER_PATH: &str = "assets/shaders/skybox.vert";
+const SKYBOX_FRAGMENT_SHADER_PATH: &str = "assets/shaders/skybox.frag";
+
+const MIN_INTENSITY: f32 = 0.0;
+const MAX_INTENSITY: f32 = 10.0;
+
+const MAX_REFLECTION_LOD: f32 = 9.0;
+
+#[repr(C)]
+#[derive(Debug, AsStd140)]
+struct EnvironmentUniforms {
+    rotation: mint::ColumnMatrix4<f32>,
+    intensity: mint::Vector2<f32>,
+    max_reflection_lod: mint::Vector2<f32>,
+    blend: f32,
+}
+
+/// Image based lighting data of a single environment: the skybox shown in the background, the
+/// diffuse irradiance and prefiltered specular radiance cubemaps, and an intensity multiplier
+/// applied to all of them.
+pub struct Environment {
+    name: String,
+    skybox: Rc<TextureCube>,
+    irradiance: Rc<TextureCube>,
+    radiance: Rc<TextureCube>,
+    spherical_harmonics: SphericalHarmonics,
+    intensity: f32,
+    max_reflection_lod: f32,
+}
+
+impl Environment {
+    /// The irradiance SH coefficients are projected from the radiance map and the max reflection
+    /// LOD defaults to the last mip of the radiance map.
+    pub fn new(
+        name: &str,
+        skybox: Rc<TextureCube>,
+        irradiance: Rc<TextureCube>,
+        radiance: Rc<TextureCube>,
+    ) -> Self {
+        let spherical_harmonics = SphericalHarmonics::from_texture_cube(&radiance);
+        let max_reflection_lod = (u32::max(radiance.mip_levels(), 1) - 1) as f32;
+
+        Self {
+            name: String::from(name),
+            skybox,
+            irradiance,
+            radiance,
+            spherical_harmonics,
+            intensity: 1.0,
+            max_reflection_lod,
+        }
+    }
+
+    pub fn from_baked(name: &str, baked: BakedEnvironment) -> Self {
+        Self::new(
+            name,
+            Rc::new(baked.skybox),
+            Rc::new(baked.irradiance),
+            Rc::new(baked.radiance),
+        )
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn skybox(&self) -> &Rc<TextureCube> {
+        &self.skybox
+    }
+
+    pub fn irradiance(&self) -> &Rc<TextureCube> {
+        &self.irradiance
+    }
+
+    pub fn radiance(&self) -> &Rc<TextureCube> {
+        &self.radiance
+    }
+
+    pub fn spherical_harmonics(&self) -> &SphericalHarmonics {
+        &self.spherical_harmonics
+    }
+
+    pub fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    pub fn set_intensity(&mut self, intensity: f32) {
+        self.intensity = f32::max(intensity, 0.0);
+    }
+
+    pub fn max_reflection_lod(&self) -> f32 {
+        self.max_reflection_lod
+    }
+
+    /// Overrides the LOD used for perceptual roughness 1, for radiance maps whose mip chain goes
+    /// further down than the prefiltered levels.
+    pub fn set_max_reflection_lod(&mut self, max_reflection_lod: f32) {
+        self.max_reflection_lod = f32::max(max_reflection_lod, 0.0);
+    }
+}
+
+#[repr(usize)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SkyboxSource {
+    Skybox,
+    Radiance,
+    Irradiance,
+}
+
+struct EnvironmentTransition {
+    target: Rc<Environment>,
+    duration: f32,
+    elapsed: f32,
+}
+
+/// Smoothstep eased blend factor of a transition, 0 at the start and 1 once `duration` seconds
+/// have elapsed.
+pub fn transition_blend_factor(elapsed: f32, duration: f32) -> f32 {
+    if duration <= 0.0 {
+        return 1.0;
+    }
+
+    let t = (elapsed / duration).max(0.0).min(1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Owns the active environment and draws the skybox. Provides the IBL maps, the
+/// `EnvironmentBlock` and the `SphericalHarmonicsBlock` to the lit shaders, including a second
+/// environment while cross-fading between two of them.
+pub struct EnvironmentRenderer {
+    current: Rc<Environment>,
+    transition: Option<EnvironmentTransition>,
+    rotation: f32,
+    intensity: f32,
+    max_reflection_lod_override: Option<f32>,
+    skybox_source: SkyboxSource,
+    skybox_shader: Rc<Shader>,
+    skybox_mesh: Mesh,
+    environment_ubo: Buffer,
+    spherical_harmonics_ubo: Buffer,
+    sampler: Sampler,
+}
+
+impl EnvironmentRenderer {
+    pub fn new(context: Context, environment: Rc<Environment>) -> Self {
+        let Context { device, .. } = context;
+
+        let skybox_shader = device.shader_manager().create_shader(
+            &ShaderCreateInfo::builder("SkyboxShader")
+                .stage(ShaderStage::Vertex, SKYBOX_VERTEX_SHADER_PATH)
+                .stage(ShaderStage::Fragment, SKYBOX_FRAGMENT_SHADER_PATH)
+                .build(),
+        );
+
+        let mut environment_ubo = Buffer::new(
+            "Environment UBO",
+            mem::size_of::<<EnvironmentUniforms as AsStd140>::Std140Type>() as isize,
+            BufferTarget::Uniform,
+            BufferStorageFlags::MAP_WRITE_PERSISTENT_COHERENT,
+        );
+        environment_ubo.bind(ENVIRONMENT_UBO_BINDING_INDEX);
+        environment_ubo.map(MapModeFlags::MAP_WRITE_PERSISTENT_COHERENT);
+
+        let mut spherical_harmonics_ubo = Buffer::new(
+            "Spherical Harmonics UBO",
+            mem::size_of::<SphericalHarmonicsBlock>() as isize,
+            BufferTarget::Uniform,
+            BufferStorageFlags::MAP_WRITE_PERSISTENT_COHERENT,
+        );
+        spherical_harmonics_ubo.bind(SPHERICAL_HARMONICS_UBO_BINDING_INDEX);
+        spherical_harmonics_ubo.map(MapModeFlags::MAP_WRITE_PERSISTENT_COHERENT);
+
+        let sampler = Sampler::new(
+            MinificationFilter::LinearMipmapLinear,
+            MagnificationFilter::Linear,
+            WrappingMode::ClampToEdge,
+            WrappingMode::ClampToEdge,
+            WrappingMode::ClampToEdge,
+            Vec4::new(0.0, 0.0, 0.0, 0.0),
+            Anisotropy::X16,
+        );
+
+        Self {
+            current: environment,
+            transition: None,
+            rotation: 0.0,
+            intensity: 1.0,
+            max_reflection_lod_override: None,
+            skybox_source: SkyboxSource::Skybox,
+            skybox_shader,
+            skybox_mesh: generate_cube(1.0),
+            environment_ubo,
+            spherical_harmonics_ubo,
+            sampler,
+        }
+    }
+
+    pub fn current(&self) -> &Rc<Environment> {
+        &self.current
+    }
+
+    /// The environment being faded in, if a transition is in progress.
+    pub fn target(&self) -> Option<&Rc<Environment>> {
+        self.transition
+            .as_ref()
+            .map(|transition| &transition.target)
+    }
+
+    /// Switches to `environment` immediately, cancelling any transition in progress.
+    pub fn set_environment(&mut self, environment: Rc<Environment>) {
+        self.current = environment;
+        self.transition = None;
+    }
+
+    /// Cross-fades from the current environment to `environment` over `duration` seconds. If a
+    /// transition is already in progress, whichever of its two environments contributes the most
+    /// becomes the starting point of the new one.
+    pub fn transition_to(&mut self, environment: Rc<Environment>, duration: f32) {
+        if duration <= 0.0 {
+            self.set_environment(environment);
+            return;
+        }
+
+        if let Some(transition) = self.transition.take() {
+            if transition_blend_factor(transition.elapsed, transition.duration) >= 0.5 {
+                self.current = transition.target;
+            }
+        }
+
+        if Rc::ptr_eq(&self.current, &environment) {
+            return;
+        }
+
+        self.transition = Some(EnvironmentTransition {
+            target: environment,
+            duration,
+            elapsed: 0.0,
+        });
+    }
+
+    pub fn is_transitioning(&self) -> bool {
+        self.transition.is_some()
+    }
+
+    /// Weight of the target environment, 0 when no transition is in progress.
+    pub fn blend_factor(&self) -> f32 {
+        self.transition.as_ref().map_or(0.0, |transition| {
+            transition_blend_factor(transition.elapsed, transition.duration)
+        })
+    }
+
+    /// Advances the transition, if any. Call once per frame.
+    pub fn update(&mut self, delta_time: f32) {
+        let finished = match self.transition.as_mut() {
+            Some(transition) => {
+                transition.elapsed += delta_time;
+                transition.elapsed >= transition.duration
+            }
+            None => false,
+        };
+
+        if finished {
+            if let Some(transition) = self.transition.take() {
+                self.current = transition.target;
+            }
+        }
+    }
+
+    /// Rotation of the environment around the world up axis, in degrees.
+    pub fn rotation(&self) -> f32 {
+        self.rotation
+    }
+
+    pub fn set_rotation(&mut self, degrees: f32) {
+        self.rotation = degrees % 360.0;
+    }
+
+    /// Environment space to world space rotation.
+    pub fn rotation_matrix(&self) -> Mat4 {
+        rotate(&Mat4::identity(), self.rotation, &Axes::up())
+    }
+
+    /// Global multiplier applied on top of the intensity of each environment.
+    pub fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    pub fn set_intensity(&mut self, intensity: f32) {
+        self.intensity = f32::max(intensity, 0.0);
+    }
+
+    pub fn max_reflection_lod_override(&self) -> Option<f32> {
+        self.max_reflection_lod_override
+    }
+
+    /// Replaces the max reflection LOD of both environments, to tune the roughness to mip mapping
+    /// of radiance maps whose last mips are not fully rough. `None` uses their own.
+    pub fn set_max_reflection_lod_override(&mut self, max_reflection_lod: Option<f32>) {
+        self.max_reflection_lod_override = max_reflection_lod.map(|lod| f32::max(lod, 0.0));
+    }
+
+    pub fn skybox_source(&self) -> SkyboxSource {
+        self.skybox_source
+    }
+
+    pub fn set_skybox_source(&mut self, skybox_source: SkyboxSource) {
+        self.skybox_source = skybox_source;
+    }
+
+    /// Writes the `EnvironmentBlock` and the blended `SphericalHarmonicsBlock`.
+    pub fn update_uniforms(&self) {
+        let target = self.target().unwrap_or(&self.current);
+        let blend = self.blend_factor();
+
+        let current_intensity = self.current.intensity * self.intensity;
+        let target_intensity = target.intensity * self.intensity;
+
+        // Lookups are done in environment space, so the shaders need the inverse rotation.
+        let uniforms = EnvironmentUniforms {
+            rotation: transpose(&self.rotation_matrix()).into(),
+            intensity: Vec2::new(current_intensity, target_intensity).into(),
+            max_reflection_lod: Vec2::new(
+                self.max_reflection_lod_override
+                    .unwrap_or(self.current.max_reflection_lod),
+                self.max_reflection_lod_override
+                    .unwrap_or(target.max_reflection_lod),
+            )
+            .into(),
+            blend,
+        };
+
+        self.environment_ubo.fill_mapped(0, &uniforms.as_std140());
+
+        let spherical_harmonics = SphericalHarmonics::lerp(
+            &self.current.spherical_harmonics.scaled(current_intensity),
+            &target.spherical_harmonics.scaled(target_intensity),
+            blend,
+        );
+
+        self.spherical_harmonics_ubo
+            .fill_mapped(0, &SphericalHarmonicsBlock::from(&spherical_harmonics));
+    }
+
+    /// Binds the irradiance and radiance maps of both environments to `shader`. Without a
+    /// transition the current environment is bound to the target slots as well.
+    pub fn bind_ibl_maps(&self, shader: &Shader) {
+        let target = self.target().unwrap_or(&self.current);
+
+        shader
+            .bind_texture_cube(
+                IRRADIANCE_MAP_BINDING_INDEX,
+                &self.current.irradiance,
+                &self.sampler,
+            )
+            .bind_texture_cube(
+                RADIANCE_MAP_BINDING_INDEX,
+                &self.current.radiance,
+                &self.sampler,
+            )
+            .bind_texture_cube(
+                TARGET_IRRADIANCE_MAP_BINDING_INDEX,
+                &target.irradiance,
+                &self.sampler,
+            )
+            .bind_texture_cube(
+                TARGET_RADIANCE_MAP_BINDING_INDEX,
+                &target.radiance,
+                &self.sampler,
+            );
+    }
+
+    /// Draws the skybox into the currently bound framebuffer, behind all the geometry.
+    pub fn draw_skybox(&self) {
+        StateManager::depth_function(DepthFunction::LessOrEqual);
+        StateManager::face_culling(FaceCulling::Front);
+
+        let target = self.target().unwrap_or(&self.current);
+
+        self.skybox_shader.bind();
+        self.skybox_shader
+            .bind_texture_cube(
+                SKYBOX_BINDING_INDEX,
+                self.skybox_map(&self.current),
+                &self.sampler,
+            )
+            .bind_texture_cube(
+                TARGET_SKYBOX_BINDING_INDEX,
+                self.skybox_map(target),
+                &self.sampler,
+            );
+
+        self.skybox_mesh.draw();
+
+        self.skybox_shader.unbind();
+
+        StateManager::depth_function(DepthFunction::Less);
+        StateManager::face_culling(FaceCulling::Back)
+    }
+
+    fn skybox_map<'a>(&self, environment: &'a Environment) -> &'a TextureCube {
+        match self.skybox_source {
+            SkyboxSource::Skybox => &environment.skybox,
+            SkyboxSource::Radiance => &environment.radiance,
+            SkyboxSource::Irradiance => &environment.irradiance,
+        }
+    }
+}
+
+impl Gui for EnvironmentRenderer {
+    fn gui(&mut self, ui: &Ui) {
+        imgui::TreeNode::new("Environment")
+            .default_open(true)
+            .open_on_arrow(true)
+            .open_on_double_click(true)
+            .framed(false)
+            .build(ui, || {
+                match self.target() {
+                    Some(target) => ui.text(format!(
+                        "{} -> {} ({:.0}%)",
+                        self.current.name,
+                        target.name,
+                        self.blend_factor() * 100.0
+                    )),
+                    None => ui.text(&self.current.name),
+                }
+
+                let skybox_source_ref =
+                    unsafe { &mut *(&mut self.skybox_source as *mut SkyboxSource as *mut usize) };
+                ui.combo_simple_string(
+                    "Skybox",
+                    skybox_source_ref,
+                    &["Original", "Radiance", "Irradiance"],
+                );
+
+                let mut rotation = self.rotation;
+                if imgui::Slider::new("Rotation", -180.0, 180.0)
+                    .display_format("%.1f")
+                    .build(ui, &mut rotation)
+                {
+                    self.set_rotation(rotation);
+                }
+
+                imgui::Slider::new("Intensity", MIN_INTENSITY, MAX_INTENSITY)
+                    .display_format("%.2f")
+                    .build(ui, &mut self.intensity);
+
+                let mut override_lod = self.max_reflection_lod_override.is_some();
+                if ui.checkbox("Override Max Reflection LOD", &mut override_lod) {
+                    self.max_reflection_lod_override =
+                        override_lod.then(|| self.current.max_reflection_lod);
+                }
+
+                if let Some(max_reflection_lod) = self.max_reflection_lod_override.as_mut() {
+                    imgui::Slider::new("Max Reflection LOD", 0.0, MAX_REFLECTION_LOD)
+                        .display_format("%.1f")
+                        .build(ui, max_reflection_lod);
+                }
+            });
+    }
+}
@@ -70,6 +70,7 @@ pub struct FramebufferAttachmentCreateInfo {
     name: String,
     format: SizedTextureFormat,
     attachment_type: AttachmentType,
+    layers: u32,
 }
 
 impl FramebufferAttachmentCreateInfo {
@@ -82,9 +83,19 @@ impl FramebufferAttachmentCreateInfo {
             name: name.to_string(),
             format,
             attachment_type,
+            layers: 1,
         }
     }
 
+    /// Creates the attachment as a 2D array texture with the given number of layers. The whole
+    /// array is attached, so the framebuffer is layered and a geometry shader selects the layer
+    /// each primitive is rendered to through gl_Layer.
+    pub fn with_layers(mut self, layers: u32) -> Self {
+        assert!(layers > 0, "An attachment needs at least one layer.");
+        self.layers = layers;
+        self
+    }
+
     pub fn format(&self) -> SizedTextureFormat {
         self.format
     }
@@ -92,6 +103,10 @@ impl FramebufferAttachmentCreateInfo {
     pub fn attachment_type(&self) -> AttachmentType {
         self.attachment_type
     }
+
+    pub fn layers(&self) -> u32 {
+        self.layers
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -100,6 +115,7 @@ pub struct FramebufferAttachment {
     format: SizedTextureFormat,
     attachment_type: AttachmentType,
     attachment_bind_point: AttachmentBindPoint,
+    layers: u32,
 }
 
 impl FramebufferAttachment {
@@ -108,12 +124,14 @@ impl FramebufferAttachment {
         format: SizedTextureFormat,
         attachment_type: AttachmentType,
         attachment_bind_point: AttachmentBindPoint,
+        layers: u32,
     ) -> Self {
         FramebufferAttachment {
             id,
             format,
             attachment_type,
             attachment_bind_point,
+            layers,
         }
     }
 
@@ -129,6 +147,10 @@ impl FramebufferAttachment {
         self.attachment_type
     }
 
+    pub fn layers(&self) -> u32 {
+        self.layers
+    }
+
     pub fn is_depth_stencil(&self) -> bool {
         match self.attachment_bind_point {
             AttachmentBindPoint::Depth(_)
@@ -180,21 +202,22 @@ impl Framebuffer {
             .collect::<Vec<_>>();
 
         if !texture_attachment_create_infos.is_empty() {
-            let texture_attachment_ids: Vec<GLuint> =
+            let mut texture_attachment_ids: Vec<GLuint> =
                 vec![0; texture_attachment_create_infos.len()];
 
-            let tex_type = match msaa {
-                Msaa::None => gl::TEXTURE_2D,
-                _ => gl::TEXTURE_2D_MULTISAMPLE,
-            };
-
-            unsafe {
-                gl::CreateTextures(
-                    tex_type,
-                    texture_attachment_ids.len() as i32,
-                    texture_attachment_ids.as_ptr() as *mut GLuint,
-                )
-            }
+            texture_attachment_create_infos
+                .iter()
+                .zip(texture_attachment_ids.iter_mut())
+                .for_each(|(&create_info, id)| {
+                    let tex_type = match (msaa, create_info.layers() > 1) {
+                        (Msaa::None, false) => gl::TEXTURE_2D,
+                        (Msaa::None, true) => gl::TEXTURE_2D_ARRAY,
+                        (_, false) => gl::TEXTURE_2D_MULTISAMPLE,
+                        (_, true) => gl::TEXTURE_2D_MULTISAMPLE_ARRAY,
+                    };
+
+                    unsafe { gl::CreateTextures(tex_type, 1, id) }
+                });
 
             texture_attachment_create_infos
                 .iter()
@@ -205,20 +228,37 @@ impl Framebuffer {
                         gl::ObjectLabel(gl::TEXTURE, *id, name.len() as i32 + 1, label.as_ptr());
 
                         //TODO: Assert that num samples is 0 if internal format is singed or unsigned int
-                        match msaa {
-                            Msaa::None => gl::TextureStorage2D(
+                        match (msaa, create_info.layers() > 1) {
+                            (Msaa::None, false) => gl::TextureStorage2D(
                                 *id,
                                 1,
                                 create_info.format() as u32,
                                 size.x as i32,
                                 size.y as i32,
                             ),
-                            _ => gl::TextureStorage2DMultisample(
+                            (Msaa::None, true) => gl::TextureStorage3D(
+                                *id,
+                                1,
+                                create_info.format() as u32,
+                                size.x as i32,
+                                size.y as i32,
+                                create_info.layers() as i32,
+                            ),
+                            (_, false) => gl::TextureStorage2DMultisample(
+                                *id,
+                                msaa as i32,
+                                create_info.format() as u32,
+                                size.x as i32,
+                                size.y as i32,
+                                gl::FALSE,
+                            ),
+                            (_, true) => gl::TextureStorage3DMultisample(
                                 *id,
                                 msaa as i32,
                                 create_info.format() as u32,
                                 size.x as i32,
                                 size.y as i32,
+                                create_info.layers() as i32,
                                 gl::FALSE,
                             ),
                         }
@@ -243,6 +283,7 @@ impl Framebuffer {
                             create_info.format(),
                             create_info.attachment_type(),
                             attachment_bind_point,
+                            create_info.layers(),
                         ))
                     } else {
                         let output_location = gl::COLOR_ATTACHMENT0 + color_attachment_count;
@@ -260,6 +301,7 @@ impl Framebuffer {
                                 output_location,
                                 color_attachment_count as i32,
                             ),
+                            create_info.layers(),
                         ));
 
                         color_attachment_count += 1
@@ -328,6 +370,7 @@ impl Framebuffer {
                             create_info.format(),
                             create_info.attachment_type(),
                             attachment_bind_point,
+                            1,
                         ))
                     } else {
                         let output_location = gl::COLOR_ATTACHMENT0 + color_attachment_count;
@@ -350,6 +393,7 @@ impl Framebuffer {
                                 output_location,
                                 color_attachment_count as i32,
                             ),
+                            1,
                         ));
 
                         color_attachment_count += 1
@@ -381,6 +425,21 @@ impl Framebuffer {
         }
     }
 
+    /// The framebuffer of the window, for passes that draw to it like to any other target. Owns no
+    /// GL object.
+    pub fn default_framebuffer(size: UVec2) -> Self {
+        Self {
+            _name: String::from("Default Framebuffer"),
+            id: 0,
+            size,
+            texture_attachments: vec![],
+            renderbuffer_attachments: vec![],
+            output_locations: vec![],
+            samples: Msaa::None as u32,
+            has_depth: false,
+        }
+    }
+
     pub fn clear(&self, clear_color: &Vec4) {
         self.texture_attachments
             .iter()
@@ -559,6 +618,59 @@ impl Framebuffer {
         }
     }
 
+    /// Copies the first color attachment of `source` into the default framebuffer, leaving its
+    /// depth and stencil untouched.
+    pub fn blit_color_to_default(
+        source: &Framebuffer,
+        default_framebuffer_size: UVec2,
+        filtering: TextureFilter,
+    ) {
+        unsafe {
+            gl::NamedFramebufferReadBuffer(source.id(), gl::COLOR_ATTACHMENT0);
+
+            gl::BlitNamedFramebuffer(
+                source.id(),
+                0,
+                0,
+                0,
+                source.size().x as i32,
+                source.size().y as i32,
+                0,
+                0,
+                default_framebuffer_size.x as i32,
+                default_framebuffer_size.y as i32,
+                gl::COLOR_BUFFER_BIT,
+                filtering as u32,
+            );
+        }
+    }
+
+    /// Copies the depth and stencil of `source` into `destination`. Both framebuffers must have
+    /// the same size and depth format.
+    pub fn blit_depth(source: &Framebuffer, destination: &Framebuffer) {
+        assert!(
+            source.has_depth && destination.has_depth,
+            "Both framebuffers need a depth attachment."
+        );
+
+        unsafe {
+            gl::BlitNamedFramebuffer(
+                source.id(),
+                destination.id(),
+                0,
+                0,
+                source.size().x as i32,
+                source.size().y as i32,
+                0,
+                0,
+                destination.size().x as i32,
+                destination.size().y as i32,
+                gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT,
+                gl::NEAREST,
+            );
+        }
+    }
+
     fn check_status(id: GLuint) -> Result<(), FramebufferError> {
         unsafe {
             let status = gl::CheckNamedFramebufferStatus(id, gl::DRAW_FRAMEBUFFER);
@@ -610,7 +722,9 @@ impl Framebuffer {
 
 impl Drop for Framebuffer {
     fn drop(&mut self) {
-        unsafe { gl::DeleteFramebuffers(1, &self.id) }
+        if self.id != 0 {
+            unsafe { gl::DeleteFramebuffers(1, &self.id) }
+        }
     }
 }
 
@@ -720,6 +834,7 @@ impl TemporaryFramebufferPool {
             name: format!("{}-Color", name),
             format,
             attachment_type: AttachmentType::Texture,
+            layers: 1,
         }];
 
         if let Some(depth_format) = depth_format {
@@ -727,6 +842,7 @@ impl TemporaryFramebufferPool {
                 name: format!("{}-Depth", name),
                 format: depth_format,
                 attachment_type: AttachmentType::Texture,
+                layers: 1,
             })
         }
 
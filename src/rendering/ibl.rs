@@ -0,0 +1,388 @@
+use std::mem;
+use std::path::Path;
+use std::rc::Rc;
+
+use crevice::std140::AsStd140;
+use gl::types::*;
+use gl_bindings as gl;
+use image::{Rgb, RgbImage};
+
+use crate::{
+    core::math::{UVec2, UVec3, Vec4},
+    rendering::{
+        buffer::{Buffer, BufferStorageFlags, BufferTarget},
+        sampler::{Anisotropy, MagnificationFilter, MinificationFilter, Sampler, WrappingMode},
+        shader::{ComputeShader, ImageAccess, ShaderCreateInfo, ShaderStage},
+        state::{MemoryBarrierFlags, StateManager},
+        texture::{SizedTextureFormat, Texture2D, TextureCube},
+    },
+    Context,
+};
+
+const UBO_BINDING_INDEX: u32 = 8;
+const LOCAL_SIZE: u32 = 8;
+
+const EQUIRECT_TO_CUBE_SHADER_PATH: &str = "assets/shaders/ibl/equirect_to_cube.comp";
+const IRRADIANCE_SHADER_PATH: &str = "assets/shaders/ibl/irradiance.comp";
+const PREFILTER_SHADER_PATH: &str = "assets/shaders/ibl/prefilter.comp";
+const BRDF_LUT_SHADER_PATH: &str = "assets/shaders/ibl/brdf_lut.comp";
+
+#[repr(C)]
+#[derive(Debug, AsStd140)]
+struct IblBakeUniforms {
+    perceptual_roughness: f32,
+    source_resolution: f32,
+    source_lod: f32,
+    sample_count: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IblBakeSettings {
+    pub environment_size: u32,
+    pub irradiance_size: u32,
+    pub irradiance_sample_count: u32,
+    pub radiance_size: u32,
+    /// Number of prefiltered mips. The last mip holds perceptual roughness 1, so shaders should
+    /// use `radiance_mip_levels - 1` as the max reflection LOD.
+    pub radiance_mip_levels: u32,
+    pub radiance_sample_count: u32,
+}
+
+impl Default for IblBakeSettings {
+    fn default() -> Self {
+        Self {
+            environment_size: 1024,
+            irradiance_size: 32,
+            irradiance_sample_count: 1024,
+            radiance_size: 256,
+            radiance_mip_levels: 6,
+            radiance_sample_count: 1024,
+        }
+    }
+}
+
+pub struct BakedEnvironment {
+    pub skybox: TextureCube,
+    pub irradiance: TextureCube,
+    pub radiance: TextureCube,
+}
+
+impl BakedEnvironment {
+    /// Writes `<name>_skybox.ktx`, `<name>_irradiance.ktx` and `<name>_radiance.ktx` to `directory`.
+    pub fn save_ktx<P: AsRef<Path>>(&self, directory: P, name: &str) -> Result<(), String> {
+        let directory = directory.as_ref();
+
+        self.skybox
+            .save_ktx(directory.join(format!("{}_skybox.ktx", name)))?;
+        self.irradiance
+            .save_ktx(directory.join(format!("{}_irradiance.ktx", name)))?;
+        self.radiance
+            .save_ktx(directory.join(format!("{}_radiance.ktx", name)))
+    }
+}
+
+/// Precomputes image based lighting data on the GPU: environment cubemaps from equirectangular
+/// HDR images, diffuse irradiance, GGX prefiltered radiance and the split sum BRDF LUT.
+pub struct IblBaker {
+    equirect_to_cube_shader: Rc<ComputeShader>,
+    irradiance_shader: Rc<ComputeShader>,
+    prefilter_shader: Rc<ComputeShader>,
+    brdf_lut_shader: Rc<ComputeShader>,
+    ubo: Buffer,
+    linear_sampler: Sampler,
+    trilinear_sampler: Sampler,
+}
+
+impl IblBaker {
+    pub fn new(context: Context) -> Self {
+        let Context { device, .. } = context;
+
+        let shader_manager = device.shader_manager();
+
+        let mut create_compute_shader = |name: &str, path: &str| {
+            shader_manager.create_compute_shader(
+                &ShaderCreateInfo::builder(name)
+                    .stage(ShaderStage::Compute, path)
+                    .build(),
+            )
+        };
+
+        let equirect_to_cube_shader =
+            create_compute_shader("IBL Equirect To Cube Shader", EQUIRECT_TO_CUBE_SHADER_PATH);
+        let irradiance_shader =
+            create_compute_shader("IBL Irradiance Shader", IRRADIANCE_SHADER_PATH);
+        let prefilter_shader = create_compute_shader("IBL Prefilter Shader", PREFILTER_SHADER_PATH);
+        let brdf_lut_shader = create_compute_shader("IBL BRDF LUT Shader", BRDF_LUT_SHADER_PATH);
+
+        let ubo = Buffer::new(
+            "IBL Bake UBO",
+            mem::size_of::<<IblBakeUniforms as AsStd140>::Std140Type>() as isize,
+            BufferTarget::Uniform,
+            BufferStorageFlags::DYNAMIC,
+        );
+
+        let linear_sampler = Sampler::new(
+            MinificationFilter::Linear,
+            MagnificationFilter::Linear,
+            WrappingMode::Repeat,
+            WrappingMode::ClampToEdge,
+            WrappingMode::ClampToEdge,
+            Vec4::new(0.0, 0.0, 0.0, 0.0),
+            Anisotropy::None,
+        );
+
+        let trilinear_sampler = Sampler::new(
+            MinificationFilter::LinearMipmapLinear,
+            MagnificationFilter::Linear,
+            WrappingMode::ClampToEdge,
+            WrappingMode::ClampToEdge,
+            WrappingMode::ClampToEdge,
+            Vec4::new(0.0, 0.0, 0.0, 0.0),
+            Anisotropy::None,
+        );
+
+        Self {
+            equirect_to_cube_shader,
+            irradiance_shader,
+            prefilter_shader,
+            brdf_lut_shader,
+            ubo,
+            linear_sampler,
+            trilinear_sampler,
+        }
+    }
+
+    /// Bakes a full environment from an equirectangular (latitude/longitude) HDR texture.
+    pub fn bake(
+        &self,
+        equirectangular: &Texture2D,
+        settings: &IblBakeSettings,
+    ) -> BakedEnvironment {
+        let skybox = self.equirectangular_to_cubemap(equirectangular, settings.environment_size);
+        let irradiance = self.irradiance(
+            &skybox,
+            settings.irradiance_size,
+            settings.irradiance_sample_count,
+        );
+        let radiance = self.prefilter(
+            &skybox,
+            settings.radiance_size,
+            settings.radiance_mip_levels,
+            settings.radiance_sample_count,
+        );
+
+        BakedEnvironment {
+            skybox,
+            irradiance,
+            radiance,
+        }
+    }
+
+    /// Projects an equirectangular texture onto a mipmapped RGBA16F cubemap.
+    pub fn equirectangular_to_cubemap(
+        &self,
+        equirectangular: &Texture2D,
+        size: u32,
+    ) -> TextureCube {
+        let mip_levels = (size as f32).log2().floor() as u32 + 1;
+        let cubemap = TextureCube::new(
+            "IBL Environment",
+            size,
+            SizedTextureFormat::Rgba16f,
+            mip_levels,
+        );
+
+        self.update_uniforms(0.0, 0.0, 0.0, 0);
+
+        self.equirect_to_cube_shader
+            .bind_texture_2d(0, equirectangular, &self.linear_sampler)
+            .bind_image_cube(
+                0,
+                &cubemap,
+                0,
+                ImageAccess::WriteOnly,
+                SizedTextureFormat::Rgba16f,
+            );
+
+        Self::dispatch_cube(&self.equirect_to_cube_shader, size);
+
+        StateManager::memory_barrier(
+            MemoryBarrierFlags::TEXTURE_FETCH | MemoryBarrierFlags::TEXTURE_UPDATE,
+        );
+
+        cubemap.generate_mipmaps();
+
+        cubemap
+    }
+
+    /// Convolves the environment with a cosine lobe. The result is irradiance divided by PI.
+    pub fn irradiance(
+        &self,
+        environment: &TextureCube,
+        size: u32,
+        sample_count: u32,
+    ) -> TextureCube {
+        let irradiance = TextureCube::new("IBL Irradiance", size, SizedTextureFormat::Rgba16f, 1);
+
+        self.update_uniforms(0.0, environment.size() as f32, 0.0, sample_count);
+
+        self.irradiance_shader
+            .bind_texture_cube(0, environment, &self.trilinear_sampler)
+            .bind_image_cube(
+                0,
+                &irradiance,
+                0,
+                ImageAccess::WriteOnly,
+                SizedTextureFormat::Rgba16f,
+            );
+
+        Self::dispatch_cube(&self.irradiance_shader, size);
+
+        StateManager::memory_barrier(
+            MemoryBarrierFlags::TEXTURE_FETCH | MemoryBarrierFlags::TEXTURE_UPDATE,
+        );
+
+        irradiance
+    }
+
+    /// Builds the GGX prefiltered radiance mip chain. Mip `m` is filtered with the perceptual
+    /// roughness that `PerceptualRoughnessToLod` in `ibl.glsl` maps to lod `m`.
+    pub fn prefilter(
+        &self,
+        environment: &TextureCube,
+        size: u32,
+        mip_levels: u32,
+        sample_count: u32,
+    ) -> TextureCube {
+        let max_mip_levels = (size as f32).log2().floor() as u32 + 1;
+        let mip_levels = mip_levels.clamp(1, max_mip_levels);
+
+        let radiance = TextureCube::new(
+            "IBL Radiance",
+            size,
+            SizedTextureFormat::Rgba16f,
+            mip_levels,
+        );
+
+        self.prefilter_shader
+            .bind_texture_cube(0, environment, &self.trilinear_sampler);
+
+        for level in 0..mip_levels {
+            let perceptual_roughness =
+                lod_to_perceptual_roughness(level as f32, (mip_levels - 1) as f32);
+
+            self.update_uniforms(
+                perceptual_roughness,
+                environment.size() as f32,
+                0.0,
+                sample_count,
+            );
+
+            self.prefilter_shader.bind_image_cube(
+                0,
+                &radiance,
+                level,
+                ImageAccess::WriteOnly,
+                SizedTextureFormat::Rgba16f,
+            );
+
+            Self::dispatch_cube(&self.prefilter_shader, u32::max(size >> level, 1));
+        }
+
+        StateManager::memory_barrier(
+            MemoryBarrierFlags::TEXTURE_FETCH | MemoryBarrierFlags::TEXTURE_UPDATE,
+        );
+
+        radiance
+    }
+
+    /// Integrates the split sum environment BRDF into an RG16F texture indexed by (NoV, perceptual roughness).
+    pub fn bake_brdf_lut(&self, size: u32, sample_count: u32) -> Texture2D {
+        let lut = Texture2D::new(
+            "IBL BRDF LUT",
+            UVec2::new(size, size),
+            SizedTextureFormat::Rg16f,
+            1,
+        );
+
+        self.update_uniforms(0.0, 0.0, 0.0, sample_count);
+
+        self.brdf_lut_shader
+            .bind_image_2d(0, &lut, 0, ImageAccess::WriteOnly);
+
+        let work_groups = ComputeShader::work_group_count(
+            UVec3::new(size, size, 1),
+            UVec3::new(LOCAL_SIZE, LOCAL_SIZE, 1),
+        );
+        self.brdf_lut_shader
+            .dispatch(work_groups.x, work_groups.y, work_groups.z);
+
+        StateManager::memory_barrier(
+            MemoryBarrierFlags::TEXTURE_FETCH | MemoryBarrierFlags::TEXTURE_UPDATE,
+        );
+
+        lut
+    }
+
+    /// Saves a LUT produced by `bake_brdf_lut` as an 8bit PNG in the layout of `ibl_brdf_lut.png`.
+    pub fn save_brdf_lut<P: AsRef<Path>>(lut: &Texture2D, path: P) -> Result<(), String> {
+        let size = lut.size();
+        let mut data = vec![0.0f32; (size.x * size.y * 2) as usize];
+
+        unsafe {
+            gl::GetTextureImage(
+                lut.get_id(),
+                0,
+                gl::RG,
+                gl::FLOAT,
+                (data.len() * mem::size_of::<f32>()) as GLsizei,
+                data.as_mut_ptr() as *mut GLvoid,
+            );
+        }
+
+        let to_unorm = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+        let image = RgbImage::from_fn(size.x, size.y, |x, y| {
+            let index = ((y * size.x + x) * 2) as usize;
+            Rgb([to_unorm(data[index]), to_unorm(data[index + 1]), 0])
+        });
+
+        image.save(path.as_ref()).map_err(|e| e.to_string())
+    }
+
+    fn update_uniforms(
+        &self,
+        perceptual_roughness: f32,
+        source_resolution: f32,
+        source_lod: f32,
+        sample_count: u32,
+    ) {
+        let uniforms = IblBakeUniforms {
+            perceptual_roughness,
+            source_resolution,
+            source_lod,
+            sample_count,
+        };
+
+        self.ubo.fill(0, &uniforms.as_std140());
+        self.ubo.bind(UBO_BINDING_INDEX);
+    }
+
+    fn dispatch_cube(shader: &ComputeShader, face_size: u32) {
+        let work_groups = ComputeShader::work_group_count(
+            UVec3::new(face_size, face_size, 6),
+            UVec3::new(LOCAL_SIZE, LOCAL_SIZE, 1),
+        );
+
+        shader.dispatch(work_groups.x, work_groups.y, work_groups.z);
+    }
+}
+
+/// Inverse of `PerceptualRoughnessToLod` in `ibl.glsl`: lod = max_lod * r * (2 - r).
+pub fn lod_to_perceptual_roughness(lod: f32, max_lod: f32) -> f32 {
+    if max_lod <= 0.0 {
+        return 0.0;
+    }
+
+    1.0 - (1.0 - (lod / max_lod).clamp(0.0, 1.0)).sqrt()
+}
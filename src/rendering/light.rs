@@ -1,9 +1,339 @@
-use crate::math::Vec3;
+use std::f32::consts::PI;
+use std::mem;
 
-#[repr(C)]
-#[derive(Debug)]
+use crate::core::math::Vec3;
+use crate::rendering::buffer::{Buffer, BufferStorageFlags, BufferTarget};
+use crate::rendering::color::kelvin_to_rgb;
+use crate::rendering::point_shadows::PointShadowAtlas;
+
+const LIGHT_BUFFER_BINDING_INDEX: u32 = 0;
+const DEFAULT_LIGHT_CAPACITY: usize = 64;
+
+// Must match the LIGHT_TYPE_* defines in lights.glsl.
+const LIGHT_TYPE_DIRECTIONAL: i32 = 0;
+const LIGHT_TYPE_POINT: i32 = 1;
+const LIGHT_TYPE_SPOT: i32 = 2;
+
+// Smallest cosine difference between the inner and outer cone, avoids a division by zero when
+// both angles are equal.
+const MIN_SPOT_COSINE_DIFFERENCE: f32 = 1e-4;
+
+/// Luminous intensity in candela of a point light emitting `luminous_power` lumens uniformly in
+/// all directions.
+pub fn point_light_intensity(luminous_power: f32) -> f32 {
+    luminous_power / (4.0 * PI)
+}
+
+/// Luminous intensity in candela of a spot light emitting `luminous_power` lumens. The intensity
+/// is intentionally decoupled from the cone angles so that changing the cone does not change the
+/// brightness of the lit area.
+pub fn spot_light_intensity(luminous_power: f32) -> f32 {
+    luminous_power / PI
+}
+
+/// Scale and offset applied to cos(angle to the spot axis) so that the angular attenuation goes
+/// from 0 at the outer angle to 1 at the inner angle. Angles are half angles in degrees.
+pub fn spot_angle_scale_offset(inner_angle: f32, outer_angle: f32) -> (f32, f32) {
+    let outer_angle = outer_angle.max(0.0).min(90.0);
+    let inner_angle = inner_angle.max(0.0).min(outer_angle);
+
+    let cos_outer = outer_angle.to_radians().cos();
+    let cos_inner = inner_angle.to_radians().cos();
+
+    let scale = 1.0 / (cos_inner - cos_outer).max(MIN_SPOT_COSINE_DIFFERENCE);
+    let offset = -cos_outer * scale;
+
+    (scale, offset)
+}
+
+pub(crate) fn light_color(color: &Vec3, temperature: Option<f32>) -> Vec3 {
+    match temperature {
+        Some(temperature) => color.component_mul(&kelvin_to_rgb(temperature)),
+        None => *color,
+    }
+}
+
+fn inverse_range_squared(range: f32) -> f32 {
+    1.0 / f32::max(range * range, f32::EPSILON)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DirectionalLight {
+    /// World space direction the light travels in.
+    pub direction: Vec3,
+    /// Linear RGB color, tinted by `temperature` when one is given.
+    pub color: Vec3,
+    /// Color temperature in Kelvin.
+    pub temperature: Option<f32>,
+    /// Illuminance in lux.
+    pub illuminance: f32,
+    /// Whether the light is shadowed by the cascaded shadow map. Only one directional light
+    /// should cast shadows.
+    pub cast_shadows: bool,
+}
+
+impl Default for DirectionalLight {
+    fn default() -> Self {
+        Self {
+            direction: Vec3::new(0.0, -1.0, 0.0),
+            color: Vec3::new(1.0, 1.0, 1.0),
+            temperature: None,
+            illuminance: 5.0,
+            cast_shadows: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointLight {
+    pub position: Vec3,
+    /// Distance at which the light no longer contributes.
+    pub range: f32,
+    /// Linear RGB color, tinted by `temperature` when one is given.
+    pub color: Vec3,
+    /// Color temperature in Kelvin.
+    pub temperature: Option<f32>,
+    /// Luminous power in lumens.
+    pub luminous_power: f32,
+    /// Whether the light requests a slot in the point shadow atlas.
+    pub cast_shadows: bool,
+}
+
+impl Default for PointLight {
+    fn default() -> Self {
+        Self {
+            position: Vec3::zeros(),
+            range: 10.0,
+            color: Vec3::new(1.0, 1.0, 1.0),
+            temperature: None,
+            luminous_power: 800.0,
+            cast_shadows: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpotLight {
+    pub position: Vec3,
+    /// World space direction the spot points to.
+    pub direction: Vec3,
+    /// Distance at which the light no longer contributes.
+    pub range: f32,
+    /// Linear RGB color, tinted by `temperature` when one is given.
+    pub color: Vec3,
+    /// Color temperature in Kelvin.
+    pub temperature: Option<f32>,
+    /// Luminous power in lumens.
+    pub luminous_power: f32,
+    /// Half angle in degrees of the fully lit cone.
+    pub inner_angle: f32,
+    /// Half angle in degrees at which the light falls off to zero.
+    pub outer_angle: f32,
+}
+
+impl Default for SpotLight {
+    fn default() -> Self {
+        Self {
+            position: Vec3::zeros(),
+            direction: Vec3::new(0.0, -1.0, 0.0),
+            range: 10.0,
+            color: Vec3::new(1.0, 1.0, 1.0),
+            temperature: None,
+            luminous_power: 800.0,
+            inner_angle: 20.0,
+            outer_angle: 30.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Light {
-    Directional { direction: Vec3, temperature: u32 },
-    Point,
-    Spotlight,
+    Directional(DirectionalLight),
+    Point(PointLight),
+    Spot(SpotLight),
+}
+
+impl From<DirectionalLight> for Light {
+    fn from(light: DirectionalLight) -> Self {
+        Light::Directional(light)
+    }
+}
+
+impl From<PointLight> for Light {
+    fn from(light: PointLight) -> Self {
+        Light::Point(light)
+    }
+}
+
+impl From<SpotLight> for Light {
+    fn from(light: SpotLight) -> Self {
+        Light::Spot(light)
+    }
+}
+
+/// GPU layout of a single entry of the `LightBuffer` shader storage block (std430).
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct GpuLight {
+    position: [f32; 3],
+    inverse_range_squared: f32,
+    color: [f32; 3],
+    intensity: f32,
+    direction: [f32; 3],
+    light_type: i32,
+    spot_scale_offset: [f32; 2],
+    shadow_index: i32,
+    _padding: f32,
+}
+
+impl GpuLight {
+    fn new(light: &Light, shadow_index: Option<usize>) -> Self {
+        let shadow_index = shadow_index.map_or(-1, |index| index as i32);
+
+        match light {
+            Light::Directional(light) => GpuLight {
+                color: light_color(&light.color, light.temperature).into(),
+                intensity: light.illuminance,
+                direction: light.direction.normalize().into(),
+                light_type: LIGHT_TYPE_DIRECTIONAL,
+                shadow_index: if light.cast_shadows { 0 } else { -1 },
+                ..Default::default()
+            },
+            Light::Point(light) => GpuLight {
+                position: light.position.into(),
+                inverse_range_squared: inverse_range_squared(light.range),
+                color: light_color(&light.color, light.temperature).into(),
+                intensity: point_light_intensity(light.luminous_power),
+                light_type: LIGHT_TYPE_POINT,
+                shadow_index,
+                ..Default::default()
+            },
+            Light::Spot(light) => {
+                let (scale, offset) = spot_angle_scale_offset(light.inner_angle, light.outer_angle);
+
+                GpuLight {
+                    position: light.position.into(),
+                    inverse_range_squared: inverse_range_squared(light.range),
+                    color: light_color(&light.color, light.temperature).into(),
+                    intensity: spot_light_intensity(light.luminous_power),
+                    direction: light.direction.normalize().into(),
+                    light_type: LIGHT_TYPE_SPOT,
+                    spot_scale_offset: [scale, offset],
+                    shadow_index: -1,
+                    ..Default::default()
+                }
+            }
+        }
+    }
+}
+
+// The light count is padded to 16 bytes so that the std430 light array that follows it starts
+// at the alignment of `GpuLight`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct LightBufferHeader {
+    light_count: u32,
+    _padding: [u32; 3],
+}
+
+/// Shader storage buffer holding every light of a scene, bound to the `LightBuffer` block.
+/// Grows when more lights than its capacity are uploaded.
+pub struct LightBuffer {
+    buffer: Buffer,
+    capacity: usize,
+    light_count: usize,
+    gpu_lights: Vec<GpuLight>,
+}
+
+impl Default for LightBuffer {
+    fn default() -> Self {
+        Self::new(DEFAULT_LIGHT_CAPACITY)
+    }
+}
+
+impl LightBuffer {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = usize::max(capacity, 1);
+
+        let light_buffer = Self {
+            buffer: Self::create_buffer(capacity),
+            capacity,
+            light_count: 0,
+            gpu_lights: Vec::with_capacity(capacity),
+        };
+
+        light_buffer.buffer.fill(0, &LightBufferHeader::default());
+        light_buffer.bind();
+
+        light_buffer
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.light_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.light_count == 0
+    }
+
+    /// Uploads `lights` and binds the buffer. Point lights are not shadowed.
+    pub fn update(&mut self, lights: &[Light]) {
+        self.upload(lights, |_| None)
+    }
+
+    /// Uploads `lights` and binds the buffer. The point lights that got a slot in `shadow_atlas`
+    /// sample their shadows from it. `lights` must be the slice the atlas was updated with.
+    pub fn update_with_shadows(&mut self, lights: &[Light], shadow_atlas: &PointShadowAtlas) {
+        self.upload(lights, |index| shadow_atlas.shadow_index(index))
+    }
+
+    fn upload<F: Fn(usize) -> Option<usize>>(&mut self, lights: &[Light], shadow_index: F) {
+        if lights.len() > self.capacity {
+            self.capacity = lights.len().next_power_of_two();
+            self.buffer = Self::create_buffer(self.capacity);
+        }
+
+        self.gpu_lights.clear();
+        self.gpu_lights.extend(
+            lights
+                .iter()
+                .enumerate()
+                .map(|(index, light)| GpuLight::new(light, shadow_index(index))),
+        );
+        self.light_count = lights.len();
+
+        let header = LightBufferHeader {
+            light_count: self.light_count as u32,
+            ..Default::default()
+        };
+
+        self.buffer.fill(0, &header);
+        if !self.gpu_lights.is_empty() {
+            self.buffer.fill_slice(
+                mem::size_of::<LightBufferHeader>() as isize,
+                &self.gpu_lights,
+            );
+        }
+
+        self.bind();
+    }
+
+    /// Binds the buffer to the `LightBuffer` block. Only needed when another pass used the same
+    /// binding since the last `update`.
+    pub fn bind(&self) {
+        self.buffer.bind(LIGHT_BUFFER_BINDING_INDEX);
+    }
+
+    fn create_buffer(capacity: usize) -> Buffer {
+        Buffer::new(
+            "Light Buffer",
+            (mem::size_of::<LightBufferHeader>() + capacity * mem::size_of::<GpuLight>()) as isize,
+            BufferTarget::ShaderStorage,
+            BufferStorageFlags::DYNAMIC,
+        )
+    }
 }
@@ -0,0 +1,339 @@
+use std::mem;
+use std::rc::Rc;
+
+use crate::{
+    core::camera::Camera,
+    core::math::{UVec2, UVec3},
+    imgui::{Gui, Ui},
+    rendering::{
+        buffer::{Buffer, BufferStorageFlags, BufferTarget},
+        shader::{ComputeShader, ShaderCreateInfo, ShaderStage},
+        state::{MemoryBarrierFlags, StateManager},
+    },
+    Context,
+};
+
+/// Must match MAX_LIGHTS_PER_CLUSTER in light_clusters.glsl.
+pub const MAX_LIGHTS_PER_CLUSTER: u32 = 128;
+
+// Size of the light index list, in indices per cluster. Clusters that do not fit are truncated.
+const AVERAGE_LIGHTS_PER_CLUSTER: u32 = 32;
+
+const CLUSTER_UBO_BINDING_INDEX: u32 = 11;
+const CLUSTER_BOUNDS_BUFFER_BINDING_INDEX: u32 = 2;
+const CLUSTER_LIGHT_GRID_BUFFER_BINDING_INDEX: u32 = 3;
+const CLUSTER_LIGHT_INDICES_BUFFER_BINDING_INDEX: u32 = 4;
+
+const LOCAL_SIZE: u32 = 64;
+
+const CLUSTER_BOUNDS_SHADER_PATH: &str = "assets/shaders/clusters/cluster_bounds.comp";
+const CLUSTER_ASSIGN_SHADER_PATH: &str = "assets/shaders/clusters/cluster_assign.comp";
+
+/// Scale and bias mapping the natural logarithm of a view depth to its depth slice, for slices
+/// distributed exponentially between `near` and `far`.
+pub fn cluster_depth_scale_bias(near: f32, far: f32, slice_count: u32) -> (f32, f32) {
+    let log_depth_range = (far / near).ln();
+    let scale = slice_count as f32 / log_depth_range;
+    let bias = -(slice_count as f32) * near.ln() / log_depth_range;
+
+    (scale, bias)
+}
+
+/// View depth at which `slice` starts. Slice `slice_count` ends at `far`.
+pub fn cluster_slice_depth(near: f32, far: f32, slice_count: u32, slice: u32) -> f32 {
+    near * (far / near).powf(slice as f32 / slice_count as f32)
+}
+
+/// Size in pixels of the screen tile covered by a cluster.
+pub fn cluster_tile_size(viewport_size: UVec2, grid_size: UVec3) -> UVec2 {
+    UVec2::new(
+        (viewport_size.x + grid_size.x - 1) / grid_size.x,
+        (viewport_size.y + grid_size.y - 1) / grid_size.y,
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LightClusterSettings {
+    /// Number of clusters along the screen width, the screen height and the view depth.
+    pub grid_size: UVec3,
+    /// Light count drawn as the hottest color of the heatmap.
+    pub heatmap_scale: f32,
+}
+
+impl Default for LightClusterSettings {
+    fn default() -> Self {
+        Self {
+            grid_size: UVec3::new(16, 9, 24),
+            heatmap_scale: 32.0,
+        }
+    }
+}
+
+// Plain std140 layout of the `ClusterBlock` uniform block.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct ClusterBlock {
+    grid_size: [u32; 4],
+    tile_size: [f32; 4],
+    depth_params: [f32; 4],
+    max_light_indices: u32,
+    heatmap_scale: f32,
+    _padding: [f32; 2],
+}
+
+// Everything the cluster bounds depend on. They are only rebuilt when one of these changes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ClusterGridKey {
+    viewport_size: UVec2,
+    grid_size: UVec3,
+    fov_deg: u32,
+    aspect_ratio: f32,
+    near: f32,
+    far: f32,
+}
+
+/// Clustered forward lighting. Splits the view frustum into a grid of froxels, exponentially
+/// along the view depth, and assigns the lights of the `LightBuffer` to every froxel they touch
+/// in a compute pass. Lit shaders then only evaluate the lights of the froxel of each fragment.
+pub struct LightClusters {
+    settings: LightClusterSettings,
+    bounds_shader: Rc<ComputeShader>,
+    assign_shader: Rc<ComputeShader>,
+    cluster_ubo: Buffer,
+    bounds_buffer: Buffer,
+    light_grid_buffer: Buffer,
+    light_indices_buffer: Buffer,
+    max_light_indices: u32,
+    built_for: Option<ClusterGridKey>,
+}
+
+impl LightClusters {
+    pub fn new(context: Context, settings: LightClusterSettings) -> Self {
+        let Context { device, .. } = context;
+
+        let shader_manager = device.shader_manager();
+
+        let mut create_compute_shader = |name: &str, path: &str| {
+            shader_manager.create_compute_shader(
+                &ShaderCreateInfo::builder(name)
+                    .stage(ShaderStage::Compute, path)
+                    .build(),
+            )
+        };
+
+        let bounds_shader =
+            create_compute_shader("Cluster Bounds Shader", CLUSTER_BOUNDS_SHADER_PATH);
+        let assign_shader = create_compute_shader(
+            "Cluster Light Assignment Shader",
+            CLUSTER_ASSIGN_SHADER_PATH,
+        );
+
+        let cluster_ubo = Buffer::new(
+            "Cluster UBO",
+            mem::size_of::<ClusterBlock>() as isize,
+            BufferTarget::Uniform,
+            BufferStorageFlags::DYNAMIC,
+        );
+
+        let settings = Self::validate_settings(settings);
+        let cluster_count = Self::grid_cluster_count(settings.grid_size);
+        let max_light_indices = cluster_count * AVERAGE_LIGHTS_PER_CLUSTER;
+
+        let clusters = Self {
+            bounds_buffer: Self::create_bounds_buffer(cluster_count),
+            light_grid_buffer: Self::create_light_grid_buffer(cluster_count),
+            light_indices_buffer: Self::create_light_indices_buffer(max_light_indices),
+            settings,
+            bounds_shader,
+            assign_shader,
+            cluster_ubo,
+            max_light_indices,
+            built_for: None,
+        };
+
+        // The lit shaders declare the cluster blocks even when clustered lighting is off, keeps
+        // them backed by buffers until the first update.
+        clusters.cluster_ubo.bind(CLUSTER_UBO_BINDING_INDEX);
+        clusters.bind();
+
+        clusters
+    }
+
+    pub fn settings(&self) -> &LightClusterSettings {
+        &self.settings
+    }
+
+    /// Recreates the cluster buffers when the grid size changes.
+    pub fn set_settings(&mut self, settings: LightClusterSettings) {
+        let settings = Self::validate_settings(settings);
+
+        if settings.grid_size != self.settings.grid_size {
+            let cluster_count = Self::grid_cluster_count(settings.grid_size);
+
+            self.max_light_indices = cluster_count * AVERAGE_LIGHTS_PER_CLUSTER;
+            self.bounds_buffer = Self::create_bounds_buffer(cluster_count);
+            self.light_grid_buffer = Self::create_light_grid_buffer(cluster_count);
+            self.light_indices_buffer = Self::create_light_indices_buffer(self.max_light_indices);
+            self.built_for = None;
+            self.bind();
+        }
+
+        self.settings = settings;
+    }
+
+    pub fn cluster_count(&self) -> u32 {
+        Self::grid_cluster_count(self.settings.grid_size)
+    }
+
+    /// Rebuilds the cluster bounds if the camera projection or the viewport changed, then
+    /// assigns the lights currently in the `LightBuffer` to the clusters. Must be called after
+    /// the `LightBuffer` has been updated for the frame.
+    pub fn update(&mut self, camera: &Camera, viewport_size: UVec2) {
+        let grid_size = self.settings.grid_size;
+        let cluster_count = self.cluster_count();
+        let near = camera.near_plane();
+        let far = camera.far_plane();
+
+        let (depth_scale, depth_bias) = cluster_depth_scale_bias(near, far, grid_size.z);
+        let tile_size = cluster_tile_size(viewport_size, grid_size);
+
+        let block = ClusterBlock {
+            grid_size: [grid_size.x, grid_size.y, grid_size.z, cluster_count],
+            tile_size: [
+                tile_size.x as f32,
+                tile_size.y as f32,
+                1.0 / viewport_size.x.max(1) as f32,
+                1.0 / viewport_size.y.max(1) as f32,
+            ],
+            depth_params: [depth_scale, depth_bias, near, far],
+            max_light_indices: self.max_light_indices,
+            heatmap_scale: self.settings.heatmap_scale,
+            ..Default::default()
+        };
+
+        self.cluster_ubo.fill(0, &block);
+        self.cluster_ubo.bind(CLUSTER_UBO_BINDING_INDEX);
+
+        self.bind();
+
+        let work_groups = ComputeShader::work_group_count(
+            UVec3::new(cluster_count, 1, 1),
+            UVec3::new(LOCAL_SIZE, 1, 1),
+        );
+
+        let key = ClusterGridKey {
+            viewport_size,
+            grid_size,
+            fov_deg: camera.fov_deg(),
+            aspect_ratio: camera.aspect_ratio(),
+            near,
+            far,
+        };
+
+        if self.built_for != Some(key) {
+            self.bounds_shader
+                .dispatch(work_groups.x, work_groups.y, work_groups.z);
+
+            StateManager::memory_barrier(MemoryBarrierFlags::SHADER_STORAGE);
+
+            self.built_for = Some(key);
+        }
+
+        // Resets the global index counter.
+        self.light_indices_buffer.fill(0, &0u32);
+
+        self.assign_shader
+            .dispatch(work_groups.x, work_groups.y, work_groups.z);
+
+        StateManager::memory_barrier(MemoryBarrierFlags::SHADER_STORAGE);
+    }
+
+    /// Binds the cluster buffers. Only needed when another pass used the same bindings since the
+    /// last `update`.
+    pub fn bind(&self) {
+        self.bounds_buffer.bind(CLUSTER_BOUNDS_BUFFER_BINDING_INDEX);
+        self.light_grid_buffer
+            .bind(CLUSTER_LIGHT_GRID_BUFFER_BINDING_INDEX);
+        self.light_indices_buffer
+            .bind(CLUSTER_LIGHT_INDICES_BUFFER_BINDING_INDEX);
+    }
+
+    fn validate_settings(settings: LightClusterSettings) -> LightClusterSettings {
+        LightClusterSettings {
+            grid_size: settings.grid_size.map(|size| size.max(1)),
+            heatmap_scale: settings.heatmap_scale.max(1.0),
+        }
+    }
+
+    fn grid_cluster_count(grid_size: UVec3) -> u32 {
+        grid_size.x * grid_size.y * grid_size.z
+    }
+
+    // Two vec4 (min and max corner) per cluster.
+    fn create_bounds_buffer(cluster_count: u32) -> Buffer {
+        Buffer::new(
+            "Cluster Bounds Buffer",
+            (cluster_count as usize * 2 * mem::size_of::<[f32; 4]>()) as isize,
+            BufferTarget::ShaderStorage,
+            BufferStorageFlags::NONE,
+        )
+    }
+
+    // Offset and count in the light index list per cluster.
+    fn create_light_grid_buffer(cluster_count: u32) -> Buffer {
+        Buffer::new(
+            "Cluster Light Grid Buffer",
+            (cluster_count as usize * mem::size_of::<[u32; 2]>()) as isize,
+            BufferTarget::ShaderStorage,
+            BufferStorageFlags::NONE,
+        )
+    }
+
+    // Global index counter followed by the light index list.
+    fn create_light_indices_buffer(max_light_indices: u32) -> Buffer {
+        Buffer::new(
+            "Cluster Light Indices Buffer",
+            ((max_light_indices as usize + 1) * mem::size_of::<u32>()) as isize,
+            BufferTarget::ShaderStorage,
+            BufferStorageFlags::DYNAMIC,
+        )
+    }
+}
+
+impl Gui for LightClusters {
+    fn gui(&mut self, ui: &Ui) {
+        imgui::TreeNode::new("Light Clusters")
+            .default_open(false)
+            .open_on_arrow(true)
+            .open_on_double_click(true)
+            .framed(false)
+            .build(ui, || {
+                ui.text(format!("Clusters: {}", self.cluster_count()));
+
+                let mut settings = self.settings;
+
+                let mut grid_size = [
+                    settings.grid_size.x as i32,
+                    settings.grid_size.y as i32,
+                    settings.grid_size.z as i32,
+                ];
+                if imgui::Drag::new("Grid Size")
+                    .range(1, 64)
+                    .speed(0.1)
+                    .build_array(ui, &mut grid_size)
+                {
+                    settings.grid_size = UVec3::new(
+                        grid_size[0] as u32,
+                        grid_size[1] as u32,
+                        grid_size[2] as u32,
+                    );
+                }
+
+                imgui::Slider::new("Heatmap Scale", 1.0, MAX_LIGHTS_PER_CLUSTER as f32)
+                    .display_format("%.0f")
+                    .build(ui, &mut settings.heatmap_scale);
+
+                self.set_settings(settings);
+            });
+    }
+}
@@ -36,6 +36,7 @@ pub trait Material: Gui {
 #[derive(Debug, Clone, Copy, AsStd140)]
 struct MaterialPropertyBlock {
     base_color: mint::Vector4<f32>,
+    emissive: mint::Vector4<f32>,
     metallic_scale: f32,
     metallic_bias: f32,
     roughness_scale: f32,
@@ -58,6 +59,7 @@ pub struct PbsMetallicRoughnessMaterial {
     sampler: Sampler,
     property_block: MaterialPropertyBlock,
     shader: Rc<Shader>,
+    gbuffer_shader: Rc<Shader>,
     material_ubo: Buffer,
     parallax_mapping_method: usize,
 }
@@ -86,12 +88,29 @@ impl PbsMetallicRoughnessMaterial {
             .keyword_set(&["_", "FEATURE_SPECULAR_AA"])
             .keyword_set(&["_", "FEATURE_SPECULAR_AO"])
             .keyword_set(&["FEATURE_BRDF_FILLAMENT", "FEATURE_BRDF_UE4"])
+            .keyword_set(&["_", "FEATURE_SH_IRRADIANCE"])
+            .keyword_set(&["_", "FEATURE_SHADOWS", "FEATURE_SHADOWS_PCF"])
             .build();
 
         let shader = device.shader_manager().create_shader(&create_info);
 
+        let gbuffer_create_info = ShaderCreateInfo::builder("PBS G-Buffer Shader")
+            .stage(
+                ShaderStage::Vertex,
+                asset_path.as_ref().join("shaders/pbs.vert"),
+            )
+            .stage(
+                ShaderStage::Fragment,
+                asset_path.as_ref().join("shaders/deferred/gbuffer.frag"),
+            )
+            .keyword_set(&["_", "FEATURE_PARALLAX_MAPPING"])
+            .build();
+
+        let gbuffer_shader = device.shader_manager().create_shader(&gbuffer_create_info);
+
         if displacement.is_some() {
-            shader.enable_keyword("FEATURE_PARALLAX_MAPPING")
+            shader.enable_keyword("FEATURE_PARALLAX_MAPPING");
+            gbuffer_shader.enable_keyword("FEATURE_PARALLAX_MAPPING");
         }
 
         let sampler = Sampler::new(
@@ -131,6 +150,7 @@ impl PbsMetallicRoughnessMaterial {
             sampler,
             property_block: MaterialPropertyBlock {
                 base_color: [1.0, 1.0, 1.0, 1.0].into(),
+                emissive: [1.0, 1.0, 1.0, 0.0].into(),
                 metallic_scale: 1.0,
                 metallic_bias: 0.0,
                 roughness_scale: 1.0,
@@ -144,20 +164,42 @@ impl PbsMetallicRoughnessMaterial {
                 parallax_mapping_method: 0,
             },
             shader,
+            gbuffer_shader,
             material_ubo,
             parallax_mapping_method: 4,
         }
     }
-}
 
-impl Material for PbsMetallicRoughnessMaterial {
-    fn bind(&self) {
-        self.shader.bind();
+    /// Shader writing the material to the G-buffer of the deferred path.
+    pub fn gbuffer_shader(&self) -> Rc<Shader> {
+        Rc::clone(&self.gbuffer_shader)
+    }
+
+    /// Binds the G-buffer shader and the material resources. Unbind with `unbind_gbuffer`.
+    pub fn bind_gbuffer(&self) {
+        self.gbuffer_shader.bind();
+        self.bind_resources(&self.gbuffer_shader);
+    }
 
+    pub fn unbind_gbuffer(&self) {
+        self.gbuffer_shader.unbind();
+    }
+
+    /// Binds the split-sum BRDF LUT used by the image based lighting of `shader`, for lighting
+    /// passes that do not bind the material itself.
+    pub fn bind_brdf_lut(&self, shader: &Shader) {
+        shader.bind_texture_2d(
+            BRDF_LUT_MAP_BINDING_INDEX,
+            &self.ibl_brdf_lut,
+            &self.sampler,
+        );
+    }
+
+    fn bind_resources(&self, shader: &Shader) {
         self.material_ubo
             .fill_mapped(0, &self.property_block.as_std140());
 
-        self.shader
+        shader
             .bind_texture_2d(ALBEDO_MAP_BINDING_INDEX, &self.albedo, &self.sampler)
             .bind_texture_2d(
                 M_R_AO_MAP_BINDING_INDEX,
@@ -172,13 +214,20 @@ impl Material for PbsMetallicRoughnessMaterial {
             );
 
         if let Some(displacement) = &self.displacement {
-            self.shader.bind_texture_2d(
+            shader.bind_texture_2d(
                 DISPLACEMENT_MAP_BINDING_INDEX,
                 displacement,
                 &self.sampler,
             );
         }
     }
+}
+
+impl Material for PbsMetallicRoughnessMaterial {
+    fn bind(&self) {
+        self.shader.bind();
+        self.bind_resources(&self.shader);
+    }
 
     fn unbind(&self) {
         self.shader.unbind();
@@ -215,6 +264,25 @@ impl Gui for PbsMetallicRoughnessMaterial {
                     {
                         self.property_block.base_color = albedo_color.into()
                     }
+
+                    let mut emissive_color = [
+                        self.property_block.emissive.x,
+                        self.property_block.emissive.y,
+                        self.property_block.emissive.z,
+                    ];
+                    if imgui::ColorEdit::new("Emissive Color", &mut emissive_color)
+                        .format(ColorFormat::Float)
+                        .alpha(false)
+                        .picker(true)
+                        .build(ui)
+                    {
+                        self.property_block.emissive.x = emissive_color[0];
+                        self.property_block.emissive.y = emissive_color[1];
+                        self.property_block.emissive.z = emissive_color[2];
+                    }
+                    imgui::Slider::new("Emissive Intensity", 0.0, 100.0)
+                        .display_format("%.1f")
+                        .build(ui, &mut self.property_block.emissive.w);
                 });
                 ui.spacing();
                 ui.spacing();
@@ -1,16 +1,26 @@
 pub mod buffer;
 pub mod color;
+pub mod deferred;
 pub mod device;
+pub mod environment;
 pub mod format;
 pub mod framebuffer;
+pub mod ibl;
 pub mod light;
+pub mod light_clusters;
 pub mod material;
 pub mod mesh;
+pub mod point_shadows;
 pub mod postprocess;
+pub mod query;
+pub mod render_graph;
 pub mod sampler;
 pub mod shader;
+pub mod shadows;
+pub mod spherical_harmonics;
 pub mod state;
 pub mod texture;
+pub mod volumetric_fog;
 
 pub trait Draw {
     fn draw(&self);
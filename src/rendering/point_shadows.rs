@@ -0,0 +1,600 @@
+use std::mem;
+use std::rc::Rc;
+
+use crate::{
+    core::camera::Camera,
+    core::math::{look_at, perspective, Mat4, UVec2, Vec3, Vec4},
+    imgui::{Gui, Ui},
+    rendering::{
+        buffer::{Buffer, BufferStorageFlags, BufferTarget},
+        framebuffer::{AttachmentType, Framebuffer, FramebufferAttachmentCreateInfo},
+        light::{Light, PointLight},
+        sampler::{
+            Anisotropy, CompareFunction, MagnificationFilter, MinificationFilter, Sampler,
+            WrappingMode,
+        },
+        shader::{Shader, ShaderCreateInfo, ShaderStage},
+        state::StateManager,
+        texture::SizedTextureFormat,
+    },
+    Context, Msaa,
+};
+
+pub const POINT_SHADOW_ATLAS_BINDING_INDEX: u32 = 10;
+const POINT_SHADOW_BUFFER_BINDING_INDEX: u32 = 1;
+const POINT_SHADOW_FACE_UBO_BINDING_INDEX: u32 = 10;
+
+const POINT_SHADOW_VERTEX_SHADER_PATH: &str = "assets/shaders/shadows/shadow_depth.vert";
+const POINT_SHADOW_GEOMETRY_SHADER_PATH: &str = "assets/shaders/shadows/point_shadow_depth.geom";
+
+pub const CUBE_FACE_COUNT: usize = 6;
+
+// Near plane of the cube face projections, relative to the light range.
+const NEAR_PLANE_RANGE_RATIO: f32 = 0.01;
+const MIN_NEAR_PLANE: f32 = 0.01;
+
+/// Forward and up vectors of a cube face, in the +X, -X, +Y, -Y, +Z, -Z order. Must match
+/// `CUBE_FACE_FORWARD` and `CUBE_FACE_UP` in point_shadows.glsl.
+pub fn cube_face_basis(face: usize) -> (Vec3, Vec3) {
+    match face {
+        0 => (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, -1.0, 0.0)),
+        1 => (Vec3::new(-1.0, 0.0, 0.0), Vec3::new(0.0, -1.0, 0.0)),
+        2 => (Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)),
+        3 => (Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 0.0, -1.0)),
+        4 => (Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, -1.0, 0.0)),
+        _ => (Vec3::new(0.0, 0.0, -1.0), Vec3::new(0.0, -1.0, 0.0)),
+    }
+}
+
+/// World to clip space matrix of one face of the cube map centered on `position`.
+pub fn cube_face_matrix(position: &Vec3, face: usize, near: f32, far: f32) -> Mat4 {
+    let (forward, up) = cube_face_basis(face);
+
+    perspective(1, 1, 90, near, far) * look_at(position, &(position + forward), &up)
+}
+
+/// Size in texels of each cube face of a point light shadow, proportional to the height on
+/// screen of the sphere of influence of the light. Lights containing the camera get
+/// `max_resolution`. The result is a power of two in [min_resolution, max_resolution].
+pub fn point_shadow_resolution(
+    light_position: &Vec3,
+    range: f32,
+    camera_position: &Vec3,
+    fov_deg: f32,
+    viewport_height: u32,
+    min_resolution: u32,
+    max_resolution: u32,
+) -> u32 {
+    let distance = (light_position - camera_position).norm();
+
+    if distance <= range {
+        return max_resolution;
+    }
+
+    let screen_coverage = range / (distance * (fov_deg.to_radians() * 0.5).tan());
+    let projected_size = (screen_coverage * viewport_height as f32).ceil() as u32;
+
+    projected_size
+        .next_power_of_two()
+        .max(min_resolution)
+        .min(max_resolution)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AtlasTile {
+    pub offset: UVec2,
+    pub size: u32,
+}
+
+/// Quadtree allocator of square, power of two sized tiles in a square atlas. Tiles are split
+/// from the smallest free tile that fits, so allocating from the largest to the smallest size
+/// packs the atlas without holes.
+#[derive(Debug)]
+pub struct ShadowAtlasAllocator {
+    size: u32,
+    min_tile_size: u32,
+    // Free tile offsets of each level, level 0 being the whole atlas.
+    free_tiles: Vec<Vec<UVec2>>,
+}
+
+impl ShadowAtlasAllocator {
+    pub fn new(size: u32, min_tile_size: u32) -> Self {
+        assert!(
+            size.is_power_of_two() && min_tile_size.is_power_of_two(),
+            "Atlas and tile sizes must be powers of two."
+        );
+        assert!(
+            min_tile_size <= size,
+            "The smallest tile cannot be larger than the atlas."
+        );
+
+        let level_count = (size / min_tile_size).trailing_zeros() as usize + 1;
+
+        let mut allocator = Self {
+            size,
+            min_tile_size,
+            free_tiles: vec![Vec::new(); level_count],
+        };
+        allocator.reset();
+
+        allocator
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Frees every tile.
+    pub fn reset(&mut self) {
+        self.free_tiles.iter_mut().for_each(Vec::clear);
+        self.free_tiles[0].push(UVec2::new(0, 0));
+    }
+
+    pub fn allocate(&mut self, tile_size: u32) -> Option<AtlasTile> {
+        let level = self.level(tile_size)?;
+        let source_level = (0..=level)
+            .rev()
+            .find(|&level| !self.free_tiles[level].is_empty())?;
+
+        let offset = self.free_tiles[source_level].pop()?;
+
+        // Keep the first quadrant at each split, the other three become free.
+        for split_level in source_level..level {
+            let half = self.size >> (split_level + 1);
+
+            self.free_tiles[split_level + 1].extend_from_slice(&[
+                offset + UVec2::new(half, half),
+                offset + UVec2::new(0, half),
+                offset + UVec2::new(half, 0),
+            ]);
+        }
+
+        Some(AtlasTile {
+            offset,
+            size: tile_size,
+        })
+    }
+
+    /// Returns a tile to the free list of its size. Freed tiles are not merged back.
+    pub fn release(&mut self, tile: AtlasTile) {
+        if let Some(level) = self.level(tile.size) {
+            self.free_tiles[level].push(tile.offset);
+        }
+    }
+
+    fn level(&self, tile_size: u32) -> Option<usize> {
+        if !tile_size.is_power_of_two() || tile_size < self.min_tile_size || tile_size > self.size {
+            return None;
+        }
+
+        Some((self.size / tile_size).trailing_zeros() as usize)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PointShadowSettings {
+    /// Width and height of the atlas in texels.
+    pub atlas_size: u32,
+    pub min_resolution: u32,
+    pub max_resolution: u32,
+    /// Shadowed point lights beyond this count, ordered by resolution, are left unshadowed.
+    pub max_shadowed_lights: usize,
+    /// Constant depth offset, in units of the smallest resolvable depth difference.
+    pub depth_bias: f32,
+    /// Depth offset scaled by the depth slope of the polygon.
+    pub slope_bias: f32,
+    /// Largest depth offset applied to a polygon, 0 disables the limit.
+    pub bias_clamp: f32,
+    /// Offset of the receiver position along its normal, in shadow map texels.
+    pub normal_offset: f32,
+}
+
+impl Default for PointShadowSettings {
+    fn default() -> Self {
+        Self {
+            atlas_size: 4096,
+            min_resolution: 64,
+            max_resolution: 1024,
+            max_shadowed_lights: 16,
+            depth_bias: 1.0,
+            slope_bias: 1.5,
+            bias_clamp: 0.005,
+            normal_offset: 1.5,
+        }
+    }
+}
+
+// GPU layout of an entry of the `PointShadowBuffer` shader storage block (std430).
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct GpuPointShadow {
+    // Offset (xy) and size (zw) of each face in atlas UV space.
+    face_rects: [[f32; 4]; CUBE_FACE_COUNT],
+    near: f32,
+    far: f32,
+    resolution: f32,
+    normal_offset: f32,
+}
+
+// Plain std140 layout of the `PointShadowFaceBlock` uniform block.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct PointShadowFaceBlock {
+    face_matrices: [mint::ColumnMatrix4<f32>; CUBE_FACE_COUNT],
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PointShadowAllocation {
+    position: Vec3,
+    near: f32,
+    far: f32,
+    faces: [AtlasTile; CUBE_FACE_COUNT],
+}
+
+/// Cube shadow maps of the shadow casting point lights, packed as six tiles per light in a
+/// single depth atlas. Each light gets a resolution matching its size on screen, and all six
+/// faces of a light are rendered in one draw through viewport arrays.
+pub struct PointShadowAtlas {
+    settings: PointShadowSettings,
+    allocator: ShadowAtlasAllocator,
+    framebuffer: Framebuffer,
+    depth_shader: Rc<Shader>,
+    shadow_buffer: Buffer,
+    face_ubo: Buffer,
+    sampler: Sampler,
+    allocations: Vec<PointShadowAllocation>,
+    light_shadow_indices: Vec<Option<usize>>,
+    gpu_shadows: Vec<GpuPointShadow>,
+}
+
+impl PointShadowAtlas {
+    pub fn new(context: Context, settings: PointShadowSettings) -> Self {
+        let Context { device, .. } = context;
+
+        let depth_shader = device.shader_manager().create_shader(
+            &ShaderCreateInfo::builder("PointShadowDepthShader")
+                .stage(ShaderStage::Vertex, POINT_SHADOW_VERTEX_SHADER_PATH)
+                .stage(ShaderStage::Geometry, POINT_SHADOW_GEOMETRY_SHADER_PATH)
+                .build(),
+        );
+
+        let face_ubo = Buffer::new(
+            "Point Shadow Face UBO",
+            mem::size_of::<PointShadowFaceBlock>() as isize,
+            BufferTarget::Uniform,
+            BufferStorageFlags::DYNAMIC,
+        );
+        face_ubo.bind(POINT_SHADOW_FACE_UBO_BINDING_INDEX);
+
+        let sampler = Sampler::new(
+            MinificationFilter::Linear,
+            MagnificationFilter::Linear,
+            WrappingMode::ClampToEdge,
+            WrappingMode::ClampToEdge,
+            WrappingMode::ClampToEdge,
+            Vec4::new(1.0, 1.0, 1.0, 1.0),
+            Anisotropy::None,
+        );
+        sampler.set_depth_compare(CompareFunction::LessOrEqual);
+
+        let settings = Self::validate_settings(settings);
+
+        Self {
+            allocator: ShadowAtlasAllocator::new(settings.atlas_size, settings.min_resolution),
+            framebuffer: Self::create_framebuffer(settings.atlas_size),
+            shadow_buffer: Self::create_shadow_buffer(settings.max_shadowed_lights),
+            settings,
+            depth_shader,
+            face_ubo,
+            sampler,
+            allocations: Vec::new(),
+            light_shadow_indices: Vec::new(),
+            gpu_shadows: Vec::new(),
+        }
+    }
+
+    pub fn settings(&self) -> &PointShadowSettings {
+        &self.settings
+    }
+
+    /// Recreates the atlas or the shadow buffer when their size changes. Takes effect on the next
+    /// `update`.
+    pub fn set_settings(&mut self, settings: PointShadowSettings) {
+        let settings = Self::validate_settings(settings);
+
+        if settings.atlas_size != self.settings.atlas_size {
+            self.framebuffer = Self::create_framebuffer(settings.atlas_size);
+        }
+
+        if settings.atlas_size != self.settings.atlas_size
+            || settings.min_resolution != self.settings.min_resolution
+        {
+            self.allocator =
+                ShadowAtlasAllocator::new(settings.atlas_size, settings.min_resolution);
+        }
+
+        if settings.max_shadowed_lights != self.settings.max_shadowed_lights {
+            self.shadow_buffer = Self::create_shadow_buffer(settings.max_shadowed_lights);
+        }
+
+        self.settings = settings;
+    }
+
+    /// Number of point lights that got a slot in the atlas during the last `update`.
+    pub fn shadowed_light_count(&self) -> usize {
+        self.allocations.len()
+    }
+
+    /// Index in the `PointShadowBuffer` of the light at `light_index` in the slice given to the
+    /// last `update`, if it is shadowed.
+    pub fn shadow_index(&self, light_index: usize) -> Option<usize> {
+        self.light_shadow_indices
+            .get(light_index)
+            .copied()
+            .flatten()
+    }
+
+    /// Allocates atlas tiles to the shadow casting point lights of `lights`, largest on screen
+    /// first, and uploads the `PointShadowBuffer`. A light that does not fit at its preferred
+    /// resolution is retried at lower ones before being left unshadowed.
+    pub fn update(&mut self, camera: &Camera, viewport_height: u32, lights: &[Light]) {
+        self.allocator.reset();
+        self.allocations.clear();
+        self.light_shadow_indices.clear();
+        self.light_shadow_indices.resize(lights.len(), None);
+
+        let mut requests: Vec<(usize, &PointLight, u32)> = lights
+            .iter()
+            .enumerate()
+            .filter_map(|(index, light)| match light {
+                Light::Point(point_light) if point_light.cast_shadows => {
+                    let resolution = point_shadow_resolution(
+                        &point_light.position,
+                        point_light.range,
+                        camera.position(),
+                        camera.fov_deg() as f32,
+                        viewport_height,
+                        self.settings.min_resolution,
+                        self.settings.max_resolution,
+                    );
+
+                    Some((index, point_light, resolution))
+                }
+                _ => None,
+            })
+            .collect();
+
+        // Stable, so lights of equal resolution keep their order between frames.
+        requests.sort_by(|a, b| b.2.cmp(&a.2));
+
+        for (index, point_light, resolution) in
+            requests.into_iter().take(self.settings.max_shadowed_lights)
+        {
+            if let Some(faces) = self.allocate_faces(resolution) {
+                self.light_shadow_indices[index] = Some(self.allocations.len());
+
+                self.allocations.push(PointShadowAllocation {
+                    position: point_light.position,
+                    near: f32::max(point_light.range * NEAR_PLANE_RANGE_RATIO, MIN_NEAR_PLANE),
+                    far: point_light.range,
+                    faces,
+                });
+            }
+        }
+
+        let atlas_size = self.allocator.size() as f32;
+        let normal_offset = self.settings.normal_offset;
+
+        self.gpu_shadows.clear();
+        self.gpu_shadows
+            .extend(self.allocations.iter().map(|allocation| {
+                let mut face_rects = [[0.0; 4]; CUBE_FACE_COUNT];
+
+                face_rects
+                    .iter_mut()
+                    .zip(allocation.faces.iter())
+                    .for_each(|(rect, tile)| {
+                        *rect = [
+                            tile.offset.x as f32 / atlas_size,
+                            tile.offset.y as f32 / atlas_size,
+                            tile.size as f32 / atlas_size,
+                            tile.size as f32 / atlas_size,
+                        ]
+                    });
+
+                GpuPointShadow {
+                    face_rects,
+                    near: allocation.near,
+                    far: allocation.far,
+                    resolution: allocation.faces[0].size as f32,
+                    normal_offset,
+                }
+            }));
+
+        if !self.gpu_shadows.is_empty() {
+            self.shadow_buffer.fill_slice(0, &self.gpu_shadows);
+        }
+
+        self.shadow_buffer.bind(POINT_SHADOW_BUFFER_BINDING_INDEX);
+    }
+
+    /// Renders the six faces of every shadowed light. `draw` is called once per light and must
+    /// issue the draw calls of the shadow casters, with their `PerDrawBlock` bound.
+    pub fn render<F: FnMut()>(&self, mut draw: F) {
+        if self.allocations.is_empty() {
+            return;
+        }
+
+        self.framebuffer.bind();
+        self.framebuffer.clear(&Vec4::new(1.0, 1.0, 1.0, 1.0));
+
+        StateManager::enable_polygon_offset(
+            self.settings.slope_bias,
+            self.settings.depth_bias,
+            self.settings.bias_clamp,
+        );
+
+        self.depth_shader.bind();
+
+        for allocation in self.allocations.iter() {
+            let face_matrix = |face| -> mint::ColumnMatrix4<f32> {
+                cube_face_matrix(&allocation.position, face, allocation.near, allocation.far).into()
+            };
+
+            let block = PointShadowFaceBlock {
+                face_matrices: [
+                    face_matrix(0),
+                    face_matrix(1),
+                    face_matrix(2),
+                    face_matrix(3),
+                    face_matrix(4),
+                    face_matrix(5),
+                ],
+            };
+            self.face_ubo.fill(0, &block);
+
+            allocation
+                .faces
+                .iter()
+                .enumerate()
+                .for_each(|(face, tile)| {
+                    StateManager::viewport_indexed(
+                        face as u32,
+                        tile.offset.x as f32,
+                        tile.offset.y as f32,
+                        tile.size as f32,
+                        tile.size as f32,
+                    )
+                });
+
+            draw();
+        }
+
+        self.depth_shader.unbind();
+
+        StateManager::disable_polygon_offset();
+
+        self.framebuffer.unbind(false);
+    }
+
+    /// Binds the atlas, with depth comparison enabled, to the point shadow slot of `shader`.
+    pub fn bind_shadow_atlas(&self, shader: &Shader) {
+        shader.bind_texture_2d_with_id(
+            POINT_SHADOW_ATLAS_BINDING_INDEX,
+            self.framebuffer.texture_attachment(0).id(),
+            &self.sampler,
+        );
+    }
+
+    fn allocate_faces(&mut self, resolution: u32) -> Option<[AtlasTile; CUBE_FACE_COUNT]> {
+        let mut resolution = resolution;
+
+        while resolution >= self.settings.min_resolution {
+            let mut faces = [AtlasTile::default(); CUBE_FACE_COUNT];
+            let mut allocated = 0;
+
+            while allocated < CUBE_FACE_COUNT {
+                match self.allocator.allocate(resolution) {
+                    Some(tile) => faces[allocated] = tile,
+                    None => break,
+                }
+                allocated += 1;
+            }
+
+            if allocated == CUBE_FACE_COUNT {
+                return Some(faces);
+            }
+
+            faces[..allocated]
+                .iter()
+                .for_each(|&tile| self.allocator.release(tile));
+
+            resolution /= 2;
+        }
+
+        None
+    }
+
+    fn validate_settings(settings: PointShadowSettings) -> PointShadowSettings {
+        let atlas_size = settings.atlas_size.next_power_of_two();
+        let min_resolution = settings.min_resolution.next_power_of_two().min(atlas_size);
+        let max_resolution = settings
+            .max_resolution
+            .next_power_of_two()
+            .max(min_resolution)
+            .min(atlas_size);
+
+        PointShadowSettings {
+            atlas_size,
+            min_resolution,
+            max_resolution,
+            max_shadowed_lights: settings.max_shadowed_lights.max(1),
+            ..settings
+        }
+    }
+
+    fn create_framebuffer(atlas_size: u32) -> Framebuffer {
+        Framebuffer::new(
+            "PointShadowAtlas",
+            UVec2::new(atlas_size, atlas_size),
+            Msaa::None,
+            vec![FramebufferAttachmentCreateInfo::new(
+                "PointShadowAtlas-Depth",
+                SizedTextureFormat::Depth32f,
+                AttachmentType::Texture,
+            )],
+        )
+        .expect("Failed to create the point shadow atlas framebuffer!")
+    }
+
+    fn create_shadow_buffer(capacity: usize) -> Buffer {
+        Buffer::new(
+            "Point Shadow Buffer",
+            (capacity * mem::size_of::<GpuPointShadow>()) as isize,
+            BufferTarget::ShaderStorage,
+            BufferStorageFlags::DYNAMIC,
+        )
+    }
+}
+
+impl Gui for PointShadowAtlas {
+    fn gui(&mut self, ui: &Ui) {
+        imgui::TreeNode::new("Point Shadow Atlas")
+            .default_open(false)
+            .open_on_arrow(true)
+            .open_on_double_click(true)
+            .framed(false)
+            .build(ui, || {
+                ui.text(format!("Shadowed lights: {}", self.shadowed_light_count()));
+
+                let mut settings = self.settings;
+
+                let mut max_shadowed_lights = settings.max_shadowed_lights as i32;
+                if imgui::Slider::new("Max Shadowed Lights", 1, 64)
+                    .build(ui, &mut max_shadowed_lights)
+                {
+                    settings.max_shadowed_lights = max_shadowed_lights as usize;
+                }
+
+                let mut max_resolution = settings.max_resolution as i32;
+                if imgui::Slider::new("Max Resolution", 64, 2048).build(ui, &mut max_resolution) {
+                    settings.max_resolution = max_resolution as u32;
+                }
+
+                imgui::Slider::new("Depth Bias", 0.0, 10.0)
+                    .display_format("%.2f")
+                    .build(ui, &mut settings.depth_bias);
+                imgui::Slider::new("Slope Bias", 0.0, 10.0)
+                    .display_format("%.2f")
+                    .build(ui, &mut settings.slope_bias);
+                imgui::Slider::new("Bias Clamp", 0.0, 0.05)
+                    .display_format("%.4f")
+                    .build(ui, &mut settings.bias_clamp);
+                imgui::Slider::new("Normal Offset (texels)", 0.0, 5.0)
+                    .display_format("%.2f")
+                    .build(ui, &mut settings.normal_offset);
+
+                self.set_settings(settings);
+            });
+    }
+}
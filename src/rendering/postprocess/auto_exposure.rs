@@ -0,0 +1,344 @@
+use std::mem;
+use std::rc::Rc;
+
+use crate::{
+    core::math::{UVec2, UVec3, Vec4},
+    imgui::{Gui, Ui},
+    rendering::{
+        buffer::{Buffer, BufferCopyInfo, BufferStorageFlags, BufferTarget, MapModeFlags},
+        sampler::{Anisotropy, MagnificationFilter, MinificationFilter, Sampler, WrappingMode},
+        shader::{ComputeShader, ShaderCreateInfo, ShaderStage},
+        state::{MemoryBarrierFlags, StateManager},
+    },
+    Context,
+};
+
+const LUMINANCE_HISTOGRAM_SHADER_PATH: &str = "assets/shaders/exposure/luminance_histogram.comp";
+const EXPOSURE_ADAPTATION_SHADER_PATH: &str = "assets/shaders/exposure/exposure_adaptation.comp";
+
+/// Must match HISTOGRAM_BIN_COUNT in exposure.glsl.
+pub const HISTOGRAM_BIN_COUNT: usize = 256;
+
+const EXPOSURE_UBO_BINDING_INDEX: u32 = 18;
+const EXPOSURE_BUFFER_BINDING_INDEX: u32 = 5;
+
+const LOCAL_SIZE: u32 = 16;
+
+// The histogram is read back for the GUI from a copy made this many frames earlier, so reading
+// it does not wait for the current frame.
+const READBACK_LATENCY: usize = 3;
+
+// Adapted log luminance, exposure and padding, followed by the histogram.
+const EXPOSURE_BUFFER_HEADER_SIZE: usize = 4 * mem::size_of::<f32>();
+const EXPOSURE_BUFFER_SIZE: usize =
+    EXPOSURE_BUFFER_HEADER_SIZE + HISTOGRAM_BIN_COUNT * mem::size_of::<u32>();
+
+/// Weights of the pixels in the average luminance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MeteringMode {
+    Average = 0,
+    CenterWeighted = 1,
+    /// Only the center of the screen, see METERING_SPOT_RADIUS in exposure.glsl.
+    Spot = 2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AutoExposureSettings {
+    /// Range of the EV100 the exposure adapts to. It is also the range of the histogram.
+    pub min_ev: f32,
+    pub max_ev: f32,
+    /// Fractions of the darkest and brightest pixels ignored by the average, from 0 to 1.
+    pub low_percent: f32,
+    pub high_percent: f32,
+    /// Adaptation speeds towards a brighter and a darker scene, in 1 / seconds.
+    pub speed_up: f32,
+    pub speed_down: f32,
+    /// Added to the exposure, in EV. Positive values brighten the image.
+    pub exposure_compensation: f32,
+    pub metering_mode: MeteringMode,
+}
+
+impl Default for AutoExposureSettings {
+    fn default() -> Self {
+        Self {
+            min_ev: -2.0,
+            max_ev: 16.0,
+            low_percent: 0.5,
+            high_percent: 0.95,
+            speed_up: 3.0,
+            speed_down: 1.0,
+            exposure_compensation: 0.0,
+            metering_mode: MeteringMode::CenterWeighted,
+        }
+    }
+}
+
+// Plain std140 layout of the `ExposureBlock` uniform block.
+#[repr(C)]
+struct ExposureUniforms {
+    min_ev: f32,
+    max_ev: f32,
+    low_percent: f32,
+    high_percent: f32,
+    speed_up: f32,
+    speed_down: f32,
+    exposure_compensation: f32,
+    delta_time: f32,
+    metering_mode: i32,
+    reset: i32,
+    _padding: [f32; 2],
+}
+
+/// Eye adaptation from a luminance histogram of the HDR image. The average log luminance
+/// between the low and high percentiles of the histogram is the target of a temporal
+/// adaptation, whose EV100 gives the exposure. Everything stays on the GPU, the exposure is read
+/// by the tone mapping shader from the exposure buffer.
+pub struct AutoExposure {
+    settings: AutoExposureSettings,
+    histogram_shader: Rc<ComputeShader>,
+    adaptation_shader: Rc<ComputeShader>,
+    ubo: Buffer,
+    exposure_buffer: Buffer,
+    readback_buffers: Vec<Buffer>,
+    readback_index: usize,
+    nearest_sampler: Sampler,
+    histogram: Vec<f32>,
+    adapted_ev100: f32,
+    reset: bool,
+}
+
+impl AutoExposure {
+    pub fn new(context: Context, settings: AutoExposureSettings) -> Self {
+        let Context { device, .. } = context;
+
+        let shader_manager = device.shader_manager();
+
+        let mut create_compute_shader = |name: &str, path: &str| {
+            shader_manager.create_compute_shader(
+                &ShaderCreateInfo::builder(name)
+                    .stage(ShaderStage::Compute, path)
+                    .build(),
+            )
+        };
+
+        let histogram_shader = create_compute_shader(
+            "Luminance Histogram Shader",
+            LUMINANCE_HISTOGRAM_SHADER_PATH,
+        );
+        let adaptation_shader = create_compute_shader(
+            "Exposure Adaptation Shader",
+            EXPOSURE_ADAPTATION_SHADER_PATH,
+        );
+
+        let mut ubo = Buffer::new(
+            "Exposure UBO",
+            mem::size_of::<ExposureUniforms>() as isize,
+            BufferTarget::Uniform,
+            BufferStorageFlags::MAP_WRITE_PERSISTENT_COHERENT,
+        );
+        ubo.map(MapModeFlags::MAP_WRITE_PERSISTENT_COHERENT);
+
+        let exposure_buffer = Buffer::new(
+            "Exposure Buffer",
+            EXPOSURE_BUFFER_SIZE as isize,
+            BufferTarget::ShaderStorage,
+            BufferStorageFlags::DYNAMIC,
+        );
+
+        let readback_buffers = (0..READBACK_LATENCY)
+            .map(|_| {
+                Buffer::new(
+                    "Exposure Readback Buffer",
+                    EXPOSURE_BUFFER_SIZE as isize,
+                    BufferTarget::CopyWrite,
+                    BufferStorageFlags::CLIENT_STORAGE,
+                )
+            })
+            .collect();
+
+        let nearest_sampler = Sampler::new(
+            MinificationFilter::Nearest,
+            MagnificationFilter::Nearest,
+            WrappingMode::ClampToEdge,
+            WrappingMode::ClampToEdge,
+            WrappingMode::ClampToEdge,
+            Vec4::new(0.0, 0.0, 0.0, 0.0),
+            Anisotropy::None,
+        );
+
+        Self {
+            settings: Self::validate_settings(settings),
+            histogram_shader,
+            adaptation_shader,
+            ubo,
+            exposure_buffer,
+            readback_buffers,
+            readback_index: 0,
+            nearest_sampler,
+            histogram: vec![0.0; HISTOGRAM_BIN_COUNT],
+            adapted_ev100: 0.0,
+            reset: true,
+        }
+    }
+
+    pub fn settings(&self) -> &AutoExposureSettings {
+        &self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: AutoExposureSettings) {
+        self.settings = Self::validate_settings(settings);
+    }
+
+    /// Jumps to the exposure of the next frame without adaptation, e.g. after a camera cut.
+    pub fn reset(&mut self) {
+        self.reset = true
+    }
+
+    /// Histogram of a recent frame, normalized to its largest bin.
+    pub fn histogram(&self) -> &[f32] {
+        &self.histogram
+    }
+
+    /// Adapted EV100 of a recent frame, before the clamping and the exposure compensation.
+    pub fn adapted_ev100(&self) -> f32 {
+        self.adapted_ev100
+    }
+
+    /// Builds the histogram of `hdr_texture_id` and updates the exposure for the frame.
+    pub fn update(&mut self, hdr_texture_id: u32, size: UVec2, delta_time: f32) {
+        self.read_back();
+
+        self.ubo.fill_mapped(
+            0,
+            &ExposureUniforms {
+                min_ev: self.settings.min_ev,
+                max_ev: self.settings.max_ev,
+                low_percent: self.settings.low_percent,
+                high_percent: self.settings.high_percent,
+                speed_up: self.settings.speed_up,
+                speed_down: self.settings.speed_down,
+                exposure_compensation: self.settings.exposure_compensation,
+                delta_time,
+                metering_mode: self.settings.metering_mode as i32,
+                reset: self.reset as i32,
+                _padding: [0.0; 2],
+            },
+        );
+        self.ubo.bind(EXPOSURE_UBO_BINDING_INDEX);
+
+        self.exposure_buffer.fill_slice(
+            EXPOSURE_BUFFER_HEADER_SIZE as isize,
+            &[0u32; HISTOGRAM_BIN_COUNT],
+        );
+        self.bind();
+
+        let work_groups = ComputeShader::work_group_count(
+            UVec3::new(size.x, size.y, 1),
+            UVec3::new(LOCAL_SIZE, LOCAL_SIZE, 1),
+        );
+        self.histogram_shader
+            .bind_texture_2d_with_id(0, hdr_texture_id, &self.nearest_sampler);
+        self.histogram_shader
+            .dispatch(work_groups.x, work_groups.y, work_groups.z);
+
+        StateManager::memory_barrier(MemoryBarrierFlags::SHADER_STORAGE);
+
+        self.adaptation_shader.dispatch(1, 1, 1);
+
+        StateManager::memory_barrier(
+            MemoryBarrierFlags::SHADER_STORAGE | MemoryBarrierFlags::BUFFER_UPDATE,
+        );
+
+        Buffer::copy(BufferCopyInfo {
+            source: &self.exposure_buffer,
+            destination: &self.readback_buffers[self.readback_index],
+            source_offset: 0,
+            destination_offset: 0,
+            size: EXPOSURE_BUFFER_SIZE as isize,
+        });
+        self.readback_index = (self.readback_index + 1) % READBACK_LATENCY;
+
+        self.reset = false;
+    }
+
+    /// Binds the exposure buffer read by the AUTO_EXPOSURE variant of the tone mapping shader.
+    pub fn bind(&self) {
+        self.exposure_buffer.bind(EXPOSURE_BUFFER_BINDING_INDEX);
+    }
+
+    // Reads the oldest copy, written READBACK_LATENCY - 1 frames ago.
+    fn read_back(&mut self) {
+        let buffer = &self.readback_buffers[self.readback_index];
+
+        let mut header = [0.0f32; 4];
+        buffer.read_slice(0, &mut header);
+
+        let mut counts = [0u32; HISTOGRAM_BIN_COUNT];
+        buffer.read_slice(EXPOSURE_BUFFER_HEADER_SIZE as isize, &mut counts);
+
+        let max_count = counts.iter().copied().max().unwrap_or(0).max(1) as f32;
+        self.histogram
+            .iter_mut()
+            .zip(counts.iter())
+            .for_each(|(bin, &count)| *bin = count as f32 / max_count);
+
+        // EV100 = log2(L * 100 / 12.5)
+        self.adapted_ev100 = header[0] + 3.0;
+    }
+
+    fn validate_settings(settings: AutoExposureSettings) -> AutoExposureSettings {
+        let low_percent = settings.low_percent.max(0.0).min(0.99);
+
+        AutoExposureSettings {
+            max_ev: settings.max_ev.max(settings.min_ev + 1.0),
+            low_percent,
+            high_percent: settings.high_percent.max(low_percent + 0.01).min(1.0),
+            speed_up: settings.speed_up.max(0.0),
+            speed_down: settings.speed_down.max(0.0),
+            ..settings
+        }
+    }
+}
+
+impl Gui for AutoExposure {
+    fn gui(&mut self, ui: &Ui) {
+        let mut settings = self.settings;
+
+        let mut metering_mode = settings.metering_mode as usize;
+        if ui.combo_simple_string(
+            "Metering",
+            &mut metering_mode,
+            &["Average", "Center Weighted", "Spot"],
+        ) {
+            settings.metering_mode = match metering_mode {
+                0 => MeteringMode::Average,
+                1 => MeteringMode::CenterWeighted,
+                _ => MeteringMode::Spot,
+            };
+        }
+
+        imgui::Slider::new("Min EV", -10.0, 20.0)
+            .display_format("%.1f")
+            .build(ui, &mut settings.min_ev);
+        imgui::Slider::new("Max EV", -10.0, 20.0)
+            .display_format("%.1f")
+            .build(ui, &mut settings.max_ev);
+        imgui::Slider::new("Low Percent", 0.0, 0.99)
+            .display_format("%.2f")
+            .build(ui, &mut settings.low_percent);
+        imgui::Slider::new("High Percent", 0.01, 1.0)
+            .display_format("%.2f")
+            .build(ui, &mut settings.high_percent);
+        imgui::Slider::new("Speed Up", 0.0, 10.0)
+            .display_format("%.2f")
+            .build(ui, &mut settings.speed_up);
+        imgui::Slider::new("Speed Down", 0.0, 10.0)
+            .display_format("%.2f")
+            .build(ui, &mut settings.speed_down);
+        imgui::Slider::new("Exposure Compensation", -5.0, 5.0)
+            .display_format("%.2f")
+            .build(ui, &mut settings.exposure_compensation);
+
+        self.set_settings(settings);
+    }
+}
@@ -8,17 +8,17 @@ use crate::rendering::shader::Shader;
 use crate::shader::ShaderCreateInfo;
 use crate::{
     color::srgb_to_linear,
-    core::math::{UVec2, Vec4},
+    core::math::{radical_inverse, UVec2, Vec4},
     imgui::{ColorFormat, Condition, Gui, TextureId, Ui},
     rendering::{
         buffer::{Buffer, BufferStorageFlags, BufferTarget, MapModeFlags},
         framebuffer::{Framebuffer, TemporaryFramebufferPool},
         mesh::utilities::draw_full_screen_quad,
-        postprocess::{AsAny, AsAnyMut, PostprocessingEffect},
+        postprocess::{AsAny, AsAnyMut, PostprocessingEffect, PostprocessingInput},
         sampler::{Anisotropy, MagnificationFilter, MinificationFilter, Sampler, WrappingMode},
         shader::ShaderStage,
         state::{BlendFactor, StateManager},
-        texture::Texture2D,
+        texture::{SizedTextureFormat, Texture2D, TextureFormat},
     },
     Context,
 };
@@ -35,6 +35,16 @@ const MIN_INTENSITY: f32 = 0.01;
 const MAX_INTENSITY: f32 = 10.0;
 const MIN_LENS_DIRT_INTENSITY: f32 = 0.0;
 const MAX_LENS_DIRT_INTENSITY: f32 = 100.0;
+const MIN_GHOST_COUNT: u32 = 1;
+const MAX_GHOST_COUNT: u32 = 16;
+const MAX_STREAK_ITERATIONS: u32 = 10;
+const STARBURST_SIZE: u32 = 256;
+const STARBURST_SPOKES: u32 = 36;
+
+// Texture units of bloom.frag.
+const LENS_FLARE_BINDING_INDEX: u32 = 3;
+const STARBURST_BINDING_INDEX: u32 = 4;
+const STREAKS_BINDING_INDEX: u32 = 5;
 
 #[repr(C)]
 #[derive(Debug, AsStd140)]
@@ -45,6 +55,17 @@ struct BloomUboData {
     use_lens_dirt: i32,
     lens_dirt_intensity: f32,
     tint: mint::Vector3<f32>,
+    use_lens_flare: i32,
+    lens_flare_intensity: f32,
+    ghost_count: i32,
+    ghost_dispersal: f32,
+    halo_width: f32,
+    chromatic_distortion: f32,
+    starburst_intensity: f32,
+    use_streaks: i32,
+    streak_intensity: f32,
+    streak_stretch: f32,
+    streak_tint: mint::Vector3<f32>,
 }
 
 impl Default for BloomUboData {
@@ -56,6 +77,72 @@ impl Default for BloomUboData {
             use_lens_dirt: 0,
             lens_dirt_intensity: 0.0,
             tint: [1.0, 1.0, 1.0].into(),
+            use_lens_flare: 0,
+            lens_flare_intensity: 0.0,
+            ghost_count: 0,
+            ghost_dispersal: 0.0,
+            halo_width: 0.0,
+            chromatic_distortion: 0.0,
+            starburst_intensity: 0.0,
+            use_streaks: 0,
+            streak_intensity: 0.0,
+            streak_stretch: 0.0,
+            streak_tint: [1.0, 1.0, 1.0].into(),
+        }
+    }
+}
+
+/// Settings of the ghosts and halo generated from one level of the bloom downsample chain.
+#[derive(Debug, Clone, Copy)]
+pub struct LensFlareSettings {
+    pub enabled: bool,
+    pub intensity: f32,
+    pub ghost_count: u32,
+    /// Spacing of the ghosts along the line through the center of the image.
+    pub ghost_dispersal: f32,
+    /// Radius of the halo in texture coordinates.
+    pub halo_width: f32,
+    /// Distance in texels between the color channels of the flare.
+    pub chromatic_distortion: f32,
+    /// Blend between no starburst (0) and the full starburst pattern (1).
+    pub starburst_intensity: f32,
+    /// Level of the downsample chain the flare is generated from, lower levels give sharper
+    /// ghosts.
+    pub source_level: u32,
+}
+
+impl Default for LensFlareSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            intensity: 0.05,
+            ghost_count: 6,
+            ghost_dispersal: 0.35,
+            halo_width: 0.45,
+            chromatic_distortion: 4.0,
+            starburst_intensity: 0.8,
+            source_level: 1,
+        }
+    }
+}
+
+/// Settings of the horizontal streaks of anamorphic lenses.
+#[derive(Debug, Clone, Copy)]
+pub struct StreakSettings {
+    pub enabled: bool,
+    pub intensity: f32,
+    /// How far the streaks spread, from 0 to 1.
+    pub stretch: f32,
+    pub tint: [f32; 3],
+}
+
+impl Default for StreakSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            intensity: 0.1,
+            stretch: 0.75,
+            tint: [0.55, 0.55, 1.0],
         }
     }
 }
@@ -80,6 +167,9 @@ pub struct Bloom {
     enable_lens_dirt: bool,
     lens_dirt_intensity: f32,
     lens_dirt: Rc<Texture2D>,
+    lens_flare: LensFlareSettings,
+    starburst: Texture2D,
+    streaks: StreakSettings,
 }
 
 impl_as_any!(Bloom);
@@ -105,6 +195,17 @@ impl Bloom {
         self.ubo_data.intensity = self.intensity;
         self.ubo_data.use_lens_dirt = self.enable_lens_dirt as i32;
         self.ubo_data.lens_dirt_intensity = self.lens_dirt_intensity;
+        self.ubo_data.use_lens_flare = self.lens_flare.enabled as i32;
+        self.ubo_data.lens_flare_intensity = self.lens_flare.intensity;
+        self.ubo_data.ghost_count = self.lens_flare.ghost_count as i32;
+        self.ubo_data.ghost_dispersal = self.lens_flare.ghost_dispersal;
+        self.ubo_data.halo_width = self.lens_flare.halo_width;
+        self.ubo_data.chromatic_distortion = self.lens_flare.chromatic_distortion;
+        self.ubo_data.starburst_intensity = self.lens_flare.starburst_intensity;
+        self.ubo_data.use_streaks = self.streaks.enabled as i32;
+        self.ubo_data.streak_intensity = self.streaks.intensity;
+        self.ubo_data.streak_stretch = self.streaks.stretch;
+        self.ubo_data.streak_tint = self.streaks.tint.into();
 
         self.ubo.fill_mapped(0, &self.ubo_data.as_std140());
     }
@@ -197,6 +298,8 @@ impl Bloom {
             current_source = Rc::clone(&current_destination);
         }
 
+        self.bloom_shader.disable_keyword("BLOOM_PASS_DOWNSAMPLE");
+
         current_source
     }
 
@@ -228,22 +331,138 @@ impl Bloom {
         current_source
     }
 
-    fn composition_pass(&self, input: &Framebuffer, output: &Framebuffer) {
+    pub fn lens_flare(&self) -> &LensFlareSettings {
+        &self.lens_flare
+    }
+
+    pub fn set_lens_flare(&mut self, settings: LensFlareSettings) {
+        self.lens_flare = LensFlareSettings {
+            ghost_count: settings
+                .ghost_count
+                .max(MIN_GHOST_COUNT)
+                .min(MAX_GHOST_COUNT),
+            ghost_dispersal: settings.ghost_dispersal.max(0.0).min(1.0),
+            halo_width: settings.halo_width.max(0.0).min(1.0),
+            source_level: settings.source_level.min(MAX_ITERATIONS - 1),
+            ..settings
+        }
+    }
+
+    pub fn streaks(&self) -> &StreakSettings {
+        &self.streaks
+    }
+
+    pub fn set_streaks(&mut self, settings: StreakSettings) {
+        self.streaks = StreakSettings {
+            stretch: settings.stretch.max(0.0).min(1.0),
+            ..settings
+        }
+    }
+
+    /// Ghosts and halo of the chosen level of the downsample chain, at the size of that level.
+    fn lens_flare_pass(&self, framebuffer_cache: &mut TemporaryFramebufferPool) -> Rc<Framebuffer> {
+        let level = (self.lens_flare.source_level as usize).min(self.blit_framebuffers.len() - 1);
+        let source = &self.blit_framebuffers[level];
+
+        let flare = framebuffer_cache.get_temporary(
+            "Bloom Lens Flare",
+            source.size(),
+            source.texture_attachment(0).format(),
+            None,
+        );
+
+        self.bloom_shader.enable_keyword("BLOOM_PASS_LENS_FLARE");
+        self.bloom_shader.bind_texture_2d_with_id(
+            0,
+            source.texture_attachment(0).id(),
+            &self.linear_sampler,
+        );
+        flare.bind();
+        draw_full_screen_quad();
+        flare.unbind(false);
+        self.bloom_shader.disable_keyword("BLOOM_PASS_LENS_FLARE");
+
+        flare
+    }
+
+    /// Streaks from the prefiltered image, downsampled horizontally only and stretched back up
+    /// to its size.
+    fn streak_passes(&self, framebuffer_cache: &mut TemporaryFramebufferPool) -> Rc<Framebuffer> {
+        let source = Rc::clone(&self.blit_framebuffers[0]);
+        let format = source.texture_attachment(0).format();
+
+        let mut levels = vec![Rc::clone(&source)];
+        let mut size = source.size();
+
+        self.bloom_shader
+            .enable_keyword("BLOOM_PASS_STREAK_DOWNSAMPLE");
+        for _ in 0..MAX_STREAK_ITERATIONS {
+            size.x /= 2;
+            if size.x < 2 {
+                break;
+            }
+
+            let destination = framebuffer_cache.get_temporary("Bloom Streak", size, format, None);
+            self.bloom_shader.bind_texture_2d_with_id(
+                0,
+                levels.last().unwrap().texture_attachment(0).id(),
+                &self.linear_sampler,
+            );
+            destination.bind();
+            draw_full_screen_quad();
+            destination.unbind(false);
+
+            levels.push(destination);
+        }
+        self.bloom_shader
+            .disable_keyword("BLOOM_PASS_STREAK_DOWNSAMPLE");
+
+        self.bloom_shader
+            .enable_keyword("BLOOM_PASS_STREAK_UPSAMPLE");
+        let mut low = levels.pop().unwrap();
+        while let Some(high) = levels.pop() {
+            let destination =
+                framebuffer_cache.get_temporary("Bloom Streak", high.size(), format, None);
+            self.bloom_shader
+                .bind_texture_2d_with_id(0, low.texture_attachment(0).id(), &self.linear_sampler)
+                .bind_texture_2d_with_id(1, high.texture_attachment(0).id(), &self.linear_sampler);
+            destination.bind();
+            draw_full_screen_quad();
+            destination.unbind(false);
+
+            framebuffer_cache.release_temporary(low);
+            if !levels.is_empty() {
+                framebuffer_cache.release_temporary(high);
+            }
+            low = destination;
+        }
+        self.bloom_shader
+            .disable_keyword("BLOOM_PASS_STREAK_UPSAMPLE");
+
+        low
+    }
+
+    fn composition_pass(&self, bloom: &Framebuffer, scene: &Framebuffer, output: &Framebuffer) {
         self.bloom_shader.disable_keyword("BLOOM_PASS_UPSAMPLE");
         self.bloom_shader
             .enable_keyword("BLOOM_PASS_UPSAMPLE_APPLY");
         self.bloom_shader.bind_texture_2d_with_id(
             0,
-            input.texture_attachments()[0].id(),
+            bloom.texture_attachments()[0].id(),
             &self.linear_sampler,
         );
         self.bloom_shader.bind_texture_2d_with_id(
             1,
-            output.texture_attachments()[0].id(),
+            scene.texture_attachments()[0].id(),
             &self.linear_sampler,
         );
         self.bloom_shader
             .bind_texture_2d_with_id(2, self.lens_dirt.get_id(), &self.linear_sampler);
+        self.bloom_shader.bind_texture_2d(
+            STARBURST_BINDING_INDEX,
+            &self.starburst,
+            &self.linear_sampler,
+        );
         output.bind();
 
         draw_full_screen_quad();
@@ -274,16 +493,47 @@ impl PostprocessingEffect for Bloom {
         self.enabled
     }
 
-    fn apply(&mut self, input: &Framebuffer, context: Context) {
+    fn apply(&mut self, input: &PostprocessingInput, output: &Framebuffer, context: Context) {
         let Context {
             framebuffer_cache, ..
         } = context;
 
         self.update_uniforms();
 
-        let mut current_source = self.downsampling_passes(input, framebuffer_cache);
+        let mut current_source = self.downsampling_passes(input.color(), framebuffer_cache);
+
+        // Both read the downsample chain before the upsampling passes add to it.
+        let lens_flare = if self.lens_flare.enabled {
+            let lens_flare = self.lens_flare_pass(framebuffer_cache);
+            self.bloom_shader.bind_texture_2d_with_id(
+                LENS_FLARE_BINDING_INDEX,
+                lens_flare.texture_attachment(0).id(),
+                &self.linear_sampler,
+            );
+            Some(lens_flare)
+        } else {
+            None
+        };
+        // The streaks need at least one horizontal downsample.
+        let streaks = if self.streaks.enabled && self.blit_framebuffers[0].size().x >= 4 {
+            let streaks = self.streak_passes(framebuffer_cache);
+            self.bloom_shader.bind_texture_2d_with_id(
+                STREAKS_BINDING_INDEX,
+                streaks.texture_attachment(0).id(),
+                &self.linear_sampler,
+            );
+            Some(streaks)
+        } else {
+            None
+        };
+
         current_source = self.upsampling_passes(Rc::clone(&current_source));
-        self.composition_pass(&current_source, input);
+        self.composition_pass(&current_source, input.color(), output);
+
+        lens_flare
+            .into_iter()
+            .chain(streaks)
+            .for_each(|framebuffer| framebuffer_cache.release_temporary(framebuffer));
     }
 }
 
@@ -393,6 +643,63 @@ impl Gui for Bloom {
                             .build(ui);
                     }
 
+                    let mut lens_flare = self.lens_flare;
+                    let mut lens_flare_changed = ui.checkbox("Lens Flare", &mut lens_flare.enabled);
+                    if lens_flare.enabled {
+                        lens_flare_changed |= imgui::Slider::new("Flare Intensity", 0.0, 1.0)
+                            .display_format("%.3f")
+                            .build(ui, &mut lens_flare.intensity);
+                        lens_flare_changed |=
+                            imgui::Slider::new("Ghosts", MIN_GHOST_COUNT, MAX_GHOST_COUNT)
+                                .build(ui, &mut lens_flare.ghost_count);
+                        lens_flare_changed |= imgui::Slider::new("Ghost Dispersal", 0.0, 1.0)
+                            .display_format("%.2f")
+                            .build(ui, &mut lens_flare.ghost_dispersal);
+                        lens_flare_changed |= imgui::Slider::new("Halo Width", 0.0, 1.0)
+                            .display_format("%.2f")
+                            .build(ui, &mut lens_flare.halo_width);
+                        lens_flare_changed |= imgui::Slider::new("Chromatic Distortion", 0.0, 16.0)
+                            .display_format("%.1f")
+                            .build(ui, &mut lens_flare.chromatic_distortion);
+                        lens_flare_changed |= imgui::Slider::new("Starburst", 0.0, 1.0)
+                            .display_format("%.2f")
+                            .build(ui, &mut lens_flare.starburst_intensity);
+                        lens_flare_changed |=
+                            imgui::Slider::new("Flare Source Level", 0, self.iterations - 1)
+                                .build(ui, &mut lens_flare.source_level);
+
+                        ui.text("Starburst Map");
+                        imgui::Image::new(
+                            TextureId::new(self.starburst.get_id() as usize),
+                            [128.0, 128.0],
+                        )
+                        .build(ui);
+                    }
+                    if lens_flare_changed {
+                        self.set_lens_flare(lens_flare);
+                    }
+
+                    let mut streaks = self.streaks;
+                    let mut streaks_changed =
+                        ui.checkbox("Anamorphic Streaks", &mut streaks.enabled);
+                    if streaks.enabled {
+                        streaks_changed |= imgui::Slider::new("Streak Intensity", 0.0, 1.0)
+                            .display_format("%.3f")
+                            .build(ui, &mut streaks.intensity);
+                        streaks_changed |= imgui::Slider::new("Streak Stretch", 0.0, 1.0)
+                            .display_format("%.2f")
+                            .build(ui, &mut streaks.stretch);
+                        streaks_changed |= imgui::ColorEdit::new("Streak Tint", &mut streaks.tint)
+                            .format(ColorFormat::Float)
+                            .options(true)
+                            .picker(true)
+                            .alpha(false)
+                            .build(ui);
+                    }
+                    if streaks_changed {
+                        self.set_streaks(streaks);
+                    }
+
                     ui.unindent()
                 });
         });
@@ -404,6 +711,8 @@ pub struct BloomBuilder {
     threshold: f32,
     smooth_fade: f32,
     intensity: f32,
+    lens_flare: LensFlareSettings,
+    streaks: StreakSettings,
     enabled: bool,
 }
 
@@ -414,6 +723,8 @@ impl Default for BloomBuilder {
             threshold: 1.0,
             smooth_fade: 0.54,
             intensity: 0.1,
+            lens_flare: Default::default(),
+            streaks: Default::default(),
             enabled: true,
         }
     }
@@ -440,6 +751,16 @@ impl BloomBuilder {
         self
     }
 
+    pub fn lens_flare(mut self, lens_flare: LensFlareSettings) -> Self {
+        self.lens_flare = lens_flare;
+        self
+    }
+
+    pub fn streaks(mut self, streaks: StreakSettings) -> Self {
+        self.streaks = streaks;
+        self
+    }
+
     pub fn enabled(mut self, enabled: bool) -> Self {
         self.enabled = enabled;
         self
@@ -467,6 +788,9 @@ impl BloomBuilder {
                     "BLOOM_PASS_DOWNSAMPLE",
                     "BLOOM_PASS_UPSAMPLE",
                     "BLOOM_PASS_UPSAMPLE_APPLY",
+                    "BLOOM_PASS_LENS_FLARE",
+                    "BLOOM_PASS_STREAK_DOWNSAMPLE",
+                    "BLOOM_PASS_STREAK_UPSAMPLE",
                 ])
                 .build(),
         );
@@ -490,7 +814,15 @@ impl BloomBuilder {
             Anisotropy::None,
         );
 
-        Bloom {
+        let starburst = Texture2D::new_from_u8(
+            "Bloom Starburst",
+            UVec2::new(STARBURST_SIZE, STARBURST_SIZE),
+            SizedTextureFormat::R8,
+            TextureFormat::Red,
+            &starburst_texture(STARBURST_SIZE),
+        );
+
+        let mut bloom = Bloom {
             iterations: self.iterations,
             spread: 1.0,
             threshold: self.threshold,
@@ -510,6 +842,44 @@ impl BloomBuilder {
             enable_lens_dirt: true,
             lens_dirt_intensity: 30.0,
             lens_dirt,
-        }
+            lens_flare: Default::default(),
+            starburst,
+            streaks: Default::default(),
+        };
+        bloom.set_lens_flare(self.lens_flare);
+        bloom.set_streaks(self.streaks);
+        bloom
     }
 }
+
+/// Spokes of uneven length and brightness radiating from the center, like the diffraction of
+/// the aperture blades and the scratches of a lens.
+fn starburst_texture(size: u32) -> Vec<u8> {
+    let spokes: Vec<(f32, f32)> = (0..STARBURST_SPOKES)
+        .map(|spoke| {
+            let jitter = radical_inverse(spoke + 1, 3) - 0.5;
+            let angle = std::f32::consts::PI * 2.0 * (spoke as f32 + jitter * 0.5)
+                / STARBURST_SPOKES as f32;
+            (angle, 0.3 + 0.7 * radical_inverse(spoke + 1, 2))
+        })
+        .collect();
+
+    (0..size * size)
+        .map(|index| {
+            let x = (index % size) as f32 / size as f32 * 2.0 - 1.0;
+            let y = (index / size) as f32 / size as f32 * 2.0 - 1.0;
+            let angle = y.atan2(x);
+
+            let value = spokes
+                .iter()
+                .map(|&(spoke_angle, brightness)| {
+                    let mut delta = (angle - spoke_angle).abs();
+                    delta = delta.min(std::f32::consts::PI * 2.0 - delta);
+                    brightness * (-delta * delta / 0.0004).exp()
+                })
+                .fold(0.0, f32::max);
+
+            (value.min(1.0) * 255.0) as u8
+        })
+        .collect()
+}
@@ -0,0 +1,405 @@
+use std::fs;
+use std::mem;
+use std::path::Path;
+
+use image::{Rgb, RgbImage};
+
+use crate::{
+    core::math::{UVec3, Vec4},
+    imgui::{Gui, Ui},
+    rendering::{
+        buffer::{Buffer, BufferStorageFlags, BufferTarget, MapModeFlags},
+        color::white_balance_lms,
+        sampler::{Anisotropy, MagnificationFilter, MinificationFilter, Sampler, WrappingMode},
+        shader::Shader,
+        texture::{SizedTextureFormat, Texture3D},
+    },
+};
+
+const COLOR_GRADING_UBO_BINDING_INDEX: u32 = 19;
+const COLOR_GRADING_LUT_BINDING_INDEX: u32 = 1;
+
+/// Size of the neutral LUTs exported for grading screenshots.
+pub const NEUTRAL_LUT_SIZE: u32 = 32;
+
+/// Identity LUT of `size`³ RGB entries, red varying fastest then green then blue, like the
+/// data of a `.cube` file.
+pub fn neutral_lut(size: u32) -> Vec<f32> {
+    let scale = 1.0 / (size.max(2) - 1) as f32;
+
+    (0..size)
+        .flat_map(|b| (0..size).flat_map(move |g| (0..size).map(move |r| (r, g, b))))
+        .flat_map(|(r, g, b)| {
+            vec![r as f32 * scale, g as f32 * scale, b as f32 * scale].into_iter()
+        })
+        .collect()
+}
+
+/// Parses an Adobe/Resolve `.cube` 3D LUT and returns its size and data, in the layout of
+/// `neutral_lut`. Only the default [0, 1] input domain is supported.
+pub fn parse_cube_lut(source: &str) -> Result<(u32, Vec<f32>), String> {
+    let mut size = None;
+    let mut data = vec![];
+
+    for (line_number, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut tokens = line.split_whitespace();
+        let keyword = tokens.next().unwrap_or_default();
+        let error = |message: &str| format!("Line {}: {}", line_number + 1, message);
+
+        match keyword {
+            "TITLE" => {}
+            "LUT_1D_SIZE" => return Err(error("1D LUTs are not supported.")),
+            "LUT_3D_SIZE" => {
+                let value = tokens
+                    .next()
+                    .and_then(|token| token.parse::<u32>().ok())
+                    .filter(|&value| value >= 2)
+                    .ok_or_else(|| error("Invalid LUT_3D_SIZE."))?;
+                size = Some(value);
+                data.reserve((value * value * value * 3) as usize);
+            }
+            "DOMAIN_MIN" | "DOMAIN_MAX" => {
+                let expected = if keyword == "DOMAIN_MIN" { 0.0 } else { 1.0 };
+                let values = tokens
+                    .map(|token| token.parse::<f32>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| error(&e.to_string()))?;
+                if values.iter().any(|&value| value != expected) {
+                    return Err(error("Only the [0, 1] domain is supported."));
+                }
+            }
+            _ => {
+                let values = line
+                    .split_whitespace()
+                    .map(|token| token.parse::<f32>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| error(&e.to_string()))?;
+                if values.len() != 3 {
+                    return Err(error("Expected an RGB triplet."));
+                }
+                data.extend(values);
+            }
+        }
+    }
+
+    let size = size.ok_or_else(|| String::from("Missing LUT_3D_SIZE."))?;
+    if data.len() != (size * size * size * 3) as usize {
+        return Err(format!(
+            "Expected {} entries for a LUT of size {}, found {}.",
+            size * size * size,
+            size,
+            data.len() / 3
+        ));
+    }
+
+    Ok((size, data))
+}
+
+/// Saves a neutral LUT as a horizontal strip of `size` slices of `size`x`size` pixels, one per
+/// blue value. Grading a screenshot that contains the strip in an external tool and loading the
+/// graded strip back with `ColorGrading::load_lut` reproduces the grade.
+pub fn save_neutral_lut_strip<P: AsRef<Path>>(path: P, size: u32) -> Result<(), String> {
+    let to_unorm = |value: u32| (value as f32 / (size - 1) as f32 * 255.0).round() as u8;
+
+    let image = RgbImage::from_fn(size * size, size, |x, y| {
+        Rgb([to_unorm(x % size), to_unorm(y), to_unorm(x / size)])
+    });
+
+    image.save(path.as_ref()).map_err(|e| e.to_string())
+}
+
+fn load_lut_strip<P: AsRef<Path>>(path: P) -> Result<(u32, Vec<f32>), String> {
+    let image = image::open(path.as_ref())
+        .map_err(|e| e.to_string())?
+        .to_rgb8();
+
+    let size = image.height();
+    if size < 2 || image.width() != size * size {
+        return Err(format!(
+            "A LUT strip of height {} must be {} pixels wide.",
+            size,
+            size * size
+        ));
+    }
+
+    let data = (0..size)
+        .flat_map(|b| (0..size).flat_map(move |g| (0..size).map(move |r| (r, g, b))))
+        .flat_map(|(r, g, b)| image.get_pixel(b * size + r, g).0.to_vec())
+        .map(|value| value as f32 / 255.0)
+        .collect();
+
+    Ok((size, data))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorGradingSettings {
+    /// White balance offsets, from -1 to 1. See `white_balance_lms`.
+    pub temperature: f32,
+    pub tint: f32,
+    /// Contrast around middle grey, 1 is neutral.
+    pub contrast: f32,
+    /// 0 is grayscale, 1 is neutral.
+    pub saturation: f32,
+    /// Shadows, midtones and highlights adjustments of the tone mapped color.
+    pub lift: [f32; 3],
+    pub gamma: [f32; 3],
+    pub gain: [f32; 3],
+    /// Contribution of the red, green and blue input channels to each output channel.
+    pub mixer_red: [f32; 3],
+    pub mixer_green: [f32; 3],
+    pub mixer_blue: [f32; 3],
+    /// Blend between the ungraded color and the LUT, from 0 to 1.
+    pub lut_contribution: f32,
+}
+
+impl Default for ColorGradingSettings {
+    fn default() -> Self {
+        Self {
+            temperature: 0.0,
+            tint: 0.0,
+            contrast: 1.0,
+            saturation: 1.0,
+            lift: [0.0, 0.0, 0.0],
+            gamma: [1.0, 1.0, 1.0],
+            gain: [1.0, 1.0, 1.0],
+            mixer_red: [1.0, 0.0, 0.0],
+            mixer_green: [0.0, 1.0, 0.0],
+            mixer_blue: [0.0, 0.0, 1.0],
+            lut_contribution: 1.0,
+        }
+    }
+}
+
+// Plain std140 layout of the `ColorGradingBlock` uniform block.
+#[repr(C)]
+struct ColorGradingUniforms {
+    white_balance: [f32; 4],
+    lift: [f32; 4],
+    gamma: [f32; 4],
+    gain: [f32; 4],
+    mixer_red: [f32; 4],
+    mixer_green: [f32; 4],
+    mixer_blue: [f32; 4],
+    params: [f32; 4],
+}
+
+/// Color grading of the tone mapping pass. White balance, channel mixer, contrast and
+/// saturation work on the scene referred color, lift/gamma/gain on the tone mapped one, and an
+/// optional 3D LUT is applied last on the sRGB encoded result.
+pub struct ColorGrading {
+    settings: ColorGradingSettings,
+    ubo: Buffer,
+    lut: Option<Texture3D>,
+    linear_sampler: Sampler,
+    lut_path: String,
+    lut_status: String,
+}
+
+impl Default for ColorGrading {
+    fn default() -> Self {
+        let mut ubo = Buffer::new(
+            "Color Grading UBO",
+            mem::size_of::<ColorGradingUniforms>() as isize,
+            BufferTarget::Uniform,
+            BufferStorageFlags::MAP_WRITE_PERSISTENT_COHERENT,
+        );
+        ubo.map(MapModeFlags::MAP_WRITE_PERSISTENT_COHERENT);
+
+        let linear_sampler = Sampler::new(
+            MinificationFilter::Linear,
+            MagnificationFilter::Linear,
+            WrappingMode::ClampToEdge,
+            WrappingMode::ClampToEdge,
+            WrappingMode::ClampToEdge,
+            Vec4::new(0.0, 0.0, 0.0, 0.0),
+            Anisotropy::None,
+        );
+
+        Self {
+            settings: ColorGradingSettings::default(),
+            ubo,
+            lut: None,
+            linear_sampler,
+            lut_path: String::new(),
+            lut_status: String::new(),
+        }
+    }
+}
+
+impl ColorGrading {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn settings(&self) -> &ColorGradingSettings {
+        &self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: ColorGradingSettings) {
+        self.settings = ColorGradingSettings {
+            temperature: settings.temperature.max(-1.0).min(1.0),
+            tint: settings.tint.max(-1.0).min(1.0),
+            contrast: settings.contrast.max(0.0),
+            saturation: settings.saturation.max(0.0),
+            gamma: [
+                settings.gamma[0].max(0.01),
+                settings.gamma[1].max(0.01),
+                settings.gamma[2].max(0.01),
+            ],
+            lut_contribution: settings.lut_contribution.max(0.0).min(1.0),
+            ..settings
+        };
+    }
+
+    pub fn lut(&self) -> Option<&Texture3D> {
+        self.lut.as_ref()
+    }
+
+    /// Loads a `.cube` file, or any other image as a LUT strip like the ones of
+    /// `save_neutral_lut_strip`.
+    pub fn load_lut<P: AsRef<Path>>(&mut self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let is_cube = path
+            .extension()
+            .map_or(false, |extension| extension.eq_ignore_ascii_case("cube"));
+
+        let (size, data) = if is_cube {
+            parse_cube_lut(&fs::read_to_string(path).map_err(|e| e.to_string())?)?
+        } else {
+            load_lut_strip(path)?
+        };
+
+        self.lut = Some(Texture3D::new_from_rgb32f(
+            &path.to_string_lossy(),
+            UVec3::new(size, size, size),
+            SizedTextureFormat::Rgb16f,
+            &data,
+        ));
+
+        Ok(())
+    }
+
+    pub fn clear_lut(&mut self) {
+        self.lut = None
+    }
+
+    /// Updates the uniforms and binds the LUT for the tone mapping shader.
+    pub fn bind(&self, shader: &Shader) {
+        let white_balance = white_balance_lms(self.settings.temperature, self.settings.tint);
+        let vec4 = |v: [f32; 3]| [v[0], v[1], v[2], 0.0];
+
+        self.ubo.fill_mapped(
+            0,
+            &ColorGradingUniforms {
+                white_balance: [white_balance.x, white_balance.y, white_balance.z, 0.0],
+                lift: vec4(self.settings.lift),
+                gamma: vec4(self.settings.gamma),
+                gain: vec4(self.settings.gain),
+                mixer_red: vec4(self.settings.mixer_red),
+                mixer_green: vec4(self.settings.mixer_green),
+                mixer_blue: vec4(self.settings.mixer_blue),
+                params: [
+                    self.settings.contrast,
+                    self.settings.saturation,
+                    self.lut.as_ref().map_or(1.0, |lut| lut.size().x as f32),
+                    self.settings.lut_contribution,
+                ],
+            },
+        );
+        self.ubo.bind(COLOR_GRADING_UBO_BINDING_INDEX);
+
+        if let Some(lut) = &self.lut {
+            shader.bind_texture_3d(COLOR_GRADING_LUT_BINDING_INDEX, lut, &self.linear_sampler);
+        }
+    }
+}
+
+impl Gui for ColorGrading {
+    fn gui(&mut self, ui: &Ui) {
+        let mut settings = self.settings;
+
+        imgui::Slider::new("Temperature", -1.0, 1.0)
+            .display_format("%.2f")
+            .build(ui, &mut settings.temperature);
+        imgui::Slider::new("Tint", -1.0, 1.0)
+            .display_format("%.2f")
+            .build(ui, &mut settings.tint);
+        imgui::Slider::new("Contrast", 0.0, 2.0)
+            .display_format("%.2f")
+            .build(ui, &mut settings.contrast);
+        imgui::Slider::new("Saturation", 0.0, 2.0)
+            .display_format("%.2f")
+            .build(ui, &mut settings.saturation);
+
+        imgui::Drag::new("Lift")
+            .range(-1.0, 1.0)
+            .display_format("%.2f")
+            .speed(0.005)
+            .build_array(ui, &mut settings.lift);
+        imgui::Drag::new("Gamma")
+            .range(0.01, 4.0)
+            .display_format("%.2f")
+            .speed(0.005)
+            .build_array(ui, &mut settings.gamma);
+        imgui::Drag::new("Gain")
+            .range(0.0, 4.0)
+            .display_format("%.2f")
+            .speed(0.005)
+            .build_array(ui, &mut settings.gain);
+
+        imgui::Drag::new("Mixer Red")
+            .range(-2.0, 2.0)
+            .display_format("%.2f")
+            .speed(0.005)
+            .build_array(ui, &mut settings.mixer_red);
+        imgui::Drag::new("Mixer Green")
+            .range(-2.0, 2.0)
+            .display_format("%.2f")
+            .speed(0.005)
+            .build_array(ui, &mut settings.mixer_green);
+        imgui::Drag::new("Mixer Blue")
+            .range(-2.0, 2.0)
+            .display_format("%.2f")
+            .speed(0.005)
+            .build_array(ui, &mut settings.mixer_blue);
+
+        ui.spacing();
+        ui.input_text("LUT Path", &mut self.lut_path).build();
+        if ui.small_button("Load LUT") {
+            self.lut_status = match self.load_lut(self.lut_path.clone()) {
+                Ok(_) => String::from("LUT loaded."),
+                Err(error) => error,
+            };
+        }
+        ui.same_line();
+        if ui.small_button("Clear LUT") {
+            self.clear_lut();
+            self.lut_status.clear();
+        }
+        ui.same_line();
+        if ui.small_button("Export Neutral LUT") {
+            self.lut_status = match save_neutral_lut_strip(&self.lut_path, NEUTRAL_LUT_SIZE) {
+                Ok(_) => format!("Neutral LUT saved to {}.", self.lut_path),
+                Err(error) => error,
+            };
+        }
+        if !self.lut_status.is_empty() {
+            ui.text_wrapped(&self.lut_status);
+        }
+
+        if self.lut.is_some() {
+            imgui::Slider::new("LUT Contribution", 0.0, 1.0)
+                .display_format("%.2f")
+                .build(ui, &mut settings.lut_contribution);
+        }
+
+        if settings != self.settings {
+            self.set_settings(settings);
+        }
+    }
+}
@@ -1,34 +1,83 @@
-use crate::framebuffer::{Framebuffer, TextureFilter};
+use crate::buffer::{Buffer, BufferStorageFlags, BufferTarget, MapModeFlags};
+use crate::camera::ThinLens;
+use crate::framebuffer::Framebuffer;
 use crate::imgui::Gui;
-use crate::postprocess::{AsAny, AsAnyMut, PostprocessingEffect, FULLSCREEN_VERTEX_SHADER_PATH};
+use crate::postprocess::{
+    AsAny, AsAnyMut, EffectInputs, PostprocessingEffect, PostprocessingInput,
+    FULLSCREEN_VERTEX_SHADER_PATH,
+};
 use crate::shader::{Shader, ShaderCreateInfo, ShaderStage};
 use crate::Context;
 use imgui::{Condition, TextureId, Ui};
 use std::any::Any;
-use std::ops::Div;
 use std::rc::Rc;
 
-use crate::math::{UVec2, Vec4};
+use crate::math::{UVec2, Vec2, Vec4};
 use crate::mesh::utilities::draw_full_screen_quad;
 use crate::sampler::{Anisotropy, MagnificationFilter, MinificationFilter, Sampler, WrappingMode};
-use crate::state::{BlendFactor, StateManager};
 use crate::texture::SizedTextureFormat;
-use gl::types::*;
-use gl_bindings as gl;
 
+/// Disk kernel used by the bokeh pass. Selected through the `BOKEH_KERNEL_SIZE` specialization
+/// constant, so it is fixed when the `DepthOfField` is created.
+#[repr(i32)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BokehKernelSize {
+    Small = 0,
+    Medium = 1,
+}
+
+const BOKEH_KERNEL_SIZE_CONSTANT_ID: u32 = 0;
+
+const DOF_UBO_BINDING_INDEX: u32 = 21;
+
+const AUTOFOCUS_KEYWORD: &str = "DOF_AUTOFOCUS";
+
+// Texture units of dof.frag.
+const COLOR_BINDING_INDEX: u32 = 0;
+const DEPTH_BINDING_INDEX: u32 = 1;
+const COC_BINDING_INDEX: u32 = 2;
+const FAR_BINDING_INDEX: u32 = 3;
+const FOCUS_BINDING_INDEX: u32 = 4;
+const NEAR_BINDING_INDEX: u32 = 5;
+
+#[repr(C)]
+struct DepthOfFieldUniforms {
+    focus_distance: f32,
+    aperture_diameter: f32,
+    focal_length: f32,
+    pixels_per_meter: f32,
+    autofocus_point: mint::Vector2<f32>,
+    autofocus_blend: f32,
+    max_coc_radius: f32,
+}
+
+/// Depth of field of a thin lens, usually the one of the `Camera` set with `set_lens` every
+/// frame. The circle of confusion follows from the aperture, focal length and focus distance, see
+/// `ThinLens::circle_of_confusion`. The near and far fields are gathered separately at half
+/// resolution so the blurred foreground bleeds over the in focus pixels behind it.
+///
+/// With autofocus the focus distance of the lens is ignored and eases towards the depth under
+/// the autofocus point instead, without a round trip to the CPU.
 pub struct DepthOfField {
     dof_shader: Rc<Shader>,
+    ubo: Buffer,
     depth_fb: Rc<Framebuffer>,
+    focus: Option<Rc<Framebuffer>>,
     linear_sampler: Sampler,
+    kernel_size: BokehKernelSize,
+    lens: ThinLens,
+    max_coc_radius: f32,
+    autofocus: bool,
+    autofocus_point: Vec2,
+    autofocus_speed: f32,
     enabled: bool,
     show_debug_window: bool,
-    linearize_depth: bool,
 }
 
 impl_as_any!(DepthOfField);
 
 impl DepthOfField {
-    pub fn new(context: Context) -> Self {
+    pub fn new(context: Context, kernel_size: BokehKernelSize) -> Self {
         let Context {
             device,
             framebuffer_cache,
@@ -41,15 +90,33 @@ impl DepthOfField {
                 .stage(ShaderStage::Vertex, FULLSCREEN_VERTEX_SHADER_PATH)
                 .stage(ShaderStage::Fragment, "assets/shaders/dof.frag")
                 .keyword_set(&[
+                    "DOF_PASS_AUTOFOCUS",
                     "DOF_PASS_COC",
                     "DOF_PASS_DOWNSAMPLE",
-                    "DOF_PASS_BOKEH",
+                    "DOF_PASS_BOKEH_FAR",
+                    "DOF_PASS_BOKEH_NEAR",
                     "DOF_PASS_BOKEH_BLUR",
                     "DOF_PASS_COMBINE",
                 ])
+                .keyword_set(&["_", AUTOFOCUS_KEYWORD])
+                .specialization_constant(
+                    ShaderStage::Fragment,
+                    "BOKEH_KERNEL_SIZE",
+                    BOKEH_KERNEL_SIZE_CONSTANT_ID,
+                    kernel_size as i32,
+                )
                 .build(),
         );
 
+        let mut ubo = Buffer::new(
+            "DoF UBO",
+            std::mem::size_of::<DepthOfFieldUniforms>() as isize,
+            BufferTarget::Uniform,
+            BufferStorageFlags::MAP_WRITE_PERSISTENT_COHERENT,
+        );
+        ubo.bind(DOF_UBO_BINDING_INDEX);
+        ubo.map(MapModeFlags::MAP_WRITE_PERSISTENT_COHERENT);
+
         let linear_sampler = Sampler::new(
             MinificationFilter::Linear,
             MagnificationFilter::Linear,
@@ -69,12 +136,77 @@ impl DepthOfField {
                 None,
             ),
             dof_shader,
+            ubo,
+            focus: None,
             enabled: true,
             show_debug_window: false,
-            linearize_depth: true,
             linear_sampler,
+            kernel_size,
+            lens: ThinLens {
+                focal_length: 0.05,
+                aperture: 1.4,
+                focus_distance: 10.0,
+                sensor_height: 0.024,
+            },
+            max_coc_radius: 16.0,
+            autofocus: false,
+            autofocus_point: Vec2::new(0.5, 0.5),
+            autofocus_speed: 4.0,
         }
     }
+
+    pub fn kernel_size(&self) -> BokehKernelSize {
+        self.kernel_size
+    }
+
+    pub fn lens(&self) -> &ThinLens {
+        &self.lens
+    }
+
+    pub fn set_lens(&mut self, lens: ThinLens) {
+        self.lens = lens
+    }
+
+    /// Largest radius of the circle of confusion in pixels, which is also the radius of the
+    /// bokeh kernel.
+    pub fn max_coc_radius(&self) -> f32 {
+        self.max_coc_radius
+    }
+
+    pub fn set_max_coc_radius(&mut self, radius: f32) {
+        self.max_coc_radius = radius.max(1.0).min(32.0)
+    }
+
+    pub fn autofocus(&self) -> bool {
+        self.autofocus
+    }
+
+    pub fn set_autofocus(&mut self, autofocus: bool) {
+        if autofocus {
+            self.dof_shader.enable_keyword(AUTOFOCUS_KEYWORD);
+        } else {
+            self.dof_shader.disable_keyword(AUTOFOCUS_KEYWORD);
+        }
+        self.autofocus = autofocus;
+    }
+
+    /// Point the autofocus samples the depth at, in texture coordinates of the image.
+    pub fn set_autofocus_point(&mut self, point: Vec2) {
+        self.autofocus_point = Vec2::new(point.x.max(0.0).min(1.0), point.y.max(0.0).min(1.0))
+    }
+
+    /// Rate at which the autofocus settles on a new distance, per second.
+    pub fn set_autofocus_speed(&mut self, speed: f32) {
+        self.autofocus_speed = speed.max(0.0)
+    }
+
+    fn run_pass(&self, keyword: &str, target: &Framebuffer) {
+        self.dof_shader.enable_keyword(keyword);
+        target.bind();
+        draw_full_screen_quad();
+        target.unbind(false);
+        self.dof_shader.disable_keyword(keyword);
+    }
 }
 
 impl PostprocessingEffect for DepthOfField {
@@ -94,94 +226,138 @@ impl PostprocessingEffect for DepthOfField {
         self.enabled
     }
 
-    fn apply(&mut self, input: &Framebuffer, context: Context) {
+    fn inputs(&self) -> EffectInputs {
+        EffectInputs::COLOR | EffectInputs::DEPTH
+    }
+
+    fn apply(&mut self, input: &PostprocessingInput, output: &Framebuffer, context: Context) {
         let Context {
-            framebuffer_cache, ..
+            timer,
+            framebuffer_cache,
+            ..
         } = context;
 
-        let color = input.texture_attachment(0);
-        let depth = input.texture_attachment(1);
-        assert!(
-            depth.is_depth_stencil(),
-            "Texture attachment at index 1 is not depth-stencil"
+        let color = input.color().texture_attachment(0);
+
+        // Settle immediately on the first frame with autofocus.
+        let autofocus_blend = if self.focus.is_some() {
+            1.0 - (-timer.delta_time() * self.autofocus_speed).exp()
+        } else {
+            1.0
+        };
+
+        self.ubo.fill_mapped(
+            0,
+            &DepthOfFieldUniforms {
+                focus_distance: self.lens.focus_distance,
+                aperture_diameter: self.lens.aperture_diameter(),
+                focal_length: self.lens.focal_length,
+                pixels_per_meter: input.size().y as f32 / self.lens.sensor_height,
+                autofocus_point: [self.autofocus_point.x, self.autofocus_point.y].into(),
+                autofocus_blend,
+                max_coc_radius: self.max_coc_radius,
+            },
         );
 
+        self.dof_shader.bind_texture_2d_with_id(
+            DEPTH_BINDING_INDEX,
+            input.depth(),
+            &self.linear_sampler,
+        );
+
+        // Autofocus pass, the focus distance lives in a 1x1 texture swapped every frame.
+        if self.autofocus {
+            let focus = framebuffer_cache.get_temporary(
+                "DoF Focus",
+                UVec2::new(1, 1),
+                SizedTextureFormat::R16f,
+                None,
+            );
+            // Without a previous focus distance anything finite will do, it is fully replaced.
+            let previous_focus = self.focus.as_ref().map_or(input.depth(), |previous| {
+                previous.texture_attachment(0).id()
+            });
+            self.dof_shader.bind_texture_2d_with_id(
+                FOCUS_BINDING_INDEX,
+                previous_focus,
+                &self.linear_sampler,
+            );
+            self.run_pass("DOF_PASS_AUTOFOCUS", &focus);
+
+            self.dof_shader.bind_texture_2d_with_id(
+                FOCUS_BINDING_INDEX,
+                focus.texture_attachment(0).id(),
+                &self.linear_sampler,
+            );
+            if let Some(previous) = self.focus.replace(focus) {
+                framebuffer_cache.release_temporary(previous);
+            }
+        } else if let Some(previous) = self.focus.take() {
+            framebuffer_cache.release_temporary(previous);
+        }
+
         // CoC pass
         self.dof_shader.enable_keyword("DOF_PASS_COC");
-        self.dof_shader
-            .bind_texture_2d_with_id(1, depth.id(), &self.linear_sampler);
         self.depth_fb.bind();
-        self.depth_fb.clear(&Vec4::new(0.5, 0.5, 0.5, 1.0));
+        self.depth_fb.clear(&Vec4::new(0.0, 0.0, 0.0, 1.0));
         draw_full_screen_quad();
         self.depth_fb.unbind(false);
         self.dof_shader.disable_keyword("DOF_PASS_COC");
 
-        // Blit to half size
+        // Downsample the color and CoC to half size.
         let half_size = input.size() / 2;
-        let tmp0 = framebuffer_cache.get_temporary("tmp0", half_size, color.format(), None);
-        self.dof_shader.enable_keyword("DOF_PASS_DOWNSAMPLE");
-        self.dof_shader.bind_texture_2d_with_id(
-            0,
-            input.texture_attachment(0).id(),
-            &self.linear_sampler,
-        );
-        self.dof_shader.bind_texture_2d_with_id(
-            2,
-            self.depth_fb.texture_attachment(0).id(),
-            &self.linear_sampler,
-        );
-        tmp0.bind();
-        draw_full_screen_quad();
-        tmp0.unbind(false);
-        self.dof_shader.disable_keyword("DOF_PASS_DOWNSAMPLE");
+        let downsampled =
+            framebuffer_cache.get_temporary("DoF Downsampled", half_size, color.format(), None);
+        self.dof_shader
+            .bind_texture_2d_with_id(COLOR_BINDING_INDEX, color.id(), &self.linear_sampler)
+            .bind_texture_2d_with_id(
+                COC_BINDING_INDEX,
+                self.depth_fb.texture_attachment(0).id(),
+                &self.linear_sampler,
+            );
+        self.run_pass("DOF_PASS_DOWNSAMPLE", &downsampled);
 
-        // Bokeh pass at half size.
-        let tmp1 = framebuffer_cache.get_temporary("tmp1", half_size, color.format(), None);
-        self.dof_shader.enable_keyword("DOF_PASS_BOKEH");
-        self.dof_shader.bind_texture_2d_with_id(
-            0,
-            tmp0.texture_attachment(0).id(),
-            &self.linear_sampler,
-        );
-        tmp1.bind();
-        draw_full_screen_quad();
-        tmp1.unbind(false);
-        self.dof_shader.disable_keyword("DOF_PASS_BOKEH");
+        // Bokeh passes at half size, then blurred to fill the gaps between the kernel samples.
+        let bokeh = framebuffer_cache.get_temporary("DoF Bokeh", half_size, color.format(), None);
+        let far = framebuffer_cache.get_temporary("DoF Far", half_size, color.format(), None);
+        let near = framebuffer_cache.get_temporary("DoF Near", half_size, color.format(), None);
+        for (keyword, field) in [("DOF_PASS_BOKEH_FAR", &far), ("DOF_PASS_BOKEH_NEAR", &near)] {
+            self.dof_shader.bind_texture_2d_with_id(
+                COLOR_BINDING_INDEX,
+                downsampled.texture_attachment(0).id(),
+                &self.linear_sampler,
+            );
+            self.run_pass(keyword, &bokeh);
 
-        // Blur Bokeh at half size.
-        self.dof_shader.enable_keyword("DOF_PASS_BOKEH_BLUR");
-        self.dof_shader.bind_texture_2d_with_id(
-            0,
-            tmp1.texture_attachment(0).id(),
-            &self.linear_sampler,
-        );
-        tmp0.bind();
-        draw_full_screen_quad();
-        tmp0.unbind(false);
-        self.dof_shader.unbind();
-        self.dof_shader.disable_keyword("DOF_PASS_BOKEH_BLUR");
+            self.dof_shader.bind_texture_2d_with_id(
+                COLOR_BINDING_INDEX,
+                bokeh.texture_attachment(0).id(),
+                &self.linear_sampler,
+            );
+            self.run_pass("DOF_PASS_BOKEH_BLUR", field);
+        }
 
-        // Blit back to main image.
-        self.dof_shader.enable_keyword("DOF_PASS_COMBINE");
+        // Combine with the full resolution image.
         self.dof_shader
-            .bind_texture_2d_with_id(0, color.id(), &self.linear_sampler);
-        self.dof_shader.bind_texture_2d_with_id(
-            2,
-            self.depth_fb.texture_attachment(0).id(),
-            &self.linear_sampler,
-        );
-        self.dof_shader.bind_texture_2d_with_id(
-            3,
-            tmp0.texture_attachment(0).id(),
-            &self.linear_sampler,
-        );
-        input.bind();
-        draw_full_screen_quad();
-        input.unbind(false);
-        self.dof_shader.disable_keyword("DOF_PASS_COMBINE");
-        // input.bind();
-        // Framebuffer::blit(tmp0.as_ref(), input, TextureFilter::Linear);
-        // input.unbind(false);
+            .bind_texture_2d_with_id(COLOR_BINDING_INDEX, color.id(), &self.linear_sampler)
+            .bind_texture_2d_with_id(
+                FAR_BINDING_INDEX,
+                far.texture_attachment(0).id(),
+                &self.linear_sampler,
+            )
+            .bind_texture_2d_with_id(
+                NEAR_BINDING_INDEX,
+                near.texture_attachment(0).id(),
+                &self.linear_sampler,
+            );
+        self.run_pass("DOF_PASS_COMBINE", output);
+
+        self.dof_shader.unbind();
+
+        framebuffer_cache.release_temporary(downsampled);
+        framebuffer_cache.release_temporary(bokeh);
+        framebuffer_cache.release_temporary(far);
+        framebuffer_cache.release_temporary(near);
     }
 }
 
@@ -198,6 +374,55 @@ impl Gui for DepthOfField {
                 .build(ui, || {
                     ui.indent();
 
+                    ui.text_disabled(format!(
+                        "f/{:.1}, {:.0}mm, aperture {:.1}mm",
+                        self.lens.aperture,
+                        self.lens.focal_length * 1000.0,
+                        self.lens.aperture_diameter() * 1000.0
+                    ));
+                    if !self.autofocus {
+                        ui.text_disabled(format!(
+                            "Focus: {:.2}m, hyperfocal: {:.2}m",
+                            self.lens.focus_distance,
+                            // Circle of confusion of one thousandth of the sensor height.
+                            self.lens
+                                .hyperfocal_distance(self.lens.sensor_height / 1000.0)
+                        ));
+                    }
+
+                    let mut max_coc_radius = self.max_coc_radius;
+                    if imgui::Slider::new("Max CoC Radius", 1.0, 32.0)
+                        .display_format("%.1f")
+                        .build(ui, &mut max_coc_radius)
+                    {
+                        self.set_max_coc_radius(max_coc_radius);
+                    }
+
+                    let mut autofocus = self.autofocus;
+                    if ui.checkbox("Autofocus", &mut autofocus) {
+                        self.set_autofocus(autofocus);
+                    }
+
+                    if self.autofocus {
+                        let mut point: [f32; 2] = [self.autofocus_point.x, self.autofocus_point.y];
+                        if imgui::Drag::new("Autofocus Point")
+                            .range(0.0, 1.0)
+                            .speed(0.005)
+                            .display_format("%.3f")
+                            .build_array(ui, &mut point)
+                        {
+                            self.set_autofocus_point(Vec2::new(point[0], point[1]));
+                        }
+
+                        let mut autofocus_speed = self.autofocus_speed;
+                        if imgui::Slider::new("Autofocus Speed", 0.1, 20.0)
+                            .display_format("%.1f")
+                            .build(ui, &mut autofocus_speed)
+                        {
+                            self.set_autofocus_speed(autofocus_speed);
+                        }
+                    }
+
                     ui.checkbox("Show debug window", &mut self.show_debug_window);
 
                     if self.show_debug_window {
@@ -0,0 +1,195 @@
+use std::any::Any;
+use std::rc::Rc;
+
+use crate::{
+    framebuffer::Framebuffer,
+    imgui::{Gui, Ui},
+    math::Vec4,
+    mesh::utilities::draw_full_screen_quad,
+    rendering::{
+        buffer::{Buffer, BufferStorageFlags, BufferTarget, MapModeFlags},
+        postprocess::{
+            AsAny, AsAnyMut, PostprocessingEffect, PostprocessingInput,
+            FULLSCREEN_VERTEX_SHADER_PATH,
+        },
+        sampler::{Anisotropy, MagnificationFilter, MinificationFilter, Sampler, WrappingMode},
+        shader::{Shader, ShaderCreateInfo, ShaderStage},
+    },
+    Context,
+};
+
+const FXAA_FRAGMENT_SHADER_PATH: &str = "assets/shaders/fxaa.frag";
+
+const FXAA_UBO_BINDING_INDEX: u32 = 16;
+
+#[repr(C)]
+struct FxaaUniforms {
+    subpixel_quality: f32,
+    edge_threshold: f32,
+    edge_threshold_min: f32,
+    _padding: f32,
+}
+
+/// FXAA 3.11 in a single pass. Works on the tone mapped image, so it must be added after the
+/// `ToneMapper`.
+pub struct Fxaa {
+    shader: Rc<Shader>,
+    ubo: Buffer,
+    linear_sampler: Sampler,
+    subpixel_quality: f32,
+    edge_threshold: f32,
+    edge_threshold_min: f32,
+    enabled: bool,
+}
+
+impl_as_any!(Fxaa);
+
+impl Fxaa {
+    pub fn new(context: Context) -> Self {
+        let Context { device, .. } = context;
+
+        let shader = device.shader_manager().create_shader(
+            &ShaderCreateInfo::builder("FXAA Shader")
+                .stage(ShaderStage::Vertex, FULLSCREEN_VERTEX_SHADER_PATH)
+                .stage(ShaderStage::Fragment, FXAA_FRAGMENT_SHADER_PATH)
+                .build(),
+        );
+
+        let mut ubo = Buffer::new(
+            "FXAA UBO",
+            std::mem::size_of::<FxaaUniforms>() as isize,
+            BufferTarget::Uniform,
+            BufferStorageFlags::MAP_WRITE_PERSISTENT_COHERENT,
+        );
+        ubo.bind(FXAA_UBO_BINDING_INDEX);
+        ubo.map(MapModeFlags::MAP_WRITE_PERSISTENT_COHERENT);
+
+        let linear_sampler = Sampler::new(
+            MinificationFilter::Linear,
+            MagnificationFilter::Linear,
+            WrappingMode::ClampToEdge,
+            WrappingMode::ClampToEdge,
+            WrappingMode::ClampToEdge,
+            Vec4::new(0.0, 0.0, 0.0, 0.0),
+            Anisotropy::None,
+        );
+
+        Self {
+            shader,
+            ubo,
+            linear_sampler,
+            subpixel_quality: 0.75,
+            edge_threshold: 0.166,
+            edge_threshold_min: 0.0833,
+            enabled: true,
+        }
+    }
+
+    /// Amount of sub-pixel aliasing removal, from 0 (off) to 1 (softest).
+    pub fn set_subpixel_quality(&mut self, subpixel_quality: f32) {
+        self.subpixel_quality = subpixel_quality.max(0.0).min(1.0)
+    }
+
+    /// Minimum local contrast, relative to the brightest neighbour, needed to process a pixel.
+    pub fn set_edge_threshold(&mut self, edge_threshold: f32) {
+        self.edge_threshold = edge_threshold.max(0.0).min(1.0)
+    }
+
+    /// Skips the dark pixels whose local contrast is below this absolute value.
+    pub fn set_edge_threshold_min(&mut self, edge_threshold_min: f32) {
+        self.edge_threshold_min = edge_threshold_min.max(0.0).min(1.0)
+    }
+}
+
+impl PostprocessingEffect for Fxaa {
+    fn name(&self) -> &str {
+        "Fxaa"
+    }
+
+    fn enable(&mut self) {
+        self.enabled = true
+    }
+
+    fn disable(&mut self) {
+        self.enabled = false
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn apply(&mut self, input: &PostprocessingInput, output: &Framebuffer, _: Context) {
+        self.ubo.fill_mapped(
+            0,
+            &FxaaUniforms {
+                subpixel_quality: self.subpixel_quality,
+                edge_threshold: self.edge_threshold,
+                edge_threshold_min: self.edge_threshold_min,
+                _padding: 0.0,
+            },
+        );
+
+        self.shader.bind();
+        self.shader.bind_texture_2d_with_id(
+            0,
+            input.color().texture_attachment(0).id(),
+            &self.linear_sampler,
+        );
+
+        output.bind();
+        draw_full_screen_quad();
+        output.unbind(false);
+
+        self.shader.unbind();
+    }
+}
+
+impl Gui for Fxaa {
+    fn gui(&mut self, ui: &Ui) {
+        ui.group(|| {
+            let mut enabled = self.enabled;
+            if ui.checkbox("##fxaa", &mut enabled) {
+                if enabled {
+                    self.enable();
+                } else {
+                    self.disable();
+                }
+            }
+            ui.same_line_with_pos(20.0);
+            imgui::TreeNode::new("FXAA")
+                .default_open(true)
+                .open_on_arrow(true)
+                .open_on_double_click(true)
+                .framed(false)
+                .build(ui, || {
+                    ui.indent();
+
+                    let mut subpixel_quality = self.subpixel_quality;
+                    if imgui::Slider::new("Subpixel Quality", 0.0, 1.0)
+                        .display_format("%.2f")
+                        .build(ui, &mut subpixel_quality)
+                    {
+                        self.set_subpixel_quality(subpixel_quality);
+                    }
+
+                    let mut edge_threshold = self.edge_threshold;
+                    if imgui::Slider::new("Edge Threshold", 0.063, 0.333)
+                        .display_format("%.3f")
+                        .build(ui, &mut edge_threshold)
+                    {
+                        self.set_edge_threshold(edge_threshold);
+                    }
+
+                    let mut edge_threshold_min = self.edge_threshold_min;
+                    if imgui::Slider::new("Edge Threshold Min", 0.0, 0.0833)
+                        .display_format("%.4f")
+                        .build(ui, &mut edge_threshold_min)
+                    {
+                        self.set_edge_threshold_min(edge_threshold_min);
+                    }
+
+                    ui.unindent()
+                });
+        });
+    }
+}
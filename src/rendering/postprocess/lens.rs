@@ -0,0 +1,507 @@
+use std::any::Any;
+use std::rc::Rc;
+
+use crate::{
+    framebuffer::Framebuffer,
+    imgui::{Gui, Ui},
+    math::{UVec2, Vec2, Vec4},
+    mesh::utilities::draw_full_screen_quad,
+    rendering::{
+        buffer::{Buffer, BufferStorageFlags, BufferTarget, MapModeFlags},
+        postprocess::{
+            AsAny, AsAnyMut, PostprocessingEffect, PostprocessingInput,
+            FULLSCREEN_VERTEX_SHADER_PATH,
+        },
+        sampler::{Anisotropy, MagnificationFilter, MinificationFilter, Sampler, WrappingMode},
+        shader::{Shader, ShaderCreateInfo, ShaderStage},
+        texture::Texture2D,
+    },
+    Context,
+};
+
+const LENS_FRAGMENT_SHADER_PATH: &str = "assets/shaders/lens.frag";
+
+const LENS_UBO_BINDING_INDEX: u32 = 22;
+
+// Texture units of lens.frag.
+const COLOR_BINDING_INDEX: u32 = 0;
+const SPECTRAL_LUT_BINDING_INDEX: u32 = 1;
+
+const SPECTRAL_LUT_SIZE: u32 = 16;
+
+/// Iterations of the inversion of the distortion, more than enough for the coefficients the
+/// setters allow.
+const DISTORTION_INVERSE_ITERATIONS: u32 = 8;
+
+bitflags! {
+    /// Sub-effects of `Lens`, each has its own shader keyword.
+    pub struct LensEffects: u32 {
+        const CHROMATIC_ABERRATION = 1 << 0;
+        const VIGNETTE = 1 << 1;
+        const FILM_GRAIN = 1 << 2;
+        const DISTORTION = 1 << 3;
+    }
+}
+
+const CHROMATIC_ABERRATION_KEYWORD: &str = "LENS_CHROMATIC_ABERRATION";
+const VIGNETTE_KEYWORD: &str = "LENS_VIGNETTE";
+const FILM_GRAIN_KEYWORD: &str = "LENS_FILM_GRAIN";
+const DISTORTION_KEYWORD: &str = "LENS_DISTORTION";
+
+const LENS_EFFECT_KEYWORDS: [(LensEffects, &str); 4] = [
+    (
+        LensEffects::CHROMATIC_ABERRATION,
+        CHROMATIC_ABERRATION_KEYWORD,
+    ),
+    (LensEffects::VIGNETTE, VIGNETTE_KEYWORD),
+    (LensEffects::FILM_GRAIN, FILM_GRAIN_KEYWORD),
+    (LensEffects::DISTORTION, DISTORTION_KEYWORD),
+];
+
+#[repr(C)]
+struct LensUniforms {
+    chromatic_aberration_intensity: f32,
+    chromatic_aberration_samples: i32,
+    vignette_intensity: f32,
+    tan_half_fov: f32,
+    grain_intensity: f32,
+    grain_size: f32,
+    grain_response: f32,
+    grain_seed: f32,
+    distortion_k1: f32,
+    distortion_k2: f32,
+    distortion_scale: f32,
+    aspect_ratio: f32,
+}
+
+/// Imperfections of a real lens and film in a single pass: chromatic aberration, vignetting,
+/// film grain and radial distortion. Works on the tone mapped image, so it must be added after
+/// the `ToneMapper`, preferably last.
+///
+/// The vignetting follows the cos^4 law for the field of view given with `set_field_of_view`,
+/// usually the one of the `Camera`. With distortion, what is on screen is no longer where the
+/// camera projects it, `screen_to_scene` and `scene_to_screen` convert between the two.
+pub struct Lens {
+    shader: Rc<Shader>,
+    ubo: Buffer,
+    spectral_lut: Texture2D,
+    linear_sampler: Sampler,
+    effects: LensEffects,
+    chromatic_aberration_intensity: f32,
+    chromatic_aberration_samples: u32,
+    vignette_intensity: f32,
+    fov_deg: f32,
+    grain_intensity: f32,
+    grain_size: f32,
+    grain_response: f32,
+    distortion_k1: f32,
+    distortion_k2: f32,
+    aspect_ratio: f32,
+    enabled: bool,
+}
+
+impl_as_any!(Lens);
+
+impl Lens {
+    pub fn new(context: Context) -> Self {
+        let Context { window, device, .. } = context;
+
+        let shader = device.shader_manager().create_shader(
+            &ShaderCreateInfo::builder("Lens Shader")
+                .stage(ShaderStage::Vertex, FULLSCREEN_VERTEX_SHADER_PATH)
+                .stage(ShaderStage::Fragment, LENS_FRAGMENT_SHADER_PATH)
+                .keyword_set(&["_", CHROMATIC_ABERRATION_KEYWORD])
+                .keyword_set(&["_", VIGNETTE_KEYWORD])
+                .keyword_set(&["_", FILM_GRAIN_KEYWORD])
+                .keyword_set(&["_", DISTORTION_KEYWORD])
+                .build(),
+        );
+
+        let mut ubo = Buffer::new(
+            "Lens UBO",
+            std::mem::size_of::<LensUniforms>() as isize,
+            BufferTarget::Uniform,
+            BufferStorageFlags::MAP_WRITE_PERSISTENT_COHERENT,
+        );
+        ubo.bind(LENS_UBO_BINDING_INDEX);
+        ubo.map(MapModeFlags::MAP_WRITE_PERSISTENT_COHERENT);
+
+        let spectral_lut = Texture2D::new_from_rgb32f(
+            "Lens Spectral LUT",
+            UVec2::new(SPECTRAL_LUT_SIZE, 1),
+            &spectral_lut(SPECTRAL_LUT_SIZE),
+            false,
+        );
+
+        let linear_sampler = Sampler::new(
+            MinificationFilter::Linear,
+            MagnificationFilter::Linear,
+            WrappingMode::ClampToEdge,
+            WrappingMode::ClampToEdge,
+            WrappingMode::ClampToEdge,
+            Vec4::new(0.0, 0.0, 0.0, 0.0),
+            Anisotropy::None,
+        );
+
+        let size = window.inner_size();
+        let mut lens = Self {
+            shader,
+            ubo,
+            spectral_lut,
+            linear_sampler,
+            effects: LensEffects::empty(),
+            chromatic_aberration_intensity: 0.02,
+            chromatic_aberration_samples: 8,
+            vignette_intensity: 1.0,
+            fov_deg: 60.0,
+            grain_intensity: 0.1,
+            grain_size: 1.5,
+            grain_response: 0.8,
+            distortion_k1: 0.1,
+            distortion_k2: 0.0,
+            aspect_ratio: size.width as f32 / size.height.max(1) as f32,
+            enabled: true,
+        };
+        lens.set_effects(LensEffects::all());
+        lens
+    }
+
+    pub fn effects(&self) -> LensEffects {
+        self.effects
+    }
+
+    pub fn set_effects(&mut self, effects: LensEffects) {
+        for (effect, keyword) in LENS_EFFECT_KEYWORDS.iter() {
+            if effects.contains(*effect) {
+                self.shader.enable_keyword(keyword);
+            } else {
+                self.shader.disable_keyword(keyword);
+            }
+        }
+        self.effects = effects;
+    }
+
+    /// Radial offset of the last wavelength in the corners, in texture coordinates.
+    pub fn set_chromatic_aberration_intensity(&mut self, intensity: f32) {
+        self.chromatic_aberration_intensity = intensity.max(0.0).min(0.2)
+    }
+
+    /// Samples along the spectrum, each tinted by the spectral LUT.
+    pub fn set_chromatic_aberration_samples(&mut self, samples: u32) {
+        self.chromatic_aberration_samples = samples.max(3).min(SPECTRAL_LUT_SIZE)
+    }
+
+    /// Blend from no vignetting (0) to the full cos^4 falloff (1).
+    pub fn set_vignette_intensity(&mut self, intensity: f32) {
+        self.vignette_intensity = intensity.max(0.0).min(1.0)
+    }
+
+    /// Vertical field of view of the camera in degrees.
+    pub fn set_field_of_view(&mut self, fov_deg: f32) {
+        self.fov_deg = fov_deg.max(1.0).min(179.0)
+    }
+
+    pub fn set_grain_intensity(&mut self, intensity: f32) {
+        self.grain_intensity = intensity.max(0.0).min(1.0)
+    }
+
+    /// Size of the grains in pixels.
+    pub fn set_grain_size(&mut self, size: f32) {
+        self.grain_size = size.max(0.5).min(4.0)
+    }
+
+    /// How much less grain shows in the highlights, from 0 (as much as in the shadows) to 1.
+    pub fn set_grain_response(&mut self, response: f32) {
+        self.grain_response = response.max(0.0).min(1.0)
+    }
+
+    /// Coefficients of the radial distortion in r^2 and r^4, the radius being 1 in the corners.
+    /// Positive values give barrel distortion and negative ones pincushion distortion. The image
+    /// is scaled so barrel distortion still fills the screen.
+    pub fn set_distortion(&mut self, k1: f32, k2: f32) {
+        self.distortion_k1 = k1.max(-0.5).min(0.5);
+        self.distortion_k2 = k2.max(-0.2).min(0.2);
+    }
+
+    /// Texture coordinates in the undistorted image of the point at `uv` on screen, e.g. to
+    /// pick the object under the cursor. Same as `Distort` in lens.frag.
+    pub fn screen_to_scene(&self, uv: Vec2) -> Vec2 {
+        if !self.distorts() {
+            return uv;
+        }
+
+        let position = self.lens_position(uv);
+        let r2 = position.dot(&position);
+        self.lens_texcoord(position * self.distortion_factor(r2) * self.distortion_scale())
+    }
+
+    /// Texture coordinates on screen of the point at `uv` in the undistorted image, e.g. to
+    /// draw a label over an object. The inverse of `screen_to_scene`.
+    pub fn scene_to_screen(&self, uv: Vec2) -> Vec2 {
+        if !self.distorts() {
+            return uv;
+        }
+
+        let position = self.lens_position(uv);
+        let target = position.norm();
+        if target <= f32::EPSILON {
+            return uv;
+        }
+
+        // Newton's method on the radius, the distortion keeps the direction.
+        let scale = self.distortion_scale();
+        let mut r = target;
+        for _ in 0..DISTORTION_INVERSE_ITERATIONS {
+            let r2 = r * r;
+            let f = r * self.distortion_factor(r2) * scale - target;
+            let df =
+                scale * (1.0 + 3.0 * self.distortion_k1 * r2 + 5.0 * self.distortion_k2 * r2 * r2);
+            r -= f / df;
+        }
+
+        self.lens_texcoord(position * (r / target))
+    }
+
+    fn distorts(&self) -> bool {
+        self.enabled && self.effects.contains(LensEffects::DISTORTION)
+    }
+
+    fn distortion_factor(&self, r2: f32) -> f32 {
+        1.0 + self.distortion_k1 * r2 + self.distortion_k2 * r2 * r2
+    }
+
+    fn distortion_scale(&self) -> f32 {
+        1.0 / self.distortion_factor(1.0).max(1.0)
+    }
+
+    fn lens_position(&self, uv: Vec2) -> Vec2 {
+        let diagonal = (self.aspect_ratio * self.aspect_ratio + 1.0).sqrt();
+        Vec2::new(
+            (uv.x * 2.0 - 1.0) * self.aspect_ratio / diagonal,
+            (uv.y * 2.0 - 1.0) / diagonal,
+        )
+    }
+
+    fn lens_texcoord(&self, position: Vec2) -> Vec2 {
+        let diagonal = (self.aspect_ratio * self.aspect_ratio + 1.0).sqrt();
+        Vec2::new(
+            position.x * diagonal / self.aspect_ratio * 0.5 + 0.5,
+            position.y * diagonal * 0.5 + 0.5,
+        )
+    }
+}
+
+/// Linear sRGB color of `count` wavelengths from 700nm to 400nm, from the multi-lobe fit of the
+/// CIE 1931 color matching functions of Wyman et al. Each channel sums to 1.
+fn spectral_lut(count: u32) -> Vec<f32> {
+    let lobe = |wavelength: f32, mean: f32, sigma_low: f32, sigma_high: f32| {
+        let sigma = if wavelength < mean {
+            sigma_low
+        } else {
+            sigma_high
+        };
+        let t = (wavelength - mean) / sigma;
+        (-0.5 * t * t).exp()
+    };
+
+    let mut lut: Vec<f32> = (0..count)
+        .flat_map(|i| {
+            let wavelength = 700.0 - 300.0 * (i as f32 + 0.5) / count as f32;
+
+            let x = 1.056 * lobe(wavelength, 599.8, 37.9, 31.0)
+                + 0.362 * lobe(wavelength, 442.0, 16.0, 26.7)
+                - 0.065 * lobe(wavelength, 501.1, 20.4, 26.2);
+            let y = 0.821 * lobe(wavelength, 568.8, 46.9, 40.5)
+                + 0.286 * lobe(wavelength, 530.9, 16.3, 31.1);
+            let z = 1.217 * lobe(wavelength, 437.0, 11.8, 36.0)
+                + 0.681 * lobe(wavelength, 459.0, 26.0, 13.8);
+
+            vec![
+                (3.2406 * x - 1.5372 * y - 0.4986 * z).max(0.0),
+                (-0.9689 * x + 1.8758 * y + 0.0415 * z).max(0.0),
+                (0.0557 * x - 0.2040 * y + 1.0570 * z).max(0.0),
+            ]
+        })
+        .collect();
+
+    for channel in 0..3 {
+        let sum: f32 = lut.iter().skip(channel).step_by(3).sum();
+        lut.iter_mut()
+            .skip(channel)
+            .step_by(3)
+            .for_each(|value| *value /= sum);
+    }
+
+    lut
+}
+
+impl PostprocessingEffect for Lens {
+    fn name(&self) -> &str {
+        "Lens"
+    }
+
+    fn enable(&mut self) {
+        self.enabled = true
+    }
+
+    fn disable(&mut self) {
+        self.enabled = false
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn apply(&mut self, input: &PostprocessingInput, output: &Framebuffer, context: Context) {
+        let Context { timer, .. } = context;
+
+        let size = input.size();
+        self.aspect_ratio = size.x as f32 / size.y.max(1) as f32;
+
+        self.ubo.fill_mapped(
+            0,
+            &LensUniforms {
+                chromatic_aberration_intensity: self.chromatic_aberration_intensity,
+                chromatic_aberration_samples: self.chromatic_aberration_samples as i32,
+                vignette_intensity: self.vignette_intensity,
+                tan_half_fov: (0.5 * self.fov_deg.to_radians()).tan(),
+                grain_intensity: self.grain_intensity,
+                grain_size: self.grain_size,
+                grain_response: self.grain_response,
+                grain_seed: timer.elapsed_time().fract(),
+                distortion_k1: self.distortion_k1,
+                distortion_k2: self.distortion_k2,
+                distortion_scale: self.distortion_scale(),
+                aspect_ratio: self.aspect_ratio,
+            },
+        );
+
+        self.shader.bind();
+        self.shader
+            .bind_texture_2d_with_id(
+                COLOR_BINDING_INDEX,
+                input.color().texture_attachment(0).id(),
+                &self.linear_sampler,
+            )
+            .bind_texture_2d(
+                SPECTRAL_LUT_BINDING_INDEX,
+                &self.spectral_lut,
+                &self.linear_sampler,
+            );
+
+        output.bind();
+        draw_full_screen_quad();
+        output.unbind(false);
+
+        self.shader.unbind();
+    }
+}
+
+impl Gui for Lens {
+    fn gui(&mut self, ui: &Ui) {
+        ui.group(|| {
+            let mut enabled = self.enabled;
+            if ui.checkbox("##lens", &mut enabled) {
+                if enabled {
+                    self.enable();
+                } else {
+                    self.disable();
+                }
+            }
+            ui.same_line_with_pos(20.0);
+            imgui::TreeNode::new("Lens")
+                .default_open(true)
+                .open_on_arrow(true)
+                .open_on_double_click(true)
+                .framed(false)
+                .build(ui, || {
+                    ui.indent();
+
+                    let effect_checkbox = |ui: &Ui, label: &str, lens: &mut Lens, effect| {
+                        let mut checked = lens.effects.contains(effect);
+                        if ui.checkbox(label, &mut checked) {
+                            let mut effects = lens.effects;
+                            effects.set(effect, checked);
+                            lens.set_effects(effects);
+                        }
+                        checked
+                    };
+
+                    if effect_checkbox(
+                        ui,
+                        "Chromatic Aberration",
+                        self,
+                        LensEffects::CHROMATIC_ABERRATION,
+                    ) {
+                        let mut intensity = self.chromatic_aberration_intensity;
+                        if imgui::Slider::new("Intensity##chromatic_aberration", 0.0, 0.2)
+                            .display_format("%.3f")
+                            .build(ui, &mut intensity)
+                        {
+                            self.set_chromatic_aberration_intensity(intensity);
+                        }
+
+                        let mut samples = self.chromatic_aberration_samples;
+                        if imgui::Slider::new("Samples", 3, SPECTRAL_LUT_SIZE)
+                            .build(ui, &mut samples)
+                        {
+                            self.set_chromatic_aberration_samples(samples);
+                        }
+                    }
+
+                    if effect_checkbox(ui, "Vignette", self, LensEffects::VIGNETTE) {
+                        let mut intensity = self.vignette_intensity;
+                        if imgui::Slider::new("Intensity##vignette", 0.0, 1.0)
+                            .display_format("%.2f")
+                            .build(ui, &mut intensity)
+                        {
+                            self.set_vignette_intensity(intensity);
+                        }
+                        ui.text_disabled(format!("Field of View: {:.0} degrees", self.fov_deg));
+                    }
+
+                    if effect_checkbox(ui, "Film Grain", self, LensEffects::FILM_GRAIN) {
+                        let mut intensity = self.grain_intensity;
+                        if imgui::Slider::new("Intensity##film_grain", 0.0, 1.0)
+                            .display_format("%.2f")
+                            .build(ui, &mut intensity)
+                        {
+                            self.set_grain_intensity(intensity);
+                        }
+
+                        let mut size = self.grain_size;
+                        if imgui::Slider::new("Size", 0.5, 4.0)
+                            .display_format("%.2f")
+                            .build(ui, &mut size)
+                        {
+                            self.set_grain_size(size);
+                        }
+
+                        let mut response = self.grain_response;
+                        if imgui::Slider::new("Luminance Response", 0.0, 1.0)
+                            .display_format("%.2f")
+                            .build(ui, &mut response)
+                        {
+                            self.set_grain_response(response);
+                        }
+                    }
+
+                    if effect_checkbox(ui, "Distortion", self, LensEffects::DISTORTION) {
+                        let mut k1 = self.distortion_k1;
+                        let mut k2 = self.distortion_k2;
+                        let k1_changed = imgui::Slider::new("K1", -0.5, 0.5)
+                            .display_format("%.3f")
+                            .build(ui, &mut k1);
+                        let k2_changed = imgui::Slider::new("K2", -0.2, 0.2)
+                            .display_format("%.3f")
+                            .build(ui, &mut k2);
+                        if k1_changed || k2_changed {
+                            self.set_distortion(k1, k2);
+                        }
+                    }
+
+                    ui.unindent()
+                });
+        });
+    }
+}
@@ -1,12 +1,141 @@
+use crate::core::application::clear_default_framebuffer;
+use crate::core::camera::Camera;
+use crate::core::math::{UVec2, Vec4};
 use crate::imgui::{Gui, Ui};
-use crate::rendering::framebuffer::Framebuffer;
+use crate::rendering::framebuffer::{Framebuffer, TextureFilter};
+use crate::rendering::state::StateManager;
 use crate::{AsAny, AsAnyMut, Context};
+use std::rc::Rc;
 
+pub mod auto_exposure;
 pub mod bloom;
+pub mod color_grading;
 pub mod tone_mapper;
 pub mod dof;
+pub mod fxaa;
+pub mod lens;
+pub mod motion_blur;
+pub mod smaa;
+pub mod ssao;
+pub mod ssr;
+pub mod taa;
 
-const FULLSCREEN_VERTEX_SHADER_PATH: &str = "assets/shaders/fullscreen.vert";
+pub(crate) const FULLSCREEN_VERTEX_SHADER_PATH: &str = "assets/shaders/fullscreen.vert";
+
+bitflags! {
+    /// Textures read by an effect.
+    pub struct EffectInputs: u32 {
+        const COLOR = 1 << 0;
+        const DEPTH = 1 << 1;
+        const VELOCITY = 1 << 2;
+        const NORMALS = 1 << 3;
+    }
+}
+
+/// The textures an effect reads. The color is the output of the previous effect, the other
+/// inputs are the ones given to `PostprocessingStack::apply`.
+#[derive(Clone, Copy)]
+pub struct PostprocessingInput<'a> {
+    color: &'a Framebuffer,
+    depth: Option<u32>,
+    velocity: Option<u32>,
+    normals: Option<u32>,
+    camera: Option<&'a Camera>,
+}
+
+impl<'a> PostprocessingInput<'a> {
+    /// Reads the color from the first attachment of `framebuffer` and the depth from its
+    /// depth-stencil texture attachment, if it has one.
+    pub fn new(framebuffer: &'a Framebuffer) -> Self {
+        let depth = framebuffer
+            .texture_attachments()
+            .iter()
+            .find(|attachment| attachment.is_depth_stencil())
+            .map(|attachment| attachment.id());
+
+        Self {
+            color: framebuffer,
+            depth,
+            velocity: None,
+            normals: None,
+            camera: None,
+        }
+    }
+
+    /// Screen space velocity, in UV units per frame.
+    pub fn with_velocity(mut self, texture_id: u32) -> Self {
+        self.velocity = Some(texture_id);
+        self
+    }
+
+    /// Octahedron encoded world space normals, like the ones of the G-buffer.
+    pub fn with_normals(mut self, texture_id: u32) -> Self {
+        self.normals = Some(texture_id);
+        self
+    }
+
+    /// The camera the image was rendered with, for the effects that follow its settings.
+    pub fn with_camera(mut self, camera: &'a Camera) -> Self {
+        self.camera = Some(camera);
+        self
+    }
+
+    fn with_color<'b>(&self, color: &'b Framebuffer) -> PostprocessingInput<'b>
+    where
+        'a: 'b,
+    {
+        PostprocessingInput {
+            color,
+            depth: self.depth,
+            velocity: self.velocity,
+            normals: self.normals,
+            camera: self.camera,
+        }
+    }
+
+    pub fn available(&self) -> EffectInputs {
+        let mut inputs = EffectInputs::COLOR;
+        inputs.set(EffectInputs::DEPTH, self.depth.is_some());
+        inputs.set(EffectInputs::VELOCITY, self.velocity.is_some());
+        inputs.set(EffectInputs::NORMALS, self.normals.is_some());
+        inputs
+    }
+
+    pub fn color(&self) -> &'a Framebuffer {
+        self.color
+    }
+
+    pub fn size(&self) -> UVec2 {
+        self.color.size()
+    }
+
+    pub fn depth(&self) -> u32 {
+        self.depth
+            .expect("No depth input, the effect must declare EffectInputs::DEPTH")
+    }
+
+    pub fn velocity(&self) -> u32 {
+        self.velocity
+            .expect("No velocity input, the effect must declare EffectInputs::VELOCITY")
+    }
+
+    pub fn camera(&self) -> Option<&'a Camera> {
+        self.camera
+    }
+
+    pub fn normals(&self) -> u32 {
+        self.normals
+            .expect("No normals input, the effect must declare EffectInputs::NORMALS")
+    }
+}
+
+/// Where the last effect of a `PostprocessingStack` writes.
+pub enum PostprocessingTarget<'a> {
+    DefaultFramebuffer,
+    /// An offscreen target, e.g. for screenshots or editor viewports. Its first color attachment
+    /// receives the result.
+    Framebuffer(&'a Framebuffer),
+}
 
 pub trait PostprocessingEffect: Gui + AsAny + AsAnyMut {
     fn name(&self) -> &str;
@@ -17,7 +146,14 @@ pub trait PostprocessingEffect: Gui + AsAny + AsAnyMut {
 
     fn enabled(&self) -> bool;
 
-    fn apply(&mut self, input: &Framebuffer, context: Context);
+    /// The stack skips the effect while one of these inputs is not available.
+    fn inputs(&self) -> EffectInputs {
+        EffectInputs::COLOR
+    }
+
+    /// Renders the effect from `input` into `output`. The output is a different framebuffer
+    /// than the input color, usually of the same size and format.
+    fn apply(&mut self, input: &PostprocessingInput, output: &Framebuffer, context: Context);
 }
 
 pub struct PostprocessingStack {
@@ -34,7 +170,32 @@ impl PostprocessingStack {
         self
     }
 
-    pub fn apply(&mut self, input: &Framebuffer, context: Context) {
+    /// Moves the effect at index `from` to index `to`, shifting the ones in between.
+    pub fn move_effect(&mut self, from: usize, to: usize) {
+        assert!(
+            from < self.post_effects.len() && to < self.post_effects.len(),
+            "Index out of bounds."
+        );
+
+        let effect = self.post_effects.remove(from);
+        self.post_effects.insert(to, effect);
+    }
+
+    pub fn effect_names(&self) -> Vec<&str> {
+        self.post_effects
+            .iter()
+            .map(|effect| effect.name())
+            .collect()
+    }
+
+    /// Applies the enabled effects in order, each one reading the output of the previous one
+    /// from a temporary framebuffer, and writes the result to `target`.
+    pub fn apply(
+        &mut self,
+        input: &PostprocessingInput,
+        target: PostprocessingTarget,
+        context: Context,
+    ) {
         let Context {
             window,
             device,
@@ -44,23 +205,83 @@ impl PostprocessingStack {
             settings,
         } = context;
 
-        if self.enabled {
+        let size = input.size();
+        let format = input.color().texture_attachment(0).format();
+        let available = input.available();
+
+        let active = if self.enabled {
             self.post_effects
-                .iter_mut()
-                .filter(|effect| effect.enabled())
-                .for_each(|effect| {
-                    effect.apply(
-                        &input,
-                        Context::new(
-                            window,
-                            device,
-                            asset_manager,
-                            timer,
-                            framebuffer_cache,
-                            settings,
-                        ),
-                    )
-                });
+                .iter()
+                .enumerate()
+                .filter(|(_, effect)| effect.enabled() && available.contains(effect.inputs()))
+                .map(|(index, _)| index)
+                .collect::<Vec<_>>()
+        } else {
+            vec![]
+        };
+
+        let window_size = window.inner_size();
+        let default_framebuffer =
+            Framebuffer::default_framebuffer(UVec2::new(window_size.width, window_size.height));
+
+        // The last effect writes to the target directly.
+        let final_output = match target {
+            PostprocessingTarget::DefaultFramebuffer => &default_framebuffer,
+            PostprocessingTarget::Framebuffer(framebuffer) => framebuffer,
+        };
+
+        let mut previous: Option<Rc<Framebuffer>> = None;
+
+        for (position, &index) in active.iter().enumerate() {
+            let last = position + 1 == active.len();
+
+            let output = (!last)
+                .then(|| framebuffer_cache.get_temporary("Postprocessing", size, format, None));
+
+            let effect_input = input.with_color(previous.as_deref().unwrap_or(input.color()));
+            let effect_output = output.as_deref().unwrap_or(final_output);
+
+            self.post_effects[index].apply(
+                &effect_input,
+                effect_output,
+                Context::new(
+                    window,
+                    device,
+                    asset_manager,
+                    timer,
+                    framebuffer_cache,
+                    settings,
+                ),
+            );
+
+            if let Some(previous) = previous.take() {
+                framebuffer_cache.release_temporary(previous);
+            }
+            previous = output;
+        }
+
+        // Without effects, the input is copied to the target.
+        if active.is_empty() {
+            match target {
+                PostprocessingTarget::DefaultFramebuffer => {
+                    StateManager::viewport(
+                        0,
+                        0,
+                        window_size.width as i32,
+                        window_size.height as i32,
+                    );
+                    clear_default_framebuffer(&Vec4::new(0.0, 0.0, 0.0, 1.0));
+
+                    Framebuffer::blit_color_to_default(
+                        input.color(),
+                        default_framebuffer.size(),
+                        TextureFilter::Linear,
+                    );
+                }
+                PostprocessingTarget::Framebuffer(framebuffer) => {
+                    Framebuffer::blit(input.color(), framebuffer, TextureFilter::Linear);
+                }
+            }
         }
     }
 
@@ -99,9 +320,25 @@ impl Gui for PostprocessingStack {
             ui.spacing();
             ui.indent();
 
-            self.post_effects
-                .iter_mut()
-                .for_each(|effect| effect.gui(ui));
+            let count = self.post_effects.len();
+            let mut moved = None;
+
+            for (index, effect) in self.post_effects.iter_mut().enumerate() {
+                if ui.small_button(format!("^##up_{}", effect.name())) && index > 0 {
+                    moved = Some((index, index - 1));
+                }
+                ui.same_line();
+                if ui.small_button(format!("v##down_{}", effect.name())) && index + 1 < count {
+                    moved = Some((index, index + 1));
+                }
+                ui.same_line();
+
+                effect.gui(ui);
+            }
+
+            if let Some((from, to)) = moved {
+                self.move_effect(from, to);
+            }
 
             ui.unindent();
         }
@@ -0,0 +1,300 @@
+use std::any::Any;
+use std::rc::Rc;
+
+use crate::{
+    framebuffer::Framebuffer,
+    imgui::{Gui, Ui},
+    math::{UVec2, Vec4},
+    mesh::utilities::draw_full_screen_quad,
+    rendering::{
+        buffer::{Buffer, BufferStorageFlags, BufferTarget, MapModeFlags},
+        postprocess::{
+            AsAny, AsAnyMut, EffectInputs, PostprocessingEffect, PostprocessingInput,
+            FULLSCREEN_VERTEX_SHADER_PATH,
+        },
+        sampler::{Anisotropy, MagnificationFilter, MinificationFilter, Sampler, WrappingMode},
+        shader::{Shader, ShaderCreateInfo, ShaderStage},
+        texture::SizedTextureFormat,
+    },
+    Context,
+};
+
+const MOTION_BLUR_FRAGMENT_SHADER_PATH: &str = "assets/shaders/motion_blur.frag";
+
+const MOTION_BLUR_UBO_BINDING_INDEX: u32 = 20;
+
+// Texture units of motion_blur.frag.
+const COLOR_BINDING_INDEX: u32 = 0;
+const DEPTH_BINDING_INDEX: u32 = 1;
+const VELOCITY_BINDING_INDEX: u32 = 2;
+const TILE_BINDING_INDEX: u32 = 3;
+
+#[repr(C)]
+struct MotionBlurUniforms {
+    shutter_fraction: f32,
+    max_blur_radius: f32,
+    sample_count: i32,
+    soft_depth_extent: f32,
+}
+
+/// Camera and per-object motion blur, reconstructed from the velocity buffer with the filter of
+/// McGuire et al. The blur vectors are reduced to tiles of `max_blur_radius` pixels and to the
+/// maximum of their neighbours, which bounds how far each pixel gathers from.
+///
+/// The length of the blur is the motion during the exposure of the camera of the
+/// `PostprocessingInput`, i.e. a shutter angle of 360 degrees times its shutter speed over the
+/// frame time. Without a camera the shutter stays open for the whole frame. Runs after temporal
+/// anti-aliasing, which would otherwise reject the blurred history.
+pub struct MotionBlur {
+    shader: Rc<Shader>,
+    ubo: Buffer,
+    linear_sampler: Sampler,
+    nearest_sampler: Sampler,
+    shutter_fraction: f32,
+    max_blur_radius: u32,
+    sample_count: u32,
+    soft_depth_extent: f32,
+    enabled: bool,
+}
+
+impl_as_any!(MotionBlur);
+
+impl MotionBlur {
+    pub fn new(context: Context) -> Self {
+        let Context { device, .. } = context;
+
+        let shader = device.shader_manager().create_shader(
+            &ShaderCreateInfo::builder("Motion Blur Shader")
+                .stage(ShaderStage::Vertex, FULLSCREEN_VERTEX_SHADER_PATH)
+                .stage(ShaderStage::Fragment, MOTION_BLUR_FRAGMENT_SHADER_PATH)
+                .keyword_set(&[
+                    "MOTION_BLUR_PASS_TILE_MAX",
+                    "MOTION_BLUR_PASS_NEIGHBOUR_MAX",
+                    "MOTION_BLUR_PASS_RECONSTRUCT",
+                ])
+                .build(),
+        );
+
+        let mut ubo = Buffer::new(
+            "Motion Blur UBO",
+            std::mem::size_of::<MotionBlurUniforms>() as isize,
+            BufferTarget::Uniform,
+            BufferStorageFlags::MAP_WRITE_PERSISTENT_COHERENT,
+        );
+        ubo.bind(MOTION_BLUR_UBO_BINDING_INDEX);
+        ubo.map(MapModeFlags::MAP_WRITE_PERSISTENT_COHERENT);
+
+        let create_sampler = |min_filter: MinificationFilter, mag_filter: MagnificationFilter| {
+            Sampler::new(
+                min_filter,
+                mag_filter,
+                WrappingMode::ClampToEdge,
+                WrappingMode::ClampToEdge,
+                WrappingMode::ClampToEdge,
+                Vec4::new(0.0, 0.0, 0.0, 0.0),
+                Anisotropy::None,
+            )
+        };
+
+        Self {
+            shader,
+            ubo,
+            linear_sampler: create_sampler(MinificationFilter::Linear, MagnificationFilter::Linear),
+            nearest_sampler: create_sampler(
+                MinificationFilter::Nearest,
+                MagnificationFilter::Nearest,
+            ),
+            shutter_fraction: 1.0,
+            max_blur_radius: 20,
+            sample_count: 15,
+            soft_depth_extent: 0.5,
+            enabled: true,
+        }
+    }
+
+    /// Shutter angle of the last applied frame in degrees. Exposures longer than a frame are
+    /// clamped to 360.
+    pub fn shutter_angle(&self) -> f32 {
+        self.shutter_fraction * 360.0
+    }
+
+    /// Longest blur in pixels, in either direction. Also the size of the velocity tiles.
+    pub fn max_blur_radius(&self) -> u32 {
+        self.max_blur_radius
+    }
+
+    pub fn set_max_blur_radius(&mut self, radius: u32) {
+        self.max_blur_radius = radius.max(1).min(64)
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    pub fn set_sample_count(&mut self, count: u32) {
+        self.sample_count = count.max(1).min(64)
+    }
+
+    /// Depth difference, in view space units, over which a surface goes from in front of to
+    /// behind another.
+    pub fn set_soft_depth_extent(&mut self, extent: f32) {
+        self.soft_depth_extent = extent.max(0.001)
+    }
+
+    fn run_pass(&self, keyword: &str, target: &Framebuffer) {
+        self.shader.enable_keyword(keyword);
+        target.bind();
+        draw_full_screen_quad();
+        target.unbind(false);
+        self.shader.disable_keyword(keyword);
+    }
+}
+
+impl PostprocessingEffect for MotionBlur {
+    fn name(&self) -> &str {
+        "MotionBlur"
+    }
+
+    fn enable(&mut self) {
+        self.enabled = true
+    }
+
+    fn disable(&mut self) {
+        self.enabled = false
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn inputs(&self) -> EffectInputs {
+        EffectInputs::COLOR | EffectInputs::DEPTH | EffectInputs::VELOCITY
+    }
+
+    fn apply(&mut self, input: &PostprocessingInput, output: &Framebuffer, context: Context) {
+        let Context {
+            timer,
+            framebuffer_cache,
+            ..
+        } = context;
+
+        let size = input.size();
+        let tile_count = UVec2::new(
+            (size.x + self.max_blur_radius - 1) / self.max_blur_radius,
+            (size.y + self.max_blur_radius - 1) / self.max_blur_radius,
+        );
+
+        self.shutter_fraction = input.camera().map_or(1.0, |camera| {
+            (camera.shutter_speed() / timer.delta_time().max(1e-4)).min(1.0)
+        });
+
+        self.ubo.fill_mapped(
+            0,
+            &MotionBlurUniforms {
+                shutter_fraction: self.shutter_fraction,
+                max_blur_radius: self.max_blur_radius as f32,
+                sample_count: self.sample_count as i32,
+                soft_depth_extent: self.soft_depth_extent,
+            },
+        );
+
+        self.shader
+            .bind_texture_2d_with_id(
+                COLOR_BINDING_INDEX,
+                input.color().texture_attachment(0).id(),
+                &self.linear_sampler,
+            )
+            .bind_texture_2d_with_id(DEPTH_BINDING_INDEX, input.depth(), &self.nearest_sampler)
+            .bind_texture_2d_with_id(
+                VELOCITY_BINDING_INDEX,
+                input.velocity(),
+                &self.nearest_sampler,
+            );
+
+        // Tile max pass
+        let tile_max = framebuffer_cache.get_temporary(
+            "Motion Blur Tile Max",
+            tile_count,
+            SizedTextureFormat::Rg16f,
+            None,
+        );
+        self.run_pass("MOTION_BLUR_PASS_TILE_MAX", &tile_max);
+
+        // Neighbour max pass
+        let neighbour_max = framebuffer_cache.get_temporary(
+            "Motion Blur Neighbour Max",
+            tile_count,
+            SizedTextureFormat::Rg16f,
+            None,
+        );
+        self.shader.bind_texture_2d_with_id(
+            TILE_BINDING_INDEX,
+            tile_max.texture_attachment(0).id(),
+            &self.nearest_sampler,
+        );
+        self.run_pass("MOTION_BLUR_PASS_NEIGHBOUR_MAX", &neighbour_max);
+
+        // Reconstruction pass
+        self.shader.bind_texture_2d_with_id(
+            TILE_BINDING_INDEX,
+            neighbour_max.texture_attachment(0).id(),
+            &self.nearest_sampler,
+        );
+        self.run_pass("MOTION_BLUR_PASS_RECONSTRUCT", output);
+
+        self.shader.unbind();
+
+        framebuffer_cache.release_temporary(tile_max);
+        framebuffer_cache.release_temporary(neighbour_max);
+    }
+}
+
+impl Gui for MotionBlur {
+    fn gui(&mut self, ui: &Ui) {
+        ui.group(|| {
+            let mut enabled = self.enabled;
+            if ui.checkbox("##motion_blur", &mut enabled) {
+                if enabled {
+                    self.enable();
+                } else {
+                    self.disable();
+                }
+            }
+            ui.same_line_with_pos(20.0);
+            imgui::TreeNode::new("Motion Blur")
+                .default_open(true)
+                .open_on_arrow(true)
+                .open_on_double_click(true)
+                .framed(false)
+                .build(ui, || {
+                    ui.indent();
+
+                    ui.text_disabled(format!(
+                        "Shutter angle: {:.0} degrees",
+                        self.shutter_angle()
+                    ));
+
+                    let mut max_blur_radius = self.max_blur_radius;
+                    if imgui::Slider::new("Max Blur Radius", 4, 64).build(ui, &mut max_blur_radius)
+                    {
+                        self.set_max_blur_radius(max_blur_radius);
+                    }
+
+                    let mut sample_count = self.sample_count;
+                    if imgui::Slider::new("Samples", 4, 32).build(ui, &mut sample_count) {
+                        self.set_sample_count(sample_count);
+                    }
+
+                    let mut soft_depth_extent = self.soft_depth_extent;
+                    if imgui::Slider::new("Soft Depth Extent", 0.01, 5.0)
+                        .display_format("%.2f")
+                        .build(ui, &mut soft_depth_extent)
+                    {
+                        self.set_soft_depth_extent(soft_depth_extent);
+                    }
+
+                    ui.unindent()
+                });
+        });
+    }
+}
@@ -0,0 +1,422 @@
+use std::any::Any;
+use std::rc::Rc;
+
+use crate::{
+    framebuffer::Framebuffer,
+    imgui::{Gui, Ui},
+    math::{UVec2, Vec4},
+    mesh::utilities::draw_full_screen_quad,
+    rendering::{
+        buffer::{Buffer, BufferStorageFlags, BufferTarget, MapModeFlags},
+        postprocess::{
+            AsAny, AsAnyMut, PostprocessingEffect, PostprocessingInput,
+            FULLSCREEN_VERTEX_SHADER_PATH,
+        },
+        sampler::{Anisotropy, MagnificationFilter, MinificationFilter, Sampler, WrappingMode},
+        shader::{Shader, ShaderCreateInfo, ShaderStage},
+        texture::{SizedTextureFormat, Texture2D, TextureFormat},
+    },
+    Context,
+};
+
+const SMAA_FRAGMENT_SHADER_PATH: &str = "assets/shaders/smaa.frag";
+
+const SMAA_UBO_BINDING_INDEX: u32 = 17;
+
+// Texture units of smaa.frag.
+const COLOR_BINDING_INDEX: u32 = 0;
+const EDGES_BINDING_INDEX: u32 = 1;
+const BLEND_BINDING_INDEX: u32 = 2;
+const AREA_BINDING_INDEX: u32 = 3;
+const SEARCH_BINDING_INDEX: u32 = 4;
+
+/// Texels per side of each crossing edge pattern in the area LUT. The distances are stored
+/// squared, so a pattern covers lines up to 15² pixels away from each end.
+pub const SMAA_AREA_LUT_MAX_DISTANCE: u32 = 16;
+/// The crossing edges at each end are one of 0, 1, 3 or 4 (see `smaa_area_lut`), 5 patterns
+/// per axis with the unused 2.
+pub const SMAA_AREA_LUT_SIZE: u32 = 5 * SMAA_AREA_LUT_MAX_DISTANCE;
+
+/// One half per search direction, indexed by the 33 possible values of a bilinear fetch of the
+/// crossing edges (x) and the followed edges (y).
+pub const SMAA_SEARCH_LUT_WIDTH: u32 = 2 * 33;
+pub const SMAA_SEARCH_LUT_HEIGHT: u32 = 33;
+
+const MAX_SEARCH_STEPS: i32 = 16;
+
+#[repr(C)]
+struct SmaaUniforms {
+    threshold: f32,
+    local_contrast_adaptation: f32,
+    max_search_steps: i32,
+    _padding: f32,
+}
+
+/// Height of the antialiased line at an end of an edge, in pixels towards the row (or column)
+/// of the pixel being processed. `crossing` is the rounded bilinear fetch of the crossing edges,
+/// weighted 3 on the side of the pixel and 1 on the other side.
+fn crossing_height(crossing: u32) -> f32 {
+    match crossing {
+        3 => 0.5,
+        1 => -0.5,
+        _ => 0.0,
+    }
+}
+
+/// Coverage of the pixel `left` pixels away from the left end of an edge of `left + right + 1`
+/// pixels. The line goes from the height of each end to the edge at the middle, which covers
+/// the L, U and Z shapes. Returns the area on the side of the pixel and on the other side.
+fn orthogonal_area(left: u32, right: u32, left_height: f32, right_height: f32) -> (f32, f32) {
+    const SAMPLES: u32 = 32;
+
+    let half_length = (left + right + 1) as f32 * 0.5;
+
+    (0..SAMPLES)
+        .map(|i| {
+            let u = left as f32 + (i as f32 + 0.5) / SAMPLES as f32;
+            if u < half_length {
+                left_height * (1.0 - u / half_length)
+            } else {
+                right_height * (u - half_length) / half_length
+            }
+        })
+        .fold((0.0, 0.0), |(inside, outside), height| {
+            (
+                inside + height.max(0.0) / SAMPLES as f32,
+                outside + (-height).max(0.0) / SAMPLES as f32,
+            )
+        })
+}
+
+/// Generates the RG8 area LUT of the orthogonal patterns, `SMAA_AREA_LUT_SIZE` texels square.
+/// The pattern is selected by the crossing edges at the left (x) and right (y) ends, and the
+/// texel inside it by the square root of the distance to each end.
+pub fn smaa_area_lut() -> Vec<u8> {
+    let mut data = Vec::with_capacity((SMAA_AREA_LUT_SIZE * SMAA_AREA_LUT_SIZE * 2) as usize);
+
+    for y in 0..SMAA_AREA_LUT_SIZE {
+        for x in 0..SMAA_AREA_LUT_SIZE {
+            let (e1, e2) = (
+                x / SMAA_AREA_LUT_MAX_DISTANCE,
+                y / SMAA_AREA_LUT_MAX_DISTANCE,
+            );
+            let (left, right) = (
+                (x % SMAA_AREA_LUT_MAX_DISTANCE).pow(2),
+                (y % SMAA_AREA_LUT_MAX_DISTANCE).pow(2),
+            );
+
+            let (inside, outside) =
+                orthogonal_area(left, right, crossing_height(e1), crossing_height(e2));
+
+            data.push((inside * 255.0).round() as u8);
+            data.push((outside * 255.0).round() as u8);
+        }
+    }
+
+    data
+}
+
+/// Decodes the four edges of a bilinear fetch made a quarter of a pixel towards the next pixel
+/// and an eighth towards the other side: `[next other, current other, next, current]`. Their
+/// weights are 1, 3, 7 and 21 in 32ths, so every combination has its own value.
+fn decode_bilinear_edges(value: u32) -> Option<[bool; 4]> {
+    const WEIGHTS: [u32; 4] = [1, 3, 7, 21];
+
+    (0..16u32)
+        .map(|bits| {
+            let mut edges = [false; 4];
+            edges
+                .iter_mut()
+                .enumerate()
+                .for_each(|(i, edge)| *edge = bits & (1 << i) != 0);
+            edges
+        })
+        .find(|edges| {
+            edges
+                .iter()
+                .zip(WEIGHTS.iter())
+                .filter(|(&edge, _)| edge)
+                .map(|(_, &weight)| weight)
+                .sum::<u32>()
+                == value
+        })
+}
+
+/// Number of pixels of the last fetch of a search towards decreasing coordinates that still
+/// belong to the edge. A crossing edge of the current pixel ends the line on it.
+fn search_delta_backward(crossing: [bool; 4], edges: [bool; 4]) -> u8 {
+    let mut delta = 0;
+    if edges[3] {
+        delta += 1;
+    }
+    if delta == 1 && edges[2] && !crossing[1] && !crossing[3] {
+        delta += 1;
+    }
+    delta
+}
+
+/// Same as `search_delta_backward` towards increasing coordinates, where the crossing edges of
+/// a pixel lie before it.
+fn search_delta_forward(crossing: [bool; 4], edges: [bool; 4]) -> u8 {
+    let mut delta = 0;
+    if edges[3] && !crossing[1] && !crossing[3] {
+        delta += 1;
+    }
+    if delta == 1 && edges[2] && !crossing[0] && !crossing[2] {
+        delta += 1;
+    }
+    delta
+}
+
+/// Generates the R8 search LUT. It corrects the end of the searches, which step two pixels at a
+/// time, from their last fetch. The value is the correction in pixels times 127.
+pub fn smaa_search_lut() -> Vec<u8> {
+    let half_width = SMAA_SEARCH_LUT_WIDTH / 2;
+
+    let mut data = Vec::with_capacity((SMAA_SEARCH_LUT_WIDTH * SMAA_SEARCH_LUT_HEIGHT) as usize);
+
+    for y in 0..SMAA_SEARCH_LUT_HEIGHT {
+        for x in 0..SMAA_SEARCH_LUT_WIDTH {
+            let delta = match (
+                decode_bilinear_edges(x % half_width),
+                decode_bilinear_edges(y),
+            ) {
+                (Some(crossing), Some(edges)) if x < half_width => {
+                    search_delta_backward(crossing, edges)
+                }
+                (Some(crossing), Some(edges)) => search_delta_forward(crossing, edges),
+                _ => 0,
+            };
+
+            data.push(delta * 127);
+        }
+    }
+
+    data
+}
+
+/// SMAA 1x with luma edge detection. Finds the edges, computes the coverage of the orthogonal
+/// lines they form from the area LUT and blends each pixel with its neighbours. Works on the
+/// tone mapped image, so it must be added after the `ToneMapper`.
+pub struct Smaa {
+    shader: Rc<Shader>,
+    ubo: Buffer,
+    area_lut: Texture2D,
+    search_lut: Texture2D,
+    linear_sampler: Sampler,
+    nearest_sampler: Sampler,
+    threshold: f32,
+    local_contrast_adaptation: f32,
+    enabled: bool,
+}
+
+impl_as_any!(Smaa);
+
+impl Smaa {
+    pub fn new(context: Context) -> Self {
+        let Context { device, .. } = context;
+
+        let shader = device.shader_manager().create_shader(
+            &ShaderCreateInfo::builder("SMAA Shader")
+                .stage(ShaderStage::Vertex, FULLSCREEN_VERTEX_SHADER_PATH)
+                .stage(ShaderStage::Fragment, SMAA_FRAGMENT_SHADER_PATH)
+                .keyword_set(&[
+                    "SMAA_PASS_EDGE_DETECTION",
+                    "SMAA_PASS_BLENDING_WEIGHTS",
+                    "SMAA_PASS_NEIGHBORHOOD_BLENDING",
+                ])
+                .build(),
+        );
+
+        let mut ubo = Buffer::new(
+            "SMAA UBO",
+            std::mem::size_of::<SmaaUniforms>() as isize,
+            BufferTarget::Uniform,
+            BufferStorageFlags::MAP_WRITE_PERSISTENT_COHERENT,
+        );
+        ubo.bind(SMAA_UBO_BINDING_INDEX);
+        ubo.map(MapModeFlags::MAP_WRITE_PERSISTENT_COHERENT);
+
+        let area_lut = Texture2D::new_from_u8(
+            "SMAA Area LUT",
+            UVec2::new(SMAA_AREA_LUT_SIZE, SMAA_AREA_LUT_SIZE),
+            SizedTextureFormat::Rg8,
+            TextureFormat::Rg,
+            &smaa_area_lut(),
+        );
+
+        let search_lut = Texture2D::new_from_u8(
+            "SMAA Search LUT",
+            UVec2::new(SMAA_SEARCH_LUT_WIDTH, SMAA_SEARCH_LUT_HEIGHT),
+            SizedTextureFormat::R8,
+            TextureFormat::Red,
+            &smaa_search_lut(),
+        );
+
+        let create_sampler = |min_filter: MinificationFilter, mag_filter: MagnificationFilter| {
+            Sampler::new(
+                min_filter,
+                mag_filter,
+                WrappingMode::ClampToEdge,
+                WrappingMode::ClampToEdge,
+                WrappingMode::ClampToEdge,
+                Vec4::new(0.0, 0.0, 0.0, 0.0),
+                Anisotropy::None,
+            )
+        };
+
+        Self {
+            shader,
+            ubo,
+            area_lut,
+            search_lut,
+            linear_sampler: create_sampler(MinificationFilter::Linear, MagnificationFilter::Linear),
+            nearest_sampler: create_sampler(
+                MinificationFilter::Nearest,
+                MagnificationFilter::Nearest,
+            ),
+            threshold: 0.1,
+            local_contrast_adaptation: 2.0,
+            enabled: true,
+        }
+    }
+
+    /// Minimum luma difference with a neighbour to detect an edge.
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.threshold = threshold.max(0.0).min(0.5)
+    }
+
+    /// Edges are dropped when a neighbouring edge is this many times stronger.
+    pub fn set_local_contrast_adaptation(&mut self, local_contrast_adaptation: f32) {
+        self.local_contrast_adaptation = local_contrast_adaptation.max(1.0)
+    }
+
+    fn run_pass(&self, keyword: &str, target: &Framebuffer) {
+        self.shader.enable_keyword(keyword);
+        target.bind();
+        draw_full_screen_quad();
+        target.unbind(false);
+        self.shader.disable_keyword(keyword);
+    }
+}
+
+impl PostprocessingEffect for Smaa {
+    fn name(&self) -> &str {
+        "Smaa"
+    }
+
+    fn enable(&mut self) {
+        self.enabled = true
+    }
+
+    fn disable(&mut self) {
+        self.enabled = false
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn apply(&mut self, input: &PostprocessingInput, output: &Framebuffer, context: Context) {
+        let Context {
+            framebuffer_cache, ..
+        } = context;
+
+        let color = input.color().texture_attachment(0);
+        let size = input.size();
+
+        self.ubo.fill_mapped(
+            0,
+            &SmaaUniforms {
+                threshold: self.threshold,
+                local_contrast_adaptation: self.local_contrast_adaptation,
+                max_search_steps: MAX_SEARCH_STEPS,
+                _padding: 0.0,
+            },
+        );
+
+        self.shader
+            .bind_texture_2d_with_id(COLOR_BINDING_INDEX, color.id(), &self.linear_sampler)
+            .bind_texture_2d_with_id(
+                AREA_BINDING_INDEX,
+                self.area_lut.get_id(),
+                &self.linear_sampler,
+            )
+            .bind_texture_2d_with_id(
+                SEARCH_BINDING_INDEX,
+                self.search_lut.get_id(),
+                &self.nearest_sampler,
+            );
+
+        // Edge detection pass, the pixels without edges are discarded.
+        let edges =
+            framebuffer_cache.get_temporary("SMAA Edges", size, SizedTextureFormat::Rg8, None);
+        edges.clear(&Vec4::new(0.0, 0.0, 0.0, 0.0));
+        self.run_pass("SMAA_PASS_EDGE_DETECTION", &edges);
+
+        // Blending weights pass, the searches rely on the bilinear filtering of the edges.
+        let blend =
+            framebuffer_cache.get_temporary("SMAA Blend", size, SizedTextureFormat::Rgba8, None);
+        self.shader.bind_texture_2d_with_id(
+            EDGES_BINDING_INDEX,
+            edges.texture_attachment(0).id(),
+            &self.linear_sampler,
+        );
+        self.run_pass("SMAA_PASS_BLENDING_WEIGHTS", &blend);
+
+        // Neighbourhood blending pass.
+        self.shader.bind_texture_2d_with_id(
+            BLEND_BINDING_INDEX,
+            blend.texture_attachment(0).id(),
+            &self.nearest_sampler,
+        );
+        self.run_pass("SMAA_PASS_NEIGHBORHOOD_BLENDING", output);
+
+        self.shader.unbind();
+
+        framebuffer_cache.release_temporary(edges);
+        framebuffer_cache.release_temporary(blend);
+    }
+}
+
+impl Gui for Smaa {
+    fn gui(&mut self, ui: &Ui) {
+        ui.group(|| {
+            let mut enabled = self.enabled;
+            if ui.checkbox("##smaa", &mut enabled) {
+                if enabled {
+                    self.enable();
+                } else {
+                    self.disable();
+                }
+            }
+            ui.same_line_with_pos(20.0);
+            imgui::TreeNode::new("SMAA")
+                .default_open(true)
+                .open_on_arrow(true)
+                .open_on_double_click(true)
+                .framed(false)
+                .build(ui, || {
+                    ui.indent();
+
+                    let mut threshold = self.threshold;
+                    if imgui::Slider::new("Threshold", 0.05, 0.2)
+                        .display_format("%.3f")
+                        .build(ui, &mut threshold)
+                    {
+                        self.set_threshold(threshold);
+                    }
+
+                    let mut local_contrast_adaptation = self.local_contrast_adaptation;
+                    if imgui::Slider::new("Local Contrast Adaptation", 1.0, 4.0)
+                        .display_format("%.1f")
+                        .build(ui, &mut local_contrast_adaptation)
+                    {
+                        self.set_local_contrast_adaptation(local_contrast_adaptation);
+                    }
+
+                    ui.unindent()
+                });
+        });
+    }
+}
@@ -0,0 +1,389 @@
+use std::rc::Rc;
+
+use crate::{
+    core::camera::Camera,
+    core::math::{inverse, lerp_scalar, radical_inverse, UVec2, Vec3, Vec4},
+    core::Msaa,
+    imgui::{Gui, Ui},
+    mesh::utilities::draw_full_screen_quad,
+    rendering::{
+        buffer::{Buffer, BufferStorageFlags, BufferTarget, MapModeFlags},
+        deferred::{GBuffer, GBufferAttachment},
+        framebuffer::{AttachmentType, Framebuffer, FramebufferAttachmentCreateInfo},
+        postprocess::FULLSCREEN_VERTEX_SHADER_PATH,
+        sampler::{Anisotropy, MagnificationFilter, MinificationFilter, Sampler, WrappingMode},
+        shader::{Shader, ShaderCreateInfo, ShaderStage},
+        texture::SizedTextureFormat,
+    },
+    Context,
+};
+
+const SSAO_FRAGMENT_SHADER_PATH: &str = "assets/shaders/ssao/ssao.frag";
+const SSAO_BLUR_FRAGMENT_SHADER_PATH: &str = "assets/shaders/ssao/ssao_blur.frag";
+
+/// Must match MAX_SSAO_SAMPLES in ambient_occlusion.glsl.
+pub const MAX_SSAO_SAMPLES: usize = 64;
+
+/// Texture unit of the ambient occlusion map in the lit shaders.
+pub const AMBIENT_OCCLUSION_MAP_BINDING_INDEX: u32 = 12;
+const UBO_BINDING_INDEX: u32 = 13;
+
+// Texture units of the SSAO passes.
+const INPUT_BINDING_INDEX: u32 = 0;
+const NORMAL_BINDING_INDEX: u32 = 1;
+const DEPTH_BINDING_INDEX: u32 = 2;
+
+/// Deterministic sample kernel in the unit hemisphere around +Z. Directions are cosine
+/// distributed and lengths are biased towards the center, so that close occluders weigh more.
+pub fn ssao_kernel(sample_count: usize) -> Vec<Vec3> {
+    (0..sample_count)
+        .map(|i| {
+            let u = (i as f32 + 0.5) / sample_count as f32;
+            let v = radical_inverse(i as u32, 2);
+            let w = radical_inverse(i as u32, 3);
+
+            let phi = 2.0 * std::f32::consts::PI * v;
+            let cos_theta = (1.0 - u).sqrt();
+            let sin_theta = u.sqrt();
+
+            let direction = Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+
+            direction * lerp_scalar(0.1, 1.0, w * w)
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AmbientOcclusionSettings {
+    /// World space radius of the sampled hemisphere.
+    pub radius: f32,
+    /// Exponent applied to the occlusion.
+    pub intensity: f32,
+    /// Depth difference below which a sample does not occlude, hides self occlusion.
+    pub bias: f32,
+    pub sample_count: usize,
+    /// Renders the occlusion at half the resolution of the G-buffer.
+    pub half_resolution: bool,
+    pub blur: bool,
+    /// How strongly the blur stops at depth discontinuities.
+    pub blur_sharpness: f32,
+}
+
+impl Default for AmbientOcclusionSettings {
+    fn default() -> Self {
+        Self {
+            radius: 0.5,
+            intensity: 1.5,
+            bias: 0.025,
+            sample_count: 16,
+            half_resolution: true,
+            blur: true,
+            blur_sharpness: 8.0,
+        }
+    }
+}
+
+// Plain std140 layout of the `AmbientOcclusionBlock` uniform block.
+#[repr(C)]
+struct AmbientOcclusionBlock {
+    inverse_projection: mint::ColumnMatrix4<f32>,
+    kernel: [[f32; 4]; MAX_SSAO_SAMPLES],
+    screen_size: [f32; 4],
+    ao_size: [f32; 4],
+    radius: f32,
+    intensity: f32,
+    bias: f32,
+    sample_count: i32,
+    blur_sharpness: f32,
+    _padding: [f32; 3],
+}
+
+/// Screen space ambient occlusion computed from the depth and normals of a `GBuffer`, with a
+/// depth aware separable blur. Runs before lighting: when the screenSpaceAO flag of their per-frame
+/// block is set, the lit shaders multiply the result into the ambient occlusion of the indirect
+/// lighting.
+pub struct ScreenSpaceAmbientOcclusion {
+    settings: AmbientOcclusionSettings,
+    ssao_shader: Rc<Shader>,
+    blur_shader: Rc<Shader>,
+    ubo: Buffer,
+    ao_framebuffer: Framebuffer,
+    blur_framebuffer: Framebuffer,
+    nearest_sampler: Sampler,
+    linear_sampler: Sampler,
+    size: UVec2,
+    enabled: bool,
+}
+
+impl ScreenSpaceAmbientOcclusion {
+    /// `size` is the size of the G-buffer the effect is applied to.
+    pub fn new(context: Context, size: UVec2, settings: AmbientOcclusionSettings) -> Self {
+        let Context { device, .. } = context;
+
+        let ssao_shader = device.shader_manager().create_shader(
+            &ShaderCreateInfo::builder("SSAO Shader")
+                .stage(ShaderStage::Vertex, FULLSCREEN_VERTEX_SHADER_PATH)
+                .stage(ShaderStage::Fragment, SSAO_FRAGMENT_SHADER_PATH)
+                .build(),
+        );
+
+        let blur_shader = device.shader_manager().create_shader(
+            &ShaderCreateInfo::builder("SSAO Blur Shader")
+                .stage(ShaderStage::Vertex, FULLSCREEN_VERTEX_SHADER_PATH)
+                .stage(ShaderStage::Fragment, SSAO_BLUR_FRAGMENT_SHADER_PATH)
+                .keyword_set(&["SSAO_BLUR_HORIZONTAL", "SSAO_BLUR_VERTICAL"])
+                .build(),
+        );
+
+        let mut ubo = Buffer::new(
+            "SSAO UBO",
+            std::mem::size_of::<AmbientOcclusionBlock>() as isize,
+            BufferTarget::Uniform,
+            BufferStorageFlags::MAP_WRITE_PERSISTENT_COHERENT,
+        );
+        ubo.bind(UBO_BINDING_INDEX);
+        ubo.map(MapModeFlags::MAP_WRITE_PERSISTENT_COHERENT);
+
+        let create_sampler = |filter: (MinificationFilter, MagnificationFilter)| {
+            Sampler::new(
+                filter.0,
+                filter.1,
+                WrappingMode::ClampToEdge,
+                WrappingMode::ClampToEdge,
+                WrappingMode::ClampToEdge,
+                Vec4::new(0.0, 0.0, 0.0, 0.0),
+                Anisotropy::None,
+            )
+        };
+
+        let settings = Self::validate_settings(settings);
+        let ao_size = Self::ao_size(size, settings.half_resolution);
+
+        Self {
+            ao_framebuffer: Self::create_framebuffer("SSAO", ao_size),
+            blur_framebuffer: Self::create_framebuffer("SSAO-Blur", ao_size),
+            settings,
+            ssao_shader,
+            blur_shader,
+            ubo,
+            nearest_sampler: create_sampler((
+                MinificationFilter::Nearest,
+                MagnificationFilter::Nearest,
+            )),
+            linear_sampler: create_sampler((
+                MinificationFilter::Linear,
+                MagnificationFilter::Linear,
+            )),
+            size,
+            enabled: true,
+        }
+    }
+
+    pub fn settings(&self) -> &AmbientOcclusionSettings {
+        &self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: AmbientOcclusionSettings) {
+        let settings = Self::validate_settings(settings);
+        let half_resolution_changed = settings.half_resolution != self.settings.half_resolution;
+
+        self.settings = settings;
+
+        if half_resolution_changed {
+            self.resize(self.size);
+        }
+    }
+
+    pub fn enable(&mut self) {
+        self.enabled = true
+    }
+
+    pub fn disable(&mut self) {
+        self.enabled = false
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Recreates the occlusion targets for a G-buffer of the given size.
+    pub fn resize(&mut self, size: UVec2) {
+        let ao_size = Self::ao_size(size, self.settings.half_resolution);
+
+        self.size = size;
+        self.ao_framebuffer = Self::create_framebuffer("SSAO", ao_size);
+        self.blur_framebuffer = Self::create_framebuffer("SSAO-Blur", ao_size);
+    }
+
+    /// Computes the occlusion of the depth and normals of `gbuffer`, as seen from `camera`.
+    pub fn apply(&self, gbuffer: &GBuffer, camera: &Camera) {
+        let kernel = ssao_kernel(self.settings.sample_count);
+        let mut kernel_block = [[0.0; 4]; MAX_SSAO_SAMPLES];
+        kernel
+            .iter()
+            .zip(kernel_block.iter_mut())
+            .for_each(|(sample, slot)| *slot = [sample.x, sample.y, sample.z, 0.0]);
+
+        let ao_size = self.ao_framebuffer.size();
+
+        self.ubo.fill_mapped(
+            0,
+            &AmbientOcclusionBlock {
+                inverse_projection: inverse(camera.projection()).into(),
+                kernel: kernel_block,
+                screen_size: [
+                    self.size.x as f32,
+                    self.size.y as f32,
+                    1.0 / self.size.x as f32,
+                    1.0 / self.size.y as f32,
+                ],
+                ao_size: [
+                    ao_size.x as f32,
+                    ao_size.y as f32,
+                    1.0 / ao_size.x as f32,
+                    1.0 / ao_size.y as f32,
+                ],
+                radius: self.settings.radius,
+                intensity: self.settings.intensity,
+                bias: self.settings.bias,
+                sample_count: self.settings.sample_count as i32,
+                blur_sharpness: self.settings.blur_sharpness,
+                _padding: [0.0; 3],
+            },
+        );
+
+        let normal = gbuffer.texture_id(GBufferAttachment::Normal);
+        let depth = gbuffer.texture_id(GBufferAttachment::Depth);
+
+        // Occlusion pass
+        self.ao_framebuffer.bind();
+        self.ssao_shader.bind();
+        self.ssao_shader
+            .bind_texture_2d_with_id(NORMAL_BINDING_INDEX, normal, &self.nearest_sampler)
+            .bind_texture_2d_with_id(DEPTH_BINDING_INDEX, depth, &self.nearest_sampler);
+        draw_full_screen_quad();
+        self.ssao_shader.unbind();
+        self.ao_framebuffer.unbind(false);
+
+        if !self.settings.blur {
+            return;
+        }
+
+        // Bilateral blur, horizontally into the blur target then vertically back.
+        self.blur_shader
+            .bind_texture_2d_with_id(DEPTH_BINDING_INDEX, depth, &self.nearest_sampler);
+
+        self.blur_shader.disable_keyword("SSAO_BLUR_VERTICAL");
+        self.blur_shader.enable_keyword("SSAO_BLUR_HORIZONTAL");
+        self.blur_pass(&self.ao_framebuffer, &self.blur_framebuffer);
+
+        self.blur_shader.disable_keyword("SSAO_BLUR_HORIZONTAL");
+        self.blur_shader.enable_keyword("SSAO_BLUR_VERTICAL");
+        self.blur_pass(&self.blur_framebuffer, &self.ao_framebuffer);
+    }
+
+    /// Binds the occlusion computed by the last `apply` for a lit shader.
+    pub fn bind_ao_map(&self, shader: &Shader) {
+        shader.bind_texture_2d_with_id(
+            AMBIENT_OCCLUSION_MAP_BINDING_INDEX,
+            self.ao_framebuffer.texture_attachment(0).id(),
+            &self.linear_sampler,
+        );
+    }
+
+    fn blur_pass(&self, source: &Framebuffer, destination: &Framebuffer) {
+        destination.bind();
+        self.blur_shader.bind();
+        self.blur_shader.bind_texture_2d_with_id(
+            INPUT_BINDING_INDEX,
+            source.texture_attachment(0).id(),
+            &self.nearest_sampler,
+        );
+        draw_full_screen_quad();
+        self.blur_shader.unbind();
+        destination.unbind(false);
+    }
+
+    fn validate_settings(settings: AmbientOcclusionSettings) -> AmbientOcclusionSettings {
+        AmbientOcclusionSettings {
+            radius: settings.radius.max(0.01),
+            sample_count: settings.sample_count.max(1).min(MAX_SSAO_SAMPLES),
+            ..settings
+        }
+    }
+
+    fn ao_size(size: UVec2, half_resolution: bool) -> UVec2 {
+        if half_resolution {
+            UVec2::new((size.x / 2).max(1), (size.y / 2).max(1))
+        } else {
+            size
+        }
+    }
+
+    fn create_framebuffer(name: &str, size: UVec2) -> Framebuffer {
+        Framebuffer::new(
+            name,
+            size,
+            Msaa::None,
+            vec![FramebufferAttachmentCreateInfo::new(
+                &format!("{}-Occlusion", name),
+                SizedTextureFormat::R8,
+                AttachmentType::Texture,
+            )],
+        )
+        .unwrap_or_else(|error| panic!("Framebuffer creation error: {}", error))
+    }
+}
+
+impl Gui for ScreenSpaceAmbientOcclusion {
+    fn gui(&mut self, ui: &Ui) {
+        imgui::TreeNode::new("Ambient Occlusion")
+            .default_open(false)
+            .open_on_arrow(true)
+            .open_on_double_click(true)
+            .framed(false)
+            .build(ui, || {
+                let mut settings = self.settings;
+
+                imgui::Slider::new("Radius", 0.05, 5.0)
+                    .display_format("%.2f")
+                    .build(ui, &mut settings.radius);
+                imgui::Slider::new("Intensity", 0.1, 4.0)
+                    .display_format("%.2f")
+                    .build(ui, &mut settings.intensity);
+                imgui::Slider::new("Bias", 0.0, 0.2)
+                    .display_format("%.3f")
+                    .build(ui, &mut settings.bias);
+
+                let mut sample_count = settings.sample_count as i32;
+                if imgui::Slider::new("Samples", 1, MAX_SSAO_SAMPLES as i32)
+                    .build(ui, &mut sample_count)
+                {
+                    settings.sample_count = sample_count as usize;
+                }
+
+                ui.checkbox("Half Resolution", &mut settings.half_resolution);
+                ui.checkbox("Bilateral Blur", &mut settings.blur);
+
+                if settings.blur {
+                    imgui::Slider::new("Blur Sharpness", 0.0, 32.0)
+                        .display_format("%.1f")
+                        .build(ui, &mut settings.blur_sharpness);
+                }
+
+                self.set_settings(settings);
+
+                let size = self.ao_framebuffer.size();
+                let width = 160.0;
+                let height = width * size.y as f32 / size.x.max(1) as f32;
+
+                imgui::Image::new(
+                    (self.ao_framebuffer.texture_attachment(0).id() as usize).into(),
+                    [width, height],
+                )
+                .uv0([0.0, 1.0])
+                .uv1([1.0, 0.0])
+                .build(ui);
+            });
+    }
+}
@@ -0,0 +1,412 @@
+use std::rc::Rc;
+
+use crate::{
+    core::camera::Camera,
+    core::math::{inverse, Mat4, UVec2, UVec3, Vec4},
+    core::Msaa,
+    imgui::{Gui, Ui},
+    mesh::utilities::draw_full_screen_quad,
+    rendering::{
+        buffer::{Buffer, BufferStorageFlags, BufferTarget, MapModeFlags},
+        deferred::{GBuffer, GBufferAttachment},
+        framebuffer::{AttachmentType, Framebuffer, FramebufferAttachmentCreateInfo},
+        postprocess::FULLSCREEN_VERTEX_SHADER_PATH,
+        sampler::{Anisotropy, MagnificationFilter, MinificationFilter, Sampler, WrappingMode},
+        shader::{ComputeShader, ImageAccess, Shader, ShaderCreateInfo, ShaderStage},
+        state::{MemoryBarrierFlags, StateManager},
+        texture::{SizedTextureFormat, Texture2D},
+    },
+    Context,
+};
+
+const HIZ_COPY_SHADER_PATH: &str = "assets/shaders/ssr/hiz_copy.comp";
+const HIZ_DOWNSAMPLE_SHADER_PATH: &str = "assets/shaders/ssr/hiz_downsample.comp";
+const SSR_FRAGMENT_SHADER_PATH: &str = "assets/shaders/ssr/ssr.frag";
+
+/// Texture unit of the reflection map in the lit shaders.
+pub const REFLECTION_MAP_BINDING_INDEX: u32 = 14;
+const UBO_BINDING_INDEX: u32 = 14;
+
+// Texture units of the SSR pass, must match ssr.frag.
+const HISTORY_BINDING_INDEX: u32 = 0;
+const NORMAL_BINDING_INDEX: u32 = 1;
+const DEPTH_BINDING_INDEX: u32 = 2;
+const METALLIC_ROUGHNESS_AO_BINDING_INDEX: u32 = 3;
+const HIZ_BINDING_INDEX: u32 = 4;
+
+const LOCAL_SIZE: u32 = 8;
+
+/// Number of mip levels of a full depth pyramid for a depth buffer of the given size, down to
+/// a single texel.
+pub fn hiz_mip_levels(size: UVec2) -> u32 {
+    (size.x.max(size.y).max(1) as f32).log2().floor() as u32 + 1
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReflectionSettings {
+    /// Maximum number of steps through the depth pyramid per ray.
+    pub max_iterations: u32,
+    /// View space thickness assumed for the depth buffer surfaces.
+    pub thickness: f32,
+    /// Surfaces rougher than this only reflect the radiance cubemap.
+    pub max_roughness: f32,
+    /// Fraction of the screen over which the reflections fade out at the borders.
+    pub screen_edge_fade: f32,
+}
+
+impl Default for ReflectionSettings {
+    fn default() -> Self {
+        Self {
+            max_iterations: 64,
+            thickness: 0.25,
+            max_roughness: 0.6,
+            screen_edge_fade: 0.1,
+        }
+    }
+}
+
+// Plain std140 layout of the `ReflectionBlock` uniform block.
+#[repr(C)]
+struct ReflectionBlock {
+    inverse_projection: mint::ColumnMatrix4<f32>,
+    inverse_view: mint::ColumnMatrix4<f32>,
+    previous_view_projection: mint::ColumnMatrix4<f32>,
+    screen_size: [f32; 4],
+    hiz_max_level: i32,
+    max_iterations: i32,
+    thickness: f32,
+    max_roughness: f32,
+    screen_edge_fade: f32,
+    history_max_lod: f32,
+    history_valid: i32,
+    _padding: f32,
+}
+
+/// Screen space reflections. Rays are traced against a hierarchical-Z pyramid of the `GBuffer`
+/// depth, and the hit points are reprojected into the lit color of the previous frame. Its mip
+/// chain is sampled along a cone whose width follows the roughness, which blurs the reflections
+/// of rough surfaces.
+///
+/// The result holds the reflected radiance and a confidence. When the screenSpaceReflections flag
+/// of their per-frame block is set, the lit shaders blend it over the radiance cubemap, which
+/// remains the fallback for rays that leave the screen or miss.
+pub struct ScreenSpaceReflections {
+    settings: ReflectionSettings,
+    hiz_copy_shader: Rc<ComputeShader>,
+    hiz_downsample_shader: Rc<ComputeShader>,
+    ssr_shader: Rc<Shader>,
+    ubo: Buffer,
+    hiz: Texture2D,
+    history: Texture2D,
+    reflection_framebuffer: Framebuffer,
+    nearest_sampler: Sampler,
+    trilinear_sampler: Sampler,
+    previous_view_projection: Mat4,
+    history_valid: bool,
+    enabled: bool,
+}
+
+impl ScreenSpaceReflections {
+    /// `size` is the size of the G-buffer and of the lit color the reflections are read from.
+    pub fn new(context: Context, size: UVec2, settings: ReflectionSettings) -> Self {
+        let Context { device, .. } = context;
+
+        let shader_manager = device.shader_manager();
+
+        let mut create_compute_shader = |name: &str, path: &str| {
+            shader_manager.create_compute_shader(
+                &ShaderCreateInfo::builder(name)
+                    .stage(ShaderStage::Compute, path)
+                    .build(),
+            )
+        };
+
+        let hiz_copy_shader = create_compute_shader("Hi-Z Copy Shader", HIZ_COPY_SHADER_PATH);
+        let hiz_downsample_shader =
+            create_compute_shader("Hi-Z Downsample Shader", HIZ_DOWNSAMPLE_SHADER_PATH);
+
+        let ssr_shader = shader_manager.create_shader(
+            &ShaderCreateInfo::builder("SSR Shader")
+                .stage(ShaderStage::Vertex, FULLSCREEN_VERTEX_SHADER_PATH)
+                .stage(ShaderStage::Fragment, SSR_FRAGMENT_SHADER_PATH)
+                .build(),
+        );
+
+        let mut ubo = Buffer::new(
+            "SSR UBO",
+            std::mem::size_of::<ReflectionBlock>() as isize,
+            BufferTarget::Uniform,
+            BufferStorageFlags::MAP_WRITE_PERSISTENT_COHERENT,
+        );
+        ubo.bind(UBO_BINDING_INDEX);
+        ubo.map(MapModeFlags::MAP_WRITE_PERSISTENT_COHERENT);
+
+        let create_sampler = |min_filter: MinificationFilter, mag_filter: MagnificationFilter| {
+            Sampler::new(
+                min_filter,
+                mag_filter,
+                WrappingMode::ClampToEdge,
+                WrappingMode::ClampToEdge,
+                WrappingMode::ClampToEdge,
+                Vec4::new(0.0, 0.0, 0.0, 0.0),
+                Anisotropy::None,
+            )
+        };
+
+        Self {
+            settings: Self::validate_settings(settings),
+            hiz_copy_shader,
+            hiz_downsample_shader,
+            ssr_shader,
+            ubo,
+            hiz: Self::create_hiz(size),
+            history: Self::create_history(size),
+            reflection_framebuffer: Self::create_reflection_framebuffer(size),
+            nearest_sampler: create_sampler(
+                MinificationFilter::NearestMipmapNearest,
+                MagnificationFilter::Nearest,
+            ),
+            trilinear_sampler: create_sampler(
+                MinificationFilter::LinearMipmapLinear,
+                MagnificationFilter::Linear,
+            ),
+            previous_view_projection: Mat4::identity(),
+            history_valid: false,
+            enabled: true,
+        }
+    }
+
+    pub fn settings(&self) -> &ReflectionSettings {
+        &self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: ReflectionSettings) {
+        self.settings = Self::validate_settings(settings);
+    }
+
+    pub fn enable(&mut self) {
+        self.enabled = true
+    }
+
+    pub fn disable(&mut self) {
+        self.enabled = false;
+        self.history_valid = false;
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Recreates the depth pyramid, the history and the reflection target for a new size.
+    pub fn resize(&mut self, size: UVec2) {
+        self.hiz = Self::create_hiz(size);
+        self.history = Self::create_history(size);
+        self.reflection_framebuffer = Self::create_reflection_framebuffer(size);
+        self.history_valid = false;
+    }
+
+    /// Builds the depth pyramid of `gbuffer` and traces the reflections of the pixels it covers.
+    /// Until `update_history` has been called once, only the confidence is meaningful and the
+    /// lit shaders fall back to the radiance cubemap.
+    pub fn apply(&self, gbuffer: &GBuffer, camera: &Camera) {
+        self.build_hiz(gbuffer);
+
+        let size = self.hiz.size();
+
+        self.ubo.fill_mapped(
+            0,
+            &ReflectionBlock {
+                inverse_projection: inverse(camera.projection()).into(),
+                inverse_view: inverse(camera.transform()).into(),
+                previous_view_projection: self.previous_view_projection.into(),
+                screen_size: [
+                    size.x as f32,
+                    size.y as f32,
+                    1.0 / size.x as f32,
+                    1.0 / size.y as f32,
+                ],
+                hiz_max_level: self.hiz.mip_levels() as i32 - 1,
+                max_iterations: self.settings.max_iterations as i32,
+                thickness: self.settings.thickness,
+                max_roughness: self.settings.max_roughness,
+                screen_edge_fade: self.settings.screen_edge_fade,
+                history_max_lod: (self.history.mip_levels() - 1) as f32,
+                history_valid: self.history_valid as i32,
+                _padding: 0.0,
+            },
+        );
+
+        self.reflection_framebuffer.bind();
+        self.reflection_framebuffer
+            .clear(&Vec4::new(0.0, 0.0, 0.0, 0.0));
+
+        self.ssr_shader.bind();
+        self.ssr_shader
+            .bind_texture_2d(
+                HISTORY_BINDING_INDEX,
+                &self.history,
+                &self.trilinear_sampler,
+            )
+            .bind_texture_2d_with_id(
+                NORMAL_BINDING_INDEX,
+                gbuffer.texture_id(GBufferAttachment::Normal),
+                &self.nearest_sampler,
+            )
+            .bind_texture_2d_with_id(
+                DEPTH_BINDING_INDEX,
+                gbuffer.texture_id(GBufferAttachment::Depth),
+                &self.nearest_sampler,
+            )
+            .bind_texture_2d_with_id(
+                METALLIC_ROUGHNESS_AO_BINDING_INDEX,
+                gbuffer.texture_id(GBufferAttachment::MetallicRoughnessAo),
+                &self.nearest_sampler,
+            )
+            .bind_texture_2d(HIZ_BINDING_INDEX, &self.hiz, &self.nearest_sampler);
+
+        draw_full_screen_quad();
+
+        self.ssr_shader.unbind();
+        self.reflection_framebuffer.unbind(false);
+    }
+
+    /// Stores the lit color of the frame, the first attachment of `lit`, as the source of the
+    /// reflections of the next frame. Must be called after lighting and before any postprocess,
+    /// with the same camera as `apply`.
+    pub fn update_history(&mut self, lit: &Framebuffer, camera: &Camera) {
+        self.history.copy_from(lit.texture_attachment(0).id());
+        self.history.generate_mipmaps();
+
+        self.previous_view_projection = camera.projection() * camera.transform();
+        self.history_valid = true;
+    }
+
+    /// Binds the reflections traced by the last `apply` for a lit shader.
+    pub fn bind_reflection_map(&self, shader: &Shader) {
+        shader.bind_texture_2d_with_id(
+            REFLECTION_MAP_BINDING_INDEX,
+            self.reflection_framebuffer.texture_attachment(0).id(),
+            &self.nearest_sampler,
+        );
+    }
+
+    // Copies the G-buffer depth into the first level of the pyramid, then reduces every level
+    // to the closest depth of the 2x2 texels below it.
+    fn build_hiz(&self, gbuffer: &GBuffer) {
+        let dispatch = |shader: &ComputeShader, size: UVec2| {
+            let work_groups = ComputeShader::work_group_count(
+                UVec3::new(size.x, size.y, 1),
+                UVec3::new(LOCAL_SIZE, LOCAL_SIZE, 1),
+            );
+            shader.dispatch(work_groups.x, work_groups.y, work_groups.z);
+
+            StateManager::memory_barrier(
+                MemoryBarrierFlags::SHADER_IMAGE_ACCESS | MemoryBarrierFlags::TEXTURE_FETCH,
+            );
+        };
+
+        let size = self.hiz.size();
+
+        self.hiz_copy_shader
+            .bind_texture_2d_with_id(
+                0,
+                gbuffer.texture_id(GBufferAttachment::Depth),
+                &self.nearest_sampler,
+            )
+            .bind_image_2d(1, &self.hiz, 0, ImageAccess::WriteOnly);
+        dispatch(&self.hiz_copy_shader, size);
+
+        for level in 1..self.hiz.mip_levels() {
+            self.hiz_downsample_shader
+                .bind_image_2d(0, &self.hiz, level - 1, ImageAccess::ReadOnly)
+                .bind_image_2d(1, &self.hiz, level, ImageAccess::WriteOnly);
+            dispatch(
+                &self.hiz_downsample_shader,
+                UVec2::new((size.x >> level).max(1), (size.y >> level).max(1)),
+            );
+        }
+    }
+
+    fn validate_settings(settings: ReflectionSettings) -> ReflectionSettings {
+        ReflectionSettings {
+            max_iterations: settings.max_iterations.max(1),
+            thickness: settings.thickness.max(0.001),
+            max_roughness: settings.max_roughness.max(0.0).min(1.0),
+            screen_edge_fade: settings.screen_edge_fade.max(0.001).min(0.5),
+        }
+    }
+
+    fn create_hiz(size: UVec2) -> Texture2D {
+        Texture2D::new(
+            "SSR Hi-Z",
+            size,
+            SizedTextureFormat::R32f,
+            hiz_mip_levels(size),
+        )
+    }
+
+    fn create_history(size: UVec2) -> Texture2D {
+        Texture2D::new(
+            "SSR History",
+            size,
+            SizedTextureFormat::Rgba16f,
+            hiz_mip_levels(size),
+        )
+    }
+
+    fn create_reflection_framebuffer(size: UVec2) -> Framebuffer {
+        Framebuffer::new(
+            "SSR",
+            size,
+            Msaa::None,
+            vec![FramebufferAttachmentCreateInfo::new(
+                "SSR-Reflection",
+                SizedTextureFormat::Rgba16f,
+                AttachmentType::Texture,
+            )],
+        )
+        .unwrap_or_else(|error| panic!("Framebuffer creation error: {}", error))
+    }
+}
+
+impl Gui for ScreenSpaceReflections {
+    fn gui(&mut self, ui: &Ui) {
+        imgui::TreeNode::new("Screen Space Reflections")
+            .default_open(false)
+            .open_on_arrow(true)
+            .open_on_double_click(true)
+            .framed(false)
+            .build(ui, || {
+                let mut settings = self.settings;
+
+                let mut max_iterations = settings.max_iterations as i32;
+                if imgui::Slider::new("Max Iterations", 1, 256).build(ui, &mut max_iterations) {
+                    settings.max_iterations = max_iterations as u32;
+                }
+
+                imgui::Slider::new("Thickness", 0.01, 2.0)
+                    .display_format("%.2f")
+                    .build(ui, &mut settings.thickness);
+                imgui::Slider::new("Max Roughness", 0.0, 1.0)
+                    .display_format("%.2f")
+                    .build(ui, &mut settings.max_roughness);
+                imgui::Slider::new("Screen Edge Fade", 0.01, 0.5)
+                    .display_format("%.2f")
+                    .build(ui, &mut settings.screen_edge_fade);
+
+                self.set_settings(settings);
+
+                let size = self.reflection_framebuffer.size();
+                let width = 160.0;
+                let height = width * size.y as f32 / size.x.max(1) as f32;
+
+                imgui::Image::new(
+                    (self.reflection_framebuffer.texture_attachment(0).id() as usize).into(),
+                    [width, height],
+                )
+                .uv0([0.0, 1.0])
+                .uv1([1.0, 0.0])
+                .build(ui);
+            });
+    }
+}
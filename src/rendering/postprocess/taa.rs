@@ -0,0 +1,297 @@
+use std::any::Any;
+use std::rc::Rc;
+
+use crate::{
+    framebuffer::Framebuffer,
+    imgui::{Gui, Ui},
+    math::Vec4,
+    mesh::utilities::draw_full_screen_quad,
+    rendering::{
+        buffer::{Buffer, BufferStorageFlags, BufferTarget, MapModeFlags},
+        postprocess::{
+            AsAny, AsAnyMut, EffectInputs, PostprocessingEffect, PostprocessingInput,
+            FULLSCREEN_VERTEX_SHADER_PATH,
+        },
+        sampler::{Anisotropy, MagnificationFilter, MinificationFilter, Sampler, WrappingMode},
+        shader::{Shader, ShaderCreateInfo, ShaderStage},
+        texture::SizedTextureFormat,
+    },
+    Context,
+};
+
+const TAA_FRAGMENT_SHADER_PATH: &str = "assets/shaders/taa.frag";
+
+const TAA_UBO_BINDING_INDEX: u32 = 15;
+
+// Texture units of taa.frag.
+const COLOR_BINDING_INDEX: u32 = 0;
+const DEPTH_BINDING_INDEX: u32 = 1;
+const HISTORY_BINDING_INDEX: u32 = 2;
+const VELOCITY_BINDING_INDEX: u32 = 3;
+
+#[repr(C)]
+struct TaaUniforms {
+    feedback: f32,
+    sharpness: f32,
+    history_valid: i32,
+    _padding: f32,
+}
+
+/// Temporal anti-aliasing. Accumulates the jittered frames rendered with
+/// `Camera::set_jitter_enabled` into a history, reprojected with a velocity buffer and clamped
+/// to the YCoCg bounding box of the neighbourhood of each pixel to reject stale samples.
+///
+/// The velocity is reconstructed from the depth of the input and the previous camera matrices,
+/// so it only accounts for the camera motion. Must run before the effects that need a stable
+/// image, like bloom and depth of field.
+pub struct TemporalAntiAliasing {
+    shader: Rc<Shader>,
+    ubo: Buffer,
+    history: Option<Rc<Framebuffer>>,
+    linear_sampler: Sampler,
+    nearest_sampler: Sampler,
+    feedback: f32,
+    sharpen: bool,
+    sharpness: f32,
+    enabled: bool,
+}
+
+impl_as_any!(TemporalAntiAliasing);
+
+impl TemporalAntiAliasing {
+    pub fn new(context: Context) -> Self {
+        let Context { device, .. } = context;
+
+        let shader = device.shader_manager().create_shader(
+            &ShaderCreateInfo::builder("TAA Shader")
+                .stage(ShaderStage::Vertex, FULLSCREEN_VERTEX_SHADER_PATH)
+                .stage(ShaderStage::Fragment, TAA_FRAGMENT_SHADER_PATH)
+                .keyword_set(&["TAA_PASS_VELOCITY", "TAA_PASS_RESOLVE", "TAA_PASS_OUTPUT"])
+                .keyword_set(&["_", "TAA_SHARPEN"])
+                .build(),
+        );
+
+        let mut ubo = Buffer::new(
+            "TAA UBO",
+            std::mem::size_of::<TaaUniforms>() as isize,
+            BufferTarget::Uniform,
+            BufferStorageFlags::MAP_WRITE_PERSISTENT_COHERENT,
+        );
+        ubo.bind(TAA_UBO_BINDING_INDEX);
+        ubo.map(MapModeFlags::MAP_WRITE_PERSISTENT_COHERENT);
+
+        let create_sampler = |min_filter: MinificationFilter, mag_filter: MagnificationFilter| {
+            Sampler::new(
+                min_filter,
+                mag_filter,
+                WrappingMode::ClampToEdge,
+                WrappingMode::ClampToEdge,
+                WrappingMode::ClampToEdge,
+                Vec4::new(0.0, 0.0, 0.0, 0.0),
+                Anisotropy::None,
+            )
+        };
+
+        Self {
+            shader,
+            ubo,
+            history: None,
+            linear_sampler: create_sampler(MinificationFilter::Linear, MagnificationFilter::Linear),
+            nearest_sampler: create_sampler(
+                MinificationFilter::Nearest,
+                MagnificationFilter::Nearest,
+            ),
+            feedback: 0.9,
+            sharpen: true,
+            sharpness: 0.25,
+            enabled: true,
+        }
+    }
+
+    /// Weight of the history in the accumulated color. Higher values converge to a smoother
+    /// image but ghost more.
+    pub fn feedback(&self) -> f32 {
+        self.feedback
+    }
+
+    pub fn set_feedback(&mut self, feedback: f32) {
+        self.feedback = feedback.max(0.0).min(0.99)
+    }
+
+    pub fn sharpness(&self) -> f32 {
+        self.sharpness
+    }
+
+    /// Strength of the sharpening that compensates the blur of the accumulation.
+    pub fn set_sharpness(&mut self, sharpness: f32) {
+        self.sharpness = sharpness.max(0.0).min(1.0)
+    }
+
+    pub fn set_sharpen(&mut self, sharpen: bool) {
+        self.sharpen = sharpen
+    }
+
+    /// Drops the accumulated history, e.g. after a camera cut.
+    pub fn reset(&mut self) {
+        self.history = None
+    }
+
+    fn run_pass(&self, keyword: &str, target: &Framebuffer) {
+        self.shader.enable_keyword(keyword);
+        target.bind();
+        draw_full_screen_quad();
+        target.unbind(false);
+        self.shader.disable_keyword(keyword);
+    }
+}
+
+impl PostprocessingEffect for TemporalAntiAliasing {
+    fn name(&self) -> &str {
+        "TemporalAntiAliasing"
+    }
+
+    fn enable(&mut self) {
+        self.enabled = true
+    }
+
+    fn disable(&mut self) {
+        self.enabled = false;
+        self.history = None;
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn inputs(&self) -> EffectInputs {
+        EffectInputs::COLOR | EffectInputs::DEPTH
+    }
+
+    fn apply(&mut self, input: &PostprocessingInput, output: &Framebuffer, context: Context) {
+        let Context {
+            framebuffer_cache, ..
+        } = context;
+
+        let color = input.color().texture_attachment(0);
+        let size = input.size();
+
+        if self
+            .history
+            .as_ref()
+            .map_or(false, |history| history.size() != size)
+        {
+            if let Some(history) = self.history.take() {
+                framebuffer_cache.release_temporary(history);
+            }
+        }
+
+        self.ubo.fill_mapped(
+            0,
+            &TaaUniforms {
+                feedback: self.feedback,
+                sharpness: self.sharpness,
+                history_valid: self.history.is_some() as i32,
+                _padding: 0.0,
+            },
+        );
+
+        if self.sharpen {
+            self.shader.enable_keyword("TAA_SHARPEN");
+        } else {
+            self.shader.disable_keyword("TAA_SHARPEN");
+        }
+
+        self.shader
+            .bind_texture_2d_with_id(COLOR_BINDING_INDEX, color.id(), &self.nearest_sampler)
+            .bind_texture_2d_with_id(DEPTH_BINDING_INDEX, input.depth(), &self.nearest_sampler);
+
+        // Velocity pass
+        let velocity =
+            framebuffer_cache.get_temporary("TAA Velocity", size, SizedTextureFormat::Rg16f, None);
+        self.run_pass("TAA_PASS_VELOCITY", &velocity);
+
+        // Resolve pass, accumulates the frame into a new history.
+        let resolved = framebuffer_cache.get_temporary("TAA History", size, color.format(), None);
+
+        if let Some(history) = self.history.as_ref() {
+            self.shader.bind_texture_2d_with_id(
+                HISTORY_BINDING_INDEX,
+                history.texture_attachment(0).id(),
+                &self.linear_sampler,
+            );
+        }
+        self.shader.bind_texture_2d_with_id(
+            VELOCITY_BINDING_INDEX,
+            velocity.texture_attachment(0).id(),
+            &self.nearest_sampler,
+        );
+        self.run_pass("TAA_PASS_RESOLVE", &resolved);
+
+        // Output pass, copies the history to the output with optional sharpening.
+        self.shader.bind_texture_2d_with_id(
+            HISTORY_BINDING_INDEX,
+            resolved.texture_attachment(0).id(),
+            &self.nearest_sampler,
+        );
+        self.run_pass("TAA_PASS_OUTPUT", output);
+
+        self.shader.unbind();
+
+        framebuffer_cache.release_temporary(velocity);
+
+        // The history stays in use across frames as long as it is referenced here.
+        if let Some(history) = self.history.replace(resolved) {
+            framebuffer_cache.release_temporary(history);
+        }
+    }
+}
+
+impl Gui for TemporalAntiAliasing {
+    fn gui(&mut self, ui: &Ui) {
+        ui.group(|| {
+            let mut enabled = self.enabled;
+            if ui.checkbox("##taa", &mut enabled) {
+                if enabled {
+                    self.enable();
+                } else {
+                    self.disable();
+                }
+            }
+            ui.same_line_with_pos(20.0);
+            imgui::TreeNode::new("Temporal Anti-Aliasing")
+                .default_open(true)
+                .open_on_arrow(true)
+                .open_on_double_click(true)
+                .framed(false)
+                .build(ui, || {
+                    ui.indent();
+
+                    let mut feedback = self.feedback;
+                    if imgui::Slider::new("Feedback", 0.5, 0.99)
+                        .display_format("%.2f")
+                        .build(ui, &mut feedback)
+                    {
+                        self.set_feedback(feedback);
+                    }
+
+                    ui.checkbox("Sharpen", &mut self.sharpen);
+
+                    if self.sharpen {
+                        let mut sharpness = self.sharpness;
+                        if imgui::Slider::new("Sharpness", 0.0, 1.0)
+                            .display_format("%.2f")
+                            .build(ui, &mut sharpness)
+                        {
+                            self.set_sharpness(sharpness);
+                        }
+                    }
+
+                    if ui.button("Reset History") {
+                        self.reset();
+                    }
+
+                    ui.unindent()
+                });
+        });
+    }
+}
@@ -1,28 +1,31 @@
 use std::any::Any;
 use std::rc::Rc;
 
-use crate::core::math::Vec2;
 use crate::rendering::postprocess::FULLSCREEN_VERTEX_SHADER_PATH;
 use crate::shader::ShaderCreateInfo;
 use crate::{
-    core::application::clear_default_framebuffer,
     framebuffer::Framebuffer,
     imgui::{Gui, Ui},
     math::Vec4,
     mesh::utilities::draw_full_screen_quad,
     rendering::{
         buffer::{Buffer, BufferStorageFlags, BufferTarget, MapModeFlags},
-        postprocess::{AsAny, AsAnyMut, PostprocessingEffect},
+        device::DisplayOutput,
+        postprocess::{
+            auto_exposure::{AutoExposure, AutoExposureSettings},
+            color_grading::ColorGrading,
+            AsAny, AsAnyMut, PostprocessingEffect, PostprocessingInput,
+        },
         sampler::{Anisotropy, MagnificationFilter, MinificationFilter, Sampler, WrappingMode},
         shader::{Shader, ShaderStage},
-        state::StateManager,
     },
     Context,
 };
 
 const TONEMAPPER_FRAGMENT_SHADER_PATH: &str = "assets/shaders/tonemap.frag";
 
-const TONEMAPPER_SHADER_KEYWORDS: [&str; 7] = [
+// The order must match ToneMapOperator in tonemapping.glsl.
+const TONEMAPPER_SHADER_KEYWORDS: [&str; 10] = [
     "TONE_MAP_FUNC_ACES_FITTED",
     "TONE_MAP_FUNC_ACES_FILMIC",
     "TONE_MAP_FUNC_REINHARD",
@@ -30,15 +33,103 @@ const TONEMAPPER_SHADER_KEYWORDS: [&str; 7] = [
     "TONE_MAP_FUNC_WHITE_PRESERVING_LUMA_BASED_REINHARD",
     "TONE_MAP_FUNC_UNCHARTED_2",
     "TONE_MAP_FUNC_ROMBINDAHOUSE",
+    "TONE_MAP_FUNC_AGX",
+    "TONE_MAP_FUNC_PBR_NEUTRAL",
+    "TONE_MAP_FUNC_GRAN_TURISMO",
 ];
 
+const TONEMAPPER_OPERATOR_NAMES: [&str; 10] = [
+    "ACESFitted",
+    "ACESFilmic",
+    "Reinhard",
+    "Luma-Based Reinhard",
+    "White-Preserving Luma-Based Reinhard",
+    "Uncharted 2",
+    "RomBinDaHouse",
+    "AgX",
+    "Khronos PBR Neutral",
+    "Gran Turismo",
+];
+
+const AUTO_EXPOSURE_KEYWORD: &str = "AUTO_EXPOSURE";
+const COLOR_GRADING_KEYWORD: &str = "COLOR_GRADING";
+const COLOR_GRADING_LUT_KEYWORD: &str = "COLOR_GRADING_LUT";
+const COMPARISON_KEYWORD: &str = "TONE_MAP_COMPARISON";
+const OUTPUT_HDR10_KEYWORD: &str = "OUTPUT_HDR10";
+const OUTPUT_SCRGB_KEYWORD: &str = "OUTPUT_SCRGB";
+
+const TONE_MAP_FUNC_REINHARD: usize = 2;
+const TONE_MAP_FUNC_LUMA_BASED_REINHARD: usize = 3;
 const TONE_MAP_FUNC_WHITE_PRESERVING_LUMA_BASED_REINHARD: usize = 4;
+const TONE_MAP_FUNC_UNCHARTED_2: usize = 5;
+const TONE_MAP_FUNC_ROMBINDAHOUSE: usize = 6;
+const TONE_MAP_FUNC_AGX: usize = 7;
+const TONE_MAP_FUNC_PBR_NEUTRAL: usize = 8;
+const TONE_MAP_FUNC_GRAN_TURISMO: usize = 9;
+
+// Input range of the curve plots, in stops around 1.
+const CURVE_MIN_STOPS: f32 = -8.0;
+const CURVE_MAX_STOPS: f32 = 8.0;
+const CURVE_SAMPLE_COUNT: usize = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AgxLook {
+    Base = 0,
+    Golden = 1,
+    Punchy = 2,
+}
+
+/// Parameters of the Gran Turismo (Uchimura) curve.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GranTurismoParameters {
+    /// Output of the shoulder asymptote.
+    pub max_brightness: f32,
+    /// Slope of the linear section.
+    pub contrast: f32,
+    pub linear_start: f32,
+    pub linear_length: f32,
+    /// Exponent of the toe, higher values darken the shadows.
+    pub black_tightness: f32,
+    pub pedestal: f32,
+}
+
+impl Default for GranTurismoParameters {
+    fn default() -> Self {
+        Self {
+            max_brightness: 1.0,
+            contrast: 1.0,
+            linear_start: 0.22,
+            linear_length: 0.4,
+            black_tightness: 1.33,
+            pedestal: 0.0,
+        }
+    }
+}
+
+/// How a second operator is shown next to the selected one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToneMapComparison {
+    Off,
+    /// The comparison operator is applied right of the split.
+    SplitScreen,
+    /// Plots the curves of both operators in the GUI.
+    Curves,
+}
 
 #[repr(C)]
 struct ToneMappingPerFrameUniforms {
     white_threshold: f32,
     exposure: f32,
-    _pad: Vec2,
+    agx_look: i32,
+    comparison_operator: i32,
+    comparison_split: f32,
+    neutral_start_compression: f32,
+    neutral_desaturation: f32,
+    paper_white_nits: f32,
+    gt_params: Vec4,
+    gt_toe_params: Vec4,
+    peak_nits: f32,
+    _pad: [f32; 3],
 }
 
 pub struct ToneMapper {
@@ -49,6 +140,21 @@ pub struct ToneMapper {
     operator: usize,
     white_threshold: f32,
     exposure: f32,
+    agx_look: AgxLook,
+    neutral_start_compression: f32,
+    neutral_desaturation: f32,
+    gran_turismo: GranTurismoParameters,
+    comparison: ToneMapComparison,
+    comparison_operator: usize,
+    comparison_split: f32,
+    output: DisplayOutput,
+    paper_white_nits: f32,
+    peak_nits: f32,
+    auto_exposure: AutoExposure,
+    auto_exposure_enabled: bool,
+    color_grading: ColorGrading,
+    color_grading_enabled: bool,
+    color_grading_lut_enabled: bool,
     enabled: bool,
 }
 
@@ -56,12 +162,38 @@ impl_as_any!(ToneMapper);
 
 impl ToneMapper {
     pub fn new(context: Context) -> Self {
-        let Context { device, .. } = context;
+        let Context {
+            window,
+            device,
+            asset_manager,
+            timer,
+            framebuffer_cache,
+            settings,
+        } = context;
+
+        let auto_exposure = AutoExposure::new(
+            Context::new(
+                window,
+                device,
+                asset_manager,
+                timer,
+                framebuffer_cache,
+                settings,
+            ),
+            AutoExposureSettings::default(),
+        );
+
+        let display_output = device.info().display_output();
 
         let create_info = ShaderCreateInfo::builder("ToneMapping Shader")
             .stage(ShaderStage::Vertex, FULLSCREEN_VERTEX_SHADER_PATH)
             .stage(ShaderStage::Fragment, TONEMAPPER_FRAGMENT_SHADER_PATH)
             .keyword_set(&TONEMAPPER_SHADER_KEYWORDS)
+            .keyword_set(&["_", AUTO_EXPOSURE_KEYWORD])
+            .keyword_set(&["_", COLOR_GRADING_KEYWORD])
+            .keyword_set(&["_", COLOR_GRADING_LUT_KEYWORD])
+            .keyword_set(&["_", COMPARISON_KEYWORD])
+            .keyword_set(&["_", OUTPUT_HDR10_KEYWORD, OUTPUT_SCRGB_KEYWORD])
             .build();
 
         let shader = device.shader_manager().create_shader(&create_info);
@@ -86,7 +218,7 @@ impl ToneMapper {
             Anisotropy::None,
         );
 
-        ToneMapper {
+        let mut tone_mapper = ToneMapper {
             shader,
             tone_mapper_ubo,
             sampler_nearest,
@@ -94,13 +226,269 @@ impl ToneMapper {
             operator: 0,
             white_threshold: 2.0,
             exposure: 1.5,
+            agx_look: AgxLook::Base,
+            neutral_start_compression: 0.8 - 0.04,
+            neutral_desaturation: 0.15,
+            gran_turismo: GranTurismoParameters::default(),
+            comparison: ToneMapComparison::Off,
+            comparison_operator: TONE_MAP_FUNC_AGX,
+            comparison_split: 0.5,
+            output: DisplayOutput::Sdr,
+            paper_white_nits: 200.0,
+            peak_nits: 1000.0,
+            auto_exposure,
+            auto_exposure_enabled: false,
+            color_grading: ColorGrading::new(),
+            color_grading_enabled: false,
+            color_grading_lut_enabled: false,
             enabled: true,
-        }
+        };
+        tone_mapper.set_output(display_output);
+
+        tone_mapper
     }
 
+    /// Manual exposure, used while the auto exposure is disabled.
     pub fn set_exposure(&mut self, exposure: f32) {
         self.exposure = exposure
     }
+
+    pub fn set_agx_look(&mut self, look: AgxLook) {
+        self.agx_look = look
+    }
+
+    pub fn set_gran_turismo_parameters(&mut self, parameters: GranTurismoParameters) {
+        self.gran_turismo = GranTurismoParameters {
+            max_brightness: parameters.max_brightness.max(0.01),
+            contrast: parameters.contrast.max(0.01),
+            linear_start: parameters.linear_start.max(0.001),
+            linear_length: parameters.linear_length.max(0.0).min(1.0),
+            black_tightness: parameters.black_tightness.max(0.01),
+            ..parameters
+        }
+    }
+
+    /// `start_compression` is where the highlights compression starts, `desaturation` how much
+    /// the compressed highlights move towards white.
+    pub fn set_pbr_neutral_parameters(&mut self, start_compression: f32, desaturation: f32) {
+        self.neutral_start_compression = start_compression.max(0.0).min(0.99);
+        self.neutral_desaturation = desaturation.max(0.0)
+    }
+
+    /// Shows `operator`, an index in the operators of the GUI, next to the selected one.
+    pub fn set_comparison(&mut self, comparison: ToneMapComparison, operator: usize) {
+        if (comparison == ToneMapComparison::SplitScreen)
+            != (self.comparison == ToneMapComparison::SplitScreen)
+        {
+            if comparison == ToneMapComparison::SplitScreen {
+                self.shader.enable_keyword(COMPARISON_KEYWORD);
+            } else {
+                self.shader.disable_keyword(COMPARISON_KEYWORD);
+            }
+        }
+
+        self.comparison = comparison;
+        self.comparison_operator = operator.min(TONEMAPPER_SHADER_KEYWORDS.len() - 1);
+    }
+
+    pub fn output(&self) -> DisplayOutput {
+        self.output
+    }
+
+    /// Encodes the result for `output`, which defaults to the output of the display. Effects after
+    /// the tone mapper receive the encoded values.
+    pub fn set_output(&mut self, output: DisplayOutput) {
+        let keyword = |output| match output {
+            DisplayOutput::Sdr => None,
+            DisplayOutput::Hdr10 => Some(OUTPUT_HDR10_KEYWORD),
+            DisplayOutput::ScRgb => Some(OUTPUT_SCRGB_KEYWORD),
+        };
+
+        if let Some(keyword) = keyword(self.output) {
+            self.shader.disable_keyword(keyword);
+        }
+        if let Some(keyword) = keyword(output) {
+            self.shader.enable_keyword(keyword);
+        }
+
+        self.output = output;
+    }
+
+    /// Luminance in nits of the SDR white and of the brightest value sent to an HDR display.
+    pub fn set_hdr_luminance(&mut self, paper_white_nits: f32, peak_nits: f32) {
+        self.paper_white_nits = paper_white_nits.max(1.0);
+        self.peak_nits = peak_nits.max(self.paper_white_nits).min(10000.0);
+    }
+
+    pub fn auto_exposure_enabled(&self) -> bool {
+        self.auto_exposure_enabled
+    }
+
+    /// Adapts the exposure to the luminance of the input instead of using the manual exposure.
+    pub fn set_auto_exposure_enabled(&mut self, enabled: bool) {
+        if enabled == self.auto_exposure_enabled {
+            return;
+        }
+
+        if enabled {
+            self.auto_exposure.reset();
+            self.shader.enable_keyword(AUTO_EXPOSURE_KEYWORD);
+        } else {
+            self.shader.disable_keyword(AUTO_EXPOSURE_KEYWORD);
+        }
+
+        self.auto_exposure_enabled = enabled;
+    }
+
+    pub fn auto_exposure(&self) -> &AutoExposure {
+        &self.auto_exposure
+    }
+
+    pub fn auto_exposure_mut(&mut self) -> &mut AutoExposure {
+        &mut self.auto_exposure
+    }
+
+    pub fn color_grading_enabled(&self) -> bool {
+        self.color_grading_enabled
+    }
+
+    pub fn set_color_grading_enabled(&mut self, enabled: bool) {
+        if enabled == self.color_grading_enabled {
+            return;
+        }
+
+        if enabled {
+            self.shader.enable_keyword(COLOR_GRADING_KEYWORD);
+        } else {
+            self.shader.disable_keyword(COLOR_GRADING_KEYWORD);
+        }
+
+        self.color_grading_enabled = enabled;
+    }
+
+    pub fn color_grading(&self) -> &ColorGrading {
+        &self.color_grading
+    }
+
+    pub fn color_grading_mut(&mut self) -> &mut ColorGrading {
+        &mut self.color_grading
+    }
+
+    // Output of an operator for a grey input, the same as the shaders up to the AgX and ACES
+    // matrices which are close to identity on greys.
+    fn operator_curve(&self, operator: usize, x: f32) -> f32 {
+        match operator {
+            TONE_MAP_FUNC_REINHARD => x / (1.0 + x / self.exposure),
+            TONE_MAP_FUNC_LUMA_BASED_REINHARD => x / (1.0 + x),
+            TONE_MAP_FUNC_WHITE_PRESERVING_LUMA_BASED_REINHARD => {
+                x * (1.0 + x / (self.white_threshold * self.white_threshold)) / (1.0 + x)
+            }
+            TONE_MAP_FUNC_UNCHARTED_2 => {
+                let curve = |x: f32| {
+                    let (a, b, c, d, e, f) = (0.22, 0.30, 0.10, 0.20, 0.01, 0.30);
+                    (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+                };
+                curve(x) / curve(11.2)
+            }
+            TONE_MAP_FUNC_ROMBINDAHOUSE => (-1.0 / (2.72 * x + 0.15)).exp(),
+            TONE_MAP_FUNC_AGX => {
+                let (min_ev, max_ev) = (-12.47393, 4.026069);
+                let v = (x.max(1e-10).log2().max(min_ev).min(max_ev) - min_ev) / (max_ev - min_ev);
+                let (v2, v4) = (v * v, v * v * v * v);
+                let v = (15.5 * v4 * v2 - 40.14 * v4 * v + 31.96 * v4 - 6.868 * v2 * v
+                    + 0.4298 * v2
+                    + 0.1191 * v
+                    - 0.00232)
+                    .max(0.0);
+
+                let (slope, power, saturation) = match self.agx_look {
+                    AgxLook::Base => ([1.0, 1.0, 1.0], 1.0, 1.0),
+                    AgxLook::Golden => ([1.0, 0.9, 0.5], 0.8, 0.8),
+                    AgxLook::Punchy => ([1.0, 1.0, 1.0], 1.35, 1.4),
+                };
+                let luminance = [0.2126, 0.7152, 0.0722]
+                    .iter()
+                    .zip(slope.iter())
+                    .map(|(weight, slope)| weight * (v * slope).powf(power))
+                    .sum::<f32>();
+                let luminance = v + saturation * (luminance - v);
+
+                luminance.max(0.0).powf(2.2)
+            }
+            TONE_MAP_FUNC_PBR_NEUTRAL => {
+                let offset = if x < 0.08 { x - 6.25 * x * x } else { 0.04 };
+                let peak = x - offset;
+                let start = self.neutral_start_compression;
+                if peak < start {
+                    peak
+                } else {
+                    let d = 1.0 - start;
+                    1.0 - d * d / (peak + d - start)
+                }
+            }
+            TONE_MAP_FUNC_GRAN_TURISMO => {
+                let GranTurismoParameters {
+                    max_brightness: p,
+                    contrast: a,
+                    linear_start: m,
+                    linear_length: l,
+                    black_tightness: c,
+                    pedestal: b,
+                } = self.gran_turismo;
+
+                let l0 = ((p - m) * l) / a;
+                let s0 = m + l0;
+                let s1 = m + a * l0;
+                let cp = -(a * p) / (p - s1) / p;
+
+                let t = (x / m).max(0.0).min(1.0);
+                let w0 = 1.0 - t * t * (3.0 - 2.0 * t);
+                let w2 = if x >= m + l0 { 1.0 } else { 0.0 };
+                let w1 = 1.0 - w0 - w2;
+
+                let toe = m * (x / m).powf(c) + b;
+                let shoulder = p - (p - s1) * (cp * (x - s0)).exp();
+                let linear = m + a * (x - m);
+
+                toe * w0 + linear * w1 + shoulder * w2
+            }
+            // ACES curves, the fitted one with its RRT and ODT fit.
+            TONE_MAP_FUNC_ACES_FILMIC => {
+                let x = x * 0.6;
+                ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14))
+                    .max(0.0)
+                    .min(1.0)
+            }
+            _ => {
+                let a = x * (x + 0.0245786) - 0.000090537;
+                let b = x * (0.983729 * x + 0.4329510) + 0.238081;
+                (a / b).max(0.0).min(1.0)
+            }
+        }
+    }
+
+    fn curve_points(&self, operator: usize) -> Vec<f32> {
+        (0..CURVE_SAMPLE_COUNT)
+            .map(|index| {
+                let t = index as f32 / (CURVE_SAMPLE_COUNT - 1) as f32;
+                let stops = CURVE_MIN_STOPS + t * (CURVE_MAX_STOPS - CURVE_MIN_STOPS);
+                self.operator_curve(operator, 2.0f32.powf(stops))
+            })
+            .collect()
+    }
+
+    // The LUT is loaded through the color grading, so its keyword is synced before drawing.
+    fn update_color_grading_lut_keyword(&mut self) {
+        let lut_enabled = self.color_grading_enabled && self.color_grading.lut().is_some();
+        if lut_enabled != self.color_grading_lut_enabled {
+            if lut_enabled {
+                self.shader.enable_keyword(COLOR_GRADING_LUT_KEYWORD);
+            } else {
+                self.shader.disable_keyword(COLOR_GRADING_LUT_KEYWORD);
+            }
+            self.color_grading_lut_enabled = lut_enabled;
+        }
+    }
 }
 
 impl PostprocessingEffect for ToneMapper {
@@ -118,32 +506,60 @@ impl PostprocessingEffect for ToneMapper {
         self.enabled
     }
 
-    fn apply(&mut self, input: &Framebuffer, context: Context) {
-        let Context { window, .. } = context;
-
-        let width = window.inner_size().width;
-        let height = window.inner_size().height;
-        clear_default_framebuffer(&Vec4::new(0.0, 1.0, 0.0, 1.0));
+    fn apply(&mut self, input: &PostprocessingInput, output: &Framebuffer, context: Context) {
+        let Context { timer, .. } = context;
 
-        StateManager::viewport(0, 0, width as i32, height as i32);
+        if self.auto_exposure_enabled {
+            self.auto_exposure.update(
+                input.color().texture_attachment(0).id(),
+                input.size(),
+                timer.delta_time(),
+            );
+        }
 
+        self.update_color_grading_lut_keyword();
         self.shader.bind();
 
+        if self.color_grading_enabled {
+            self.color_grading.bind(&self.shader);
+        }
+
         let tone_mapping_uniforms = ToneMappingPerFrameUniforms {
             white_threshold: self.white_threshold,
             exposure: self.exposure,
-            _pad: Vec2::new(0.0, 0.0),
+            agx_look: self.agx_look as i32,
+            comparison_operator: self.comparison_operator as i32,
+            comparison_split: self.comparison_split,
+            neutral_start_compression: self.neutral_start_compression,
+            neutral_desaturation: self.neutral_desaturation,
+            paper_white_nits: self.paper_white_nits,
+            gt_params: Vec4::new(
+                self.gran_turismo.max_brightness,
+                self.gran_turismo.contrast,
+                self.gran_turismo.linear_start,
+                self.gran_turismo.linear_length,
+            ),
+            gt_toe_params: Vec4::new(
+                self.gran_turismo.black_tightness,
+                self.gran_turismo.pedestal,
+                0.0,
+                0.0,
+            ),
+            peak_nits: self.peak_nits,
+            _pad: [0.0; 3],
         };
 
         self.tone_mapper_ubo.fill_mapped(0, &tone_mapping_uniforms);
 
         self.shader.bind_texture_2d_with_id(
             0,
-            input.texture_attachment(0).id(),
+            input.color().texture_attachment(0).id(),
             &self.sampler_nearest,
         );
 
+        output.bind();
         draw_full_screen_quad();
+        output.unbind(false);
 
         self.shader.unbind()
     }
@@ -160,15 +576,7 @@ impl Gui for ToneMapper {
                 if ui.combo_simple_string(
                     "Operator",
                     &mut self.operator,
-                    &[
-                        "ACESFitted",
-                        "ACESFilmic",
-                        "Reinhard",
-                        "Luma-Based Reinhard",
-                        "White-Preserving Luma-Based Reinhard",
-                        "Uncharted 2",
-                        "RomBinDaHouse",
-                    ],
+                    &TONEMAPPER_OPERATOR_NAMES,
                 ) && self.prev_operator != self.operator
                 {
                     println!("TONE MAP FUNC CHANGE!");
@@ -193,6 +601,164 @@ impl Gui for ToneMapper {
                         .build(ui, &mut self.white_threshold);
                 }
 
+                let operator = self.operator;
+                let compared = match self.comparison {
+                    ToneMapComparison::Off => self.operator,
+                    _ => self.comparison_operator,
+                };
+                let uses = |index: usize| operator == index || compared == index;
+
+                if uses(TONE_MAP_FUNC_AGX) {
+                    let mut look = self.agx_look as usize;
+                    if ui.combo_simple_string("AgX Look", &mut look, &["Base", "Golden", "Punchy"])
+                    {
+                        self.set_agx_look(match look {
+                            0 => AgxLook::Base,
+                            1 => AgxLook::Golden,
+                            _ => AgxLook::Punchy,
+                        });
+                    }
+                }
+
+                if uses(TONE_MAP_FUNC_PBR_NEUTRAL) {
+                    let mut start_compression = self.neutral_start_compression;
+                    let mut desaturation = self.neutral_desaturation;
+                    let changed = imgui::Slider::new("Start Compression", 0.0, 0.99)
+                        .display_format("%.2f")
+                        .build(ui, &mut start_compression)
+                        | imgui::Slider::new("Desaturation", 0.0, 1.0)
+                            .display_format("%.2f")
+                            .build(ui, &mut desaturation);
+                    if changed {
+                        self.set_pbr_neutral_parameters(start_compression, desaturation);
+                    }
+                }
+
+                if uses(TONE_MAP_FUNC_GRAN_TURISMO) {
+                    let mut parameters = self.gran_turismo;
+                    imgui::Slider::new("Max Brightness", 0.5, 4.0)
+                        .display_format("%.2f")
+                        .build(ui, &mut parameters.max_brightness);
+                    imgui::Slider::new("Contrast", 0.1, 3.0)
+                        .display_format("%.2f")
+                        .build(ui, &mut parameters.contrast);
+                    imgui::Slider::new("Linear Start", 0.01, 1.0)
+                        .display_format("%.2f")
+                        .build(ui, &mut parameters.linear_start);
+                    imgui::Slider::new("Linear Length", 0.0, 1.0)
+                        .display_format("%.2f")
+                        .build(ui, &mut parameters.linear_length);
+                    imgui::Slider::new("Black Tightness", 1.0, 3.0)
+                        .display_format("%.2f")
+                        .build(ui, &mut parameters.black_tightness);
+                    imgui::Slider::new("Pedestal", 0.0, 0.1)
+                        .display_format("%.3f")
+                        .build(ui, &mut parameters.pedestal);
+                    self.set_gran_turismo_parameters(parameters);
+                }
+
+                ui.spacing();
+                let mut comparison = self.comparison as usize;
+                let mut comparison_operator = self.comparison_operator;
+                let changed = ui.combo_simple_string(
+                    "Compare",
+                    &mut comparison,
+                    &["Off", "Split Screen", "Curves"],
+                ) | (self.comparison != ToneMapComparison::Off
+                    && ui.combo_simple_string(
+                        "Compare With",
+                        &mut comparison_operator,
+                        &TONEMAPPER_OPERATOR_NAMES,
+                    ));
+                if changed {
+                    let comparison = match comparison {
+                        0 => ToneMapComparison::Off,
+                        1 => ToneMapComparison::SplitScreen,
+                        _ => ToneMapComparison::Curves,
+                    };
+                    self.set_comparison(comparison, comparison_operator);
+                }
+
+                match self.comparison {
+                    ToneMapComparison::SplitScreen => {
+                        imgui::Slider::new("Split", 0.0, 1.0)
+                            .display_format("%.2f")
+                            .build(ui, &mut self.comparison_split);
+                    }
+                    ToneMapComparison::Curves => {
+                        ui.text_disabled(format!(
+                            "Input from 2^{} to 2^{}",
+                            CURVE_MIN_STOPS, CURVE_MAX_STOPS
+                        ));
+                        let operators = [self.operator, self.comparison_operator];
+                        for (index, &operator) in operators.iter().enumerate() {
+                            imgui::PlotLines::new(
+                                ui,
+                                &format!("##tone_curve_{}", index),
+                                &self.curve_points(operator),
+                            )
+                            .overlay_text(TONEMAPPER_OPERATOR_NAMES[operator])
+                            .scale_min(0.0)
+                            .scale_max(1.0)
+                            .graph_size([0.0, 80.0])
+                            .build();
+                        }
+                    }
+                    ToneMapComparison::Off => {}
+                }
+
+                ui.spacing();
+                let mut output = self.output as usize;
+                if ui.combo_simple_string("Output", &mut output, &["SDR", "HDR10", "scRGB"]) {
+                    self.set_output(match output {
+                        0 => DisplayOutput::Sdr,
+                        1 => DisplayOutput::Hdr10,
+                        _ => DisplayOutput::ScRgb,
+                    });
+                }
+
+                if self.output != DisplayOutput::Sdr {
+                    let mut paper_white_nits = self.paper_white_nits;
+                    let mut peak_nits = self.peak_nits;
+                    let changed = imgui::Slider::new("Paper White (nits)", 80.0, 500.0)
+                        .display_format("%.0f")
+                        .build(ui, &mut paper_white_nits)
+                        | imgui::Slider::new("Peak (nits)", 400.0, 4000.0)
+                            .display_format("%.0f")
+                            .build(ui, &mut peak_nits);
+                    if changed {
+                        self.set_hdr_luminance(paper_white_nits, peak_nits);
+                    }
+                }
+
+                let mut auto_exposure_enabled = self.auto_exposure_enabled;
+                if ui.checkbox("Auto Exposure", &mut auto_exposure_enabled) {
+                    self.set_auto_exposure_enabled(auto_exposure_enabled);
+                }
+
+                if self.auto_exposure_enabled {
+                    imgui::PlotHistogram::new(
+                        ui,
+                        "##luminance_histogram",
+                        self.auto_exposure.histogram(),
+                    )
+                    .overlay_text(format!("EV100 {:.2}", self.auto_exposure.adapted_ev100()))
+                    .scale_min(0.0)
+                    .scale_max(1.0)
+                    .graph_size([0.0, 80.0])
+                    .build();
+
+                    self.auto_exposure.gui(ui);
+                }
+
+                let mut color_grading_enabled = self.color_grading_enabled;
+                if ui.checkbox("Color Grading", &mut color_grading_enabled) {
+                    self.set_color_grading_enabled(color_grading_enabled);
+                }
+                if self.color_grading_enabled {
+                    self.color_grading.gui(ui);
+                }
+
                 ui.new_line()
             });
     }
@@ -0,0 +1,97 @@
+use gl::types::*;
+use gl_bindings as gl;
+
+// Results are read back a few frames late so that reading them never stalls the pipeline.
+const TIMER_QUERY_COUNT: usize = 4;
+
+/// Measures the GPU time spent between `begin` and `end` with GL_TIME_ELAPSED queries. Only one
+/// timer can be running at a time, time elapsed queries cannot be nested.
+pub struct GpuTimer {
+    queries: [GLuint; TIMER_QUERY_COUNT],
+    pending: [bool; TIMER_QUERY_COUNT],
+    current: usize,
+    elapsed_ms: f32,
+}
+
+impl Default for GpuTimer {
+    fn default() -> Self {
+        let mut queries = [0; TIMER_QUERY_COUNT];
+
+        unsafe {
+            gl::CreateQueries(
+                gl::TIME_ELAPSED,
+                TIMER_QUERY_COUNT as i32,
+                queries.as_mut_ptr(),
+            )
+        }
+
+        GpuTimer {
+            queries,
+            pending: [false; TIMER_QUERY_COUNT],
+            current: 0,
+            elapsed_ms: 0.0,
+        }
+    }
+}
+
+impl GpuTimer {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn begin(&mut self) {
+        self.read_back();
+
+        unsafe { gl::BeginQuery(gl::TIME_ELAPSED, self.queries[self.current]) }
+    }
+
+    pub fn end(&mut self) {
+        unsafe { gl::EndQuery(gl::TIME_ELAPSED) }
+
+        self.pending[self.current] = true;
+        self.current = (self.current + 1) % TIMER_QUERY_COUNT;
+    }
+
+    /// Time measured by the most recent query whose result is available, in milliseconds.
+    pub fn elapsed_ms(&self) -> f32 {
+        self.elapsed_ms
+    }
+
+    // Collects the results of the finished queries, oldest first.
+    fn read_back(&mut self) {
+        for offset in 0..TIMER_QUERY_COUNT {
+            let index = (self.current + offset) % TIMER_QUERY_COUNT;
+
+            if !self.pending[index] {
+                continue;
+            }
+
+            let mut available: GLint = 0;
+            unsafe {
+                gl::GetQueryObjectiv(
+                    self.queries[index],
+                    gl::QUERY_RESULT_AVAILABLE,
+                    &mut available,
+                )
+            }
+
+            if available == gl::FALSE as GLint {
+                break;
+            }
+
+            let mut elapsed_ns: GLuint64 = 0;
+            unsafe {
+                gl::GetQueryObjectui64v(self.queries[index], gl::QUERY_RESULT, &mut elapsed_ns)
+            }
+
+            self.elapsed_ms = elapsed_ns as f32 / 1_000_000.0;
+            self.pending[index] = false;
+        }
+    }
+}
+
+impl Drop for GpuTimer {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteQueries(TIMER_QUERY_COUNT as i32, self.queries.as_ptr()) }
+    }
+}
@@ -0,0 +1,733 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::{
+    core::math::UVec2,
+    imgui::{Gui, Ui},
+    rendering::{
+        framebuffer::Framebuffer,
+        state::{MemoryBarrierFlags, StateManager},
+        texture::SizedTextureFormat,
+    },
+    Context,
+};
+
+/// A version of a graph resource. Each write creates a new version, so the passes reading a
+/// version run after the pass that wrote it and before the pass that writes the next one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceHandle {
+    index: usize,
+    version: u32,
+}
+
+/// How a pass writes a resource. Image and storage writes are made visible to the following
+/// readers with a memory barrier, render target writes need none.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WriteAccess {
+    RenderTarget,
+    Image,
+    Storage,
+}
+
+impl WriteAccess {
+    fn barrier(self) -> MemoryBarrierFlags {
+        match self {
+            WriteAccess::RenderTarget => MemoryBarrierFlags::empty(),
+            WriteAccess::Image => {
+                MemoryBarrierFlags::SHADER_IMAGE_ACCESS | MemoryBarrierFlags::TEXTURE_FETCH
+            }
+            WriteAccess::Storage => MemoryBarrierFlags::SHADER_STORAGE,
+        }
+    }
+}
+
+enum ResourceKind {
+    /// Owned outside of the graph, the passes access it directly.
+    Imported,
+    /// Allocated from the `TemporaryFramebufferPool` for the passes that use it.
+    Transient {
+        size: UVec2,
+        format: SizedTextureFormat,
+        depth_format: Option<SizedTextureFormat>,
+    },
+}
+
+struct Resource {
+    name: String,
+    kind: ResourceKind,
+    version: u32,
+}
+
+#[derive(Default)]
+pub struct PassBuilder {
+    reads: Vec<ResourceHandle>,
+    writes: Vec<(ResourceHandle, WriteAccess)>,
+    side_effect: bool,
+}
+
+impl PassBuilder {
+    pub fn read(&mut self, resource: ResourceHandle) -> &mut Self {
+        self.reads.push(resource);
+        self
+    }
+
+    /// The handle must be the latest version of the resource, the version written by the pass
+    /// is returned by `RenderGraph::add_pass` in the order of the writes.
+    pub fn write(&mut self, resource: ResourceHandle, access: WriteAccess) -> &mut Self {
+        self.writes.push((resource, access));
+        self
+    }
+
+    /// Keeps the pass even when nothing reads what it writes.
+    pub fn side_effect(&mut self) -> &mut Self {
+        self.side_effect = true;
+        self
+    }
+}
+
+type PassCallback<'a, T> = Box<dyn FnMut(&mut T, &RenderGraphResources, Context) + 'a>;
+
+struct Pass<'a, T> {
+    name: String,
+    reads: Vec<ResourceHandle>,
+    writes: Vec<(ResourceHandle, WriteAccess)>,
+    side_effect: bool,
+    execute: PassCallback<'a, T>,
+}
+
+/// Schedules the passes of a frame from the resources they read and write. Built every frame,
+/// then compiled and executed with the data the passes work on.
+pub struct RenderGraph<'a, T> {
+    resources: Vec<Resource>,
+    passes: Vec<Pass<'a, T>>,
+    outputs: Vec<ResourceHandle>,
+}
+
+impl<'a, T> Default for RenderGraph<'a, T> {
+    fn default() -> Self {
+        Self {
+            resources: vec![],
+            passes: vec![],
+            outputs: vec![],
+        }
+    }
+}
+
+impl<'a, T> RenderGraph<'a, T> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn import(&mut self, name: &str) -> ResourceHandle {
+        self.add_resource(name, ResourceKind::Imported)
+    }
+
+    pub fn create_transient(
+        &mut self,
+        name: &str,
+        size: UVec2,
+        format: SizedTextureFormat,
+        depth_format: Option<SizedTextureFormat>,
+    ) -> ResourceHandle {
+        self.add_resource(
+            name,
+            ResourceKind::Transient {
+                size,
+                format,
+                depth_format,
+            },
+        )
+    }
+
+    fn add_resource(&mut self, name: &str, kind: ResourceKind) -> ResourceHandle {
+        self.resources.push(Resource {
+            name: name.to_string(),
+            kind,
+            version: 0,
+        });
+
+        ResourceHandle {
+            index: self.resources.len() - 1,
+            version: 0,
+        }
+    }
+
+    /// The passes contributing to an output are never culled.
+    pub fn mark_output(&mut self, resource: ResourceHandle) {
+        self.outputs.push(resource)
+    }
+
+    /// Adds a pass and returns the new versions of the resources it writes.
+    pub fn add_pass<S, E>(&mut self, name: &str, setup: S, execute: E) -> Vec<ResourceHandle>
+    where
+        S: FnOnce(&mut PassBuilder),
+        E: FnMut(&mut T, &RenderGraphResources, Context) + 'a,
+    {
+        let mut builder = PassBuilder::default();
+        setup(&mut builder);
+
+        let written = builder
+            .writes
+            .iter()
+            .map(|&(handle, _)| {
+                let resource = &mut self.resources[handle.index];
+                assert_eq!(
+                    handle.version, resource.version,
+                    "Pass {} writes an old version of {}",
+                    name, resource.name
+                );
+
+                resource.version += 1;
+                ResourceHandle {
+                    index: handle.index,
+                    version: resource.version,
+                }
+            })
+            .collect();
+
+        self.passes.push(Pass {
+            name: name.to_string(),
+            reads: builder.reads,
+            writes: builder.writes,
+            side_effect: builder.side_effect,
+            execute: Box::new(execute),
+        });
+
+        written
+    }
+
+    /// Sorts the passes, culls the ones that do not contribute to an output or have side
+    /// effects, and computes the lifetimes of the transient resources and the barriers.
+    pub fn compile(self) -> CompiledRenderGraph<'a, T> {
+        let pass_count = self.passes.len();
+
+        // The pass that produced each version, and the readers of each version.
+        let mut producers = std::collections::HashMap::new();
+        self.passes.iter().enumerate().for_each(|(index, pass)| {
+            pass.writes.iter().for_each(|&(handle, _)| {
+                producers.insert((handle.index, handle.version + 1), index);
+            })
+        });
+
+        let producer = |handle: ResourceHandle| producers.get(&(handle.index, handle.version));
+
+        // Dependencies needed for the content of the resources, and ordering only ones.
+        let mut content_dependencies = vec![BTreeSet::new(); pass_count];
+        let mut order_dependencies = vec![BTreeSet::new(); pass_count];
+
+        for (index, pass) in self.passes.iter().enumerate() {
+            let used = pass
+                .reads
+                .iter()
+                .chain(pass.writes.iter().map(|(handle, _)| handle));
+
+            for &handle in used {
+                if let Some(&producer) = producer(handle) {
+                    if producer != index {
+                        content_dependencies[index].insert(producer);
+                    }
+                }
+            }
+
+            // The readers of a version run before it is overwritten.
+            for &(handle, _) in pass.writes.iter() {
+                self.passes
+                    .iter()
+                    .enumerate()
+                    .filter(|&(reader, other)| reader != index && other.reads.contains(&handle))
+                    .for_each(|(reader, _)| {
+                        order_dependencies[index].insert(reader);
+                    });
+            }
+        }
+
+        // Culling, walks back from the passes that must run.
+        let mut kept = vec![false; pass_count];
+        let mut stack = self
+            .passes
+            .iter()
+            .enumerate()
+            .filter(|(_, pass)| pass.side_effect)
+            .map(|(index, _)| index)
+            .chain(
+                self.outputs
+                    .iter()
+                    .filter_map(|&handle| producer(handle).copied()),
+            )
+            .collect::<Vec<_>>();
+
+        while let Some(index) = stack.pop() {
+            if !kept[index] {
+                kept[index] = true;
+                stack.extend(content_dependencies[index].iter().copied());
+            }
+        }
+
+        // Topological sort of the kept passes, in declaration order when independent.
+        let mut remaining = (0..pass_count)
+            .map(|index| {
+                content_dependencies[index]
+                    .union(&order_dependencies[index])
+                    .filter(|&&dependency| kept[dependency])
+                    .count()
+            })
+            .collect::<Vec<_>>();
+
+        let mut ready = (0..pass_count)
+            .filter(|&index| kept[index] && remaining[index] == 0)
+            .collect::<BTreeSet<_>>();
+
+        let mut order = Vec::with_capacity(pass_count);
+        while let Some(&index) = ready.iter().next() {
+            ready.remove(&index);
+            order.push(index);
+
+            for dependent in (0..pass_count).filter(|&dependent| {
+                kept[dependent]
+                    && (content_dependencies[dependent].contains(&index)
+                        || order_dependencies[dependent].contains(&index))
+            }) {
+                remaining[dependent] -= 1;
+                if remaining[dependent] == 0 {
+                    ready.insert(dependent);
+                }
+            }
+        }
+
+        assert_eq!(
+            order.len(),
+            kept.iter().filter(|&&kept| kept).count(),
+            "The render graph has a cycle."
+        );
+
+        // Lifetimes of the transient resources over the sorted passes.
+        let mut first_use = vec![None; self.resources.len()];
+        let mut last_use = vec![None; self.resources.len()];
+        for (position, &index) in order.iter().enumerate() {
+            let pass = &self.passes[index];
+            for handle in pass
+                .reads
+                .iter()
+                .chain(pass.writes.iter().map(|(handle, _)| handle))
+            {
+                first_use[handle.index].get_or_insert(position);
+                last_use[handle.index] = Some(position);
+            }
+        }
+
+        let is_transient = |resource: usize| {
+            matches!(
+                self.resources[resource].kind,
+                ResourceKind::Transient { .. }
+            )
+        };
+
+        let steps = order
+            .iter()
+            .enumerate()
+            .map(|(position, &index)| {
+                let pass = &self.passes[index];
+
+                let barrier = pass
+                    .reads
+                    .iter()
+                    .filter_map(|&handle| {
+                        producer(handle).and_then(|&producer| {
+                            self.passes[producer]
+                                .writes
+                                .iter()
+                                .find(|(written, _)| written.index == handle.index)
+                                .map(|&(_, access)| access.barrier())
+                        })
+                    })
+                    .fold(MemoryBarrierFlags::empty(), |flags, barrier| {
+                        flags | barrier
+                    });
+
+                Step {
+                    pass: index,
+                    barrier,
+                    allocate: (0..self.resources.len())
+                        .filter(|&resource| {
+                            is_transient(resource) && first_use[resource] == Some(position)
+                        })
+                        .collect(),
+                    release: (0..self.resources.len())
+                        .filter(|&resource| {
+                            is_transient(resource) && last_use[resource] == Some(position)
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+
+        let culled = (0..pass_count)
+            .filter(|&index| !kept[index])
+            .map(|index| self.passes[index].name.clone())
+            .collect();
+
+        CompiledRenderGraph {
+            resources: self.resources,
+            passes: self.passes,
+            steps,
+            culled,
+        }
+    }
+}
+
+struct Step {
+    pass: usize,
+    barrier: MemoryBarrierFlags,
+    allocate: Vec<usize>,
+    release: Vec<usize>,
+}
+
+pub struct CompiledRenderGraph<'a, T> {
+    resources: Vec<Resource>,
+    passes: Vec<Pass<'a, T>>,
+    steps: Vec<Step>,
+    culled: Vec<String>,
+}
+
+impl<'a, T> CompiledRenderGraph<'a, T> {
+    /// Runs the passes in order. The transient resources are taken from the framebuffer pool
+    /// before their first use and returned after their last one, so resources with disjoint
+    /// lifetimes share the same framebuffers.
+    pub fn execute(&mut self, data: &mut T, context: Context) {
+        let Context {
+            window,
+            device,
+            asset_manager,
+            timer,
+            framebuffer_cache,
+            settings,
+        } = context;
+
+        let mut resources = RenderGraphResources {
+            framebuffers: vec![None; self.resources.len()],
+        };
+
+        for step in self.steps.iter() {
+            for &index in step.allocate.iter() {
+                let resource = &self.resources[index];
+                if let ResourceKind::Transient {
+                    size,
+                    format,
+                    depth_format,
+                } = resource.kind
+                {
+                    resources.framebuffers[index] = Some(framebuffer_cache.get_temporary(
+                        &resource.name,
+                        size,
+                        format,
+                        depth_format,
+                    ));
+                }
+            }
+
+            if !step.barrier.is_empty() {
+                StateManager::memory_barrier(step.barrier);
+            }
+
+            (self.passes[step.pass].execute)(
+                data,
+                &resources,
+                Context::new(
+                    window,
+                    device,
+                    asset_manager,
+                    timer,
+                    framebuffer_cache,
+                    settings,
+                ),
+            );
+
+            for &index in step.release.iter() {
+                if let Some(framebuffer) = resources.framebuffers[index].take() {
+                    framebuffer.invalidate();
+                    framebuffer_cache.release_temporary(framebuffer);
+                }
+            }
+        }
+    }
+
+    pub fn summary(&self) -> RenderGraphSummary {
+        let resource_name = |handle: &ResourceHandle| {
+            format!("{}#{}", self.resources[handle.index].name, handle.version)
+        };
+
+        RenderGraphSummary {
+            passes: self
+                .steps
+                .iter()
+                .map(|step| {
+                    let pass = &self.passes[step.pass];
+                    PassSummary {
+                        name: pass.name.clone(),
+                        reads: pass.reads.iter().map(resource_name).collect(),
+                        writes: pass
+                            .writes
+                            .iter()
+                            .map(|(handle, access)| {
+                                format!(
+                                    "{}#{} ({:?})",
+                                    self.resources[handle.index].name,
+                                    handle.version + 1,
+                                    access
+                                )
+                            })
+                            .collect(),
+                        barrier: (!step.barrier.is_empty()).then(|| format!("{:?}", step.barrier)),
+                        allocates: step
+                            .allocate
+                            .iter()
+                            .map(|&index| self.resources[index].name.clone())
+                            .collect(),
+                        releases: step
+                            .release
+                            .iter()
+                            .map(|&index| self.resources[index].name.clone())
+                            .collect(),
+                    }
+                })
+                .collect(),
+            culled: self.culled.clone(),
+        }
+    }
+}
+
+/// The framebuffers of the transient resources, while a pass executes.
+pub struct RenderGraphResources {
+    framebuffers: Vec<Option<Rc<Framebuffer>>>,
+}
+
+impl RenderGraphResources {
+    pub fn framebuffer(&self, resource: ResourceHandle) -> &Framebuffer {
+        self.framebuffers[resource.index]
+            .as_deref()
+            .expect("Only the transient resources used by the pass have a framebuffer.")
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PassSummary {
+    name: String,
+    reads: Vec<String>,
+    writes: Vec<String>,
+    barrier: Option<String>,
+    allocates: Vec<String>,
+    releases: Vec<String>,
+}
+
+/// Description of a compiled graph for debugging, printed with `Display` or shown in ImGui.
+#[derive(Debug, Clone, Default)]
+pub struct RenderGraphSummary {
+    passes: Vec<PassSummary>,
+    culled: Vec<String>,
+}
+
+impl fmt::Display for RenderGraphSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (position, pass) in self.passes.iter().enumerate() {
+            writeln!(f, "{}: {}", position, pass.name)?;
+            if let Some(barrier) = &pass.barrier {
+                writeln!(f, "    barrier: {}", barrier)?;
+            }
+            if !pass.allocates.is_empty() {
+                writeln!(f, "    allocate: {}", pass.allocates.join(", "))?;
+            }
+            if !pass.reads.is_empty() {
+                writeln!(f, "    read: {}", pass.reads.join(", "))?;
+            }
+            if !pass.writes.is_empty() {
+                writeln!(f, "    write: {}", pass.writes.join(", "))?;
+            }
+            if !pass.releases.is_empty() {
+                writeln!(f, "    release: {}", pass.releases.join(", "))?;
+            }
+        }
+
+        if !self.culled.is_empty() {
+            writeln!(f, "culled: {}", self.culled.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Gui for RenderGraphSummary {
+    fn gui(&mut self, ui: &Ui) {
+        imgui::TreeNode::new("Render Graph")
+            .default_open(false)
+            .open_on_arrow(true)
+            .open_on_double_click(true)
+            .build(ui, || {
+                for (position, pass) in self.passes.iter().enumerate() {
+                    imgui::TreeNode::new(&format!("{}: {}", position, pass.name))
+                        .open_on_arrow(true)
+                        .open_on_double_click(true)
+                        .build(ui, || {
+                            if let Some(barrier) = &pass.barrier {
+                                ui.text(format!("Barrier: {}", barrier));
+                            }
+                            pass.allocates
+                                .iter()
+                                .for_each(|name| ui.text(format!("Allocate: {}", name)));
+                            pass.reads
+                                .iter()
+                                .for_each(|name| ui.text(format!("Read: {}", name)));
+                            pass.writes
+                                .iter()
+                                .for_each(|name| ui.text(format!("Write: {}", name)));
+                            pass.releases
+                                .iter()
+                                .for_each(|name| ui.text(format!("Release: {}", name)));
+                        });
+                }
+
+                self.culled
+                    .iter()
+                    .for_each(|name| ui.text_disabled(format!("Culled: {}", name)));
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type TestGraph = RenderGraph<'static, ()>;
+
+    fn add_pass(
+        graph: &mut TestGraph,
+        name: &str,
+        reads: &[ResourceHandle],
+        writes: &[ResourceHandle],
+    ) -> Vec<ResourceHandle> {
+        graph.add_pass(
+            name,
+            |pass| {
+                reads.iter().for_each(|&handle| {
+                    pass.read(handle);
+                });
+                writes.iter().for_each(|&handle| {
+                    pass.write(handle, WriteAccess::RenderTarget);
+                });
+            },
+            |_, _, _| {},
+        )
+    }
+
+    fn pass_order(graph: &CompiledRenderGraph<()>) -> Vec<String> {
+        graph
+            .steps
+            .iter()
+            .map(|step| graph.passes[step.pass].name.clone())
+            .collect()
+    }
+
+    fn transient(graph: &mut TestGraph, name: &str) -> ResourceHandle {
+        graph.create_transient(name, UVec2::new(1, 1), SizedTextureFormat::Rgba16f, None)
+    }
+
+    #[test]
+    fn passes_run_after_the_producers_of_what_they_read() {
+        let mut graph = TestGraph::new();
+        let color = transient(&mut graph, "Color");
+        let backbuffer = graph.import("Backbuffer");
+
+        let color = add_pass(&mut graph, "Scene", &[], &[color])[0];
+        let color = add_pass(&mut graph, "Skybox", &[], &[color])[0];
+        let backbuffer = add_pass(&mut graph, "Post", &[color], &[backbuffer])[0];
+        graph.mark_output(backbuffer);
+
+        assert_eq!(pass_order(&graph.compile()), ["Scene", "Skybox", "Post"]);
+    }
+
+    #[test]
+    fn readers_of_a_version_run_before_it_is_overwritten() {
+        let mut graph = TestGraph::new();
+        let history = graph.import("History");
+        let backbuffer = graph.import("Backbuffer");
+
+        // Declared after the pass that overwrites the history, but reads the previous version.
+        let new_history = add_pass(&mut graph, "Update History", &[], &[history])[0];
+        let backbuffer = add_pass(&mut graph, "Resolve", &[history], &[backbuffer])[0];
+        graph.mark_output(new_history);
+        graph.mark_output(backbuffer);
+
+        assert_eq!(pass_order(&graph.compile()), ["Resolve", "Update History"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "The render graph has a cycle.")]
+    fn reading_both_versions_of_a_resource_is_a_cycle() {
+        let mut graph = TestGraph::new();
+        let history = graph.import("History");
+        let backbuffer = graph.import("Backbuffer");
+
+        // Must run after the writer for the new version and before it for the old one.
+        let new_history = add_pass(&mut graph, "Update History", &[], &[history])[0];
+        let backbuffer = add_pass(
+            &mut graph,
+            "Resolve",
+            &[history, new_history],
+            &[backbuffer],
+        )[0];
+        graph.mark_output(backbuffer);
+
+        graph.compile();
+    }
+
+    #[test]
+    fn passes_not_contributing_to_an_output_are_culled() {
+        let mut graph = TestGraph::new();
+        let debug = transient(&mut graph, "Debug");
+        let timer = graph.import("Timer");
+        let backbuffer = graph.import("Backbuffer");
+
+        add_pass(&mut graph, "Debug View", &[], &[debug]);
+        graph.add_pass(
+            "Timer Query",
+            |pass| {
+                pass.write(timer, WriteAccess::Storage).side_effect();
+            },
+            |_, _, _| {},
+        );
+        let backbuffer = add_pass(&mut graph, "Scene", &[], &[backbuffer])[0];
+        graph.mark_output(backbuffer);
+
+        let graph = graph.compile();
+
+        assert_eq!(pass_order(&graph), ["Timer Query", "Scene"]);
+        assert_eq!(graph.culled, ["Debug View"]);
+    }
+
+    #[test]
+    fn transient_resources_live_from_their_first_to_their_last_use() {
+        let mut graph = TestGraph::new();
+        let color = transient(&mut graph, "Color");
+        let bloom = transient(&mut graph, "Bloom");
+        let backbuffer = graph.import("Backbuffer");
+
+        let color = add_pass(&mut graph, "Scene", &[], &[color])[0];
+        let bloom = add_pass(&mut graph, "Bloom", &[color], &[bloom])[0];
+        let backbuffer = add_pass(&mut graph, "Combine", &[color, bloom], &[backbuffer])[0];
+        graph.mark_output(backbuffer);
+
+        let graph = graph.compile();
+
+        let lifetimes = graph
+            .steps
+            .iter()
+            .map(|step| (step.allocate.clone(), step.release.clone()))
+            .collect::<Vec<_>>();
+
+        // Color is resource 0 and bloom resource 1, the imported backbuffer is never allocated.
+        assert_eq!(
+            lifetimes,
+            [(vec![0], vec![]), (vec![1], vec![]), (vec![], vec![0, 1])]
+        );
+    }
+}
@@ -26,6 +26,16 @@ pub enum WrappingMode {
     Repeat = gl::REPEAT,
     ClampToEdge = gl::CLAMP_TO_EDGE,
     MirroredRepeat = gl::MIRRORED_REPEAT,
+    ClampToBorder = gl::CLAMP_TO_BORDER,
+}
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy)]
+pub enum CompareFunction {
+    Less = gl::LESS,
+    LessOrEqual = gl::LEQUAL,
+    Greater = gl::GREATER,
+    GreaterOrEqual = gl::GEQUAL,
 }
 
 #[repr(u32)]
@@ -83,6 +93,20 @@ impl Sampler {
             border_color,
         }
     }
+
+    /// Turns the sampler into a shadow sampler: depth textures sampled through it return the
+    /// result of comparing the reference value against the stored depth, filtered when the
+    /// filters are linear.
+    pub fn set_depth_compare(&self, compare_function: CompareFunction) {
+        unsafe {
+            gl::SamplerParameteri(
+                self.id,
+                gl::TEXTURE_COMPARE_MODE,
+                gl::COMPARE_REF_TO_TEXTURE as i32,
+            );
+            gl::SamplerParameteri(self.id, gl::TEXTURE_COMPARE_FUNC, compare_function as i32)
+        }
+    }
 }
 
 impl Drop for Sampler {
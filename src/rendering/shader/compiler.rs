@@ -1,4 +1,4 @@
-use crate::rendering::shader::ShaderStage;
+use crate::rendering::shader::{ShaderStage, SpecializationConstant};
 use shaderc::{
     CompilationArtifact, CompileOptions, EnvVersion, IncludeCallbackResult, IncludeType,
     ResolvedInclude, ShaderKind, TargetEnv,
@@ -30,7 +30,11 @@ impl Compiler {
         stage: ShaderStage,
         defines: Option<&[&str]>,
     ) -> shaderc::Result<CompilationArtifact> {
-        let compile_options = Self::setup_compile_options(defines);
+        let mut compile_options = Self::setup_compile_options(defines);
+
+        // Specialization constants are resolved by the driver when the SPIR-V module is
+        // specialized, so shaders can declare them with layout(constant_id = ...).
+        compile_options.add_macro_definition("SPECIALIZATION_CONSTANTS_SUPPORTED", None);
 
         self.compiler.compile_into_spirv(
             source,
@@ -46,8 +50,18 @@ impl Compiler {
         source: &str,
         source_file_name: &str,
         defines: Option<&[&str]>,
+        specialization_constants: &[SpecializationConstant],
     ) -> shaderc::Result<CompilationArtifact> {
         let mut compile_options = Self::setup_compile_options(defines);
+
+        // GLSL sources have no notion of specialization so the constants are baked in as defines.
+        specialization_constants.iter().for_each(|constant| {
+            compile_options.add_macro_definition(
+                constant.name(),
+                Some(constant.value().to_glsl_literal().as_str()),
+            )
+        });
+
         compile_options.set_target_env(TargetEnv::OpenGL, EnvVersion::OpenGL4_5 as u32);
         compile_options.set_include_callback(Self::include_resolve_callback);
         self.compiler
@@ -104,6 +118,7 @@ impl From<ShaderStage> for ShaderKind {
             ShaderStage::TesselationEvaluation => ShaderKind::TessEvaluation,
             ShaderStage::Geometry => ShaderKind::Geometry,
             ShaderStage::Fragment => ShaderKind::Fragment,
+            ShaderStage::Compute => ShaderKind::Compute,
         }
     }
 }
@@ -116,6 +131,7 @@ impl From<ShaderKind> for ShaderStage {
             ShaderKind::TessEvaluation => ShaderStage::TesselationEvaluation,
             ShaderKind::Geometry => ShaderStage::Geometry,
             ShaderKind::Fragment => ShaderStage::Fragment,
+            ShaderKind::Compute => ShaderStage::Compute,
             _ => panic!("Unsupported Shader Kind. Cannot convert to shader stage."),
         }
     }
@@ -9,9 +9,12 @@ use std::collections::HashMap;
 use std::path::PathBuf;
 use std::{fmt::Debug, path::Path};
 
+use crate::math::UVec3;
+use crate::rendering::buffer::{Buffer, BufferTarget};
+use crate::rendering::framebuffer::FramebufferAttachment;
 use crate::rendering::sampler::Sampler;
 use crate::rendering::shader::program::ShaderProgram;
-use crate::rendering::texture::{Texture2D, TextureCube};
+use crate::rendering::texture::{SizedTextureFormat, Texture2D, Texture3D, TextureCube};
 use gl::types::*;
 use gl_bindings as gl;
 use itertools::Itertools;
@@ -50,12 +53,105 @@ pub enum ShaderStage {
     TesselationEvaluation = gl::TESS_EVALUATION_SHADER,
     Geometry = gl::GEOMETRY_SHADER,
     Fragment = gl::FRAGMENT_SHADER,
+    Compute = gl::COMPUTE_SHADER,
+}
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageAccess {
+    ReadOnly = gl::READ_ONLY,
+    WriteOnly = gl::WRITE_ONLY,
+    ReadWrite = gl::READ_WRITE,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpecializationConstantValue {
+    Bool(bool),
+    Int(i32),
+    UInt(u32),
+    Float(f32),
+}
+
+impl SpecializationConstantValue {
+    /// The raw 32bit representation expected by glSpecializeShader.
+    pub(crate) fn to_bits(self) -> u32 {
+        match self {
+            SpecializationConstantValue::Bool(value) => value as u32,
+            SpecializationConstantValue::Int(value) => value as u32,
+            SpecializationConstantValue::UInt(value) => value,
+            SpecializationConstantValue::Float(value) => value.to_bits(),
+        }
+    }
+
+    /// The GLSL literal used when the constant is injected as a #define on the text path.
+    pub(crate) fn to_glsl_literal(self) -> String {
+        match self {
+            SpecializationConstantValue::Bool(value) => value.to_string(),
+            SpecializationConstantValue::Int(value) => value.to_string(),
+            SpecializationConstantValue::UInt(value) => format!("{}u", value),
+            SpecializationConstantValue::Float(value) => format!("{:?}", value),
+        }
+    }
+}
+
+impl From<bool> for SpecializationConstantValue {
+    fn from(value: bool) -> Self {
+        SpecializationConstantValue::Bool(value)
+    }
+}
+
+impl From<i32> for SpecializationConstantValue {
+    fn from(value: i32) -> Self {
+        SpecializationConstantValue::Int(value)
+    }
+}
+
+impl From<u32> for SpecializationConstantValue {
+    fn from(value: u32) -> Self {
+        SpecializationConstantValue::UInt(value)
+    }
+}
+
+impl From<f32> for SpecializationConstantValue {
+    fn from(value: f32) -> Self {
+        SpecializationConstantValue::Float(value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpecializationConstant {
+    name: String,
+    id: u32,
+    value: SpecializationConstantValue,
+}
+
+impl SpecializationConstant {
+    pub fn new<V: Into<SpecializationConstantValue>>(name: &str, id: u32, value: V) -> Self {
+        Self {
+            name: name.to_string(),
+            id,
+            value: value.into(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn value(&self) -> SpecializationConstantValue {
+        self.value
+    }
 }
 
 pub struct ShaderCreateInfo<'a> {
     name: String,
     keyword_sets: Vec<Vec<&'a str>>,
     stages: Vec<(ShaderStage, PathBuf)>,
+    specialization_constants: Vec<(ShaderStage, SpecializationConstant)>,
 }
 
 impl<'a> ShaderCreateInfo<'a> {
@@ -72,6 +168,7 @@ pub struct ShaderCreateInfoBuilder<'a> {
     name: String,
     keyword_sets: Vec<Vec<&'a str>>,
     modules: Vec<(ShaderStage, PathBuf)>,
+    specialization_constants: Vec<(ShaderStage, SpecializationConstant)>,
 }
 
 impl<'a> ShaderCreateInfoBuilder<'a> {
@@ -86,6 +183,25 @@ impl<'a> ShaderCreateInfoBuilder<'a> {
         self
     }
 
+    /// Sets a specialization constant for the module of the given stage.
+    ///
+    /// When SPIR-V is in use the value is applied through glSpecializeShader. Otherwise it is
+    /// injected as a `#define` named after the constant, so the GLSL declaration must be guarded
+    /// with `#ifndef <name>` (see `SPECIALIZATION_CONSTANT` in `core_defines.glsl`).
+    pub fn specialization_constant<V: Into<SpecializationConstantValue>>(
+        mut self,
+        stage: ShaderStage,
+        name: &str,
+        id: u32,
+        value: V,
+    ) -> Self {
+        self.specialization_constants
+            .retain(|(s, constant)| !(*s == stage && constant.id == id));
+        self.specialization_constants
+            .push((stage, SpecializationConstant::new(name, id, value)));
+        self
+    }
+
     pub fn build(self) -> ShaderCreateInfo<'a> {
         let keyword_sets = if self.keyword_sets.is_empty() {
             let mut set = vec!["_"];
@@ -98,6 +214,7 @@ impl<'a> ShaderCreateInfoBuilder<'a> {
             name: self.name,
             keyword_sets,
             stages: self.modules,
+            specialization_constants: self.specialization_constants,
         }
     }
 }
@@ -135,6 +252,20 @@ impl Shader {
         self
     }
 
+    pub fn bind_texture_3d(
+        &self,
+        binding_location: u32,
+        texture: &Texture3D,
+        sampler: &Sampler,
+    ) -> &Self {
+        unsafe {
+            gl::BindTextureUnit(binding_location as GLuint, texture.get_id());
+            gl::BindSampler(binding_location as GLuint, sampler.id)
+        }
+
+        self
+    }
+
     pub fn bind_texture_cube(
         &self,
         binding_location: u32,
@@ -149,6 +280,95 @@ impl Shader {
         self
     }
 
+    pub fn bind_image_2d(
+        &self,
+        unit: u32,
+        texture: &Texture2D,
+        level: u32,
+        access: ImageAccess,
+    ) -> &Self {
+        self.bind_image_2d_with_id(unit, texture.get_id(), level, access, texture.format())
+    }
+
+    pub fn bind_image_2d_with_id(
+        &self,
+        unit: u32,
+        texture_id: u32,
+        level: u32,
+        access: ImageAccess,
+        format: SizedTextureFormat,
+    ) -> &Self {
+        unsafe {
+            gl::BindImageTexture(
+                unit as GLuint,
+                texture_id,
+                level as GLint,
+                gl::FALSE,
+                0,
+                access as GLenum,
+                format as GLenum,
+            )
+        }
+
+        self
+    }
+
+    pub fn bind_image_attachment(
+        &self,
+        unit: u32,
+        attachment: &FramebufferAttachment,
+        access: ImageAccess,
+    ) -> &Self {
+        self.bind_image_2d_with_id(unit, attachment.id(), 0, access, attachment.format())
+    }
+
+    /// Binds all faces of a cubemap mip level. Shaders access it through an `imageCube`.
+    pub fn bind_image_cube(
+        &self,
+        unit: u32,
+        texture: &TextureCube,
+        level: u32,
+        access: ImageAccess,
+        format: SizedTextureFormat,
+    ) -> &Self {
+        unsafe {
+            gl::BindImageTexture(
+                unit as GLuint,
+                texture.get_id(),
+                level as GLint,
+                gl::TRUE,
+                0,
+                access as GLenum,
+                format as GLenum,
+            )
+        }
+
+        self
+    }
+
+    /// Binds all slices of a volume mip level. Shaders access it through an `image3D`.
+    pub fn bind_image_3d(
+        &self,
+        unit: u32,
+        texture: &Texture3D,
+        level: u32,
+        access: ImageAccess,
+    ) -> &Self {
+        unsafe {
+            gl::BindImageTexture(
+                unit as GLuint,
+                texture.get_id(),
+                level as GLint,
+                gl::TRUE,
+                0,
+                access as GLenum,
+                texture.format() as GLenum,
+            )
+        }
+
+        self
+    }
+
     pub fn enable_keyword(&self, keyword: &str) {
         if let Some(&bits) = self.keyword_bitfield_map.get(keyword) {
             {
@@ -197,8 +417,52 @@ impl Shader {
     }
 }
 
-pub struct ComputeShader {}
+/// A shader made of a single compute stage. Keywords, texture and image bindings are
+/// accessible through the underlying `Shader`.
+#[derive(Debug)]
+pub struct ComputeShader {
+    shader: Shader,
+}
 
 impl ComputeShader {
-    pub fn dispatch(&self) {}
+    pub fn dispatch(&self, work_groups_x: u32, work_groups_y: u32, work_groups_z: u32) {
+        self.shader.bind();
+
+        unsafe { gl::DispatchCompute(work_groups_x, work_groups_y, work_groups_z) }
+    }
+
+    /// Dispatches the work group counts stored in `buffer` at `offset` as three consecutive u32s.
+    pub fn dispatch_indirect(&self, buffer: &Buffer, offset: isize) {
+        assert!(
+            offset + 3 * std::mem::size_of::<u32>() as isize <= buffer.get_size(),
+            "Indirect dispatch arguments out of buffer range. Buffer size: {}, Requested offset: {}",
+            buffer.get_size(),
+            offset
+        );
+
+        self.shader.bind();
+
+        unsafe {
+            gl::BindBuffer(BufferTarget::DispatchIndirect as GLenum, buffer.get_id());
+            gl::DispatchComputeIndirect(offset as GLintptr);
+            gl::BindBuffer(BufferTarget::DispatchIndirect as GLenum, 0);
+        }
+    }
+
+    /// Number of work groups needed to cover `size` invocations with the given local size.
+    pub fn work_group_count(size: UVec3, local_size: UVec3) -> UVec3 {
+        UVec3::new(
+            (size.x + local_size.x - 1) / local_size.x,
+            (size.y + local_size.y - 1) / local_size.y,
+            (size.z + local_size.z - 1) / local_size.z,
+        )
+    }
+}
+
+impl std::ops::Deref for ComputeShader {
+    type Target = Shader;
+
+    fn deref(&self) -> &Self::Target {
+        &self.shader
+    }
 }
@@ -5,7 +5,7 @@ use gl::types::*;
 use gl_bindings as gl;
 use shaderc::CompilationArtifact;
 
-use crate::shader::ShaderStage;
+use crate::shader::{ShaderStage, SpecializationConstant};
 
 #[derive(Debug)]
 pub(crate) struct ShaderModule {
@@ -17,19 +17,29 @@ impl ShaderModule {
     pub fn new(
         stage: ShaderStage,
         compilation_artifact: &CompilationArtifact,
+        specialization_constants: &[SpecializationConstant],
     ) -> Result<ShaderModule, String> {
         if cfg!(feature = "use-spirv") {
             let spirv = compilation_artifact.as_binary_u8();
-            Self::new_from_spirv(stage, spirv)
+            Self::new_from_spirv(stage, spirv, specialization_constants)
         } else {
             let text_source = compilation_artifact.as_text();
             Self::new_from_text(stage, &text_source)
         }
     }
 
-    fn new_from_spirv(stage: ShaderStage, spir_v: &[u8]) -> Result<ShaderModule, String> {
+    fn new_from_spirv(
+        stage: ShaderStage,
+        spir_v: &[u8],
+        specialization_constants: &[SpecializationConstant],
+    ) -> Result<ShaderModule, String> {
         let id: GLuint;
 
+        let (constant_ids, constant_values): (Vec<GLuint>, Vec<GLuint>) = specialization_constants
+            .iter()
+            .map(|constant| (constant.id(), constant.value().to_bits()))
+            .unzip();
+
         unsafe {
             id = gl::CreateShader(stage as u32);
 
@@ -47,10 +57,10 @@ impl ShaderModule {
             gl::SpecializeShaderARB(
                 id, //obj id
                 cstr.as_ptr() as *const GLchar,
-                0,               // no specialization constants
-                ptr::null_mut(), // no specialization constants
-                ptr::null_mut(),
-            ); // no specialization constants
+                constant_ids.len() as GLuint,
+                constant_ids.as_ptr(),
+                constant_values.as_ptr(),
+            );
 
             let mut compilation_status: GLint = 0;
 
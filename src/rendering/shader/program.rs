@@ -18,12 +18,12 @@ impl ShaderProgram {
 }
 
 pub(crate) struct ShaderProgramBuilder<'a> {
-    modules: [Option<&'a ShaderModule>; 5],
+    modules: [Option<&'a ShaderModule>; 6],
 }
 
 impl Default for ShaderProgramBuilder<'_> {
     fn default() -> Self {
-        Self { modules: [None; 5] }
+        Self { modules: [None; 6] }
     }
 }
 
@@ -114,6 +114,7 @@ impl<'a> ShaderProgramBuilder<'a> {
             ShaderStage::TesselationEvaluation => 2,
             ShaderStage::Geometry => 3,
             ShaderStage::Fragment => 4,
+            ShaderStage::Compute => 5,
         }
     }
 
@@ -124,6 +125,7 @@ impl<'a> ShaderProgramBuilder<'a> {
             ShaderStage::TesselationEvaluation => gl::TESS_EVALUATION_SHADER_BIT,
             ShaderStage::Geometry => gl::GEOMETRY_SHADER_BIT,
             ShaderStage::Fragment => gl::FRAGMENT_SHADER_BIT,
+            ShaderStage::Compute => gl::COMPUTE_SHADER_BIT,
         }
     }
 }
@@ -2,11 +2,11 @@ use crate::imgui::{Gui, Ui};
 use crate::rendering::shader::compiler::Compiler;
 use crate::rendering::shader::module::ShaderModule;
 use crate::rendering::shader::program::{ShaderProgram, ShaderProgramBuilder};
-use crate::rendering::shader::Shader;
-use crate::shader::{ShaderCreateInfo, ShaderStage};
+use crate::rendering::shader::{ComputeShader, Shader};
+use crate::shader::{ShaderCreateInfo, ShaderStage, SpecializationConstant};
 use itertools::Itertools;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::fs::File;
 use std::io::Read;
@@ -20,17 +20,69 @@ struct CompileItem {
     shader_stage: ShaderStage,
     file_name: String,
     source: String,
+    specialization_constants: Vec<SpecializationConstant>,
+    /// Keywords of the shader referenced by the stage source or its includes. The others cannot
+    /// change the module, which is shared between the variants that only differ by them.
+    keywords: Vec<String>,
 }
 
 #[derive(Default)]
 pub struct ShaderManager {
     compiler: Compiler,
     shaders: Vec<Rc<Shader>>,
+    compute_shaders: Vec<Rc<ComputeShader>>,
     shader_module_cache: ShaderModuleCache,
 }
 
 impl ShaderManager {
     pub fn create_shader(&mut self, create_info: &ShaderCreateInfo) -> Rc<Shader> {
+        assert!(
+            create_info
+                .stages
+                .iter()
+                .all(|(stage, _)| *stage != ShaderStage::Compute),
+            "Shader \"{}\" contains a compute stage. Use create_compute_shader(...) instead.",
+            create_info.name
+        );
+
+        let shader = Rc::new(self.build_shader(create_info));
+
+        self.shaders.push(Rc::clone(&shader));
+
+        shader
+    }
+
+    pub fn create_compute_shader(&mut self, create_info: &ShaderCreateInfo) -> Rc<ComputeShader> {
+        assert!(
+            create_info.stages.len() == 1 && create_info.stages[0].0 == ShaderStage::Compute,
+            "Compute shader \"{}\" must consist of exactly one compute stage.",
+            create_info.name
+        );
+
+        let compute_shader = Rc::new(ComputeShader {
+            shader: self.build_shader(create_info),
+        });
+
+        self.compute_shaders.push(Rc::clone(&compute_shader));
+
+        compute_shader
+    }
+
+    pub fn find_shader(&self, name: &str) -> Option<Rc<Shader>> {
+        self.shaders
+            .iter()
+            .find(|&entry| entry.name == name)
+            .cloned()
+    }
+
+    pub fn find_compute_shader(&self, name: &str) -> Option<Rc<ComputeShader>> {
+        self.compute_shaders
+            .iter()
+            .find(|&entry| entry.name == name)
+            .cloned()
+    }
+
+    fn build_shader(&mut self, create_info: &ShaderCreateInfo) -> Shader {
         let keyword_bitfield_map = Self::create_keyword_bitfield_map(create_info);
 
         let stages = Self::create_compile_items(create_info);
@@ -51,24 +103,13 @@ impl ShaderManager {
             &mut self.shader_module_cache,
         );
 
-        let shader = Rc::new(Shader {
+        Shader {
             name: create_info.name.clone(),
             active_variant: RefCell::new(shader_variants[&default_variant_bitfield].id()),
             active_variant_bitfield: RefCell::new(default_variant_bitfield),
             shader_variants,
             keyword_bitfield_map,
-        });
-
-        self.shaders.push(Rc::clone(&shader));
-
-        shader
-    }
-
-    pub fn find_shader(&self, name: &str) -> Option<Rc<Shader>> {
-        self.shaders
-            .iter()
-            .find(|&entry| entry.name == name)
-            .cloned()
+        }
     }
 
     fn create_keyword_bitfield_map(create_info: &ShaderCreateInfo) -> HashMap<String, u32> {
@@ -101,11 +142,29 @@ impl ShaderManager {
             .map(|(stage, path)| {
                 let file_name = path.file_name().unwrap().to_string_lossy().to_string();
                 let source = load_shader_source(path);
+                let specialization_constants = create_info
+                    .specialization_constants
+                    .iter()
+                    .filter(|(constant_stage, _)| constant_stage == stage)
+                    .map(|(_, constant)| constant.clone())
+                    .collect_vec();
+
+                let identifiers = source_identifiers(&source);
+                let keywords = create_info
+                    .keyword_sets
+                    .iter()
+                    .flatten()
+                    .filter(|&&keyword| keyword != "_" && identifiers.contains(keyword))
+                    .unique()
+                    .map(|&keyword| String::from(keyword))
+                    .collect_vec();
 
                 CompileItem {
                     shader_stage: *stage,
                     file_name,
                     source,
+                    specialization_constants,
+                    keywords,
                 }
             })
             .collect_vec()
@@ -141,17 +200,29 @@ impl ShaderManager {
                 shader_stage,
                 file_name,
                 source,
+                specialization_constants,
+                keywords,
             } in compile_items.iter()
             {
-                match shader_module_cache.get(file_name.as_str()) {
+                let filtered_keywords = keyword_set
+                    .iter()
+                    .filter(|&keyword| keywords.iter().any(|used| used == keyword))
+                    .copied()
+                    .collect_vec();
+
+                let cache_key =
+                    module_cache_key(file_name, &filtered_keywords, specialization_constants);
+
+                let cached_module = shader_module_cache.get(&cache_key).or_else(|| {
+                    cache_additions
+                        .iter()
+                        .find(|(key, _)| *key == cache_key)
+                        .map(|(_, module)| module)
+                });
+
+                match cached_module {
                     Some(module) => shader_modules.push(Rc::clone(module)),
                     None => {
-                        let filtered_keywords = keyword_set
-                            .iter()
-                            .filter(|&keyword| *keyword != "_")
-                            .copied()
-                            .collect_vec();
-
                         let maybe_keywords =
                             (!filtered_keywords.is_empty()).then(|| filtered_keywords.as_slice());
 
@@ -160,13 +231,26 @@ impl ShaderManager {
                                 .compile(source, file_name, *shader_stage, maybe_keywords)
                                 .unwrap()
                         } else {
-                            compiler.preprocess(source, file_name, maybe_keywords).unwrap()
+                            compiler
+                                .preprocess(
+                                    source,
+                                    file_name,
+                                    maybe_keywords,
+                                    specialization_constants,
+                                )
+                                .unwrap()
                         };
 
-                        let module =
-                            Rc::new(ShaderModule::new(*shader_stage, &compiled_artifact).unwrap());
+                        let module = Rc::new(
+                            ShaderModule::new(
+                                *shader_stage,
+                                &compiled_artifact,
+                                specialization_constants,
+                            )
+                            .unwrap(),
+                        );
                         shader_modules.push(Rc::clone(&module));
-                        cache_additions.push((file_name.clone(), module))
+                        cache_additions.push((cache_key, module))
                     }
                 }
             }
@@ -203,6 +287,55 @@ impl Gui for ShaderManager {
     }
 }
 
+/// Modules are shared between shaders and variants only when they were built from the same file
+/// with the same keywords used by the stage and the same specialization constants.
+fn module_cache_key(
+    file_name: &str,
+    keywords: &[&str],
+    specialization_constants: &[SpecializationConstant],
+) -> String {
+    let keywords = keywords.iter().sorted().join(",");
+
+    let constants = specialization_constants
+        .iter()
+        .sorted_by_key(|constant| constant.id())
+        .map(|constant| format!("{}={:?}", constant.id(), constant.value()))
+        .join(",");
+
+    format!("{}[{}][{}]", file_name, keywords, constants)
+}
+
+/// Identifiers of a shader source and of the files it includes, recursively. Includes are resolved
+/// the same way as by the compiler, relative to the working directory, and panic when missing.
+fn source_identifiers(source: &str) -> HashSet<String> {
+    let mut identifiers = HashSet::new();
+    let mut visited = HashSet::new();
+    let mut pending = vec![source.to_string()];
+
+    while let Some(source) = pending.pop() {
+        for line in source.lines() {
+            let include = line
+                .trim_start()
+                .strip_prefix("#include")
+                .and_then(|rest| rest.split('"').nth(1));
+
+            if let Some(include) = include {
+                if visited.insert(include.to_string()) {
+                    pending.push(load_shader_source(include));
+                }
+            }
+
+            identifiers.extend(
+                line.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .filter(|token| !token.is_empty())
+                    .map(String::from),
+            );
+        }
+    }
+
+    identifiers
+}
+
 fn load_shader_source<P: AsRef<Path> + Debug>(path: P) -> String {
     let mut source = String::new();
 
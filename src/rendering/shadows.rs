@@ -0,0 +1,492 @@
+use std::mem;
+use std::rc::Rc;
+
+use crate::{
+    core::camera::Camera,
+    core::math::{inverse, look_at, orthographic, Axes, Mat4, UVec2, Vec3, Vec4},
+    imgui::{Gui, Ui},
+    rendering::{
+        buffer::{Buffer, BufferStorageFlags, BufferTarget, MapModeFlags},
+        framebuffer::{AttachmentType, Framebuffer, FramebufferAttachmentCreateInfo},
+        sampler::{
+            Anisotropy, CompareFunction, MagnificationFilter, MinificationFilter, Sampler,
+            WrappingMode,
+        },
+        shader::{Shader, ShaderCreateInfo, ShaderStage},
+        state::StateManager,
+        texture::SizedTextureFormat,
+    },
+    Context, Msaa,
+};
+
+/// Must match MAX_SHADOW_CASCADES in shadows.glsl.
+pub const MAX_CASCADES: usize = 4;
+
+pub const SHADOW_MAP_BINDING_INDEX: u32 = 9;
+const SHADOW_UBO_BINDING_INDEX: u32 = 9;
+
+const SHADOW_DEPTH_VERTEX_SHADER_PATH: &str = "assets/shaders/shadows/shadow_depth.vert";
+const SHADOW_DEPTH_GEOMETRY_SHADER_PATH: &str = "assets/shaders/shadows/shadow_depth.geom";
+
+const MAX_PCF_RADIUS: i32 = 3;
+const SHADOW_MAP_RESOLUTIONS: [u32; 4] = [512, 1024, 2048, 4096];
+
+// Sphere radii are rounded up to this step so that small changes of the camera frustum do not
+// change the size of a shadow map texel.
+const RADIUS_QUANTIZATION_STEP: f32 = 1.0 / 16.0;
+
+/// Split distances of `cascade_count` cascades covering [near, far], blending a logarithmic
+/// distribution (`lambda` = 1) with a uniform one (`lambda` = 0). Each entry is the far distance
+/// of a cascade; unused entries are set to `far`.
+pub fn cascade_split_distances(
+    near: f32,
+    far: f32,
+    cascade_count: usize,
+    lambda: f32,
+) -> [f32; MAX_CASCADES] {
+    let cascade_count = cascade_count.max(1).min(MAX_CASCADES);
+    let mut splits = [far; MAX_CASCADES];
+
+    splits
+        .iter_mut()
+        .take(cascade_count)
+        .enumerate()
+        .for_each(|(i, split)| {
+            let p = (i + 1) as f32 / cascade_count as f32;
+            let logarithmic = near * (far / near).powf(p);
+            let uniform = near + (far - near) * p;
+
+            *split = uniform + (logarithmic - uniform) * lambda;
+        });
+
+    splits
+}
+
+/// World space corners of the part of a perspective frustum between the `near` and `far`
+/// distances. `inverse_view` is the camera to world transform.
+pub fn frustum_slice_corners(
+    inverse_view: &Mat4,
+    fov_deg: f32,
+    aspect_ratio: f32,
+    near: f32,
+    far: f32,
+) -> [Vec3; 8] {
+    let tan_half_vertical = (fov_deg.to_radians() * 0.5).tan();
+    let tan_half_horizontal = tan_half_vertical * aspect_ratio;
+
+    let mut corners = [Vec3::zeros(); 8];
+
+    for (i, distance) in [near, far].iter().enumerate() {
+        let x = distance * tan_half_horizontal;
+        let y = distance * tan_half_vertical;
+
+        let view_corners = [
+            Vec4::new(-x, -y, -distance, 1.0),
+            Vec4::new(x, -y, -distance, 1.0),
+            Vec4::new(x, y, -distance, 1.0),
+            Vec4::new(-x, y, -distance, 1.0),
+        ];
+
+        for (j, corner) in view_corners.iter().enumerate() {
+            corners[i * 4 + j] = (inverse_view * corner).xyz();
+        }
+    }
+
+    corners
+}
+
+/// Sphere enclosing `points`, centered on their average. The radius is rounded up so that it
+/// stays constant while the camera rotates.
+pub fn bounding_sphere(points: &[Vec3]) -> (Vec3, f32) {
+    let center = points.iter().fold(Vec3::zeros(), |acc, point| acc + point) / points.len() as f32;
+
+    let radius = points
+        .iter()
+        .map(|point| (point - center).norm())
+        .fold(0.0f32, f32::max);
+
+    let radius = (radius / RADIUS_QUANTIZATION_STEP).ceil() * RADIUS_QUANTIZATION_STEP;
+
+    (center, radius)
+}
+
+/// Rotation only view matrix of a directional light travelling in `light_direction`.
+pub fn light_view_matrix(light_direction: &Vec3) -> Mat4 {
+    let direction = light_direction.normalize();
+
+    let up = if direction.y.abs() > 0.99 {
+        Axes::forward()
+    } else {
+        Axes::up()
+    };
+
+    look_at(&Vec3::zeros(), &direction, &up)
+}
+
+/// World to shadow clip space matrix of a cascade bounding the sphere (`center`, `radius`).
+/// The center is snapped to the shadow map texel grid in light space so that shadow edges do not
+/// shimmer when the camera moves. Casters in front of the near plane are handled by depth
+/// clamping during the depth pass.
+pub fn stable_cascade_matrix(
+    light_direction: &Vec3,
+    center: &Vec3,
+    radius: f32,
+    resolution: u32,
+) -> Mat4 {
+    let light_view = light_view_matrix(light_direction);
+
+    let texel_size = 2.0 * radius / resolution as f32;
+    let light_space_center = (light_view * Vec4::new(center.x, center.y, center.z, 1.0)).xyz();
+
+    let x = (light_space_center.x / texel_size).floor() * texel_size;
+    let y = (light_space_center.y / texel_size).floor() * texel_size;
+    let depth = -light_space_center.z;
+
+    let projection = orthographic(
+        x - radius,
+        x + radius,
+        y - radius,
+        y + radius,
+        depth - radius,
+        depth + radius,
+    );
+
+    projection * light_view
+}
+
+#[repr(usize)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShadowFilter {
+    Off,
+    Hard,
+    Pcf,
+}
+
+impl ShadowFilter {
+    /// Enables the shader variant matching the filter.
+    pub fn apply_keywords(self, shader: &Shader) {
+        match self {
+            ShadowFilter::Off => {
+                shader.disable_keyword("FEATURE_SHADOWS");
+                shader.disable_keyword("FEATURE_SHADOWS_PCF");
+            }
+            ShadowFilter::Hard => {
+                shader.disable_keyword("FEATURE_SHADOWS_PCF");
+                shader.enable_keyword("FEATURE_SHADOWS");
+            }
+            ShadowFilter::Pcf => {
+                shader.disable_keyword("FEATURE_SHADOWS");
+                shader.enable_keyword("FEATURE_SHADOWS_PCF");
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CascadedShadowSettings {
+    pub cascade_count: usize,
+    /// Width and height of each cascade in texels.
+    pub resolution: u32,
+    /// Blend between uniform (0) and logarithmic (1) split distances.
+    pub split_lambda: f32,
+    /// View distance covered by the cascades, limited by the camera far plane.
+    pub max_distance: f32,
+    /// Constant depth offset, in units of the smallest resolvable depth difference.
+    pub depth_bias: f32,
+    /// Depth offset scaled by the depth slope of the polygon.
+    pub slope_bias: f32,
+    /// Largest depth offset applied to a polygon, 0 disables the limit.
+    pub bias_clamp: f32,
+    /// Offset of the receiver position along its normal, in shadow map texels.
+    pub normal_offset: f32,
+    /// Half size in texels of the PCF kernel.
+    pub pcf_radius: i32,
+}
+
+impl Default for CascadedShadowSettings {
+    fn default() -> Self {
+        Self {
+            cascade_count: MAX_CASCADES,
+            resolution: 2048,
+            split_lambda: 0.75,
+            max_distance: 150.0,
+            depth_bias: 1.0,
+            slope_bias: 2.0,
+            bias_clamp: 0.005,
+            normal_offset: 1.5,
+            pcf_radius: 1,
+        }
+    }
+}
+
+// Plain std140 layout of the `ShadowBlock` uniform block.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct ShadowBlock {
+    cascade_matrices: [mint::ColumnMatrix4<f32>; MAX_CASCADES],
+    cascade_splits: [f32; MAX_CASCADES],
+    cascade_texel_sizes: [f32; MAX_CASCADES],
+    normal_offset: f32,
+    pcf_radius: i32,
+    cascade_count: i32,
+    debug_cascades: i32,
+}
+
+/// Shadow map of a directional light split into up to four cascades along the camera view
+/// direction, stored in the layers of a depth array texture and rendered in a single pass.
+pub struct CascadedShadowMap {
+    settings: CascadedShadowSettings,
+    framebuffer: Framebuffer,
+    depth_shader: Rc<Shader>,
+    shadow_ubo: Buffer,
+    sampler: Sampler,
+    cascade_matrices: [Mat4; MAX_CASCADES],
+    cascade_splits: [f32; MAX_CASCADES],
+    cascade_texel_sizes: [f32; MAX_CASCADES],
+    debug_cascades: bool,
+}
+
+impl CascadedShadowMap {
+    pub fn new(context: Context, settings: CascadedShadowSettings) -> Self {
+        let Context { device, .. } = context;
+
+        let depth_shader = device.shader_manager().create_shader(
+            &ShaderCreateInfo::builder("ShadowDepthShader")
+                .stage(ShaderStage::Vertex, SHADOW_DEPTH_VERTEX_SHADER_PATH)
+                .stage(ShaderStage::Geometry, SHADOW_DEPTH_GEOMETRY_SHADER_PATH)
+                .build(),
+        );
+
+        let mut shadow_ubo = Buffer::new(
+            "Shadow UBO",
+            mem::size_of::<ShadowBlock>() as isize,
+            BufferTarget::Uniform,
+            BufferStorageFlags::MAP_WRITE_PERSISTENT_COHERENT,
+        );
+        shadow_ubo.bind(SHADOW_UBO_BINDING_INDEX);
+        shadow_ubo.map(MapModeFlags::MAP_WRITE_PERSISTENT_COHERENT);
+
+        // Lookups outside of the cascades compare against a depth of 1 and are never shadowed.
+        let sampler = Sampler::new(
+            MinificationFilter::Linear,
+            MagnificationFilter::Linear,
+            WrappingMode::ClampToBorder,
+            WrappingMode::ClampToBorder,
+            WrappingMode::ClampToBorder,
+            Vec4::new(1.0, 1.0, 1.0, 1.0),
+            Anisotropy::None,
+        );
+        sampler.set_depth_compare(CompareFunction::LessOrEqual);
+
+        let settings = CascadedShadowSettings {
+            cascade_count: settings.cascade_count.max(1).min(MAX_CASCADES),
+            ..settings
+        };
+
+        Self {
+            framebuffer: Self::create_framebuffer(settings.resolution),
+            settings,
+            depth_shader,
+            shadow_ubo,
+            sampler,
+            cascade_matrices: [Mat4::identity(); MAX_CASCADES],
+            cascade_splits: [0.0; MAX_CASCADES],
+            cascade_texel_sizes: [0.0; MAX_CASCADES],
+            debug_cascades: false,
+        }
+    }
+
+    pub fn settings(&self) -> &CascadedShadowSettings {
+        &self.settings
+    }
+
+    /// Recreates the shadow map when the resolution changes.
+    pub fn set_settings(&mut self, settings: CascadedShadowSettings) {
+        let settings = CascadedShadowSettings {
+            cascade_count: settings.cascade_count.max(1).min(MAX_CASCADES),
+            ..settings
+        };
+
+        if settings.resolution != self.settings.resolution {
+            self.framebuffer = Self::create_framebuffer(settings.resolution);
+        }
+
+        self.settings = settings;
+    }
+
+    pub fn cascade_matrices(&self) -> &[Mat4] {
+        &self.cascade_matrices[..self.settings.cascade_count]
+    }
+
+    /// Far view distance of each cascade.
+    pub fn cascade_splits(&self) -> &[f32] {
+        &self.cascade_splits[..self.settings.cascade_count]
+    }
+
+    pub fn debug_cascades(&self) -> bool {
+        self.debug_cascades
+    }
+
+    /// Tints the lit surfaces with the color of the cascade they sample.
+    pub fn set_debug_cascades(&mut self, debug_cascades: bool) {
+        self.debug_cascades = debug_cascades;
+    }
+
+    /// Fits the cascades to the frustum of `camera` and writes the `ShadowBlock`.
+    /// `light_direction` is the direction the light travels in.
+    pub fn update(&mut self, camera: &Camera, light_direction: &Vec3) {
+        let near = camera.near_plane();
+        let far = f32::min(self.settings.max_distance, camera.far_plane()).max(near);
+        let inverse_view = inverse(camera.transform());
+
+        self.cascade_splits = cascade_split_distances(
+            near,
+            far,
+            self.settings.cascade_count,
+            self.settings.split_lambda,
+        );
+
+        for i in 0..self.settings.cascade_count {
+            let slice_near = if i == 0 {
+                near
+            } else {
+                self.cascade_splits[i - 1]
+            };
+
+            let corners = frustum_slice_corners(
+                &inverse_view,
+                camera.fov_deg() as f32,
+                camera.aspect_ratio(),
+                slice_near,
+                self.cascade_splits[i],
+            );
+
+            let (center, radius) = bounding_sphere(&corners);
+
+            self.cascade_matrices[i] =
+                stable_cascade_matrix(light_direction, &center, radius, self.settings.resolution);
+            self.cascade_texel_sizes[i] = 2.0 * radius / self.settings.resolution as f32;
+        }
+
+        let block = ShadowBlock {
+            cascade_matrices: [
+                self.cascade_matrices[0].into(),
+                self.cascade_matrices[1].into(),
+                self.cascade_matrices[2].into(),
+                self.cascade_matrices[3].into(),
+            ],
+            cascade_splits: self.cascade_splits,
+            cascade_texel_sizes: self.cascade_texel_sizes,
+            normal_offset: self.settings.normal_offset,
+            pcf_radius: self.settings.pcf_radius,
+            cascade_count: self.settings.cascade_count as i32,
+            debug_cascades: self.debug_cascades as i32,
+        };
+
+        self.shadow_ubo.fill_mapped(0, &block);
+    }
+
+    /// Renders the depth of every cascade. `draw` must issue the draw calls of the shadow
+    /// casters, with their `PerDrawBlock` bound.
+    pub fn render<F: FnMut()>(&self, mut draw: F) {
+        self.framebuffer.bind();
+        self.framebuffer.clear(&Vec4::new(1.0, 1.0, 1.0, 1.0));
+
+        StateManager::depth_clamp(true);
+        StateManager::enable_polygon_offset(
+            self.settings.slope_bias,
+            self.settings.depth_bias,
+            self.settings.bias_clamp,
+        );
+
+        self.depth_shader.bind();
+        draw();
+        self.depth_shader.unbind();
+
+        StateManager::disable_polygon_offset();
+        StateManager::depth_clamp(false);
+
+        self.framebuffer.unbind(false);
+    }
+
+    /// Binds the cascades, with depth comparison enabled, to the shadow map slot of `shader`.
+    pub fn bind_shadow_map(&self, shader: &Shader) {
+        shader.bind_texture_2d_with_id(
+            SHADOW_MAP_BINDING_INDEX,
+            self.framebuffer.texture_attachment(0).id(),
+            &self.sampler,
+        );
+    }
+
+    fn create_framebuffer(resolution: u32) -> Framebuffer {
+        Framebuffer::new(
+            "ShadowCascades",
+            UVec2::new(resolution, resolution),
+            Msaa::None,
+            vec![FramebufferAttachmentCreateInfo::new(
+                "ShadowCascades-Depth",
+                SizedTextureFormat::Depth32f,
+                AttachmentType::Texture,
+            )
+            .with_layers(MAX_CASCADES as u32)],
+        )
+        .expect("Failed to create the shadow cascades framebuffer!")
+    }
+}
+
+impl Gui for CascadedShadowMap {
+    fn gui(&mut self, ui: &Ui) {
+        imgui::TreeNode::new("Cascaded Shadow Map")
+            .default_open(true)
+            .open_on_arrow(true)
+            .open_on_double_click(true)
+            .framed(false)
+            .build(ui, || {
+                let mut settings = self.settings;
+
+                let mut cascade_count = settings.cascade_count as i32;
+                if imgui::Slider::new("Cascades", 1, MAX_CASCADES as i32)
+                    .build(ui, &mut cascade_count)
+                {
+                    settings.cascade_count = cascade_count as usize;
+                }
+
+                let mut resolution_index = SHADOW_MAP_RESOLUTIONS
+                    .iter()
+                    .position(|&resolution| resolution == settings.resolution)
+                    .unwrap_or(2);
+                if ui.combo_simple_string(
+                    "Resolution",
+                    &mut resolution_index,
+                    &["512", "1024", "2048", "4096"],
+                ) {
+                    settings.resolution = SHADOW_MAP_RESOLUTIONS[resolution_index];
+                }
+
+                imgui::Slider::new("Split Lambda", 0.0, 1.0)
+                    .display_format("%.2f")
+                    .build(ui, &mut settings.split_lambda);
+                imgui::Slider::new("Max Distance", 1.0, 500.0)
+                    .display_format("%.1f")
+                    .build(ui, &mut settings.max_distance);
+                imgui::Slider::new("Depth Bias", 0.0, 10.0)
+                    .display_format("%.2f")
+                    .build(ui, &mut settings.depth_bias);
+                imgui::Slider::new("Slope Bias", 0.0, 10.0)
+                    .display_format("%.2f")
+                    .build(ui, &mut settings.slope_bias);
+                imgui::Slider::new("Bias Clamp", 0.0, 0.05)
+                    .display_format("%.4f")
+                    .build(ui, &mut settings.bias_clamp);
+                imgui::Slider::new("Normal Offset (texels)", 0.0, 5.0)
+                    .display_format("%.2f")
+                    .build(ui, &mut settings.normal_offset);
+                imgui::Slider::new("PCF Radius", 1, MAX_PCF_RADIUS)
+                    .build(ui, &mut settings.pcf_radius);
+
+                self.set_settings(settings);
+
+                ui.checkbox("Debug Cascades", &mut self.debug_cascades);
+            });
+    }
+}
@@ -0,0 +1,341 @@
+use gl::types::*;
+use gl_bindings as gl;
+
+use crate::core::math::{vec3_lerp, Vec3};
+use crate::rendering::texture::TextureCube;
+
+pub const SH9_COEFFICIENT_COUNT: usize = 9;
+
+// Cosine lobe convolution factors (A0 = PI, A1 = 2PI/3, A2 = PI/4), divided by PI so that the
+// evaluated irradiance matches the convention of the irradiance cubemaps (irradiance / PI).
+const IRRADIANCE_BAND_FACTORS: [f32; SH9_COEFFICIENT_COUNT] = [
+    1.0,
+    2.0 / 3.0,
+    2.0 / 3.0,
+    2.0 / 3.0,
+    0.25,
+    0.25,
+    0.25,
+    0.25,
+    0.25,
+];
+
+// Largest face size read back when projecting a cubemap on the CPU.
+const MAX_PROJECTION_FACE_SIZE: u32 = 64;
+
+/// Real L2 spherical harmonics basis, ordered (l, m): (0,0), (1,-1), (1,0), (1,1), (2,-2),
+/// (2,-1), (2,0), (2,1), (2,2). Matches `SHBasis` in `spherical_harmonics.glsl`.
+pub fn sh9_basis(direction: &Vec3) -> [f32; SH9_COEFFICIENT_COUNT] {
+    let (x, y, z) = (direction.x, direction.y, direction.z);
+
+    [
+        0.282_095,
+        0.488_603 * y,
+        0.488_603 * z,
+        0.488_603 * x,
+        1.092_548 * x * y,
+        1.092_548 * y * z,
+        0.315_392 * (3.0 * z * z - 1.0),
+        1.092_548 * x * z,
+        0.546_274 * (x * x - y * y),
+    ]
+}
+
+/// Direction through the center of texel (x, y) of a cubemap face, using the OpenGL face
+/// orientation table.
+pub fn cubemap_texel_direction(face: usize, x: u32, y: u32, face_size: u32) -> Vec3 {
+    let u = (x as f32 + 0.5) / face_size as f32 * 2.0 - 1.0;
+    let v = (y as f32 + 0.5) / face_size as f32 * 2.0 - 1.0;
+
+    let direction = match face {
+        0 => Vec3::new(1.0, -v, -u),
+        1 => Vec3::new(-1.0, -v, u),
+        2 => Vec3::new(u, 1.0, v),
+        3 => Vec3::new(u, -1.0, -v),
+        4 => Vec3::new(u, -v, 1.0),
+        _ => Vec3::new(-u, -v, -1.0),
+    };
+
+    direction.normalize()
+}
+
+/// Solid angle subtended by texel (x, y) of a cubemap face, approximated at the texel center.
+pub fn cubemap_texel_solid_angle(x: u32, y: u32, face_size: u32) -> f32 {
+    let u = (x as f32 + 0.5) / face_size as f32 * 2.0 - 1.0;
+    let v = (y as f32 + 0.5) / face_size as f32 * 2.0 - 1.0;
+    let texel_size = 2.0 / face_size as f32;
+
+    texel_size * texel_size / (1.0 + u * u + v * v).powf(1.5)
+}
+
+/// GPU layout of the `SphericalHarmonicsBlock` uniform block (std140 compatible). Holds the
+/// irradiance coefficients, one per vec4.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SphericalHarmonicsBlock {
+    pub coefficients: [[f32; 4]; SH9_COEFFICIENT_COUNT],
+}
+
+impl From<&SphericalHarmonics> for SphericalHarmonicsBlock {
+    fn from(sh: &SphericalHarmonics) -> Self {
+        let mut block = SphericalHarmonicsBlock::default();
+
+        block
+            .coefficients
+            .iter_mut()
+            .zip(sh.irradiance_coefficients().iter())
+            .for_each(|(destination, coefficient)| {
+                *destination = [coefficient.x, coefficient.y, coefficient.z, 0.0]
+            });
+
+        block
+    }
+}
+
+/// Nine RGB coefficients of the radiance of an environment projected onto the L2 SH basis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SphericalHarmonics {
+    coefficients: [Vec3; SH9_COEFFICIENT_COUNT],
+}
+
+impl Default for SphericalHarmonics {
+    fn default() -> Self {
+        Self {
+            coefficients: [Vec3::zeros(); SH9_COEFFICIENT_COUNT],
+        }
+    }
+}
+
+impl SphericalHarmonics {
+    pub fn new(coefficients: [Vec3; SH9_COEFFICIENT_COUNT]) -> Self {
+        Self { coefficients }
+    }
+
+    /// Projects a radiance function sampled at the texel centers of a virtual cubemap with the
+    /// given face size. The result is normalized so that the texel solid angles sum to 4PI.
+    pub fn project<F: Fn(&Vec3) -> Vec3>(face_size: u32, radiance: F) -> Self {
+        Self::project_texels(face_size, |_, _, _, direction| radiance(direction))
+    }
+
+    /// Projects tightly packed RGBA32F cubemap face data, laid out face after face.
+    pub fn project_cubemap_data(face_size: u32, data: &[f32]) -> Self {
+        let face_texels = (face_size * face_size) as usize;
+        assert_eq!(
+            data.len(),
+            face_texels * 6 * 4,
+            "Cubemap data does not match the given face size."
+        );
+
+        Self::project_texels(face_size, |face, x, y, _| {
+            let index = (face * face_texels + (y * face_size + x) as usize) * 4;
+            Vec3::new(data[index], data[index + 1], data[index + 2])
+        })
+    }
+
+    /// Reads back a mip of the cubemap no larger than 64x64 per face and projects it on the CPU.
+    pub fn from_texture_cube(cubemap: &TextureCube) -> Self {
+        let mut level = 0;
+        while level + 1 < cubemap.mip_levels()
+            && (cubemap.size() >> level) > MAX_PROJECTION_FACE_SIZE
+        {
+            level += 1;
+        }
+
+        let face_size = u32::max(cubemap.size() >> level, 1);
+        let mut data = vec![0.0f32; (face_size * face_size * 6 * 4) as usize];
+
+        unsafe {
+            gl::GetTextureImage(
+                cubemap.get_id(),
+                level as i32,
+                gl::RGBA,
+                gl::FLOAT,
+                (data.len() * std::mem::size_of::<f32>()) as GLsizei,
+                data.as_mut_ptr() as *mut GLvoid,
+            );
+        }
+
+        Self::project_cubemap_data(face_size, &data)
+    }
+
+    pub fn coefficients(&self) -> &[Vec3; SH9_COEFFICIENT_COUNT] {
+        &self.coefficients
+    }
+
+    /// Reconstructed radiance in the given direction.
+    pub fn evaluate(&self, direction: &Vec3) -> Vec3 {
+        sh9_basis(direction)
+            .iter()
+            .zip(self.coefficients.iter())
+            .fold(Vec3::zeros(), |acc, (basis, coefficient)| {
+                acc + coefficient * *basis
+            })
+    }
+
+    /// Coefficients convolved with the clamped cosine lobe and divided by PI, ready to be
+    /// uploaded to the `SphericalHarmonicsBlock`.
+    pub fn irradiance_coefficients(&self) -> [Vec3; SH9_COEFFICIENT_COUNT] {
+        let mut coefficients = self.coefficients;
+
+        coefficients
+            .iter_mut()
+            .zip(IRRADIANCE_BAND_FACTORS.iter())
+            .for_each(|(coefficient, factor)| *coefficient *= *factor);
+
+        coefficients
+    }
+
+    /// Irradiance divided by PI for a surface with the given normal.
+    pub fn evaluate_irradiance(&self, normal: &Vec3) -> Vec3 {
+        sh9_basis(normal)
+            .iter()
+            .zip(self.irradiance_coefficients().iter())
+            .fold(Vec3::zeros(), |acc, (basis, coefficient)| {
+                acc + coefficient * *basis
+            })
+    }
+
+    fn project_texels<F: Fn(usize, u32, u32, &Vec3) -> Vec3>(face_size: u32, radiance: F) -> Self {
+        let mut coefficients = [Vec3::zeros(); SH9_COEFFICIENT_COUNT];
+        let mut total_weight = 0.0;
+
+        for face in 0..6 {
+            for y in 0..face_size {
+                for x in 0..face_size {
+                    let direction = cubemap_texel_direction(face, x, y, face_size);
+                    let weight = cubemap_texel_solid_angle(x, y, face_size);
+                    let sample = radiance(face, x, y, &direction);
+
+                    sh9_basis(&direction)
+                        .iter()
+                        .zip(coefficients.iter_mut())
+                        .for_each(|(basis, coefficient)| {
+                            *coefficient += sample * (*basis * weight)
+                        });
+
+                    total_weight += weight;
+                }
+            }
+        }
+
+        let normalization = 4.0 * std::f32::consts::PI / total_weight;
+        coefficients
+            .iter_mut()
+            .for_each(|coefficient| *coefficient *= normalization);
+
+        Self { coefficients }
+    }
+
+    pub fn scaled(&self, factor: f32) -> SphericalHarmonics {
+        let mut coefficients = self.coefficients;
+        coefficients
+            .iter_mut()
+            .for_each(|coefficient| *coefficient *= factor);
+
+        SphericalHarmonics { coefficients }
+    }
+
+    pub fn lerp(a: &SphericalHarmonics, b: &SphericalHarmonics, t: f32) -> SphericalHarmonics {
+        let mut coefficients = [Vec3::zeros(); SH9_COEFFICIENT_COUNT];
+
+        coefficients
+            .iter_mut()
+            .zip(a.coefficients.iter().zip(b.coefficients.iter()))
+            .for_each(|(coefficient, (a, b))| *coefficient = vec3_lerp(a, b, t));
+
+        SphericalHarmonics { coefficients }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::f32::consts::PI;
+
+    const FACE_SIZE: u32 = 32;
+    const TOLERANCE: f32 = 1e-2;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < TOLERANCE,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn constant_radiance_projects_to_the_first_band_only() {
+        let radiance = Vec3::new(0.5, 1.0, 2.0);
+        let sh = SphericalHarmonics::project(FACE_SIZE, |_| radiance);
+
+        let expected = radiance * (4.0 * PI).sqrt();
+        for channel in 0..3 {
+            assert_close(sh.coefficients()[0][channel], expected[channel]);
+        }
+
+        for coefficient in sh.coefficients().iter().skip(1) {
+            for channel in 0..3 {
+                assert_close(coefficient[channel], 0.0);
+            }
+        }
+
+        // Irradiance / PI of a constant environment is its radiance whatever the normal.
+        let normals = [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(1.0, 1.0, 1.0).normalize(),
+        ];
+        for normal in normals.iter() {
+            let irradiance = sh.evaluate_irradiance(normal);
+            for channel in 0..3 {
+                assert_close(irradiance[channel], radiance[channel]);
+            }
+        }
+    }
+
+    #[test]
+    fn radiance_linear_in_z_projects_to_the_l1_z_coefficient_only() {
+        let sh = SphericalHarmonics::project(FACE_SIZE, |direction| Vec3::repeat(direction.z));
+
+        for (index, coefficient) in sh.coefficients().iter().enumerate() {
+            // The integral of z * Y(1, 0) over the sphere, sqrt(4PI / 3).
+            let expected = if index == 2 {
+                (4.0 * PI / 3.0).sqrt()
+            } else {
+                0.0
+            };
+
+            for channel in 0..3 {
+                assert_close(coefficient[channel], expected);
+            }
+        }
+    }
+
+    #[test]
+    fn basis_is_orthonormal() {
+        let mut products = [[0.0f32; SH9_COEFFICIENT_COUNT]; SH9_COEFFICIENT_COUNT];
+
+        for face in 0..6 {
+            for y in 0..FACE_SIZE {
+                for x in 0..FACE_SIZE {
+                    let basis = sh9_basis(&cubemap_texel_direction(face, x, y, FACE_SIZE));
+                    let weight = cubemap_texel_solid_angle(x, y, FACE_SIZE);
+
+                    for i in 0..SH9_COEFFICIENT_COUNT {
+                        for j in 0..SH9_COEFFICIENT_COUNT {
+                            products[i][j] += basis[i] * basis[j] * weight;
+                        }
+                    }
+                }
+            }
+        }
+
+        for i in 0..SH9_COEFFICIENT_COUNT {
+            for j in 0..SH9_COEFFICIENT_COUNT {
+                assert_close(products[i][j], if i == j { 1.0 } else { 0.0 });
+            }
+        }
+    }
+}
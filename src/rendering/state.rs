@@ -2,6 +2,27 @@ use gl_bindings as gl;
 
 pub struct StateManager;
 
+bitflags! {
+    pub struct MemoryBarrierFlags : u32 {
+        const VERTEX_ATTRIB_ARRAY = gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT;
+        const ELEMENT_ARRAY = gl::ELEMENT_ARRAY_BARRIER_BIT;
+        const UNIFORM = gl::UNIFORM_BARRIER_BIT;
+        const TEXTURE_FETCH = gl::TEXTURE_FETCH_BARRIER_BIT;
+        const SHADER_IMAGE_ACCESS = gl::SHADER_IMAGE_ACCESS_BARRIER_BIT;
+        const COMMAND = gl::COMMAND_BARRIER_BIT;
+        const PIXEL_BUFFER = gl::PIXEL_BUFFER_BARRIER_BIT;
+        const TEXTURE_UPDATE = gl::TEXTURE_UPDATE_BARRIER_BIT;
+        const BUFFER_UPDATE = gl::BUFFER_UPDATE_BARRIER_BIT;
+        const FRAMEBUFFER = gl::FRAMEBUFFER_BARRIER_BIT;
+        const TRANSFORM_FEEDBACK = gl::TRANSFORM_FEEDBACK_BARRIER_BIT;
+        const ATOMIC_COUNTER = gl::ATOMIC_COUNTER_BARRIER_BIT;
+        const SHADER_STORAGE = gl::SHADER_STORAGE_BARRIER_BIT;
+        const CLIENT_MAPPED_BUFFER = gl::CLIENT_MAPPED_BUFFER_BARRIER_BIT;
+        const QUERY_BUFFER = gl::QUERY_BUFFER_BARRIER_BIT;
+        const ALL = gl::ALL_BARRIER_BITS;
+    }
+}
+
 #[repr(u32)]
 pub enum BlendFactor {
     Zero = gl::ZERO,
@@ -51,6 +72,11 @@ impl StateManager {
         unsafe { gl::Viewport(x, y, width, height) }
     }
 
+    /// Sets one of the viewports selected through gl_ViewportIndex in a geometry shader.
+    pub fn viewport_indexed(index: u32, x: f32, y: f32, width: f32, height: f32) {
+        unsafe { gl::ViewportIndexedf(index, x, y, width, height) }
+    }
+
     pub fn depth_write(state: bool) {
         unsafe { gl::DepthMask(state as u8) }
     }
@@ -96,4 +122,37 @@ impl StateManager {
     pub fn front_face(front_face: FrontFace) {
         unsafe { gl::FrontFace(front_face as u32) }
     }
+
+    /// Clamps fragment depth to the near and far planes instead of clipping primitives against
+    /// them.
+    pub fn depth_clamp(enabled: bool) {
+        if enabled {
+            unsafe { gl::Enable(gl::DEPTH_CLAMP) }
+        } else {
+            unsafe { gl::Disable(gl::DEPTH_CLAMP) }
+        }
+    }
+
+    /// Enables the depth offset of filled polygons. The slope scaled part of the offset is
+    /// limited to `clamp` (GL_ARB_polygon_offset_clamp), 0 disables the limit.
+    pub fn enable_polygon_offset(slope_factor: f32, units: f32, clamp: f32) {
+        unsafe {
+            gl::Enable(gl::POLYGON_OFFSET_FILL);
+            gl::PolygonOffsetClamp(slope_factor, units, clamp)
+        }
+    }
+
+    pub fn disable_polygon_offset() {
+        unsafe { gl::Disable(gl::POLYGON_OFFSET_FILL) }
+    }
+
+    pub fn memory_barrier(barriers: MemoryBarrierFlags) {
+        unsafe { gl::MemoryBarrier(barriers.bits()) }
+    }
+
+    /// Only valid for barriers between fragment shader invocations writing to and reading from
+    /// the same framebuffer region.
+    pub fn memory_barrier_by_region(barriers: MemoryBarrierFlags) {
+        unsafe { gl::MemoryBarrierByRegion(barriers.bits()) }
+    }
 }
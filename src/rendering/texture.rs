@@ -1,11 +1,15 @@
 use std::ffi::CString;
 use image;
+use image::codecs::hdr::HdrDecoder;
 use image::{ColorType, DynamicImage, GenericImageView};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
 
 use gli::GliTexture;
 use gli_rs as gli;
 
 use crate::core::asset::Asset;
+use crate::math::{UVec2, UVec3};
 use gl::types::*;
 use gl_bindings as gl;
 use std::path::Path;
@@ -20,6 +24,7 @@ pub enum SizedTextureFormat {
     Srgb8 = gl::SRGB8,
     Rgba8 = gl::RGBA8,
     Srgb8A8 = gl::SRGB8_ALPHA8,
+    Rgb10A2 = gl::RGB10_A2,
     R16f = gl::R16F,
     Rg16f = gl::RG16F,
     Rgb16f = gl::RGB16F,
@@ -69,6 +74,21 @@ impl Utils {
         }
     }
 
+    fn open_hdr_file<P: AsRef<Path>>(path: P) -> Result<(u32, u32, Vec<f32>), String> {
+        let file = File::open(path.as_ref()).map_err(|e| e.to_string())?;
+        let decoder = HdrDecoder::new(BufReader::new(file)).map_err(|e| e.to_string())?;
+
+        let metadata = decoder.metadata();
+        let pixels = decoder.read_image_hdr().map_err(|e| e.to_string())?;
+
+        let data = pixels
+            .iter()
+            .flat_map(|pixel| pixel.0.iter().copied())
+            .collect::<Vec<f32>>();
+
+        Ok((metadata.width, metadata.height, data))
+    }
+
     fn color_type_to_texture_formats(
         color_type: ColorType,
         is_srgb: bool,
@@ -97,7 +117,10 @@ impl Utils {
 
 pub struct Texture2D {
     id: GLuint,
-    image: DynamicImage,
+    size: UVec2,
+    format: SizedTextureFormat,
+    mip_levels: u32,
+    image: Option<DynamicImage>,
 }
 
 pub struct Texture2DLoadConfig {
@@ -122,14 +145,137 @@ impl Asset for Texture2D {
             generate_mipmap = config.generate_mipmap;
         }
 
+        let name = path.as_ref().file_name().unwrap().to_str().unwrap();
+
+        let is_hdr = path
+            .as_ref()
+            .extension()
+            .map_or(false, |extension| extension.eq_ignore_ascii_case("hdr"));
+
+        if is_hdr {
+            let (width, height, data) = Utils::open_hdr_file(path.as_ref())?;
+            return Ok(Self::new_from_rgb32f(
+                name,
+                UVec2::new(width, height),
+                &data,
+                generate_mipmap,
+            ));
+        }
+
         match Utils::open_image_file(path.as_ref()) {
-            Ok(img) => Ok(Self::new_from_image(path.as_ref().file_name().unwrap().to_str().unwrap(),img, generate_mipmap, is_srgb)?),
+            Ok(img) => Ok(Self::new_from_image(name, img, generate_mipmap, is_srgb)?),
             Err(e) => Err(e.to_string()),
         }
     }
 }
 
 impl Texture2D {
+    /// Creates a texture with uninitialized storage, e.g. to be written by a compute shader.
+    pub fn new(name: &str, size: UVec2, format: SizedTextureFormat, mip_levels: u32) -> Self {
+        let mut id: GLuint = 0;
+        unsafe {
+            gl::CreateTextures(gl::TEXTURE_2D, 1, &mut id);
+
+            let label = CString::new(name).unwrap();
+            gl::ObjectLabel(gl::TEXTURE, id, name.len() as i32 + 1, label.as_ptr());
+
+            gl::TextureStorage2D(
+                id,
+                mip_levels as i32,
+                format as u32,
+                size.x as i32,
+                size.y as i32,
+            );
+        }
+
+        Self {
+            id,
+            size,
+            format,
+            mip_levels,
+            image: None,
+        }
+    }
+
+    /// Creates a floating point texture from tightly packed RGB data, e.g. a decoded Radiance HDR image.
+    pub fn new_from_rgb32f(name: &str, size: UVec2, data: &[f32], generate_mipmap: bool) -> Self {
+        assert_eq!(
+            data.len(),
+            (size.x * size.y * 3) as usize,
+            "RGB32F data does not match the requested texture size."
+        );
+
+        let mip_levels = if generate_mipmap {
+            (f32::floor(f32::log2(f32::max(size.x as f32, size.y as f32))) + 1.0) as u32
+        } else {
+            1
+        };
+
+        let texture = Self::new(name, size, SizedTextureFormat::Rgb32f, mip_levels);
+
+        unsafe {
+            gl::TextureSubImage2D(
+                texture.id,
+                0,
+                0,
+                0,
+                size.x as i32,
+                size.y as i32,
+                TextureFormat::Rgb as u32,
+                gl::FLOAT,
+                data.as_ptr() as *const GLvoid,
+            );
+
+            if generate_mipmap {
+                gl::GenerateTextureMipmap(texture.id)
+            }
+        }
+
+        texture
+    }
+
+    /// Creates a single mip texture from tightly packed 8-bit data, e.g. a generated lookup table.
+    pub fn new_from_u8(
+        name: &str,
+        size: UVec2,
+        format: SizedTextureFormat,
+        data_format: TextureFormat,
+        data: &[u8],
+    ) -> Self {
+        let components = match data_format {
+            TextureFormat::Red => 1,
+            TextureFormat::Rg => 2,
+            TextureFormat::Rgb => 3,
+            TextureFormat::Rgba => 4,
+        };
+
+        assert_eq!(
+            data.len(),
+            (size.x * size.y * components) as usize,
+            "8-bit data does not match the requested texture size."
+        );
+
+        let texture = Self::new(name, size, format, 1);
+
+        unsafe {
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::TextureSubImage2D(
+                texture.id,
+                0,
+                0,
+                0,
+                size.x as i32,
+                size.y as i32,
+                data_format as u32,
+                gl::UNSIGNED_BYTE,
+                data.as_ptr() as *const GLvoid,
+            );
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+        }
+
+        texture
+    }
+
     pub fn new_from_image(
         name: &str,
         image: DynamicImage,
@@ -182,15 +328,61 @@ impl Texture2D {
             }
         }
 
-        Ok(Self { id, image })
+        Ok(Self {
+            id,
+            size: UVec2::new(width, height),
+            format: formats.0,
+            mip_levels: mip_levels as u32,
+            image: Some(image),
+        })
     }
 
     pub fn get_id(&self) -> GLuint {
         self.id
     }
 
-    pub fn get_image(&self) -> &DynamicImage {
-        &self.image
+    pub fn get_image(&self) -> Option<&DynamicImage> {
+        self.image.as_ref()
+    }
+
+    pub fn size(&self) -> UVec2 {
+        self.size
+    }
+
+    pub fn format(&self) -> SizedTextureFormat {
+        self.format
+    }
+
+    pub fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    pub fn generate_mipmaps(&self) {
+        unsafe { gl::GenerateTextureMipmap(self.id) }
+    }
+
+    /// Copies the first level of the 2D texture `source_id` into the first mip level. The source
+    /// must have the size and a format compatible with this texture.
+    pub fn copy_from(&self, source_id: GLuint) {
+        unsafe {
+            gl::CopyImageSubData(
+                source_id,
+                gl::TEXTURE_2D,
+                0,
+                0,
+                0,
+                0,
+                self.id,
+                gl::TEXTURE_2D,
+                0,
+                0,
+                0,
+                0,
+                self.size.x as i32,
+                self.size.y as i32,
+                1,
+            )
+        }
     }
 }
 
@@ -200,8 +392,104 @@ impl Drop for Texture2D {
     }
 }
 
+/// A volume texture, e.g. a color grading lookup table.
+pub struct Texture3D {
+    id: GLuint,
+    size: UVec3,
+    format: SizedTextureFormat,
+    mip_levels: u32,
+}
+
+impl Texture3D {
+    /// Creates a volume texture with uninitialized storage.
+    pub fn new(name: &str, size: UVec3, format: SizedTextureFormat, mip_levels: u32) -> Self {
+        let mut id: GLuint = 0;
+        unsafe {
+            gl::CreateTextures(gl::TEXTURE_3D, 1, &mut id);
+
+            let label = CString::new(name).unwrap();
+            gl::ObjectLabel(gl::TEXTURE, id, name.len() as i32 + 1, label.as_ptr());
+
+            gl::TextureStorage3D(
+                id,
+                mip_levels as i32,
+                format as u32,
+                size.x as i32,
+                size.y as i32,
+                size.z as i32,
+            );
+        }
+
+        Self {
+            id,
+            size,
+            format,
+            mip_levels,
+        }
+    }
+
+    /// Creates a single mip texture from tightly packed RGB data, x varying fastest then y then z.
+    pub fn new_from_rgb32f(
+        name: &str,
+        size: UVec3,
+        format: SizedTextureFormat,
+        data: &[f32],
+    ) -> Self {
+        assert_eq!(
+            data.len(),
+            (size.x * size.y * size.z * 3) as usize,
+            "RGB32F data does not match the requested texture size."
+        );
+
+        let texture = Self::new(name, size, format, 1);
+
+        unsafe {
+            gl::TextureSubImage3D(
+                texture.id,
+                0,
+                0,
+                0,
+                0,
+                size.x as i32,
+                size.y as i32,
+                size.z as i32,
+                TextureFormat::Rgb as u32,
+                gl::FLOAT,
+                data.as_ptr() as *const GLvoid,
+            );
+        }
+
+        texture
+    }
+
+    pub fn get_id(&self) -> GLuint {
+        self.id
+    }
+
+    pub fn size(&self) -> UVec3 {
+        self.size
+    }
+
+    pub fn format(&self) -> SizedTextureFormat {
+        self.format
+    }
+
+    pub fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+}
+
+impl Drop for Texture3D {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteTextures(1, &self.id) }
+    }
+}
+
 pub struct TextureCube {
     id: GLuint,
+    size: u32,
+    format: SizedTextureFormat,
+    mip_levels: u32,
 }
 
 impl Asset for TextureCube {
@@ -279,7 +567,12 @@ impl Asset for TextureCube {
                     }
                 }
 
-                Ok(TextureCube { id })
+                Ok(TextureCube {
+                    id,
+                    size: tex.extent(0).width as u32,
+                    format: internal_format,
+                    mip_levels: tex.levels() as u32,
+                })
             }
             Err(e) => Err(e.to_string()),
         }
@@ -287,6 +580,32 @@ impl Asset for TextureCube {
 }
 
 impl TextureCube {
+    /// Creates a cubemap with uninitialized storage. `size` is the width and height of each face.
+    pub fn new(name: &str, size: u32, format: SizedTextureFormat, mip_levels: u32) -> Self {
+        let mut id: GLuint = 0;
+        unsafe {
+            gl::CreateTextures(gl::TEXTURE_CUBE_MAP, 1, &mut id);
+
+            let label = CString::new(name).unwrap();
+            gl::ObjectLabel(gl::TEXTURE, id, name.len() as i32 + 1, label.as_ptr());
+
+            gl::TextureStorage2D(
+                id,
+                mip_levels as i32,
+                format as u32,
+                size as i32,
+                size as i32,
+            );
+        }
+
+        Self {
+            id,
+            size,
+            format,
+            mip_levels,
+        }
+    }
+
     //TODO: To be removed
     pub fn new_from_file<P: AsRef<Path>>(path: P) -> Result<Self, String> {
         let result: gli::Result<gli::TextureCube> = gli::load(path.as_ref());
@@ -353,7 +672,12 @@ impl TextureCube {
                     }
                 }
 
-                Ok(TextureCube { id })
+                Ok(TextureCube {
+                    id,
+                    size: tex.extent(0).width as u32,
+                    format: internal_format,
+                    mip_levels: tex.levels() as u32,
+                })
             }
             Err(e) => Err(e.to_string()),
         }
@@ -363,6 +687,92 @@ impl TextureCube {
         self.id
     }
 
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn format(&self) -> SizedTextureFormat {
+        self.format
+    }
+
+    pub fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    pub fn generate_mipmaps(&self) {
+        unsafe { gl::GenerateTextureMipmap(self.id) }
+    }
+
+    /// Reads the cubemap back from the GPU and writes it as a KTX 1.1 file that can be loaded
+    /// with `TextureCube::new_from_file`. Only RGBA16F and RGBA32F cubemaps are supported.
+    pub fn save_ktx<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        const KTX_IDENTIFIER: [u8; 12] = [
+            0xAB, 0x4B, 0x54, 0x58, 0x20, 0x31, 0x31, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+        ];
+        const KTX_ENDIANNESS: u32 = 0x04030201;
+
+        let (data_type, type_size) = match self.format {
+            SizedTextureFormat::Rgba16f => (gl::HALF_FLOAT, 2u32),
+            SizedTextureFormat::Rgba32f => (gl::FLOAT, 4u32),
+            unsupported => {
+                return Err(format!(
+                    "Cannot save cubemap with format {:?} as KTX. Only Rgba16f and Rgba32f are supported.",
+                    unsupported
+                ))
+            }
+        };
+
+        let header = [
+            KTX_ENDIANNESS,
+            data_type,
+            type_size,
+            gl::RGBA,
+            self.format as u32,
+            gl::RGBA,
+            self.size,
+            self.size,
+            0, // pixel depth
+            0, // array elements
+            6, // faces
+            self.mip_levels,
+            0, // key/value data
+        ];
+
+        let file = File::create(path.as_ref()).map_err(|e| e.to_string())?;
+        let mut writer = BufWriter::new(file);
+
+        let mut write = |bytes: &[u8]| writer.write_all(bytes).map_err(|e| e.to_string());
+
+        write(&KTX_IDENTIFIER)?;
+        header
+            .iter()
+            .try_for_each(|value| write(&value.to_le_bytes()))?;
+
+        for level in 0..self.mip_levels {
+            let level_size = u32::max(self.size >> level, 1);
+            // RGBA texels are always 4 byte aligned, so no row or face padding is required.
+            let face_size = level_size * level_size * 4 * type_size;
+            let mut data = vec![0u8; (face_size * 6) as usize];
+
+            // DSA readback of a cubemap returns all six faces, in face order.
+            unsafe {
+                gl::GetTextureImage(
+                    self.id,
+                    level as i32,
+                    gl::RGBA,
+                    data_type,
+                    data.len() as i32,
+                    data.as_mut_ptr() as *mut GLvoid,
+                );
+            }
+
+            write(&face_size.to_le_bytes())?;
+            write(&data)?;
+        }
+
+        Ok(())
+    }
+
     fn translate_gli_format_info(
         format: gli::Format,
     ) -> (SizedTextureFormat, TextureFormat, GLenum) {
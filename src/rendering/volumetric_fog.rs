@@ -0,0 +1,458 @@
+use std::rc::Rc;
+
+use crate::{
+    core::camera::Camera,
+    core::math::{inverse, radical_inverse, Mat4, UVec3, Vec3, Vec4},
+    imgui::{Gui, Ui},
+    rendering::{
+        buffer::{Buffer, BufferStorageFlags, BufferTarget, MapModeFlags},
+        environment::EnvironmentRenderer,
+        light::{light_color, DirectionalLight},
+        sampler::{Anisotropy, MagnificationFilter, MinificationFilter, Sampler, WrappingMode},
+        shader::{ComputeShader, ImageAccess, Shader, ShaderCreateInfo, ShaderStage},
+        shadows::CascadedShadowMap,
+        state::{MemoryBarrierFlags, StateManager},
+        texture::{SizedTextureFormat, Texture3D},
+    },
+    Context,
+};
+
+const FOG_SCATTERING_SHADER_PATH: &str = "assets/shaders/volumetric_fog/fog_scattering.comp";
+const FOG_INTEGRATION_SHADER_PATH: &str = "assets/shaders/volumetric_fog/fog_integration.comp";
+
+/// Texture unit of the integrated fog volume in the lit shaders.
+pub const FOG_VOLUME_BINDING_INDEX: u32 = 13;
+const UBO_BINDING_INDEX: u32 = 23;
+
+// Texture and image units of fog_scattering.comp and fog_integration.comp.
+const HISTORY_BINDING_INDEX: u32 = 0;
+const SOURCE_IMAGE_BINDING_INDEX: u32 = 0;
+const TARGET_IMAGE_BINDING_INDEX: u32 = 1;
+
+const SCATTERING_LOCAL_SIZE: u32 = 4;
+const INTEGRATION_LOCAL_SIZE: u32 = 8;
+
+// Length of the sequence of depth jitters inside the slices.
+const JITTER_SEQUENCE_LENGTH: u32 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VolumetricFogSettings {
+    /// Number of froxels along the screen width, the screen height and the view depth.
+    pub grid_size: UVec3,
+    /// View distance covered by the volume. Surfaces further away get the fog of its last slice.
+    pub max_distance: f32,
+    /// Extinction coefficient at and below the base height, per meter.
+    pub density: f32,
+    /// Exponential falloff of the density above the base height, per meter.
+    pub height_falloff: f32,
+    pub base_height: f32,
+    /// Ratio of scattering to extinction per channel, the rest is absorbed.
+    pub albedo: Vec3,
+    /// Henyey-Greenstein asymmetry, positive values scatter forward into light shafts.
+    pub anisotropy: f32,
+    /// Scale of the light scattered from the environment.
+    pub ambient_intensity: f32,
+    /// Modulates the density with animated 3D noise.
+    pub noise: bool,
+    /// Frequency of the noise, per meter.
+    pub noise_frequency: f32,
+    /// How much the noise varies the density, 0 leaves it uniform.
+    pub noise_intensity: f32,
+    /// Velocity the noise is scrolled at, in meters per second.
+    pub wind: Vec3,
+    /// Weight of the reprojected previous frame.
+    pub temporal_blend: f32,
+}
+
+impl Default for VolumetricFogSettings {
+    fn default() -> Self {
+        Self {
+            grid_size: UVec3::new(160, 90, 64),
+            max_distance: 64.0,
+            density: 0.02,
+            height_falloff: 0.2,
+            base_height: 0.0,
+            albedo: Vec3::new(1.0, 1.0, 1.0),
+            anisotropy: 0.6,
+            ambient_intensity: 1.0,
+            noise: true,
+            noise_frequency: 0.15,
+            noise_intensity: 0.5,
+            wind: Vec3::new(1.0, 0.0, 0.5),
+            temporal_blend: 0.9,
+        }
+    }
+}
+
+// Plain std140 layout of the `FogBlock` uniform block.
+#[repr(C)]
+struct FogBlock {
+    view_projection: mint::ColumnMatrix4<f32>,
+    inverse_view_projection: mint::ColumnMatrix4<f32>,
+    previous_view_projection: mint::ColumnMatrix4<f32>,
+    camera_position: [f32; 4],
+    camera_forward: [f32; 4],
+    light_direction: [f32; 4],
+    light_color: [f32; 4],
+    albedo: [f32; 4],
+    noise_offset: [f32; 4],
+    grid_size: [u32; 4],
+    depth_params: [f32; 4],
+    density: f32,
+    height_falloff: f32,
+    base_height: f32,
+    noise_intensity: f32,
+    temporal_blend: f32,
+    history_valid: i32,
+    _padding: [f32; 2],
+}
+
+/// Froxel based volumetric fog. The view frustum, up to `max_distance`, is split into a grid of
+/// froxels whose extinction comes from an exponential height fog, optionally modulated by noise.
+/// A first compute pass evaluates the light scattered in every froxel from the directional light,
+/// through the cascaded shadow map when one is given, and from the environment, and blends it
+/// with the reprojected result of the previous frame. A second pass integrates the froxels front
+/// to back into a volume of in-scattering and transmittance.
+///
+/// When the volumetricFog flag of their per-frame block is set, the lit shaders look up the
+/// integrated volume at the position of their surface. The skybox is not fogged.
+pub struct VolumetricFog {
+    settings: VolumetricFogSettings,
+    scattering_shader: Rc<ComputeShader>,
+    integration_shader: Rc<ComputeShader>,
+    ubo: Buffer,
+    // Ping-pong pair, the one written last frame is the history of the next one.
+    scattering_volumes: [Texture3D; 2],
+    integrated_volume: Texture3D,
+    sampler: Sampler,
+    current_volume: usize,
+    frame_index: u32,
+    previous_view_projection: Mat4,
+    history_valid: bool,
+    enabled: bool,
+}
+
+impl VolumetricFog {
+    pub fn new(context: Context, settings: VolumetricFogSettings) -> Self {
+        let Context { device, .. } = context;
+
+        let shader_manager = device.shader_manager();
+
+        let scattering_shader = shader_manager.create_compute_shader(
+            &ShaderCreateInfo::builder("Fog Scattering Shader")
+                .stage(ShaderStage::Compute, FOG_SCATTERING_SHADER_PATH)
+                .keyword_set(&["_", "FOG_SHADOWS"])
+                .keyword_set(&["_", "FOG_NOISE"])
+                .build(),
+        );
+        let integration_shader = shader_manager.create_compute_shader(
+            &ShaderCreateInfo::builder("Fog Integration Shader")
+                .stage(ShaderStage::Compute, FOG_INTEGRATION_SHADER_PATH)
+                .build(),
+        );
+
+        let mut ubo = Buffer::new(
+            "Volumetric Fog UBO",
+            std::mem::size_of::<FogBlock>() as isize,
+            BufferTarget::Uniform,
+            BufferStorageFlags::MAP_WRITE_PERSISTENT_COHERENT,
+        );
+        ubo.bind(UBO_BINDING_INDEX);
+        ubo.map(MapModeFlags::MAP_WRITE_PERSISTENT_COHERENT);
+
+        let settings = Self::validate_settings(settings);
+
+        Self {
+            settings,
+            scattering_shader,
+            integration_shader,
+            ubo,
+            scattering_volumes: Self::create_scattering_volumes(settings.grid_size),
+            integrated_volume: Self::create_volume("Fog Integrated Volume", settings.grid_size),
+            sampler: Sampler::new(
+                MinificationFilter::Linear,
+                MagnificationFilter::Linear,
+                WrappingMode::ClampToEdge,
+                WrappingMode::ClampToEdge,
+                WrappingMode::ClampToEdge,
+                Vec4::new(0.0, 0.0, 0.0, 0.0),
+                Anisotropy::None,
+            ),
+            current_volume: 0,
+            frame_index: 0,
+            previous_view_projection: Mat4::identity(),
+            history_valid: false,
+            enabled: false,
+        }
+    }
+
+    pub fn settings(&self) -> &VolumetricFogSettings {
+        &self.settings
+    }
+
+    /// Recreates the volumes when the grid size changes.
+    pub fn set_settings(&mut self, settings: VolumetricFogSettings) {
+        let settings = Self::validate_settings(settings);
+
+        if settings.grid_size != self.settings.grid_size {
+            self.scattering_volumes = Self::create_scattering_volumes(settings.grid_size);
+            self.integrated_volume =
+                Self::create_volume("Fog Integrated Volume", settings.grid_size);
+            self.history_valid = false;
+        }
+
+        self.settings = settings;
+    }
+
+    pub fn enable(&mut self) {
+        self.enabled = true
+    }
+
+    pub fn disable(&mut self) {
+        self.enabled = false;
+        self.history_valid = false;
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Fills the froxels for the frame of `camera` and integrates them. `light` should be the
+    /// directional light of the scene, shadowed by `shadows` when given, in which case they must
+    /// have been rendered for the frame. `time` animates the noise, in seconds.
+    pub fn update(
+        &mut self,
+        camera: &Camera,
+        light: &DirectionalLight,
+        shadows: Option<&CascadedShadowMap>,
+        environment: &EnvironmentRenderer,
+        time: f32,
+    ) {
+        let settings = &self.settings;
+        let grid_size = settings.grid_size;
+
+        let view = camera.transform();
+        let view_projection = camera.unjittered_projection() * view;
+        let forward = -Vec3::new(view[(2, 0)], view[(2, 1)], view[(2, 2)]);
+        let direction = light.direction.normalize();
+        let color = light_color(&light.color, light.temperature) * light.illuminance;
+        let noise_offset = -settings.wind * time;
+
+        self.frame_index = (self.frame_index + 1) % JITTER_SEQUENCE_LENGTH;
+
+        self.ubo.fill_mapped(
+            0,
+            &FogBlock {
+                view_projection: view_projection.into(),
+                inverse_view_projection: inverse(&view_projection).into(),
+                previous_view_projection: self.previous_view_projection.into(),
+                camera_position: camera.position().push(1.0).into(),
+                camera_forward: forward.push(0.0).into(),
+                light_direction: direction.push(settings.anisotropy).into(),
+                light_color: color.push(0.0).into(),
+                albedo: settings.albedo.push(settings.ambient_intensity).into(),
+                noise_offset: noise_offset.push(settings.noise_frequency).into(),
+                grid_size: [grid_size.x, grid_size.y, grid_size.z, self.frame_index],
+                depth_params: [
+                    camera.near_plane(),
+                    settings.max_distance.max(camera.near_plane() + 0.01),
+                    radical_inverse(self.frame_index + 1, 3),
+                    0.0,
+                ],
+                density: settings.density,
+                height_falloff: settings.height_falloff,
+                base_height: settings.base_height,
+                noise_intensity: settings.noise_intensity,
+                temporal_blend: settings.temporal_blend,
+                history_valid: self.history_valid as i32,
+                _padding: [0.0; 2],
+            },
+        );
+        self.ubo.bind(UBO_BINDING_INDEX);
+
+        let history = &self.scattering_volumes[1 - self.current_volume];
+        let scattering = &self.scattering_volumes[self.current_volume];
+
+        // Scattering pass
+        match shadows {
+            Some(shadows) => {
+                shadows.bind_shadow_map(&self.scattering_shader);
+                self.scattering_shader.enable_keyword("FOG_SHADOWS");
+            }
+            None => self.scattering_shader.disable_keyword("FOG_SHADOWS"),
+        }
+
+        if settings.noise {
+            self.scattering_shader.enable_keyword("FOG_NOISE");
+        } else {
+            self.scattering_shader.disable_keyword("FOG_NOISE");
+        }
+
+        environment.bind_ibl_maps(&self.scattering_shader);
+        self.scattering_shader
+            .bind_texture_3d(HISTORY_BINDING_INDEX, history, &self.sampler)
+            .bind_image_3d(
+                TARGET_IMAGE_BINDING_INDEX,
+                scattering,
+                0,
+                ImageAccess::WriteOnly,
+            );
+
+        let work_groups = ComputeShader::work_group_count(
+            grid_size,
+            UVec3::new(
+                SCATTERING_LOCAL_SIZE,
+                SCATTERING_LOCAL_SIZE,
+                SCATTERING_LOCAL_SIZE,
+            ),
+        );
+        self.scattering_shader
+            .dispatch(work_groups.x, work_groups.y, work_groups.z);
+
+        StateManager::memory_barrier(MemoryBarrierFlags::SHADER_IMAGE_ACCESS);
+
+        // Integration pass
+        self.integration_shader
+            .bind_image_3d(
+                SOURCE_IMAGE_BINDING_INDEX,
+                scattering,
+                0,
+                ImageAccess::ReadOnly,
+            )
+            .bind_image_3d(
+                TARGET_IMAGE_BINDING_INDEX,
+                &self.integrated_volume,
+                0,
+                ImageAccess::WriteOnly,
+            );
+
+        let work_groups = ComputeShader::work_group_count(
+            UVec3::new(grid_size.x, grid_size.y, 1),
+            UVec3::new(INTEGRATION_LOCAL_SIZE, INTEGRATION_LOCAL_SIZE, 1),
+        );
+        self.integration_shader
+            .dispatch(work_groups.x, work_groups.y, work_groups.z);
+
+        StateManager::memory_barrier(MemoryBarrierFlags::TEXTURE_FETCH);
+
+        self.current_volume = 1 - self.current_volume;
+        self.previous_view_projection = view_projection;
+        self.history_valid = true;
+    }
+
+    /// Binds the fog integrated by the last `update` for a lit shader.
+    pub fn bind_fog_volume(&self, shader: &Shader) {
+        shader.bind_texture_3d(
+            FOG_VOLUME_BINDING_INDEX,
+            &self.integrated_volume,
+            &self.sampler,
+        );
+    }
+
+    fn validate_settings(settings: VolumetricFogSettings) -> VolumetricFogSettings {
+        VolumetricFogSettings {
+            grid_size: settings.grid_size.map(|size| size.max(1).min(256)),
+            max_distance: settings.max_distance.max(1.0),
+            density: settings.density.max(0.0),
+            height_falloff: settings.height_falloff.max(0.0),
+            albedo: settings.albedo.map(|albedo| albedo.max(0.0).min(1.0)),
+            anisotropy: settings.anisotropy.max(-0.99).min(0.99),
+            ambient_intensity: settings.ambient_intensity.max(0.0),
+            noise_frequency: settings.noise_frequency.max(0.001),
+            noise_intensity: settings.noise_intensity.max(0.0).min(1.0),
+            temporal_blend: settings.temporal_blend.max(0.0).min(0.98),
+            ..settings
+        }
+    }
+
+    fn create_volume(name: &str, grid_size: UVec3) -> Texture3D {
+        Texture3D::new(name, grid_size, SizedTextureFormat::Rgba16f, 1)
+    }
+
+    fn create_scattering_volumes(grid_size: UVec3) -> [Texture3D; 2] {
+        [
+            Self::create_volume("Fog Scattering Volume 0", grid_size),
+            Self::create_volume("Fog Scattering Volume 1", grid_size),
+        ]
+    }
+}
+
+impl Gui for VolumetricFog {
+    fn gui(&mut self, ui: &Ui) {
+        imgui::TreeNode::new("Volumetric Fog Settings")
+            .default_open(false)
+            .open_on_arrow(true)
+            .open_on_double_click(true)
+            .framed(false)
+            .build(ui, || {
+                let mut settings = self.settings;
+
+                let mut grid_size = [
+                    settings.grid_size.x as i32,
+                    settings.grid_size.y as i32,
+                    settings.grid_size.z as i32,
+                ];
+                if imgui::Drag::new("Grid Size")
+                    .range(1, 256)
+                    .speed(0.5)
+                    .build_array(ui, &mut grid_size)
+                {
+                    settings.grid_size = UVec3::new(
+                        grid_size[0] as u32,
+                        grid_size[1] as u32,
+                        grid_size[2] as u32,
+                    );
+                }
+
+                imgui::Slider::new("Max Distance", 1.0, 500.0)
+                    .display_format("%.0f")
+                    .build(ui, &mut settings.max_distance);
+                imgui::Slider::new("Density", 0.0, 0.5)
+                    .display_format("%.3f")
+                    .build(ui, &mut settings.density);
+                imgui::Slider::new("Height Falloff", 0.0, 2.0)
+                    .display_format("%.2f")
+                    .build(ui, &mut settings.height_falloff);
+                imgui::Slider::new("Base Height", -50.0, 50.0)
+                    .display_format("%.1f")
+                    .build(ui, &mut settings.base_height);
+
+                let mut albedo: [f32; 3] = settings.albedo.into();
+                if imgui::ColorEdit::new("Albedo", &mut albedo).build(ui) {
+                    settings.albedo = albedo.into();
+                }
+
+                imgui::Slider::new("Anisotropy", -0.99, 0.99)
+                    .display_format("%.2f")
+                    .build(ui, &mut settings.anisotropy);
+                imgui::Slider::new("Ambient Intensity", 0.0, 4.0)
+                    .display_format("%.2f")
+                    .build(ui, &mut settings.ambient_intensity);
+
+                ui.checkbox("Noise", &mut settings.noise);
+                if settings.noise {
+                    imgui::Slider::new("Noise Frequency", 0.01, 2.0)
+                        .display_format("%.2f")
+                        .build(ui, &mut settings.noise_frequency);
+                    imgui::Slider::new("Noise Intensity", 0.0, 1.0)
+                        .display_format("%.2f")
+                        .build(ui, &mut settings.noise_intensity);
+
+                    let mut wind: [f32; 3] = settings.wind.into();
+                    if imgui::Drag::new("Wind")
+                        .display_format("%.1f")
+                        .speed(0.1)
+                        .build_array(ui, &mut wind)
+                    {
+                        settings.wind = wind.into();
+                    }
+                }
+
+                imgui::Slider::new("Temporal Blend", 0.0, 0.98)
+                    .display_format("%.2f")
+                    .build(ui, &mut settings.temporal_blend);
+
+                self.set_settings(settings);
+            });
+    }
+}